@@ -0,0 +1,49 @@
+//! Compares `Msg::to_json_value()` with `serde_json::to_value()`, run it by `cargo bench --bench msg_json`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use edgelink_core::runtime::model::{Msg, Variant};
+use serde::Deserialize;
+
+const ROUNDS: u32 = 20_000;
+
+fn make_msg() -> Msg {
+    let readings: Vec<_> =
+        (0..64).map(|i| serde_json::json!({"t": i, "v": i as f64 * 0.5, "ok": i % 3 != 0})).collect();
+    let mut msg = Msg::deserialize(serde_json::json!({
+        "_msgid": "0000000000000001",
+        "topic": "sensors/room1",
+        "payload": {
+            "readings": readings,
+            "meta": {"site": "plant-a", "tags": ["x", "y", "z"], "depth": {"a": {"b": {"c": null}}}}
+        }
+    }))
+    .unwrap();
+    msg["raw"] = Variant::Bytes((0..=255).collect::<Vec<u8>>().into());
+    msg["time"] = Variant::Date(std::time::SystemTime::now());
+    msg
+}
+
+fn measure(name: &str, mut f: impl FnMut() -> serde_json::Value) -> Duration {
+    // Warm up the caches and the allocator
+    for _ in 0..ROUNDS / 10 {
+        black_box(f());
+    }
+    let begin = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    let elapsed = begin.elapsed();
+    println!("{:<24} {:>10.2?} per msg", name, elapsed / ROUNDS);
+    elapsed
+}
+
+fn main() {
+    let msg = make_msg();
+    assert_eq!(msg.to_json_value(), serde_json::to_value(&msg).unwrap());
+
+    let serde = measure("serde_json::to_value", || serde_json::to_value(black_box(&msg)).unwrap());
+    let direct = measure("Msg::to_json_value", || black_box(&msg).to_json_value());
+    println!("speed-up: {:.2}x", serde.as_secs_f64() / direct.as_secs_f64());
+}
@@ -0,0 +1,71 @@
+//! Compares the memory and the time of decoding many similar msgs with and without the interned keys, run it by
+//! `cargo bench --bench variant_keys`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use edgelink_core::runtime::model::{Variant, VariantKey};
+
+const MSGS: usize = 20_000;
+
+/// Counts the bytes allocated and not freed yet.
+struct CountingAlloc;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn make_json(i: usize) -> String {
+    serde_json::json!({
+        "_msgid": format!("{:016x}", i),
+        "topic": "sensors/room1",
+        "payload": {
+            "temperature": i as f64 * 0.1,
+            "humidity": i % 100,
+            "location": {"building": "plant-a", "floor": i % 4, "room": "r1"},
+            "status": {"online": true, "battery_level": 87, "signal_strength": -61}
+        }
+    })
+    .to_string()
+}
+
+fn measure(name: &str, jsons: &[String], interning: bool) -> (usize, Duration) {
+    VariantKey::set_interning(interning);
+    let before = LIVE_BYTES.load(Ordering::Relaxed);
+    let begin = Instant::now();
+    let msgs: Vec<Variant> = jsons.iter().map(|x| serde_json::from_str(x).unwrap()).collect();
+    let elapsed = begin.elapsed();
+    let bytes = LIVE_BYTES.load(Ordering::Relaxed) - before;
+    println!("{:<24} {:>10} bytes {:>10.2?} per msg", name, bytes / msgs.len(), elapsed / msgs.len() as u32);
+    black_box(msgs);
+    VariantKey::set_interning(false);
+    (bytes, elapsed)
+}
+
+fn main() {
+    let jsons: Vec<String> = (0..MSGS).map(make_json).collect();
+    let a: Variant = serde_json::from_str(&jsons[1]).unwrap();
+    VariantKey::set_interning(true);
+    let b: Variant = serde_json::from_str(&jsons[1]).unwrap();
+    VariantKey::set_interning(false);
+    assert_eq!(a, b);
+
+    let (plain, _) = measure("owned keys", &jsons, false);
+    let (interned, _) = measure("interned keys", &jsons, true);
+    println!("memory: {:.1}% of the owned keys", interned as f64 * 100.0 / plain as f64);
+}
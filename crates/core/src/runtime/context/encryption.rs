@@ -0,0 +1,270 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::prelude::*;
+
+use super::ContextStoreOptions;
+use crate::runtime::model::*;
+use crate::*;
+
+/// The prefix of the encrypted values, the version allows changing the format later.
+const ENCRYPTED_PREFIX: &str = "$aes-gcm:v2:";
+
+const NONCE_LEN: usize = 12;
+
+/// Encrypts the values of a context store with AES-256-GCM, every value gets its own random nonce.
+///
+/// The keys are 32 bytes encoded in base64, configured in the options of the store:
+/// ```toml
+/// [runtime.context.stores]
+/// secrets = { provider = "localfs", encryption_key_env = "EDGELINK_CONTEXT_KEY", previous_encryption_keys = ["..."] }
+/// ```
+/// The `encryption_key` option takes the key directly instead of `encryption_key_env`. The previous keys are only used
+/// to decrypt, so the values encrypted by them are rotated to the current key once they are written again.
+///
+/// The scope and the key of the property are authenticated as the associated data, so an encrypted value cannot be
+/// moved to another property or scope. A plaintext value in the store is an error, unless the `migrate_plaintext`
+/// option is `true` to accept the values written before the key was configured and encrypt them on the next save.
+pub(crate) struct ValueCipher {
+    /// The current key comes first
+    ciphers: Vec<Aes256Gcm>,
+    migrate_plaintext: bool,
+}
+
+impl ValueCipher {
+    /// Returns `None` if the store has no encryption key configured.
+    pub fn from_options(store_name: &str, options: Option<&ContextStoreOptions>) -> crate::Result<Option<Self>> {
+        let Some(options) = options.map(|x| &x.options) else {
+            return Ok(None);
+        };
+        let bad_option =
+            |option: &str| config_error(format!("Bad option `{}` of the context store '{}'", option, store_name));
+
+        let key = match (options.get("encryption_key"), options.get("encryption_key_env")) {
+            (Some(key), _) => key.clone().into_string().map_err(|_| bad_option("encryption_key"))?,
+            (None, Some(env_name)) => {
+                let env_name = env_name.clone().into_string().map_err(|_| bad_option("encryption_key_env"))?;
+                std::env::var(&env_name).map_err(|_| {
+                    config_error(format!(
+                        "The environment variable '{}' of the encryption key of the context store '{}' is not set",
+                        env_name, store_name
+                    ))
+                })?
+            }
+            (None, None) => return Ok(None),
+        };
+
+        let mut keys = vec![key];
+        if let Some(previous) = options.get("previous_encryption_keys") {
+            for key in previous.clone().into_array().map_err(|_| bad_option("previous_encryption_keys"))? {
+                keys.push(key.into_string().map_err(|_| bad_option("previous_encryption_keys"))?);
+            }
+        }
+
+        let migrate_plaintext = match options.get("migrate_plaintext") {
+            Some(x) => x.clone().into_bool().map_err(|_| bad_option("migrate_plaintext"))?,
+            None => false,
+        };
+
+        let ciphers = keys
+            .iter()
+            .map(|key| {
+                let key = BASE64_STANDARD.decode(key.trim()).map_err(|_| bad_option("encryption_key"))?;
+                Aes256Gcm::new_from_slice(&key).map_err(|_| {
+                    config_error(format!("The encryption keys of the context store '{}' must be 32 bytes", store_name))
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(Some(ValueCipher { ciphers, migrate_plaintext }))
+    }
+
+    /// Encrypts the value of the property `key` in the `scope` into a string of the nonce and the ciphertext.
+    pub fn encrypt(&self, scope: &str, key: &str, value: &Variant) -> crate::Result<Variant> {
+        let plain = serde_json::to_vec(value)?;
+        let aad = associated_data(scope, key);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = self.ciphers[0]
+            .encrypt(&nonce, Payload { msg: plain.as_slice(), aad: &aad })
+            .map_err(|_| EdgelinkError::InvalidOperation("Failed to encrypt the context value".into()))?;
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&encrypted);
+        Ok(Variant::String(format!("{}{}", ENCRYPTED_PREFIX, BASE64_STANDARD.encode(data))))
+    }
+
+    /// Decrypts the value of the property `key` in the `scope` by any of the keys, also returns `true` if it was not
+    /// encrypted by the current key.
+    pub fn decrypt(&self, scope: &str, key: &str, value: &Variant) -> crate::Result<(Variant, bool)> {
+        let data = match value.as_str().and_then(|x| x.strip_prefix(ENCRYPTED_PREFIX)) {
+            Some(data) => data,
+            None => return Err(EdgelinkError::InvalidOperation("The context value is not encrypted".into()).into()),
+        };
+        let aad = associated_data(scope, key);
+        let data = BASE64_STANDARD
+            .decode(data)
+            .map_err(|_| EdgelinkError::InvalidOperation("The encrypted context value is corrupt".into()))?;
+        if data.len() < NONCE_LEN {
+            return Err(EdgelinkError::InvalidOperation("The encrypted context value is corrupt".into()).into());
+        }
+
+        let (nonce, encrypted) = data.split_at(NONCE_LEN);
+        for (i, cipher) in self.ciphers.iter().enumerate() {
+            if let Ok(plain) = cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: encrypted, aad: &aad }) {
+                let value = serde_json::from_slice(&plain)?;
+                return Ok((value, i > 0));
+            }
+        }
+        Err(EdgelinkError::InvalidOperation(
+            "Failed to decrypt the context value, it is corrupt or encrypted by an unknown key".into(),
+        )
+        .into())
+    }
+
+    /// Encrypts every property of the scope, the keys are kept as they are.
+    pub fn encrypt_scope(&self, scope: &str, scope_map: &Variant) -> crate::Result<Variant> {
+        let mut encrypted = VariantObjectMap::new();
+        if let Some(obj) = scope_map.as_object() {
+            for (key, value) in obj.iter() {
+                encrypted.insert(key.clone(), self.encrypt(scope, key, value)?);
+            }
+        }
+        Ok(Variant::Object(encrypted))
+    }
+
+    /// Decrypts every property of the scope, also returns `true` if any of them needs to be rotated to the current
+    /// key or is a plaintext value being migrated.
+    pub fn decrypt_scope(&self, scope: &str, scope_map: &Variant) -> crate::Result<(Variant, bool)> {
+        let mut decrypted = VariantObjectMap::new();
+        let mut outdated = false;
+        if let Some(obj) = scope_map.as_object() {
+            for (key, value) in obj.iter() {
+                if !is_encrypted(value) {
+                    if !self.migrate_plaintext {
+                        return Err(EdgelinkError::InvalidOperation(format!(
+                            "The context property '{}' is not encrypted, set the `migrate_plaintext` option of the \
+                            store to encrypt the plaintext values",
+                            key
+                        ))
+                        .into());
+                    }
+                    log::warn!(
+                        "[context] The property '{}' of the scope '{}' is not encrypted, it will be encrypted on the \
+                        next save",
+                        key,
+                        scope
+                    );
+                    outdated = true;
+                    decrypted.insert(key.clone(), value.clone());
+                    continue;
+                }
+                let (value, old_key) = self
+                    .decrypt(scope, key, value)
+                    .with_context(|| format!("Bad encrypted context property '{}'", key))?;
+                outdated |= old_key;
+                decrypted.insert(key.clone(), value);
+            }
+        }
+        Ok((Variant::Object(decrypted), outdated))
+    }
+}
+
+fn is_encrypted(value: &Variant) -> bool {
+    value.as_str().is_some_and(|x| x.starts_with(ENCRYPTED_PREFIX))
+}
+
+/// The scope and the key separated by a NUL, which neither of them contains.
+fn associated_data(scope: &str, key: &str) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(scope.len() + key.len() + 1);
+    aad.extend_from_slice(scope.as_bytes());
+    aad.push(0);
+    aad.extend_from_slice(key.as_bytes());
+    aad
+}
+
+fn config_error(message: String) -> anyhow::Error {
+    anyhow::Error::new(EdgelinkError::Configuration).context(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn build_cipher(keys: &[[u8; 32]]) -> ValueCipher {
+        let mut options =
+            HashMap::from([("encryption_key".to_string(), config::Value::from(BASE64_STANDARD.encode(keys[0])))]);
+        let previous: Vec<config::Value> =
+            keys[1..].iter().map(|x| config::Value::from(BASE64_STANDARD.encode(x))).collect();
+        options.insert("previous_encryption_keys".to_string(), config::Value::from(previous));
+        let options = ContextStoreOptions { provider: "localfs".into(), options };
+        ValueCipher::from_options("test", Some(&options)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_it_should_encrypt_with_unique_nonces() {
+        let cipher = build_cipher(&[[1; 32]]);
+        let value = Variant::from(serde_json::json!({"password": "secret"}));
+        let a = cipher.encrypt("node", "password", &value).unwrap();
+        let b = cipher.encrypt("node", "password", &value).unwrap();
+        assert_ne!(a, b);
+        assert!(!a.as_str().unwrap().contains("secret"));
+        assert_eq!(cipher.decrypt("node", "password", &a).unwrap(), (value.clone(), false));
+        assert_eq!(cipher.decrypt("node", "password", &b).unwrap(), (value, false));
+    }
+
+    #[test]
+    fn test_it_should_decrypt_by_the_previous_keys() {
+        let old = build_cipher(&[[1; 32]]);
+        let encrypted = old.encrypt("node", "answer", &Variant::from(42)).unwrap();
+
+        let rotated = build_cipher(&[[2; 32], [1; 32]]);
+        assert_eq!(rotated.decrypt("node", "answer", &encrypted).unwrap(), (Variant::from(42), true));
+
+        let unknown = build_cipher(&[[3; 32]]);
+        let err = unknown.decrypt("node", "answer", &encrypted).unwrap_err();
+        assert!(err.to_string().contains("unknown key"));
+    }
+
+    #[test]
+    fn test_it_should_reject_corrupt_values() {
+        let cipher = build_cipher(&[[1; 32]]);
+        let encrypted = cipher.encrypt("node", "x", &Variant::from("x")).unwrap();
+        let mut tampered = encrypted.as_str().unwrap().to_string();
+        tampered.replace_range(tampered.len() - 4.., "AAAA");
+        assert!(cipher.decrypt("node", "x", &Variant::String(tampered)).is_err());
+        assert!(cipher
+            .decrypt("node", "x", &Variant::from("plain"))
+            .unwrap_err()
+            .to_string()
+            .contains("not encrypted"));
+        assert!(cipher.decrypt("node", "x", &Variant::from(format!("{}%%%", ENCRYPTED_PREFIX))).is_err());
+    }
+
+    #[test]
+    fn test_it_should_bind_the_values_to_their_scope_and_key() {
+        let cipher = build_cipher(&[[1; 32]]);
+        let encrypted = cipher.encrypt("node1", "password", &Variant::from("secret")).unwrap();
+        assert!(cipher.decrypt("node1", "password", &encrypted).is_ok());
+        assert!(cipher.decrypt("node2", "password", &encrypted).is_err());
+        assert!(cipher.decrypt("node1", "token", &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_it_should_only_accept_the_plaintext_values_to_migrate() {
+        let mut cipher = build_cipher(&[[1; 32]]);
+        let mut scope = VariantObjectMap::new();
+        scope.insert("plain".into(), Variant::from("hunter2"));
+        scope.insert("secret".into(), cipher.encrypt("node", "secret", &Variant::from(42)).unwrap());
+        let scope = Variant::Object(scope);
+
+        let err = cipher.decrypt_scope("node", &scope).unwrap_err();
+        assert!(format!("{:#}", err).contains("'plain' is not encrypted"));
+
+        cipher.migrate_plaintext = true;
+        let (decrypted, outdated) = cipher.decrypt_scope("node", &scope).unwrap();
+        assert!(outdated);
+        assert_eq!(decrypted.get_nav("plain", &[]), Some(&Variant::from("hunter2")));
+        assert_eq!(decrypted.get_nav("secret", &[]), Some(&Variant::from(42)));
+
+        let encrypted = cipher.encrypt_scope("node", &decrypted).unwrap();
+        assert_eq!(cipher.decrypt_scope("node", &encrypted).unwrap(), (decrypted, false));
+    }
+}
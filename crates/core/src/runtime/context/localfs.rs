@@ -1 +1,378 @@
-// .keepme
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use propex::PropexSegment;
+use tokio::sync::RwLock;
+
+use super::encryption::ValueCipher;
+use super::{EdgelinkError, ElementId, Variant};
+use crate::runtime::context::*;
+use crate::Result;
+
+inventory::submit! {
+    ProviderMetadata { type_: "localfs", factory: LocalFsContextStore::build }
+}
+
+/// The parent directory of the stores without the `dir` option, each of them gets a sub-directory of its name.
+const DEFAULT_BASE_DIR: &str = "context";
+
+/// A context store persisting every scope as the JSON file `{dir}/{scope}.json`, like the `localfilesystem` store of
+/// Node-RED but always writing through. The scope `{node}:{flow}` of a node in a flow is stored as
+/// `{dir}/{flow}/{node}.json` like Node-RED, no file name contains a ':' which Windows does not allow.
+///
+/// Each configured instance owns its own directory, so several named stores can be used side by side:
+/// ```toml
+/// [runtime.context.stores]
+/// file = { provider = "localfs", dir = "/var/lib/edgelink/context" }
+/// archive = { provider = "localfs", dir = "/mnt/archive/context" }
+/// ```
+///
+/// The values are encrypted on the disk if an encryption key is configured, see `ValueCipher`.
+struct LocalFsContextStore {
+    name: String,
+    dir: PathBuf,
+    cipher: Option<ValueCipher>,
+    /// The scopes loaded from the disk so far
+    scopes: RwLock<HashMap<String, Variant>>,
+}
+
+impl LocalFsContextStore {
+    fn build(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Box<dyn ContextStore>> {
+        let dir = match options.and_then(|x| x.options.get("dir")) {
+            Some(dir) => {
+                let dir = dir.clone().into_string().map_err(|_| EdgelinkError::Configuration);
+                PathBuf::from(
+                    dir.with_context(|| format!("The `dir` of the context store '{}' must be a string", name))?,
+                )
+            }
+            None => Path::new(DEFAULT_BASE_DIR).join(&name),
+        };
+        let cipher = ValueCipher::from_options(&name, options)?;
+        let this = LocalFsContextStore { name, dir, cipher, scopes: RwLock::new(HashMap::new()) };
+        Ok(Box::new(this))
+    }
+
+    fn scope_path(&self, scope: &str) -> PathBuf {
+        match scope.split_once(':') {
+            Some((node, flow)) => self.dir.join(flow).join(format!("{}.json", node)),
+            None => self.dir.join(format!("{}.json", scope)),
+        }
+    }
+
+    async fn load_scope(&self, scope: &str) -> Result<Variant> {
+        let loaded: Variant = match tokio::fs::read(self.scope_path(scope)).await {
+            Ok(data) => serde_json::from_slice(&data).with_context(|| {
+                format!("Failed to load the scope '{}' of the context store '{}'", scope, self.name)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Variant::empty_object()),
+            Err(e) => return Err(e.into()),
+        };
+        match &self.cipher {
+            Some(cipher) => {
+                let (decrypted, outdated) = cipher.decrypt_scope(scope, &loaded).with_context(|| {
+                    format!("Failed to decrypt the scope '{}' of the context store '{}'", scope, self.name)
+                })?;
+                // Rotates the values encrypted by the previous keys and encrypts the plaintext ones
+                if outdated {
+                    self.save_scope(scope, &decrypted).await?;
+                }
+                Ok(decrypted)
+            }
+            None => Ok(loaded),
+        }
+    }
+
+    async fn save_scope(&self, scope: &str, scope_map: &Variant) -> Result<()> {
+        let path = self.scope_path(scope);
+        tokio::fs::create_dir_all(path.parent().unwrap_or(&self.dir)).await?;
+        let data = match &self.cipher {
+            Some(cipher) => serde_json::to_vec(&cipher.encrypt_scope(scope, scope_map)?)?,
+            None => serde_json::to_vec(scope_map)?,
+        };
+        // Replaces the file at once, a crash while writing leaves the previous content intact
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    /// Deletes the scopes `{node}:{flow}` of the inactive nodes in the directory of the flow, and the directory once it
+    /// is empty.
+    async fn clean_flow_dir(
+        &self,
+        scopes: &mut HashMap<String, Variant>,
+        flow_dir: &Path,
+        flow: &str,
+        active_nodes: &[ElementId],
+    ) -> Result<()> {
+        let mut entries = tokio::fs::read_dir(flow_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some(node) = file_name.to_str().and_then(|x| x.strip_suffix(".json")) else {
+                continue;
+            };
+            if node.parse::<ElementId>().is_ok_and(|id| !active_nodes.contains(&id)) {
+                let scope = format!("{}:{}", node, flow);
+                log::debug!("Deleting the inactive scope '{}' of the context store '{}'", scope, self.name);
+                scopes.remove(&scope);
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        // Fails if any scope is left
+        let _ = tokio::fs::remove_dir(flow_dir).await;
+        Ok(())
+    }
+
+    /// Makes sure the scope has been loaded into `scopes`.
+    async fn ensure_scope<'a>(&self, scopes: &'a mut HashMap<String, Variant>, scope: &str) -> Result<&'a mut Variant> {
+        if !scopes.contains_key(scope) {
+            let loaded = self.load_scope(scope).await?;
+            scopes.insert(scope.to_string(), loaded);
+        }
+        Ok(scopes.get_mut(scope).expect("scope"))
+    }
+}
+
+#[async_trait]
+impl ContextStore for LocalFsContextStore {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn open(&self) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        // Every change has been written through
+        Ok(())
+    }
+
+    async fn get_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = self.ensure_scope(&mut scopes, scope).await?;
+        scope_map.get_segs(path).cloned().ok_or(EdgelinkError::OutOfRange.into())
+    }
+
+    async fn get_many(&self, scope: &str, keys: &[&str]) -> Result<Vec<Variant>> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = self.ensure_scope(&mut scopes, scope).await?;
+        Ok(keys.iter().filter_map(|key| scope_map.get_nav(key, &[]).cloned()).collect())
+    }
+
+    async fn get_keys(&self, scope: &str) -> Result<Vec<String>> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = self.ensure_scope(&mut scopes, scope).await?;
+        Ok(scope_map.as_object().map(|x| x.keys().map(|k| k.to_string()).collect()).unwrap_or_default())
+    }
+
+    async fn set_one(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<()> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = self.ensure_scope(&mut scopes, scope).await?;
+        scope_map.set_segs_property(path, value, true)?;
+        self.save_scope(scope, scope_map).await
+    }
+
+    async fn set_many(&self, scope: &str, pairs: Vec<(String, Variant)>) -> Result<()> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = self.ensure_scope(&mut scopes, scope).await?;
+        let obj = scope_map.as_object_mut().ok_or(EdgelinkError::InvalidOperation("Bad scope".into()))?;
+        for (key, value) in pairs {
+            let _ = obj.insert(key.into(), value);
+        }
+        self.save_scope(scope, scope_map).await
+    }
+
+    async fn remove_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = self.ensure_scope(&mut scopes, scope).await?;
+        let removed =
+            scope_map.as_object_mut().and_then(|x| x.remove_segs_property(path)).ok_or(EdgelinkError::OutOfRange)?;
+        self.save_scope(scope, scope_map).await?;
+        Ok(removed)
+    }
+
+    async fn delete(&self, scope: &str) -> Result<()> {
+        let mut scopes = self.scopes.write().await;
+        scopes.remove(scope);
+        match tokio::fs::remove_file(self.scope_path(scope)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes the scopes of the nodes not in `active_nodes`, the global scope and the unknown files are kept.
+    async fn clean(&self, active_nodes: &[ElementId]) -> Result<()> {
+        let mut scopes = self.scopes.write().await;
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            // The directory of the node scopes of a flow
+            if entry.file_type().await?.is_dir() {
+                if file_name.parse::<ElementId>().is_ok() {
+                    self.clean_flow_dir(&mut scopes, &entry.path(), file_name, active_nodes).await?;
+                }
+                continue;
+            }
+            let Some(scope) = file_name.strip_suffix(".json") else {
+                continue;
+            };
+            if scope == GLOBAL_CONTEXT_NAME {
+                continue;
+            }
+            if scope.parse::<ElementId>().is_ok_and(|id| !active_nodes.contains(&id)) {
+                log::debug!("Deleting the inactive scope '{}' of the context store '{}'", scope, self.name);
+                scopes.remove(scope);
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("edgelink-localfs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn build_store(name: &str, dir: &Path) -> Box<dyn ContextStore> {
+        build_store_with(name, dir, [])
+    }
+
+    fn build_store_with<const N: usize>(
+        name: &str,
+        dir: &Path,
+        extra_options: [(&str, config::Value); N],
+    ) -> Box<dyn ContextStore> {
+        let mut options = HashMap::from([("dir".to_string(), config::Value::from(dir.to_string_lossy().to_string()))]);
+        options.extend(extra_options.into_iter().map(|(k, v)| (k.to_string(), v)));
+        let options = ContextStoreOptions { provider: "localfs".into(), options };
+        LocalFsContextStore::build(name.to_string(), Some(&options)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_persist_properties() {
+        let dir = test_dir("persist");
+        let store = build_store("file", &dir);
+        store.set_one("nodeX", &propex::parse("foo.bar").unwrap(), "test".into()).await.unwrap();
+        assert!(dir.join("nodeX.json").exists());
+
+        // A new instance reads it back from the disk
+        let store = build_store("file", &dir);
+        assert_eq!(store.get_one("nodeX", &propex::parse("foo.bar").unwrap()).await.unwrap(), "test".into());
+        assert_eq!(store.get_keys("nodeX").await.unwrap(), vec!["foo".to_string()]);
+
+        store.remove_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap();
+        let store = build_store("file", &dir);
+        assert!(store.get_one("nodeX", &propex::parse("foo").unwrap()).await.is_err());
+
+        store.delete("nodeX").await.unwrap();
+        assert!(!dir.join("nodeX.json").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_clean_the_inactive_scopes() {
+        let dir = test_dir("clean");
+        let store = build_store("file", &dir);
+        let path = propex::parse("foo").unwrap();
+        for scope in ["global", "1", "2", "3:100", "4:200", "2:200"] {
+            store.set_one(scope, &path, "test".into()).await.unwrap();
+        }
+        std::fs::write(dir.join("notes.json"), "{}").unwrap();
+        // No temporary file is left behind and no file name has a ':'
+        assert!(!dir.join("1.json.tmp").exists());
+        assert!(dir.join("100").join("3.json").exists());
+        assert!(!dir.join("3:100.json").exists());
+
+        store.clean(&[ElementId::from(2)]).await.unwrap();
+        assert!(dir.join("global.json").exists());
+        assert!(!dir.join("1.json").exists());
+        assert!(dir.join("2.json").exists());
+        assert!(!dir.join("100").exists());
+        assert!(!dir.join("200").join("4.json").exists());
+        assert!(dir.join("200").join("2.json").exists());
+        assert!(dir.join("notes.json").exists());
+        assert!(store.get_one("1", &path).await.is_err());
+        assert_eq!(store.get_one("2", &path).await.unwrap(), "test".into());
+        assert_eq!(store.get_one("2:200", &path).await.unwrap(), "test".into());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_encrypt_values_at_rest() {
+        use base64::prelude::*;
+
+        let dir = test_dir("encrypted");
+        let old_key = BASE64_STANDARD.encode([1u8; 32]);
+        let new_key = BASE64_STANDARD.encode([2u8; 32]);
+
+        let store = build_store_with("secrets", &dir, [("encryption_key", config::Value::from(old_key.clone()))]);
+        store.set_one("nodeX", &propex::parse("password").unwrap(), "hunter2".into()).await.unwrap();
+        let on_disk = std::fs::read_to_string(dir.join("nodeX.json")).unwrap();
+        assert!(on_disk.contains("password"));
+        assert!(!on_disk.contains("hunter2"));
+        assert!(on_disk.contains("$aes-gcm:v2:"));
+
+        // Rotates to the new key once loaded
+        let store = build_store_with(
+            "secrets",
+            &dir,
+            [
+                ("encryption_key", config::Value::from(new_key.clone())),
+                ("previous_encryption_keys", config::Value::from(vec![config::Value::from(old_key)])),
+            ],
+        );
+        assert_eq!(store.get_one("nodeX", &propex::parse("password").unwrap()).await.unwrap(), "hunter2".into());
+        let store = build_store_with("secrets", &dir, [("encryption_key", config::Value::from(new_key))]);
+        assert_eq!(store.get_one("nodeX", &propex::parse("password").unwrap()).await.unwrap(), "hunter2".into());
+
+        // A wrong key fails clearly instead of returning garbage
+        let store = build_store_with(
+            "secrets",
+            &dir,
+            [("encryption_key", config::Value::from(BASE64_STANDARD.encode([3u8; 32])))],
+        );
+        let err = store.get_one("nodeX", &propex::parse("password").unwrap()).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to decrypt the scope 'nodeX'"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_migrate_the_plaintext_values_on_load() {
+        use base64::prelude::*;
+
+        let dir = test_dir("plaintext");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("nodeY.json"), r#"{"password":"hunter2"}"#).unwrap();
+
+        let key = BASE64_STANDARD.encode([1u8; 32]);
+        let store = build_store_with("secrets", &dir, [("encryption_key", config::Value::from(key.clone()))]);
+        assert!(store.get_one("nodeY", &propex::parse("password").unwrap()).await.is_err());
+
+        let store = build_store_with(
+            "secrets",
+            &dir,
+            [("encryption_key", config::Value::from(key)), ("migrate_plaintext", config::Value::from(true))],
+        );
+        assert_eq!(store.get_one("nodeY", &propex::parse("password").unwrap()).await.unwrap(), "hunter2".into());
+        let on_disk = std::fs::read_to_string(dir.join("nodeY.json")).unwrap();
+        assert!(!on_disk.contains("hunter2"));
+        assert!(on_disk.contains("$aes-gcm:v2:"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
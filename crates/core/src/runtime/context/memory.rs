@@ -67,7 +67,7 @@ impl ContextStore for MemoryContextStore {
     async fn get_keys(&self, scope: &str) -> Result<Vec<String>> {
         let scopes = self.scopes.read().await;
         if let Some(scope_map) = scopes.get(scope) {
-            return Ok(scope_map.as_object().unwrap().keys().cloned().collect::<Vec<_>>());
+            return Ok(scope_map.as_object().unwrap().keys().map(|k| k.to_string()).collect::<Vec<_>>());
         }
         Err(EdgelinkError::OutOfRange.into())
     }
@@ -83,7 +83,7 @@ impl ContextStore for MemoryContextStore {
         let mut scopes = self.scopes.write().await;
         let scope_map = scopes.entry(scope.to_string()).or_insert_with(Variant::empty_object);
         for (key, value) in pairs {
-            let _ = scope_map.as_object_mut().unwrap().insert(key, value);
+            let _ = scope_map.as_object_mut().unwrap().insert(key.into(), value);
         }
         Ok(())
     }
@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
     sync::{Arc, Weak},
 };
 
@@ -12,6 +13,7 @@ use serde;
 use crate::*;
 use runtime::model::*;
 
+mod encryption;
 mod localfs;
 mod memory;
 
@@ -19,6 +21,9 @@ pub const GLOBAL_CONTEXT_NAME: &str = "global";
 pub const DEFAULT_STORE_NAME: &str = "default";
 pub const DEFAULT_STORE_NAME_ALIAS: &str = "_";
 
+/// The prefix of the keys to access the parent context, e.g. the group of a node, it can be repeated.
+pub const PARENT_CONTEXT_PREFIX: &str = "$parent.";
+
 type StoreFactoryFn = fn(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Box<dyn ContextStore>>;
 
 #[derive(Debug, Clone, Copy)]
@@ -80,10 +85,28 @@ pub struct Context {
 
 pub type ContextStoreHandle = Arc<dyn ContextStore>;
 
+/// Receives the new value of the watched key, `None` if it has been removed, or the error of reading it.
+pub type ContextWatchCallback = Arc<dyn Fn(Result<Option<Variant>>) + Send + Sync>;
+
+/// Identifies a watch registered by `Context::watch()`, unique in the `ContextManager`.
+pub type ContextWatchId = u64;
+
+struct ContextWatcher {
+    store: String,
+    scope: String,
+    key: String,
+    /// The top-level property of the `key`.
+    property: String,
+    callback: ContextWatchCallback,
+}
+
 pub struct ContextManager {
     default_store: ContextStoreHandle,
+    default_store_name: String,
     stores: HashMap<String, ContextStoreHandle>,
     contexts: DashMap<String, Arc<Context>>,
+    watchers: DashMap<ContextWatchId, ContextWatcher>,
+    watcher_id_seed: AtomicU64,
 }
 
 pub struct ContextManagerBuilder {
@@ -93,21 +116,51 @@ pub struct ContextManagerBuilder {
 }
 
 impl Context {
+    /// Gets the value of the key from the store, or from the store of its `#:(store)::` prefix if `storage` is
+    /// `None`, which defaults to the default store.
     pub async fn get_one(&self, storage: Option<&str>, key: &str, eval_env: &[PropexEnv<'_>]) -> Option<Variant> {
-        let manager = self.manager.upgrade()?;
-        let store =
-            if let Some(storage) = storage { manager.get_context_store(storage)? } else { manager.get_default_store() };
+        self.try_get_one(storage, key, eval_env).await.ok().flatten()
+    }
+
+    /// Like `get_one()`, but fails if the store cannot be found or the key is invalid, a missing value is `None`.
+    pub async fn try_get_one(
+        &self,
+        storage: Option<&str>,
+        key: &str,
+        eval_env: &[PropexEnv<'_>],
+    ) -> Result<Option<Variant>> {
+        let manager = self.manager.upgrade().ok_or(EdgelinkError::InvalidOperation("The manager is gone".into()))?;
+        let (storage, key) = match storage {
+            Some(storage) => (storage, key),
+            None => {
+                let parsed = manager.parse_context_store(key)?;
+                (parsed.store.unwrap_or_default(), parsed.key)
+            }
+        };
+        let store = manager
+            .get_context_store(storage)
+            .ok_or(EdgelinkError::BadArgument("storage"))
+            .with_context(|| format!("Unknown context store: '{}'", storage))?;
+        let (scope, key) = self.resolve_parent(key)?;
         // TODO FIXME change it to fixed length stack-allocated string
-        let mut path = propex::parse(key).ok()?;
-        expand_propex_segments(&mut path, eval_env).ok()?;
-        store.get_one(&self.scope, &path).await.ok()
+        let mut path = propex::parse(key)?;
+        expand_propex_segments(&mut path, eval_env)?;
+        Ok(store.get_one(&scope, &path).await.ok())
     }
 
     pub async fn keys(&self, store: Option<&str>) -> Option<Vec<String>> {
-        let manager = self.manager.upgrade()?;
-        let store =
-            if let Some(storage) = store { manager.get_context_store(storage)? } else { manager.get_default_store() };
-        store.get_keys(&self.scope).await.ok()
+        self.try_keys(store).await.ok()
+    }
+
+    /// Like `keys()`, but fails if the store cannot be found or cannot read the scope.
+    pub async fn try_keys(&self, store: Option<&str>) -> Result<Vec<String>> {
+        let manager = self.manager.upgrade().ok_or(EdgelinkError::InvalidOperation("The manager is gone".into()))?;
+        let storage = store.unwrap_or(DEFAULT_STORE_NAME);
+        let store = manager
+            .get_context_store(storage)
+            .ok_or(EdgelinkError::BadArgument("storage"))
+            .with_context(|| format!("Unknown context store: '{}'", storage))?;
+        store.get_keys(&self.scope).await
     }
 
     pub async fn set_one(
@@ -118,22 +171,99 @@ impl Context {
         eval_env: &[PropexEnv<'_>],
     ) -> Result<()> {
         let manager = self.manager.upgrade().expect("manager");
-        let store = if let Some(storage) = storage {
-            manager
-                .get_context_store(storage)
-                .ok_or(EdgelinkError::BadArgument("storage"))
-                .with_context(|| format!("Cannot found the storage: '{}'", storage))?
-        } else {
-            manager.get_default_store()
+        let (storage, key) = match storage {
+            Some(storage) => (storage, key),
+            None => {
+                let parsed = manager.parse_context_store(key)?;
+                (parsed.store.unwrap_or_default(), parsed.key)
+            }
         };
+        let store = manager
+            .get_context_store(storage)
+            .ok_or(EdgelinkError::BadArgument("storage"))
+            .with_context(|| format!("Cannot found the storage: '{}'", storage))?;
+        let (scope, key) = self.resolve_parent(key)?;
         let mut path = propex::parse(key)?;
         expand_propex_segments(&mut path, eval_env)?;
         if let Some(value) = value {
-            store.set_one(&self.scope, &path, value).await
+            store.set_one(&scope, &path, value).await?;
         } else {
-            let _ = store.remove_one(&self.scope, &path).await?;
-            Ok(())
+            let _ = store.remove_one(&scope, &path).await?;
         }
+        manager.notify_watchers(manager.canonical_store_name(storage), &scope, &path, store).await;
+        Ok(())
+    }
+
+    /// Calls the `callback` with the new value whenever the `key`, one of its parents or one of its children is set or
+    /// removed through any `Context` of the same scope, the store is resolved like `set_one()`.
+    ///
+    /// The watch lasts until `unwatch()`, so the owner must unwatch it when it stops.
+    pub fn watch<F>(&self, storage: Option<&str>, key: &str, callback: F) -> Result<ContextWatchId>
+    where
+        F: Fn(Result<Option<Variant>>) + Send + Sync + 'static,
+    {
+        let manager = self.manager.upgrade().ok_or(EdgelinkError::InvalidOperation("The manager is gone".into()))?;
+        let (storage, key) = match storage {
+            Some(storage) => (storage, key),
+            None => {
+                let parsed = manager.parse_context_store(key)?;
+                (parsed.store.unwrap_or_default(), parsed.key)
+            }
+        };
+        if manager.get_context_store(storage).is_none() {
+            return Err(EdgelinkError::BadArgument("storage"))
+                .with_context(|| format!("Unknown context store: '{}'", storage));
+        }
+        let (scope, key) = self.resolve_parent(key)?;
+        // Only the plain properties can be watched, the indices and nested expressions are evaluated per message
+        let path = propex::parse(key)?;
+        let property = match path.first() {
+            Some(PropexSegment::Property(p))
+                if !path.iter().any(|x| matches!(x, PropexSegment::Nested(_) | PropexSegment::Append)) =>
+            {
+                p.to_string()
+            }
+            _ => {
+                return Err(EdgelinkError::BadArgument("key"))
+                    .with_context(|| format!("Cannot watch the key: '{}'", key))
+            }
+        };
+        let id = manager.watcher_id_seed.fetch_add(1, Ordering::Relaxed) + 1;
+        let watcher = ContextWatcher {
+            store: manager.canonical_store_name(storage).to_string(),
+            scope,
+            key: key.to_string(),
+            property,
+            callback: Arc::new(callback),
+        };
+        manager.watchers.insert(id, watcher);
+        Ok(id)
+    }
+
+    /// Removes the watch, returns `false` if there is no such watch.
+    pub fn unwatch(&self, id: ContextWatchId) -> bool {
+        self.manager.upgrade().is_some_and(|x| x.watchers.remove(&id).is_some())
+    }
+
+    /// Returns the scope of the context addressed by the `$parent.` prefixes of the key and the rest of the key.
+    ///
+    /// The chain of a node is its groups from the innermost one, then the flow, and then the global context.
+    fn resolve_parent<'k>(&self, key: &'k str) -> Result<(String, &'k str)> {
+        let mut key = key;
+        let mut target: Option<Arc<Context>> = None;
+        while let Some(rest) = key.strip_prefix(PARENT_CONTEXT_PREFIX) {
+            let parent = match target {
+                Some(ref ctx) => ctx.parent.as_ref(),
+                None => self.parent.as_ref(),
+            };
+            target = Some(
+                parent
+                    .and_then(|x| x.upgrade())
+                    .ok_or(EdgelinkError::InvalidOperation(format!("The context '{}' has no parent", self.scope)))?,
+            );
+            key = rest;
+        }
+        Ok((target.map(|x| x.scope.clone()).unwrap_or_else(|| self.scope.clone()), key))
     }
 }
 
@@ -145,7 +275,14 @@ impl Default for ContextManager {
             (memory_metadata.factory)("memory".into(), None).expect("Create memory storage cannot go wrong.");
         let mut stores: HashMap<std::string::String, ContextStoreHandle> = HashMap::with_capacity(1);
         stores.insert("memory".to_string(), Arc::from(memory_store));
-        Self { default_store: stores["memory"].clone(), contexts: DashMap::new(), stores }
+        Self {
+            default_store: stores["memory"].clone(),
+            default_store_name: "memory".into(),
+            contexts: DashMap::new(),
+            watchers: DashMap::new(),
+            watcher_id_seed: AtomicU64::new(0),
+            stores,
+        }
     }
 }
 
@@ -196,20 +333,30 @@ impl ContextManagerBuilder {
                 )
             });
         }
+        self.default_store = settings.default.clone();
         self.settings = Some(settings);
         Ok(self)
     }
 
+    /// Overrides the default store, which is `memory` or the `default` of the configuration.
     pub fn default_store(&mut self, default: String) -> &mut Self {
         self.default_store = default;
         self
     }
 
     pub fn build(&self) -> crate::Result<Arc<ContextManager>> {
+        let default_store = self
+            .stores
+            .get(&self.default_store)
+            .ok_or(EdgelinkError::Configuration)
+            .with_context(|| format!("Cannot found the default context storage '{}'", self.default_store))?;
         let cm = ContextManager {
-            default_store: self.stores[&self.default_store].clone(),
+            default_store: default_store.clone(),
+            default_store_name: self.default_store.clone(),
             stores: self.stores.clone(),
             contexts: DashMap::new(),
+            watchers: DashMap::new(),
+            watcher_id_seed: AtomicU64::new(0),
         };
         Ok(Arc::new(cm))
     }
@@ -237,6 +384,66 @@ impl ContextManager {
         &self.default_store
     }
 
+    /// Returns the name of the default store, the one used by the keys without a store.
+    pub fn default_store_name(&self) -> &str {
+        &self.default_store_name
+    }
+
+    /// Parses the key like `evaluate_key()`, but resolves the store to the default store if no `#:(store)::` prefix is
+    /// present.
+    pub fn parse_context_store<'a>(&'a self, key: &'a str) -> crate::Result<ContextKey<'a>> {
+        let mut parsed = evaluate_key(key)?;
+        parsed.store = Some(parsed.store.unwrap_or(&self.default_store_name));
+        Ok(parsed)
+    }
+
+    /// Maps the aliases of the default store to its name.
+    fn canonical_store_name<'a>(&'a self, store_name: &'a str) -> &'a str {
+        match store_name {
+            DEFAULT_STORE_NAME | DEFAULT_STORE_NAME_ALIAS | "" => &self.default_store_name,
+            _ => store_name,
+        }
+    }
+
+    /// Calls the callbacks watching the top-level property of the `path` in the `scope` of the `store`.
+    async fn notify_watchers(
+        &self,
+        store_name: &str,
+        scope: &str,
+        path: &[PropexSegment<'_>],
+        store: &ContextStoreHandle,
+    ) {
+        let Some(PropexSegment::Property(changed)) = path.first() else {
+            return;
+        };
+        // Never hold the map while calling back, a callback may watch or unwatch
+        let matched: Vec<(String, ContextWatchCallback)> = self
+            .watchers
+            .iter()
+            .filter(|x| x.store == store_name && x.scope == scope && x.property == *changed)
+            .map(|x| (x.key.clone(), x.callback.clone()))
+            .collect();
+        for (key, callback) in matched.into_iter() {
+            let watched = match propex::parse(&key) {
+                Ok(watched) => watched,
+                Err(err) => {
+                    callback(Err(err.into()));
+                    continue;
+                }
+            };
+            if !paths_overlap(path, &watched) {
+                continue;
+            }
+            let value = match store.get_one(scope, &watched).await {
+                Ok(value) => Ok(Some(value)),
+                // The stores report a missing value as out of range
+                Err(err) if matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::OutOfRange)) => Ok(None),
+                Err(err) => Err(err),
+            };
+            callback(value);
+        }
+    }
+
     pub fn get_context_store<'a>(&'a self, store_name: &str) -> Option<&'a ContextStoreHandle> {
         match store_name {
             DEFAULT_STORE_NAME | DEFAULT_STORE_NAME_ALIAS | "" => Some(&self.default_store),
@@ -245,6 +452,19 @@ impl ContextManager {
     }
 }
 
+/// Whether one of the paths is a prefix of the other, so setting the `changed` one may change the `watched` one.
+fn paths_overlap(changed: &[PropexSegment<'_>], watched: &[PropexSegment<'_>]) -> bool {
+    for (c, w) in changed.iter().zip(watched.iter()) {
+        match c {
+            // The appended item may be any index
+            PropexSegment::Append => return true,
+            _ if c != w => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
 fn parse_store_expr(input: &str) -> nom::IResult<&str, &str, nom::error::VerboseError<&str>> {
     use crate::text::nom_parsers::*;
     use nom::{
@@ -317,4 +537,151 @@ mod tests {
         let foo = global.get_one(None, "foo", &[]).await.unwrap();
         assert_eq!(foo, "bar".into());
     }
+
+    #[tokio::test]
+    async fn test_try_keys_should_fail_with_an_unknown_store() {
+        let ctxman = ContextManagerBuilder::new().load_default().build().unwrap();
+        let global = ctxman.new_global_context();
+        global.set_one(None, "foo", Some(Variant::from("bar")), &[]).await.unwrap();
+
+        assert_eq!(global.try_keys(None).await.unwrap(), vec!["foo".to_string()]);
+        let err = global.try_keys(Some("no-such-store")).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Unknown context store: 'no-such-store'"));
+        assert!(global.keys(Some("no-such-store")).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_watch_should_notify_the_changes_of_the_same_scope() {
+        let ctxman = ContextManagerBuilder::new().load_default().build().unwrap();
+        let global = ctxman.new_global_context();
+        let flow = ctxman.new_context(&global, "100".to_string());
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let cloned = changes.clone();
+        let id = global.watch(None, "foo", move |value| cloned.lock().unwrap().push(value.unwrap())).unwrap();
+
+        global.set_one(None, "foo", Some(Variant::from("bar")), &[]).await.unwrap();
+        global.set_one(None, "foo", Some(Variant::empty_object()), &[]).await.unwrap();
+        global.set_one(Some("default"), "foo.baz", Some(Variant::from(1)), &[]).await.unwrap();
+        global.set_one(None, "other", Some(Variant::from(2)), &[]).await.unwrap();
+        flow.set_one(None, "foo", Some(Variant::from("flow")), &[]).await.unwrap();
+        flow.set_one(None, "$parent.foo", Some(Variant::from("qux")), &[]).await.unwrap();
+        global.set_one(None, "foo", None, &[]).await.unwrap();
+        assert!(global.unwatch(id));
+        global.set_one(None, "foo", Some(Variant::from("unwatched")), &[]).await.unwrap();
+
+        let changes = changes.lock().unwrap().clone();
+        assert_eq!(changes.len(), 5, "{:?}", changes);
+        assert_eq!(changes[0], Some(Variant::from("bar")));
+        assert_eq!(changes[2], Some(Variant::from(serde_json::json!({"baz": 1}))));
+        assert_eq!(changes[3], Some(Variant::from("qux")));
+        assert_eq!(changes[4], None);
+        assert!(!global.unwatch(id));
+    }
+
+    #[tokio::test]
+    async fn test_watch_should_only_notify_the_overlapping_keys() {
+        let ctxman = ContextManagerBuilder::new().load_default().build().unwrap();
+        let global = ctxman.new_global_context();
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let cloned = changes.clone();
+        global.watch(None, "foo.bar", move |value| cloned.lock().unwrap().push(value.unwrap())).unwrap();
+
+        global.set_one(None, "foo", Some(Variant::empty_object()), &[]).await.unwrap();
+        global.set_one(None, "foo.baz", Some(Variant::from(1)), &[]).await.unwrap();
+        global.set_one(None, "foo.bar", Some(Variant::empty_object()), &[]).await.unwrap();
+        global.set_one(None, "foo.bar.qux", Some(Variant::from(3)), &[]).await.unwrap();
+        global.set_one(None, "foo.bar", None, &[]).await.unwrap();
+
+        let changes = changes.lock().unwrap().clone();
+        assert_eq!(changes.len(), 4, "{:?}", changes);
+        assert_eq!(changes[0], None);
+        assert_eq!(changes[2], Some(Variant::from(serde_json::json!({"qux": 3}))));
+        assert_eq!(changes[3], None);
+        assert!(global.watch(None, "foo[msg.key]", |_| {}).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_named_file_stores_should_be_isolated() {
+        let base_dir = std::env::temp_dir().join(format!("edgelink-named-stores-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let toml = format!(
+            r#"
+            [runtime.context]
+            default = "memory"
+
+            [runtime.context.stores]
+            memory = {{ provider = "memory" }}
+            file = {{ provider = "localfs", dir = "{0}/file" }}
+            archive = {{ provider = "localfs", dir = "{0}/archive" }}
+            "#,
+            base_dir.to_string_lossy().replace('\\', "/")
+        );
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let ctxman = ContextManagerBuilder::new().with_config(&cfg).unwrap().build().unwrap();
+        let global = ctxman.new_global_context();
+
+        let key = evaluate_key("#:(file)::foo").unwrap();
+        global.set_one(key.store, key.key, Some("in file".into()), &[]).await.unwrap();
+        let key = evaluate_key("#:(archive)::foo").unwrap();
+        global.set_one(key.store, key.key, Some("in archive".into()), &[]).await.unwrap();
+
+        assert_eq!(global.get_one(Some("file"), "foo", &[]).await.unwrap(), "in file".into());
+        assert_eq!(global.get_one(Some("archive"), "foo", &[]).await.unwrap(), "in archive".into());
+        assert!(global.get_one(None, "foo", &[]).await.is_none());
+        assert!(base_dir.join("file").join("global.json").exists());
+        assert!(base_dir.join("archive").join("global.json").exists());
+
+        // Reloaded from the directory of each store
+        let ctxman = ContextManagerBuilder::new().with_config(&cfg).unwrap().build().unwrap();
+        let global = ctxman.new_global_context();
+        assert_eq!(global.get_one(Some("archive"), "foo", &[]).await.unwrap(), "in archive".into());
+        let _ = std::fs::remove_dir_all(&base_dir);
+    }
+
+    #[tokio::test]
+    async fn test_plain_keys_should_hit_the_default_store() {
+        let dir = std::env::temp_dir().join(format!("edgelink-default-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let toml = format!(
+            r#"
+            [runtime.context]
+            default = "file"
+
+            [runtime.context.stores]
+            memory = {{ provider = "memory" }}
+            file = {{ provider = "localfs", dir = "{}" }}
+            "#,
+            dir.to_string_lossy().replace('\\', "/")
+        );
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let ctxman = ContextManagerBuilder::new().with_config(&cfg).unwrap().build().unwrap();
+        assert_eq!(ctxman.default_store_name(), "file");
+
+        let parsed = ctxman.parse_context_store("foo").unwrap();
+        assert_eq!((parsed.store, parsed.key), (Some("file"), "foo"));
+        let parsed = ctxman.parse_context_store("#:(memory)::foo").unwrap();
+        assert_eq!((parsed.store, parsed.key), (Some("memory"), "foo"));
+
+        let global = ctxman.new_global_context();
+        global.set_one(None, "foo", Some("bar".into()), &[]).await.unwrap();
+        assert_eq!(global.get_one(None, "foo", &[]).await.unwrap(), "bar".into());
+        assert_eq!(global.get_one(Some("file"), "foo", &[]).await.unwrap(), "bar".into());
+        assert!(global.get_one(Some("memory"), "foo", &[]).await.is_none());
+        assert!(global.get_one(None, "#:(memory)::foo", &[]).await.is_none());
+        assert!(dir.join("global.json").exists());
+
+        // The default of the configuration can be overridden
+        let ctxman =
+            ContextManagerBuilder::new().with_config(&cfg).unwrap().default_store("memory".into()).build().unwrap();
+        let global = ctxman.new_global_context();
+        assert!(global.get_one(None, "foo", &[]).await.is_none());
+        assert!(ContextManagerBuilder::new().default_store("nothing".into()).build().is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
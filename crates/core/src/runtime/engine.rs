@@ -1,7 +1,10 @@
+use std::collections::BTreeMap;
 use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Weak};
 
 use dashmap::DashMap;
+use itertools::Itertools;
 use runtime::flow::*;
 use runtime::registry::RegistryHandle;
 use serde::Deserialize;
@@ -12,19 +15,188 @@ use super::env::*;
 use super::model::json::{RedFlowConfig, RedGlobalNodeConfig};
 use super::model::*;
 use super::nodes::FlowNodeBehavior;
+use super::validation::{validate_flows_json, Diagnostic};
 use crate::runtime::model::Variant;
+use crate::runtime::nodes::common_nodes::unknown::{UnknownFlowNode, UnknownGlobalNode};
 use crate::runtime::nodes::{GlobalNodeBehavior, NodeFactory};
+use crate::utils::time::{Clock, SystemClock};
 use crate::*;
 
+/// Deep enough for the sane flows, a recursive link call chain fails long before exhausting the memory.
+pub const DEFAULT_MAX_LINK_CALL_DEPTH: usize = 100;
+
+pub const DEFAULT_CLONE_WARNING_SIZE: usize = 1024 * 1024;
+
+pub const DEFAULT_CLONE_WARNING_COUNT: u64 = 100;
+
+pub const DEFAULT_CLONE_WARNING_WINDOW_SECS: u64 = 60;
+
+pub const DEFAULT_NODE_MESSAGE_BUFFER_MAX_LENGTH: usize = 10000;
+
+/// The globals of the sandbox of the `function` nodes, which the constants of `function_global_context` cannot shadow.
+const RESERVED_FUNCTION_GLOBALS: &[&str] = &[
+    "node",
+    "env",
+    "RED",
+    "context",
+    "flow",
+    "global",
+    "msg",
+    "util",
+    "Buffer",
+    "console",
+    "process",
+    "require",
+    "module",
+    "exports",
+    "setTimeout",
+    "clearTimeout",
+    "setInterval",
+    "clearInterval",
+    "globalThis",
+];
+
+/// A node warns about the clone storm when it has deep-cloned `count` messages of `size` bytes or more within
+/// `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloneWarningThreshold {
+    pub size: usize,
+    pub count: u64,
+    pub window: std::time::Duration,
+}
+
+impl Default for CloneWarningThreshold {
+    fn default() -> Self {
+        CloneWarningThreshold {
+            size: DEFAULT_CLONE_WARNING_SIZE,
+            count: DEFAULT_CLONE_WARNING_COUNT,
+            window: std::time::Duration::from_secs(DEFAULT_CLONE_WARNING_WINDOW_SECS),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct EngineArgs {
     //node_msg_queue_capacity: usize,
+    /// The maximum approximate size in bytes of an injected message, `None` means unlimited.
+    #[serde(default)]
+    pub max_msg_size: Option<usize>,
+
+    /// The maximum number of the nested link calls of a message, defaults to `DEFAULT_MAX_LINK_CALL_DEPTH`.
+    #[serde(default)]
+    pub max_link_call_depth: Option<usize>,
+
+    /// Counts the messages deep-cloned by every node, see `CloneStats`; disabled by default, since it measures every
+    /// cloned message.
+    #[serde(default)]
+    pub clone_stats: bool,
+
+    /// The approximate size in bytes from which a message deep-cloned in the fan-out counts as large, defaults to
+    /// `DEFAULT_CLONE_WARNING_SIZE`.
+    #[serde(default)]
+    pub clone_warning_size: Option<usize>,
+
+    /// A node warns about the clone storm when it has deep-cloned this many large messages within
+    /// `clone_warning_window_secs`, defaults to `DEFAULT_CLONE_WARNING_COUNT`.
+    #[serde(default)]
+    pub clone_warning_count: Option<u64>,
+
+    /// The time window in seconds of `clone_warning_count`, defaults to `DEFAULT_CLONE_WARNING_WINDOW_SECS`.
+    #[serde(default)]
+    pub clone_warning_window_secs: Option<u64>,
+
+    /// The maximum number of messages a node holds while waiting for the rest of their sequences, like
+    /// `nodeMessageBufferMaxLength` of Node-RED; `0` means unlimited, defaults to
+    /// `DEFAULT_NODE_MESSAGE_BUFFER_MAX_LENGTH`.
+    #[serde(default)]
+    pub node_message_buffer_max_length: Option<usize>,
+
+    /// The constants exposed as read-only globals to every `function` node, like `functionGlobalContext` of
+    /// Node-RED. They are also seeded into the default store of the global context when the engine starts, so
+    /// `global.get()` reads them too. The names of the sandbox like `node` or `msg` are rejected.
+    #[serde(default)]
+    pub function_global_context: VariantObjectMap,
+
+    /// The JavaScript module files the `function` nodes are allowed to load by their `libs`, keyed by the module
+    /// name. A module name not listed here cannot be loaded.
+    #[serde(default)]
+    pub function_modules: BTreeMap<String, std::path::PathBuf>,
+
+    /// Exposes a read-only `process.env` backed by the env store of the node to the `function` nodes, which have
+    /// no `process` otherwise.
+    #[serde(default)]
+    pub function_process_env: bool,
+
+    /// Generates the message IDs from this seed to make the outputs reproducible, for the snapshot tests only.
+    #[serde(default)]
+    pub msg_id_seed: Option<u64>,
+
+    /// Logs every nav-property access of the messages in the nodes of this engine, see `msg::trace`.
+    #[serde(default)]
+    pub trace_msg_properties: bool,
+
+    /// The msg property paths masked in the `debug` node and the property trace, see `msg::redact`.
+    #[serde(default)]
+    pub redact_msg_properties: Vec<String>,
+
+    /// Shares the buffers of the repeated object keys of the messages, see `VariantKey::set_interning()`. It is
+    /// process-wide, once enabled by an engine it stays enabled.
+    #[serde(default)]
+    pub intern_msg_keys: bool,
 }
 
 impl EngineArgs {
     pub fn load(cfg: Option<&config::Config>) -> crate::Result<Self> {
         match cfg {
             Some(cfg) => match cfg.get::<Self>("runtime.engine") {
+                Ok(res) => {
+                    res.validate()?;
+                    Ok(res)
+                }
+                Err(config::ConfigError::NotFound(_)) => Ok(Self::default()),
+                Err(e) => Err(e.into()),
+            },
+            _ => Ok(Self::default()),
+        }
+    }
+
+    fn validate(&self) -> crate::Result<()> {
+        for key in self.function_global_context.keys() {
+            if RESERVED_FUNCTION_GLOBALS.contains(&key.as_str()) || key.starts_with("__edgelink") {
+                return Err(EdgelinkError::BadArgument("function_global_context")).with_context(|| {
+                    format!("The `runtime.engine.function_global_context` cannot shadow the reserved global '{}'", key)
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The tuning of the tokio runtime running the engine, the `[runtime.tokio]` section of the configuration.
+///
+/// All knobs are optional and fall back to the defaults of tokio:
+///
+/// * `worker_threads`       - the number of the worker threads, defaults to the number of CPU cores
+/// * `max_blocking_threads` - the upper limit of the blocking pool, e.g. file I/O and the debug file sink, defaults
+///   to 512
+/// * `thread_stack_size`    - the stack size in bytes of every thread, defaults to 2MiB; the heavy JS flows may
+///   need more
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct RuntimeArgs {
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+
+    #[serde(default)]
+    pub max_blocking_threads: Option<usize>,
+
+    #[serde(default)]
+    pub thread_stack_size: Option<usize>,
+}
+
+impl RuntimeArgs {
+    pub fn load(cfg: Option<&config::Config>) -> crate::Result<Self> {
+        match cfg {
+            Some(cfg) => match cfg.get::<Self>("runtime.tokio") {
                 Ok(res) => Ok(res),
                 Err(config::ConfigError::NotFound(_)) => Ok(Self::default()),
                 Err(e) => Err(e.into()),
@@ -32,6 +204,79 @@ impl EngineArgs {
             _ => Ok(Self::default()),
         }
     }
+
+    /// Builds a multi-threaded tokio runtime with all drivers enabled.
+    pub fn build_runtime(&self) -> crate::Result<tokio::runtime::Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all().thread_name("edgelink-worker");
+        if let Some(n) = self.worker_threads {
+            if n == 0 {
+                return Err(EdgelinkError::BadArgument("worker_threads"))
+                    .with_context(|| "The `runtime.tokio.worker_threads` must be greater than 0");
+            }
+            builder.worker_threads(n);
+        }
+        if let Some(n) = self.max_blocking_threads {
+            if n == 0 {
+                return Err(EdgelinkError::BadArgument("max_blocking_threads"))
+                    .with_context(|| "The `runtime.tokio.max_blocking_threads` must be greater than 0");
+            }
+            builder.max_blocking_threads(n);
+        }
+        if let Some(n) = self.thread_stack_size {
+            builder.thread_stack_size(n);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// The outcome of injecting a message into a flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowInjectResult {
+    /// The message has been delivered to the flow.
+    Injected,
+
+    /// The flow is disabled, the message has been dropped.
+    FlowDisabled,
+}
+
+/// A callback receiving a copy of every message sent by a flow node, along with the output port.
+pub type OutputCallback = Arc<dyn Fn(usize, Msg) + Send + Sync>;
+
+/// A callback receiving the errors not handled by any `catch` node: the ID of the node raising it, the error message,
+/// and the message being processed if any.
+pub type ErrorCallback = Arc<dyn Fn(ElementId, &str, Option<MsgHandle>) + Send + Sync>;
+
+/// The counts of the flows and nodes loaded by the engine, see `Engine::summary()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct EngineSummary {
+    pub flows: usize,
+    pub subflows: usize,
+    pub disabled_flows: usize,
+    pub flow_nodes: usize,
+    pub global_nodes: usize,
+    /// The nodes disabled by themselves or by their enclosing groups.
+    pub disabled_nodes: usize,
+    pub nodes_by_type: BTreeMap<String, usize>,
+    /// The node types missing in the registry, with the IDs of the nodes of each type.
+    pub unknown_types: BTreeMap<String, Vec<ElementId>>,
+}
+
+impl std::fmt::Display for EngineSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} flow(s), {} subflow(s), {} disabled flow(s), {} flow node(s), {} global node(s), {} disabled node(s)",
+            self.flows, self.subflows, self.disabled_flows, self.flow_nodes, self.global_nodes, self.disabled_nodes
+        )?;
+        for (type_name, count) in self.nodes_by_type.iter() {
+            write!(f, "\n  {}: {}", type_name, count)?;
+        }
+        for (type_name, ids) in self.unknown_types.iter() {
+            write!(f, "\n  UNKNOWN TYPE '{}': {}", type_name, ids.iter().join(", "))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -50,10 +295,14 @@ impl WeakEngine {
     }
 }
 
+/// The final messages not received yet, the `test-once` nodes wait while it is full rather than buffering forever.
+#[cfg(any(test, feature = "pymod"))]
+const FINAL_MSGS_CAPACITY: usize = 1024;
+
 struct InnerEngine {
     shutdown: tokio::sync::RwLock<bool>,
     stop_token: CancellationToken,
-    _args: EngineArgs,
+    args: EngineArgs,
     envs: Envs,
     context_manager: Arc<ContextManager>,
     context: Arc<Context>,
@@ -62,12 +311,51 @@ struct InnerEngine {
     flows: DashMap<ElementId, Flow>,
     global_nodes: DashMap<ElementId, Arc<dyn GlobalNodeBehavior>>,
     all_flow_nodes: DashMap<ElementId, Arc<dyn FlowNodeBehavior>>,
+    output_callbacks: DashMap<ElementId, Vec<OutputCallback>>,
+    received_taps: DashMap<ElementId, Vec<(u64, tokio::sync::mpsc::UnboundedSender<MsgHandle>)>>,
+    received_tap_seed: AtomicU64,
+    error_callbacks: std::sync::RwLock<Vec<ErrorCallback>>,
+    sink_tx: std::sync::Mutex<Option<tokio::sync::mpsc::UnboundedSender<(ElementId, Msg)>>>,
+    clock: std::sync::RwLock<Arc<dyn Clock>>,
+    msg_id_seed: std::sync::RwLock<Option<u64>>,
+    redactor: Arc<redact::Redactor>,
+    msg_tracer: Option<Arc<trace::MsgTracer>>,
+    global_catch_nodes: std::sync::RwLock<Vec<Arc<dyn FlowNodeBehavior>>>,
+    global_status_nodes: std::sync::RwLock<Vec<Arc<dyn FlowNodeBehavior>>>,
+    /// The `complete` nodes watching the nodes of other flows, by the IDs of the watched nodes.
+    global_complete_nodes: DashMap<ElementId, Vec<Arc<dyn FlowNodeBehavior>>>,
 
     #[cfg(any(test, feature = "pymod"))]
-    final_msgs_rx: MsgUnboundedReceiverHolder,
+    final_msgs_rx: MsgReceiverHolder,
 
     #[cfg(any(test, feature = "pymod"))]
-    final_msgs_tx: MsgUnboundedSender,
+    final_msgs_tx: MsgSender,
+}
+
+/// Removes a tap of `Engine::inject_and_collect_at()` when the collection ends.
+struct ReceivedTap<'a> {
+    engine: &'a Engine,
+    sink_id: ElementId,
+    tap_id: u64,
+}
+
+impl Drop for ReceivedTap<'_> {
+    fn drop(&mut self) {
+        let taps = &self.engine.inner.received_taps;
+        // The entry must be released before `remove_if()` locks the same shard again
+        if let Some(mut entry) = taps.get_mut(&self.sink_id) {
+            entry.retain(|(id, _)| *id != self.tap_id);
+        }
+        // The flag is cleared while the entry is locked, so a tap registered meanwhile cannot be missed
+        taps.remove_if(&self.sink_id, |_, x| {
+            if x.is_empty() {
+                if let Some(node) = self.engine.inner.all_flow_nodes.get(&self.sink_id) {
+                    node.get_node().received_tapped.store(false, Ordering::Relaxed);
+                }
+            }
+            x.is_empty()
+        });
+    }
 }
 
 impl Engine {
@@ -99,23 +387,46 @@ impl Engine {
         let context = context_manager.new_global_context();
 
         #[cfg(any(test, feature = "pymod"))]
-        let final_msgs_channel = tokio::sync::mpsc::unbounded_channel();
+        let final_msgs_channel = tokio::sync::mpsc::channel(FINAL_MSGS_CAPACITY);
 
+        let args = EngineArgs::load(elcfg)?;
+        if args.intern_msg_keys {
+            VariantKey::set_interning(true);
+        }
+        let redactor = Arc::new(redact::Redactor::new(&args.redact_msg_properties)?);
+        let msg_tracer = if args.trace_msg_properties {
+            log::warn!("Tracing the property accesses of the messages, it slows the flows down");
+            Some(Arc::new(trace::MsgTracer::new(redactor.clone())))
+        } else {
+            None
+        };
         let engine = Self {
             inner: Arc::new(InnerEngine {
                 shutdown: tokio::sync::RwLock::new(true),
                 stop_token: CancellationToken::new(),
                 all_flow_nodes: DashMap::new(),
+                output_callbacks: DashMap::new(),
+                received_taps: DashMap::new(),
+                received_tap_seed: AtomicU64::new(1),
+                error_callbacks: std::sync::RwLock::new(Vec::new()),
+                sink_tx: std::sync::Mutex::new(None),
+                clock: std::sync::RwLock::new(Arc::new(SystemClock::new())),
+                msg_id_seed: std::sync::RwLock::new(args.msg_id_seed),
+                redactor,
+                msg_tracer,
+                global_catch_nodes: std::sync::RwLock::new(Vec::new()),
+                global_status_nodes: std::sync::RwLock::new(Vec::new()),
+                global_complete_nodes: DashMap::new(),
                 global_nodes: DashMap::new(),
                 flows: DashMap::new(),
                 _context: Variant::empty_object(),
                 envs,
-                _args: EngineArgs::load(elcfg)?,
+                args,
                 context_manager,
                 context,
 
                 #[cfg(any(test, feature = "pymod"))]
-                final_msgs_rx: MsgUnboundedReceiverHolder::new(final_msgs_channel.1),
+                final_msgs_rx: MsgReceiverHolder::new(final_msgs_channel.1),
 
                 #[cfg(any(test, feature = "pymod"))]
                 final_msgs_tx: final_msgs_channel.0,
@@ -134,10 +445,32 @@ impl Engine {
         flows_json_path: &str,
         elcfg: Option<&config::Config>,
     ) -> crate::Result<Engine> {
-        let mut file = std::fs::File::open(flows_json_path)?;
-        let mut json_str = String::new();
-        file.read_to_string(&mut json_str)?;
-        Self::with_json_string(reg, json_str, elcfg)
+        let file = std::fs::File::open(flows_json_path)?;
+        Self::with_flows_reader(reg, file, elcfg)
+    }
+
+    /// Loads the flows JSON from a reader, e.g. the stdin, see `read_flows_json()` for the size limit.
+    pub fn with_flows_reader(
+        reg: &RegistryHandle,
+        reader: impl Read,
+        elcfg: Option<&config::Config>,
+    ) -> crate::Result<Engine> {
+        let json_bytes = read_flows_json(reader)?;
+        let json: serde_json::Value = serde_json::from_slice(&json_bytes)?;
+        Self::with_json(reg, json, elcfg)
+    }
+
+    /// Downloads the flows JSON from an `http://` or `https://` URL.
+    #[cfg(feature = "net")]
+    pub async fn with_flows_url(
+        reg: &RegistryHandle,
+        url: &str,
+        elcfg: Option<&config::Config>,
+    ) -> crate::Result<Engine> {
+        let json_bytes = fetch_limited(url, MAX_FLOWS_JSON_SIZE).await?;
+        let json: serde_json::Value = serde_json::from_slice(&json_bytes)
+            .with_context(|| format!("Failed to parse the flows JSON from '{}'", url))?;
+        Self::with_json(reg, json, elcfg)
     }
 
     pub fn with_json_string(
@@ -149,16 +482,79 @@ impl Engine {
         Self::with_json(reg, json, elcfg)
     }
 
+    /// Checks the flows JSON without loading it, e.g. for CI, see `validation::validate_flows_json()`.
+    pub fn validate(reg: &RegistryHandle, json: &serde_json::Value) -> Vec<Diagnostic> {
+        validate_flows_json(reg.as_ref(), json)
+    }
+
+    /// Summarizes the loaded flows and nodes, it only counts the loaded elements and never blocks.
+    pub fn summary(&self) -> EngineSummary {
+        let mut summary = EngineSummary::default();
+        for flow in self.inner.flows.iter() {
+            if flow.is_subflow() {
+                summary.subflows += 1;
+            } else {
+                summary.flows += 1;
+            }
+            if flow.is_disabled() {
+                summary.disabled_flows += 1;
+            }
+        }
+
+        for node in self.inner.all_flow_nodes.iter() {
+            summary.flow_nodes += 1;
+            if node.is_effectively_disabled() {
+                summary.disabled_nodes += 1;
+            }
+            match node.as_any().downcast_ref::<UnknownFlowNode>() {
+                Some(unknown) => summary.unknown_types.entry(unknown.type_name.clone()).or_default().push(node.id()),
+                None => *summary.nodes_by_type.entry(node.type_str().to_string()).or_default() += 1,
+            }
+        }
+
+        for node in self.inner.global_nodes.iter() {
+            summary.global_nodes += 1;
+            if node.is_disabled() {
+                summary.disabled_nodes += 1;
+            }
+            match node.as_any().downcast_ref::<UnknownGlobalNode>() {
+                Some(unknown) => summary.unknown_types.entry(unknown.type_name.clone()).or_default().push(node.id()),
+                None => *summary.nodes_by_type.entry(node.type_str().to_string()).or_default() += 1,
+            }
+        }
+
+        for ids in summary.unknown_types.values_mut() {
+            ids.sort();
+        }
+        summary
+    }
+
     pub fn get_flow(&self, id: &ElementId) -> Option<Flow> {
         self.inner.flows.get(id).map(|x| x.value().clone())
     }
 
+    /// Returns the wires of all flows and subflows as `(source node, port, target node)`, like they are at runtime.
+    ///
+    /// The instance node of a subflow is wired to the nodes connected to the input of the subflow, and the outputs
+    /// of the nodes in the subflow are wired back to the instance node, which forwards the messages to its own wires.
+    pub fn wiring_graph(&self) -> Vec<(ElementId, usize, ElementId)> {
+        self.inner
+            .flows
+            .iter()
+            .map(|x| x.value().clone())
+            .sorted_by_key(|x| x.ordering())
+            .flat_map(|x| x.wiring_graph())
+            .collect()
+    }
+
     fn load_flows(
         &self,
         flow_cfg: Vec<RedFlowConfig>,
         reg: &RegistryHandle,
         elcfg: Option<&config::Config>,
     ) -> crate::Result<()> {
+        self.clear_global_scoped_nodes();
+
         // load flows
         for flow_config in flow_cfg.into_iter() {
             log::debug!("---- Loading flow/subflow: (id='{}', label='{}')...", flow_config.id, flow_config.label);
@@ -214,7 +610,9 @@ impl Engine {
                 }
             };
 
-            self.inner.global_nodes.insert(global_node.id(), Arc::from(global_node));
+            let global_node: Arc<dyn GlobalNodeBehavior> = Arc::from(global_node);
+            global_node.on_loaded().with_context(|| format!("Failed to load the global node {}", global_node))?;
+            self.inner.global_nodes.insert(global_node.id(), global_node);
         }
         Ok(())
     }
@@ -224,11 +622,16 @@ impl Engine {
         flow_id: ElementId,
         msg: MsgHandle,
         cancel: CancellationToken,
-    ) -> crate::Result<()> {
+    ) -> crate::Result<FlowInjectResult> {
         let flow = self.inner.flows.get(&flow_id).as_deref().cloned();
         if let Some(flow) = flow {
+            if flow.is_disabled() {
+                log::debug!("The flow(id='{}') is disabled, dropping the injected message", flow_id);
+                return Ok(FlowInjectResult::FlowDisabled);
+            }
+            self.check_msg_size(&msg).await?;
             flow.inject_msg(msg, cancel.clone()).await?;
-            Ok(())
+            Ok(FlowInjectResult::Injected)
         } else {
             Err(EdgelinkError::BadArgument("flow_id")).with_context(|| format!("Can not found flow_id: {}", flow_id))
         }
@@ -260,8 +663,27 @@ impl Engine {
         if self.inner.flows.is_empty() {
             return Err(EdgelinkError::invalid_operation("no flows loaded in the engine."));
         }
-        for f in self.inner.flows.iter() {
-            f.value().start().await?;
+        let global_nodes = self.inner.global_nodes.iter().map(|x| x.value().clone()).sorted_by_key(|x| x.ordering());
+        for global_node in global_nodes {
+            global_node
+                .on_starting()
+                .await
+                .with_context(|| format!("Failed to start the global node {}", global_node))?;
+        }
+
+        self.seed_function_global_context().await?;
+
+        let flows: Vec<Flow> = self.inner.flows.iter().map(|x| x.value().clone()).collect();
+        for (i, flow) in flows.iter().enumerate() {
+            if let Err(err) = flow.start().await {
+                // Stop the flows already started, including the failed one
+                for started in flows[..=i].iter() {
+                    if let Err(stop_err) = started.stop().await {
+                        log::warn!("Failed to stop the flow (id='{}'): {}", started.id(), stop_err);
+                    }
+                }
+                return Err(err);
+            }
         }
 
         *shutdown_lock = false;
@@ -298,37 +720,39 @@ impl Engine {
     ) -> crate::Result<Vec<Msg>> {
         self.start().await?;
 
-        let mut count = 0;
-        let mut received = Vec::new();
-
         // Clear the final_msgs channel
         {
             let mut rx = self.inner.final_msgs_rx.rx.lock().await;
             while rx.try_recv().is_ok() {}
         }
 
+        // The final messages are drained while injecting, so the injects cannot block on a full channel, and both
+        // are bounded by the timeout
         let cancel = CancellationToken::new();
-        for msg in msgs_to_inject.drain(..) {
-            self.inject_msg(&msg.0, MsgHandle::new(msg.1), cancel.clone()).await?;
-        }
-
         let result = tokio::time::timeout(timeout, async {
-            while !cancel.is_cancelled() && count < expected_msgs {
-                let msg = self.inner.final_msgs_rx.recv_msg(cancel.clone()).await?;
-                count += 1;
-                let msg = msg.unwrap().await;
-                received.push(msg);
-            }
-            cancel.cancel();
-            cancel.cancelled().await;
-            Ok(())
+            let inject = async {
+                for (id, msg) in msgs_to_inject.drain(..) {
+                    self.inject_msg(&id, MsgHandle::new(msg), cancel.clone()).await?;
+                }
+                crate::Result::<()>::Ok(())
+            };
+            let receive = async {
+                let mut received = Vec::with_capacity(expected_msgs);
+                while received.len() < expected_msgs {
+                    let msg = self.inner.final_msgs_rx.recv_msg(cancel.clone()).await?;
+                    received.push(msg.unwrap().await);
+                }
+                crate::Result::<Vec<Msg>>::Ok(received)
+            };
+            let ((), received) = tokio::try_join!(inject, receive)?;
+            crate::Result::<Vec<Msg>>::Ok(received)
         })
         .await;
+        cancel.cancel();
 
         self.stop().await?;
         match result {
-            Ok(Ok(())) => Ok(received),
-            Ok(Err(e)) => Err(e),
+            Ok(received) => received,
             Err(_) => Err(EdgelinkError::Timeout.into()),
         }
     }
@@ -342,15 +766,47 @@ impl Engine {
         self.inner.all_flow_nodes.get(id).map(|x| x.value().clone())
     }
 
+    pub fn find_global_node_by_id(&self, id: &ElementId) -> Option<Arc<dyn GlobalNodeBehavior>> {
+        self.inner.global_nodes.get(id).map(|x| x.value().clone())
+    }
+
+    /// Finds the flow node by its name in all flows, the name is case-sensitive.
+    ///
+    /// It is an error if more than one node has the name, the error message lists the IDs of them.
     pub fn find_flow_node_by_name(&self, name: &str) -> crate::Result<Option<Arc<dyn FlowNodeBehavior>>> {
-        for i in self.inner.flows.iter() {
-            let flow = i.value();
-            let opt_node = flow.get_node_by_name(name)?;
-            if opt_node.is_some() {
-                return Ok(opt_node.clone());
+        runtime::flow::unique_node_by_name(name, self.inner.all_flow_nodes.iter().map(|x| x.value().clone()))
+    }
+
+    /// Like `find_flow_node_by_name()` but only the nodes of the type `type_str` are considered.
+    pub fn find_flow_node_by_type_and_name(
+        &self,
+        type_str: &str,
+        name: &str,
+    ) -> crate::Result<Option<Arc<dyn FlowNodeBehavior>>> {
+        let nodes = self.inner.all_flow_nodes.iter().map(|x| x.value().clone()).filter(|x| x.type_str() == type_str);
+        runtime::flow::unique_node_by_name(name, nodes)
+    }
+
+    /// Injects the message into the flow node named `name`, see `Engine::find_flow_node_by_name()`.
+    pub async fn inject_msg_by_name(&self, name: &str, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let node = self
+            .find_flow_node_by_name(name)?
+            .ok_or(EdgelinkError::BadArgument("name"))
+            .with_context(|| format!("Cannot found the flow node, name='{}'", name))?;
+        self.inject_msg(&node.id(), msg, cancel).await
+    }
+
+    /// Fires the `inject` node `node_id` immediately, like its button in the editor, whatever its schedule is.
+    ///
+    /// The message is made from the properties of the node at the time, the same as the scheduled ones.
+    pub async fn trigger_inject(&self, node_id: &ElementId, cancel: CancellationToken) -> crate::Result<()> {
+        match self.find_flow_node_by_id(node_id) {
+            Some(node) if node.type_str() == "inject" => {
+                self.inject_msg(node_id, MsgHandle::new(Msg::default()), cancel).await
             }
+            _ => Err(EdgelinkError::BadArgument("node_id"))
+                .with_context(|| format!("Cannot found the inject node, id='{}'", node_id)),
         }
-        Ok(None)
     }
 
     pub async fn inject_msg(
@@ -363,9 +819,279 @@ impl Engine {
             .find_flow_node_by_id(flow_node_id)
             .ok_or(EdgelinkError::BadArgument("flow_node_id"))
             .with_context(|| format!("Cannot found the flow node, id='{}'", flow_node_id))?;
+        if node.flow().is_some_and(|x| x.is_disabled()) || node.is_effectively_disabled() {
+            log::debug!("The flow node(id='{}') is disabled, dropping the injected message", flow_node_id);
+            return Ok(());
+        }
+        self.check_msg_size(&msg).await?;
         node.inject_msg(msg, cancel).await
     }
 
+    /// Registers a callback receiving a copy of every message sent by the flow node `node_id`.
+    ///
+    /// This is the way to get the results out of an engine embedded in another application. The callback is invoked
+    /// in the task of the sending node before the message was delivered to the wires, so it should return quickly;
+    /// use `Engine::tap_output()` to process the messages elsewhere.
+    pub fn on_output<F>(&self, node_id: &ElementId, callback: F) -> crate::Result<()>
+    where
+        F: Fn(usize, Msg) + Send + Sync + 'static,
+    {
+        if !self.inner.all_flow_nodes.contains_key(node_id) {
+            return Err(EdgelinkError::BadArgument("node_id"))
+                .with_context(|| format!("Cannot found the flow node, id='{}'", node_id));
+        }
+        let callback: OutputCallback = Arc::new(callback);
+        self.inner.output_callbacks.entry(*node_id).or_default().push(callback.clone());
+
+        // A late subscriber gets the retained message immediately
+        if let Some(retained) = self.find_flow_node_by_id(node_id).and_then(|x| x.retained_msg()) {
+            callback(0, retained);
+        }
+        Ok(())
+    }
+
+    /// Returns a channel receiving `(port, msg)` for every message sent by the flow node `node_id`.
+    pub fn tap_output(&self, node_id: &ElementId) -> crate::Result<tokio::sync::mpsc::UnboundedReceiver<(usize, Msg)>> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.on_output(node_id, move |port, msg| {
+            // The receiver has gone, nothing to do
+            let _ = tx.send((port, msg));
+        })?;
+        Ok(rx)
+    }
+
+    /// Injects the message into the flow node `node_id`, then collects a copy of every message received by the flow
+    /// node `sink_id` until `timeout` elapses.
+    ///
+    /// The engine must be running. Unlike `run_once_with_inject()`, the sink can be any flow node and the engine is
+    /// neither started nor stopped here; the messages received by the sink from other sources in the meantime are
+    /// collected too. The collection always lasts for `timeout`, and it stops even if the returned future is dropped.
+    pub async fn inject_and_collect_at(
+        &self,
+        node_id: &ElementId,
+        sink_id: &ElementId,
+        msg: Msg,
+        timeout: std::time::Duration,
+    ) -> crate::Result<Vec<Msg>> {
+        if *self.inner.shutdown.read().await {
+            return Err(EdgelinkError::invalid_operation("not started."));
+        }
+        if !self.inner.all_flow_nodes.contains_key(sink_id) {
+            return Err(EdgelinkError::BadArgument("sink_id"))
+                .with_context(|| format!("Cannot found the flow node, id='{}'", sink_id));
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let tap_id = self.inner.received_tap_seed.fetch_add(1, Ordering::Relaxed);
+        {
+            // Like the removal, the flag is set while the entry is locked
+            let mut entry = self.inner.received_taps.entry(*sink_id).or_default();
+            entry.push((tap_id, tx));
+            if let Some(node) = self.inner.all_flow_nodes.get(sink_id) {
+                node.get_node().received_tapped.store(true, Ordering::Relaxed);
+            }
+        }
+        let _tap = ReceivedTap { engine: self, sink_id: *sink_id, tap_id };
+
+        let cancel = self.inner.stop_token.child_token();
+        self.inject_msg(node_id, MsgHandle::new(msg), cancel.clone()).await?;
+
+        let mut collected = Vec::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Some(msg) => collected.push(msg.read().await.clone()),
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(deadline) => break,
+                _ = cancel.cancelled() => break,
+            }
+        }
+        Ok(collected)
+    }
+
+    /// Enables or disables the group `group_id` at runtime, including all its subgroups.
+    ///
+    /// The member nodes whose effective state has changed are stopped or started again if the engine is running; a
+    /// disabled node drops the messages sent to it. The nodes are not rebuilt, so their state and context are restored
+    /// when the group gets re-enabled.
+    pub async fn set_group_enabled(&self, group_id: &ElementId, enabled: bool) -> crate::Result<()> {
+        let (flow, group) = self
+            .inner
+            .flows
+            .iter()
+            .find_map(|x| x.value().get_group(group_id).map(|g| (x.value().clone(), g)))
+            .ok_or(EdgelinkError::BadArgument("group_id"))
+            .with_context(|| format!("Cannot found the group, id='{}'", group_id))?;
+
+        let members: Vec<(Arc<dyn FlowNodeBehavior>, bool)> = flow
+            .get_all_flow_nodes()
+            .into_iter()
+            .filter(|node| node.group().is_some_and(|g| g.is_within(group_id)))
+            .map(|node| {
+                let was_disabled = node.is_effectively_disabled();
+                (node, was_disabled)
+            })
+            .collect();
+
+        group.set_disabled(!enabled);
+        if *self.inner.shutdown.read().await {
+            return Ok(());
+        }
+        for (node, was_disabled) in members.iter() {
+            if node.is_effectively_disabled() != *was_disabled {
+                flow.restart_node(node).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn has_received_taps(&self) -> bool {
+        !self.inner.received_taps.is_empty()
+    }
+
+    /// Never waits for the message, a snapshot is taken if it can be read right now, otherwise the collector reads the
+    /// shared message later.
+    pub(crate) fn notify_received(&self, node_id: &ElementId, msg: &MsgHandle) {
+        if let Some(taps) = self.inner.received_taps.get(node_id) {
+            let snapshot = msg.try_read().map(|x| MsgHandle::new(x.clone())).unwrap_or_else(|_| msg.clone());
+            for (_, tx) in taps.iter() {
+                // The collector has gone, the tap will be removed soon
+                let _ = tx.send(snapshot.clone());
+            }
+        }
+    }
+
+    /// Registers a callback receiving the errors that no `catch` node handled, a dead-letter sink to centralize the
+    /// error handling of an embedded engine.
+    ///
+    /// The errors handled by the `catch` nodes never reach it, including the ones caught by the `uncaught` mode. Like
+    /// `Engine::on_output()`, the callback is invoked in the task of the failing node.
+    pub fn on_uncaught_error<F>(&self, callback: F)
+    where
+        F: Fn(ElementId, &str, Option<MsgHandle>) + Send + Sync + 'static,
+    {
+        self.inner.error_callbacks.write().expect("error_callbacks").push(Arc::new(callback));
+    }
+
+    /// Returns a channel receiving `(node_id, error, msg)` for every error that no `catch` node handled.
+    pub fn dead_letter_receiver(&self) -> tokio::sync::mpsc::UnboundedReceiver<(ElementId, String, Option<MsgHandle>)> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.on_uncaught_error(move |node_id, error, msg| {
+            // The receiver has gone, nothing to do
+            let _ = tx.send((node_id, error.to_string(), msg));
+        });
+        rx
+    }
+
+    /// Returns `false` if there is no callback of the uncaught errors.
+    pub(crate) fn notify_uncaught_error(&self, node_id: &ElementId, error: &str, msg: Option<MsgHandle>) -> bool {
+        let callbacks = self.inner.error_callbacks.read().expect("error_callbacks").clone();
+        for callback in callbacks.iter() {
+            callback(*node_id, error, msg.clone());
+        }
+        !callbacks.is_empty()
+    }
+
+    /// Registers the `catch` or `status` node with the `"global"` scope, which watches the nodes of all flows.
+    pub(crate) fn register_global_scoped_node(&self, node: Arc<dyn FlowNodeBehavior>) {
+        match node.type_str() {
+            "catch" => self.inner.global_catch_nodes.write().expect("global_catch_nodes").push(node),
+            "status" => self.inner.global_status_nodes.write().expect("global_status_nodes").push(node),
+            _ => {}
+        }
+    }
+
+    /// Registers the `complete` node watching the node `src_id` of another flow, which has no way to notify it.
+    pub(crate) fn register_global_complete_node(&self, src_id: ElementId, node: Arc<dyn FlowNodeBehavior>) {
+        let mut complete_nodes = self.inner.global_complete_nodes.entry(src_id).or_default();
+        if !complete_nodes.iter().any(|x| x.id() == node.id()) {
+            complete_nodes.push(node);
+        }
+    }
+
+    /// Releases the `catch` and `status` nodes with the `"global"` scope and the `complete` nodes watching other flows
+    /// before the flows are loaded again.
+    ///
+    /// They are kept after `stop()`, only the flows being loaded register them, and `start()` loads nothing.
+    fn clear_global_scoped_nodes(&self) {
+        self.inner.global_catch_nodes.write().expect("global_catch_nodes").clear();
+        self.inner.global_status_nodes.write().expect("global_status_nodes").clear();
+        self.inner.global_complete_nodes.clear();
+    }
+
+    pub(crate) fn global_catch_nodes(&self) -> Vec<Arc<dyn FlowNodeBehavior>> {
+        self.inner.global_catch_nodes.read().expect("global_catch_nodes").clone()
+    }
+
+    pub(crate) fn global_status_nodes(&self) -> Vec<Arc<dyn FlowNodeBehavior>> {
+        self.inner.global_status_nodes.read().expect("global_status_nodes").clone()
+    }
+
+    /// Returns the `complete` nodes of other flows watching the node `src_id`.
+    pub(crate) fn global_complete_nodes(&self, src_id: &ElementId) -> Vec<Arc<dyn FlowNodeBehavior>> {
+        self.inner.global_complete_nodes.get(src_id).map(|x| x.clone()).unwrap_or_default()
+    }
+
+    /// Returns a channel receiving `(sink_node_id, msg)` for every message arrived at any `sink` node.
+    ///
+    /// The channel is unbounded and there is only one receiver at a time, calling it again replaces the previous one.
+    /// Before the first call the messages of the `sink` nodes are dropped, except the ones retained.
+    pub fn sink_receiver(&self) -> tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut sink_tx = self.inner.sink_tx.lock().expect("sink_tx");
+        let retained_msgs = self
+            .inner
+            .all_flow_nodes
+            .iter()
+            .filter(|x| x.type_str() == "sink")
+            .filter_map(|x| x.retained_msg().map(|msg| (x.id(), msg)))
+            .sorted_by_key(|(id, _)| *id);
+        for retained in retained_msgs {
+            let _ = tx.send(retained);
+        }
+        *sink_tx = Some(tx);
+        rx
+    }
+
+    /// Returns `false` if the message has been dropped because there is no receiver of the sink.
+    pub(crate) fn send_to_sink(&self, node_id: &ElementId, msg: Msg) -> bool {
+        let sink_tx = self.inner.sink_tx.lock().expect("sink_tx");
+        match sink_tx.as_ref() {
+            Some(tx) => tx.send((*node_id, msg)).is_ok(),
+            None => false,
+        }
+    }
+
+    pub(crate) fn has_output_callbacks(&self) -> bool {
+        !self.inner.output_callbacks.is_empty()
+    }
+
+    pub(crate) fn notify_output(&self, node_id: &ElementId, port: usize, msg: &Msg) {
+        // Never hold the map while calling back, a callback may register another one
+        let callbacks = match self.inner.output_callbacks.get(node_id) {
+            Some(callbacks) => callbacks.clone(),
+            None => return,
+        };
+        for callback in callbacks.iter() {
+            callback(port, msg.clone());
+        }
+    }
+
+    async fn check_msg_size(&self, msg: &MsgHandle) -> crate::Result<()> {
+        if let Some(max_msg_size) = self.inner.args.max_msg_size {
+            let size = msg.read().await.approx_size();
+            if size > max_msg_size {
+                return Err(EdgelinkError::OutOfRange).with_context(|| {
+                    format!("The message is too large: approx. {} bytes, the limit is {} bytes", size, max_msg_size)
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_envs(&self) -> Envs {
         self.inner.envs.clone()
     }
@@ -382,11 +1108,110 @@ impl Engine {
         self.inner.context.clone()
     }
 
+    /// Writes the constants of `function_global_context` into the default store of the global context.
+    async fn seed_function_global_context(&self) -> crate::Result<()> {
+        for (key, value) in self.inner.args.function_global_context.iter() {
+            self.inner
+                .context
+                .set_one(None, key, Some(value.clone()), &[])
+                .await
+                .with_context(|| format!("Failed to seed the global context with '{}'", key))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the constants configured in `runtime.engine.function_global_context`.
+    pub fn function_global_context(&self) -> &VariantObjectMap {
+        &self.inner.args.function_global_context
+    }
+
+    /// Returns the maximum number of the nested link calls of a message, see `EngineArgs::max_link_call_depth`.
+    pub fn max_link_call_depth(&self) -> usize {
+        self.inner.args.max_link_call_depth.unwrap_or(DEFAULT_MAX_LINK_CALL_DEPTH)
+    }
+
+    /// Returns the maximum number of messages held by a node, `None` if unlimited, see
+    /// `EngineArgs::node_message_buffer_max_length`.
+    pub fn node_message_buffer_max_length(&self) -> Option<usize> {
+        match self.inner.args.node_message_buffer_max_length.unwrap_or(DEFAULT_NODE_MESSAGE_BUFFER_MAX_LENGTH) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    /// Returns `true` if the nodes count their deep clones, see `CloneStats`.
+    pub fn clone_stats_enabled(&self) -> bool {
+        self.inner.args.clone_stats
+    }
+
+    /// Returns the threshold of the clone storm warning, see `CloneStats`.
+    pub fn clone_warning_threshold(&self) -> CloneWarningThreshold {
+        let args = &self.inner.args;
+        CloneWarningThreshold {
+            size: args.clone_warning_size.unwrap_or(DEFAULT_CLONE_WARNING_SIZE),
+            count: args.clone_warning_count.unwrap_or(DEFAULT_CLONE_WARNING_COUNT).max(1),
+            window: std::time::Duration::from_secs(
+                args.clone_warning_window_secs.unwrap_or(DEFAULT_CLONE_WARNING_WINDOW_SECS),
+            ),
+        }
+    }
+
+    /// Returns whether the `function` nodes have the read-only `process.env`.
+    pub fn function_process_env(&self) -> bool {
+        self.inner.args.function_process_env
+    }
+
+    /// Returns the module files configured in `runtime.engine.function_modules`.
+    pub fn function_modules(&self) -> &BTreeMap<String, std::path::PathBuf> {
+        &self.inner.args.function_modules
+    }
+
+    /// Returns the seed of the message IDs, `None` means the IDs are unpredictable.
+    pub fn msg_id_seed(&self) -> Option<u64> {
+        *self.inner.msg_id_seed.read().expect("msg_id_seed")
+    }
+
+    /// Makes the message IDs generated by the nodes reproducible, it takes effect on the next start.
+    ///
+    /// Every node gets its own sequence derived from the seed and its ID, so the IDs do not depend on the scheduling
+    /// of the nodes.
+    pub fn set_msg_id_seed(&self, seed: Option<u64>) {
+        *self.inner.msg_id_seed.write().expect("msg_id_seed") = seed;
+    }
+
+    /// Returns the masked msg properties, see `EngineArgs::redact_msg_properties`.
+    pub fn redactor(&self) -> Arc<redact::Redactor> {
+        self.inner.redactor.clone()
+    }
+
+    /// Returns the tracer of the property accesses if enabled by `EngineArgs::trace_msg_properties`, its sink can
+    /// collect the traced accesses.
+    pub fn msg_tracer(&self) -> Option<Arc<trace::MsgTracer>> {
+        self.inner.msg_tracer.clone()
+    }
+
+    /// Returns the clock driving the timers of the nodes.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.inner.clock.read().expect("clock").clone()
+    }
+
+    /// Replaces the clock of the timers, e.g. with a `MockClock` in tests. It should be called before `start()`.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.inner.clock.write().expect("clock") = clock;
+    }
+
     #[cfg(any(test, feature = "pymod"))]
-    pub fn recv_final_msg(&self, msg: MsgHandle) -> crate::Result<()> {
-        self.inner.final_msgs_tx.send(msg)?;
+    pub async fn recv_final_msg(&self, msg: MsgHandle) -> crate::Result<()> {
+        self.inner.final_msgs_tx.send(msg).await?;
         Ok(())
     }
+
+    /// Waits for the next message arrived at any `test-once` node, returns `None` once the engine has stopped.
+    #[cfg(any(test, feature = "pymod"))]
+    pub async fn next_final_msg(&self) -> Option<Msg> {
+        let msg = self.inner.final_msgs_rx.recv_msg(self.inner.stop_token.clone()).await.ok()?;
+        Some(msg.unwrap().await)
+    }
 }
 
 impl std::fmt::Debug for InnerEngine {
@@ -396,6 +1221,57 @@ impl std::fmt::Debug for InnerEngine {
     }
 }
 
+/// The maximum size in bytes of a flows JSON to load.
+pub const MAX_FLOWS_JSON_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Reads the whole flows JSON, returns an error if it exceeds `MAX_FLOWS_JSON_SIZE`.
+pub fn read_flows_json(reader: impl Read) -> crate::Result<Vec<u8>> {
+    read_limited(reader, MAX_FLOWS_JSON_SIZE)
+}
+
+fn read_limited(reader: impl Read, limit: u64) -> crate::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > limit {
+        return Err(flows_json_too_large(limit));
+    }
+    Ok(buf)
+}
+
+#[cfg(feature = "net")]
+async fn fetch_limited(url: &str, limit: u64) -> crate::Result<Vec<u8>> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(EdgelinkError::BadArgument("url")).with_context(|| format!("Not an HTTP(S) URL: '{}'", url));
+    }
+    let mut resp = reqwest::get(url).await.with_context(|| format!("Failed to fetch the flows JSON from '{}'", url))?;
+    if !resp.status().is_success() {
+        return Err(EdgelinkError::InvalidOperation(format!(
+            "Failed to fetch the flows JSON from '{}': HTTP status {}",
+            url,
+            resp.status()
+        ))
+        .into());
+    }
+    if resp.content_length().is_some_and(|len| len > limit) {
+        return Err(flows_json_too_large(limit));
+    }
+
+    let mut buf = Vec::new();
+    while let Some(chunk) =
+        resp.chunk().await.with_context(|| format!("Failed to fetch the flows JSON from '{}'", url))?
+    {
+        if (buf.len() + chunk.len()) as u64 > limit {
+            return Err(flows_json_too_large(limit));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+fn flows_json_too_large(limit: u64) -> anyhow::Error {
+    EdgelinkError::BadFlowsJson(format!("The flows JSON exceeds the size limit of {} bytes", limit)).into()
+}
+
 #[cfg(test)]
 pub fn build_test_engine(flows_json: serde_json::Value) -> crate::Result<Engine> {
     let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
@@ -473,6 +1349,22 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_it_should_inject_more_msgs_than_the_final_msgs_channel_holds() {
+        let flows_json = serde_json::json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let count = FINAL_MSGS_CAPACITY * 2;
+        let msgs_to_inject = (0..count)
+            .map(|i| (ElementId::from(1), Msg::deserialize(serde_json::json!({"payload": i})).unwrap()))
+            .collect::<Vec<_>>();
+        let msgs = engine.run_once_with_inject(count, Duration::from_secs(5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), count);
+        assert_eq!(msgs[count - 1]["payload"].as_u64(), Some(count as u64 - 1));
+    }
+
     #[tokio::test]
     async fn test_it_should_load_and_run_simple_json_without_configuration() {
         let flows_json = make_simple_flows_json();
@@ -493,6 +1385,563 @@ mod tests {
         assert_eq!(msg.get("payload").unwrap(), &Variant::from(123 * 2));
     }
 
+    #[tokio::test]
+    async fn test_it_should_skip_disabled_flows_when_injecting() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1", "disabled": true },
+            { "id": "1", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let cancel = CancellationToken::new();
+
+        let flow_id = ElementId::from(0x100);
+        let res = engine.inject_msg_to_flow(flow_id, MsgHandle::new(Msg::default()), cancel.clone()).await.unwrap();
+        assert_eq!(res, FlowInjectResult::FlowDisabled);
+
+        // Not-found is still an error
+        let res = engine.inject_msg_to_flow(ElementId::from(0x999), MsgHandle::new(Msg::default()), cancel).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_skip_nodes_in_disabled_groups() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "200", "type": "group", "z": "100", "disabled": true, "nodes": ["1"] },
+            { "id": "1", "z": "100", "g": "200", "type": "test-once" },
+            { "id": "2", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+            ["2", {"payload": "bar"}],
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from("bar"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_generate_reproducible_msg_ids_with_seed() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "split", "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "test-once" }
+        ]);
+        async fn run(flows_json: &serde_json::Value, seed: Option<u64>) -> Vec<(Variant, Variant)> {
+            let engine = build_test_engine(flows_json.clone()).unwrap();
+            engine.set_msg_id_seed(seed);
+            let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": [1, 2, 3]})).unwrap())];
+            let msgs = engine.run_once_with_inject(3, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+            msgs.iter()
+                .map(|x| (x[wellknown::MSG_ID_PROPERTY].clone(), x.get_nav("parts.id").cloned().unwrap()))
+                .collect()
+        }
+
+        let ids = run(&flows_json, Some(42)).await;
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids, run(&flows_json, Some(42)).await);
+        assert_ne!(ids, run(&flows_json, Some(43)).await);
+        assert_ne!(ids, run(&flows_json, None).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_expose_function_global_context() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "function", "wires": [["2"]], "func": r#"
+                msg.payload = answer + settings.units.length;
+                msg.seeded = global.get('answer');
+                try {
+                    answer = 0;
+                } catch (e) {
+                    msg.reassign = e.name;
+                }
+                msg.unchanged = answer;
+                try {
+                    settings.units.push('mm');
+                } catch (e) {
+                    msg.mutate = e.name;
+                }
+                return msg;
+            "#},
+            { "id": "2", "z": "100", "type": "test-once" }
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.engine.function_global_context]
+                answer = 42
+                settings = { units = ["cm", "m"] }
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        assert_eq!(engine.function_global_context()["answer"].as_i64(), Some(42));
+
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": 0})).unwrap())];
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_f64(), Some(44.0));
+        assert_eq!(msgs[0]["reassign"].as_str(), Some("TypeError"));
+        assert_eq!(msgs[0]["unchanged"].as_f64(), Some(42.0));
+        assert_eq!(msgs[0]["mutate"].as_str(), Some("TypeError"));
+        assert_eq!(msgs[0]["seeded"].as_f64(), Some(42.0));
+    }
+
+    #[test]
+    fn test_it_should_reject_the_reserved_function_global_context() {
+        // The configuration lowercases the keys, so `RED` could not shadow anything
+        for reserved in ["node", "msg", "console", "__edgelink_util"] {
+            let cfg = config::Config::builder()
+                .set_override(format!("runtime.engine.function_global_context.{}", reserved), 1)
+                .unwrap()
+                .build()
+                .unwrap();
+            let err = EngineArgs::load(Some(&cfg)).unwrap_err();
+            assert!(format!("{:#}", err).contains(&format!("reserved global '{}'", reserved)));
+        }
+    }
+
+    #[test]
+    fn test_it_should_build_the_tuned_runtime() {
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.tokio]
+                worker_threads = 3
+                max_blocking_threads = 8
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let args = RuntimeArgs::load(Some(&cfg)).unwrap();
+        assert_eq!(args.max_blocking_threads, Some(8));
+        let runtime = args.build_runtime().unwrap();
+        assert_eq!(runtime.metrics().num_workers(), 3);
+        assert_eq!(runtime.block_on(async { 40 + 2 }), 42);
+
+        assert!(RuntimeArgs::load(None).unwrap().build_runtime().is_ok());
+        assert!(RuntimeArgs { worker_threads: Some(0), ..Default::default() }.build_runtime().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_reject_oversized_msgs() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "test-once" }
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.engine]
+                max_msg_size = 1024
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+
+        let small_msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+        assert!(small_msg.approx_size() < 1024);
+        let big_msg = Msg::deserialize(json!({"payload": "x".repeat(4096)})).unwrap();
+        assert!(big_msg.approx_size() > 4096);
+
+        let cancel = CancellationToken::new();
+        let res = engine.inject_msg(&ElementId::from(1), MsgHandle::new(big_msg), cancel.clone()).await;
+        assert!(res
+            .unwrap_err()
+            .downcast_ref::<EdgelinkError>()
+            .is_some_and(|e| matches!(e, EdgelinkError::OutOfRange)));
+        engine.inject_msg(&ElementId::from(1), MsgHandle::new(small_msg), cancel).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_inject_and_collect_at_the_sink_node() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "step", "pt": "msg", "to": "1", "tot": "num"}]},
+            {"id": "2", "z": "100", "type": "change", "wires": [["3", "4"]], "rules": [
+                {"t": "set", "p": "step", "pt": "msg", "to": "2", "tot": "num"}]},
+            {"id": "3", "z": "100", "type": "junction", "wires": []},
+            {"id": "4", "z": "100", "type": "junction", "wires": [["3"]]}
+        ]);
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, None).unwrap();
+        let msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+
+        let sink: ElementId = "3".parse().unwrap();
+        let res =
+            engine.inject_and_collect_at(&ElementId::from(1), &sink, msg.clone(), Duration::from_millis(100)).await;
+        assert!(res.is_err(), "The engine is not started yet");
+
+        engine.start().await.unwrap();
+        assert!(engine
+            .inject_and_collect_at(
+                &ElementId::from(1),
+                &"999".parse().unwrap(),
+                msg.clone(),
+                Duration::from_millis(100)
+            )
+            .await
+            .is_err());
+
+        let collected = engine
+            .inject_and_collect_at(&ElementId::from(1), &sink, msg.clone(), Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert_eq!(collected.len(), 2);
+        assert!(collected.iter().all(|x| x["payload"].as_str() == Some("foo") && x["step"].as_i64() == Some(2)));
+
+        let collected = engine
+            .inject_and_collect_at(&ElementId::from(1), &"2".parse().unwrap(), msg, Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert_eq!(collected.len(), 1);
+        assert_eq!(collected[0]["step"].as_i64(), Some(1));
+        // The taps are removed after the collection
+        assert!(!engine.has_received_taps());
+        let sink_node = engine.find_flow_node_by_id(&sink).unwrap();
+        assert!(!sink_node.get_node().received_tapped.load(Ordering::Relaxed));
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_it_should_report_the_wiring_graph() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": [["3"], []]},
+            {"id": "3", "z": "100", "type": "junction", "wires": []},
+            {"id": "4", "z": "100", "type": "subflow:200", "wires": [["3"]]},
+            {"id": "200", "type": "subflow", "name": "Subflow", "in": [{"wires": [{"id": "5"}]}],
+                "out": [{"wires": [{"id": "5", "port": 0}]}]},
+            {"id": "5", "z": "200", "type": "junction", "wires": []}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let id = |x: u64| ElementId::from(x);
+        // The elements of the subflow instance have got their own IDs
+        let subflow = engine.inner.flows.iter().map(|x| x.value().clone()).find(|x| x.is_subflow()).unwrap();
+        let child_id = subflow.get_all_flow_nodes()[0].id();
+        assert_eq!(subflow.wiring_graph(), vec![(id(4), 0, child_id), (child_id, 0, id(4))]);
+
+        let mut edges = engine.wiring_graph();
+        edges.sort();
+        let mut expected = vec![
+            (id(1), 0, id(2)),
+            (id(1), 0, id(3)),
+            (id(2), 0, id(3)),
+            (id(4), 0, id(3)),
+            (id(4), 0, child_id),
+            (child_id, 0, id(4)),
+        ];
+        expected.sort();
+        assert_eq!(edges, expected);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_resolve_the_target_nodes_of_the_wires() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": [["1"]]},
+            {"id": "3", "z": "100", "type": "subflow:200", "wires": [["2"]]},
+            {"id": "200", "type": "subflow", "name": "Subflow", "in": [{"wires": [{"id": "4"}]}],
+                "out": [{"wires": [{"id": "4", "port": 0}]}]},
+            {"id": "4", "z": "200", "type": "junction", "wires": []}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let mut wires_count = 0;
+        for node in engine.inner.all_flow_nodes.iter() {
+            for wire in node.get_node().ports.iter().flat_map(|x| x.wires.iter()) {
+                let target = wire.target_node().unwrap();
+                assert_eq!(target.id(), wire.target_node_id);
+                wires_count += 1;
+            }
+        }
+        assert_eq!(wires_count, 5);
+
+        // The wires do not keep the nodes wired in a loop alive
+        let node = Arc::downgrade(&engine.find_flow_node_by_id(&ElementId::from(1)).unwrap());
+        drop(engine);
+        assert!(node.upgrade().is_none());
+    }
+
+    async fn recv_sink_payload(sink_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>) -> Variant {
+        let (_, msg) = tokio::time::timeout(Duration::from_secs(3), sink_rx.recv()).await.unwrap().unwrap();
+        msg["payload"].clone()
+    }
+
+    /// Waits until the messages queued for the node have been taken, the disabled nodes drop them.
+    async fn wait_for_drained(node: &Arc<dyn FlowNodeBehavior>) {
+        let msg_tx = &node.get_node().msg_tx;
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while msg_tx.capacity() != msg_tx.max_capacity() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_enable_and_disable_groups_at_runtime() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "group", "z": "100", "nodes": ["210"]},
+            {"id": "210", "type": "group", "z": "100", "g": "200", "nodes": ["2"]},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "g": "210", "type": "function", "wires": [["3"]],
+                "func": "context.set('count', (context.get('count') || 0) + 1);\nmsg.payload = context.get('count');\n\
+                    return msg;"},
+            {"id": "3", "z": "100", "type": "sink"}
+        ]);
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, None).unwrap();
+        let group_id: ElementId = "200".parse().unwrap();
+        let msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+        assert!(engine.set_group_enabled(&"999".parse().unwrap(), false).await.is_err());
+
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        engine.inject_msg(&ElementId::from(1), MsgHandle::new(msg.clone()), cancel.clone()).await.unwrap();
+        assert_eq!(recv_sink_payload(&mut sink_rx).await, Variant::from(1));
+
+        // The node in the nested subgroup stops processing, the messages wired to it are dropped
+        engine.set_group_enabled(&group_id, false).await.unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::from(2)).unwrap();
+        assert!(node.is_effectively_disabled());
+        for _ in 0..3 {
+            node.inject_msg(MsgHandle::new(msg.clone()), cancel.clone()).await.unwrap();
+        }
+        wait_for_drained(&node).await;
+        assert!(sink_rx.try_recv().is_err());
+
+        // And resumes with its context
+        engine.set_group_enabled(&group_id, true).await.unwrap();
+        engine.inject_msg(&ElementId::from(1), MsgHandle::new(msg.clone()), cancel.clone()).await.unwrap();
+        assert_eq!(recv_sink_payload(&mut sink_rx).await, Variant::from(2));
+
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_wait_for_the_message_in_process_when_reenabling_a_group() {
+        let log = |event: &str| format!("global.set('events', (global.get('events') || '') + '{event};');");
+        let func = format!("{} await new Promise(r => setTimeout(r, 300)); {} return msg;", log("begin"), log("end"));
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "group", "z": "100", "nodes": ["2"]},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "g": "200", "type": "function", "wires": [["3"]],
+                "initialize": log("init"), "finalize": log("final"),
+                "func": func},
+            {"id": "3", "z": "100", "type": "sink"}
+        ]);
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, None).unwrap();
+        let group_id: ElementId = "200".parse().unwrap();
+        let msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+        let events = || async { engine.context().get_one(None, "events", &[]).await };
+
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        engine.inject_msg(&ElementId::from(1), MsgHandle::new(msg.clone()), cancel.clone()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while events().await != Some(Variant::String("init;begin;".to_string())) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // The old task is still processing the message while the group is toggled
+        engine.set_group_enabled(&group_id, false).await.unwrap();
+        engine.set_group_enabled(&group_id, true).await.unwrap();
+        assert_eq!(events().await, Some(Variant::String("init;begin;end;final;init;".to_string())));
+
+        // The message in process has been sent before the task stopped, then the new task processes the next one
+        assert_eq!(recv_sink_payload(&mut sink_rx).await, Variant::from("foo"));
+        engine.inject_msg(&ElementId::from(1), MsgHandle::new(msg), cancel).await.unwrap();
+        assert_eq!(recv_sink_payload(&mut sink_rx).await, Variant::from("foo"));
+
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_receive_outputs_of_embedded_engine() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "step", "pt": "msg", "to": "1", "tot": "num"}]},
+            {"id": "2", "z": "100", "type": "switch", "property": "payload", "outputs": 2, "wires": [[], []],
+                "rules": [{"t": "lt", "v": "0", "vt": "num"}, {"t": "else"}]}
+        ]);
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, None).unwrap();
+
+        let outputs = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let outputs_cloned = outputs.clone();
+        engine
+            .on_output(&"2".parse().unwrap(), move |port, msg| {
+                outputs_cloned.lock().unwrap().push((port, msg["payload"].as_i64().unwrap()));
+            })
+            .unwrap();
+        let mut tapped = engine.tap_output(&"1".parse().unwrap()).unwrap();
+        assert!(engine.on_output(&"999".parse().unwrap(), |_, _| {}).is_err());
+
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        for i in 1..=3 {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": i})).unwrap());
+            engine.inject_msg(&"1".parse().unwrap(), msg, cancel.clone()).await.unwrap();
+        }
+        for i in 1..=3 {
+            let (port, msg) = tokio::time::timeout(Duration::from_secs(1), tapped.recv()).await.unwrap().unwrap();
+            assert_eq!(port, 0);
+            assert_eq!(msg["payload"].as_i64(), Some(i));
+            assert_eq!(msg["step"].as_i64(), Some(1));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        engine.stop().await.unwrap();
+
+        assert_eq!(*outputs.lock().unwrap(), vec![(1, 1), (1, 2), (1, 3)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_output_callbacks_should_register_other_callbacks() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [[]]}
+        ]);
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, None).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let weak = engine.downgrade();
+        let registered = std::sync::atomic::AtomicBool::new(false);
+        engine
+            .on_output(&ElementId::from(1), move |_, _| {
+                if registered.swap(true, Ordering::Relaxed) {
+                    return;
+                }
+                let tx = tx.clone();
+                // The same node hits the same shard of the map, this used to deadlock
+                let engine = weak.upgrade().unwrap();
+                engine.on_output(&ElementId::from(1), move |_, msg| tx.send(msg["payload"].as_i64()).unwrap()).unwrap();
+            })
+            .unwrap();
+
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        for i in 1..=2 {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": i})).unwrap());
+            engine.inject_msg(&ElementId::from(1), msg, cancel.clone()).await.unwrap();
+        }
+        let received = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        engine.stop().await.unwrap();
+        assert_eq!(received, Some(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_uncaught_errors_should_reach_the_dead_letter_sink() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file in", "filename": "/nonexistent/dead-letter.txt",
+                "filenameType": "str", "wires": []},
+            {"id": "2", "z": "200", "type": "function", "func": "throw new Error('caught');", "wires": []},
+            {"id": "3", "z": "200", "type": "catch", "scope": null, "uncaught": false, "wires": [["4"]]},
+            {"id": "4", "z": "200", "type": "sink"}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let mut dead_letters = engine.dead_letter_receiver();
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        let cancel = CancellationToken::new();
+        for id in [2, 1] {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": id, "topic": "origin"})).unwrap());
+            engine.inject_msg(&ElementId::from(id), msg, cancel.clone()).await.unwrap();
+        }
+
+        // The caught one goes to the catch node only
+        let (_, caught) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(caught.get_nav("error.source.id").and_then(|x| x.as_str()), Some("0000000000000002"));
+
+        let (node_id, error, msg) =
+            tokio::time::timeout(Duration::from_secs(1), dead_letters.recv()).await.unwrap().unwrap();
+        assert_eq!(node_id, ElementId::from(1));
+        assert!(error.contains("dead-letter.txt"), "{}", error);
+        let msg = msg.unwrap().read().await.clone();
+        assert_eq!(msg["topic"].as_str(), Some("origin"));
+        assert_eq!(msg["payload"].as_i64(), Some(1));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        engine.stop().await.unwrap();
+        assert!(dead_letters.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_inject_msgs_by_node_name() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "name": "Marker", "wires": [["3"]], "rules": [
+                {"t": "set", "p": "marked", "pt": "msg", "to": "true", "tot": "bool"}]},
+            {"id": "2", "z": "100", "type": "junction", "name": "marker", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "sink"}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        let cancel = CancellationToken::new();
+        let msg = MsgHandle::new(Msg::deserialize(json!({"payload": "foo"})).unwrap());
+        engine.inject_msg_by_name("Marker", msg, cancel.clone()).await.unwrap();
+        let msg = MsgHandle::new(Msg::deserialize(json!({"payload": "foo"})).unwrap());
+        assert!(engine.inject_msg_by_name("MARKER", msg, cancel.clone()).await.is_err());
+
+        let (_, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+        engine.stop().await.unwrap();
+        assert_eq!(msg["marked"], Variant::Bool(true));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_report_ids_of_duplicated_node_names() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "name": "dup", "wires": []},
+            {"id": "2", "z": "200", "type": "junction", "name": "dup", "wires": []}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let err = engine.find_flow_node_by_name("dup").err().unwrap().to_string();
+        assert!(err.contains("'0000000000000001'") && err.contains("'0000000000000002'"), "{}", err);
+        assert!(engine.find_flow_node_by_name("nothing").unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_it_should_json_flows_multiple_times() {
         let flows_json = make_flows_json_that_contains_subflows();
@@ -501,4 +1950,116 @@ mod tests {
             assert!(res.is_ok());
         }
     }
+
+    #[tokio::test]
+    async fn test_it_should_load_flows_from_reader() {
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let json_text = make_simple_flows_json().to_string();
+        let engine = Engine::with_flows_reader(&registry, std::io::Cursor::new(json_text), None).unwrap();
+        assert!(engine.get_flow(&"100".parse().unwrap()).is_some());
+
+        let err = Engine::with_flows_reader(&registry, std::io::Cursor::new("[{"), None).err().unwrap();
+        assert!(err.downcast_ref::<serde_json::Error>().is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_stream_the_final_msgs_until_stopped() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        for payload in ["foo", "bar"] {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": payload})).unwrap());
+            engine.inject_msg(&"1".parse().unwrap(), msg, cancel.clone()).await.unwrap();
+            let received = engine.next_final_msg().await.unwrap();
+            assert_eq!(received["payload"].as_str(), Some(payload));
+        }
+        engine.stop().await.unwrap();
+        assert!(engine.next_final_msg().await.is_none());
+    }
+
+    #[test]
+    fn test_it_should_cap_the_size_of_flows_json() {
+        assert_eq!(read_limited(std::io::Cursor::new("[]"), 2).unwrap(), b"[]");
+        let err = read_limited(std::io::Cursor::new("[ ]"), 2).err().unwrap();
+        assert!(matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::BadFlowsJson(_))));
+    }
+
+    /// Serves a single HTTP response on a local port, returns the base URL.
+    #[cfg(feature = "net")]
+    async fn serve_once(status: &'static str, body: String) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let resp = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(resp.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[cfg(feature = "net")]
+    #[tokio::test]
+    async fn test_it_should_load_flows_from_url() {
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let base_url = serve_once("200 OK", make_simple_flows_json().to_string()).await;
+        let engine = Engine::with_flows_url(&registry, &format!("{}/flows.json", base_url), None).await.unwrap();
+        assert!(engine.get_flow(&"100".parse().unwrap()).is_some());
+
+        let base_url = serve_once("404 Not Found", String::new()).await;
+        let err = Engine::with_flows_url(&registry, &format!("{}/flows.json", base_url), None).await.err().unwrap();
+        assert!(err.to_string().contains("404"), "{}", err);
+
+        let base_url = serve_once("200 OK", "[]".to_string()).await;
+        let err = fetch_limited(&format!("{}/flows.json", base_url), 1).await.err().unwrap();
+        assert!(matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::BadFlowsJson(_))));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_summarize_the_loaded_flows() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "tab", "disabled": true},
+            {"id": "300", "type": "subflow", "name": "sf", "in": [], "out": []},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "junction", "d": true, "wires": []},
+            {"id": "3", "z": "200", "type": "no-such-node", "wires": []},
+            {"id": "4", "z": "300", "type": "junction", "wires": []},
+            {"id": "5", "type": "no-such-config"},
+            {"id": "6", "z": "100", "type": "subflow:300", "wires": []}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let summary = engine.summary();
+        assert_eq!(summary.flows, 2);
+        assert_eq!(summary.subflows, 1);
+        assert_eq!(summary.disabled_flows, 1);
+        assert_eq!(summary.flow_nodes, 5);
+        assert_eq!(summary.global_nodes, 1);
+        assert_eq!(summary.disabled_nodes, 1);
+        assert_eq!(summary.nodes_by_type.get("junction"), Some(&3));
+        assert_eq!(summary.unknown_types["no-such-node"], vec!["3".parse::<ElementId>().unwrap()]);
+        assert_eq!(summary.unknown_types["no-such-config"], vec!["5".parse::<ElementId>().unwrap()]);
+        assert!(summary.to_string().contains("UNKNOWN TYPE 'no-such-node'"));
+    }
 }
@@ -10,6 +10,7 @@ use serde::Deserialize;
 use serde_json::Value as JsonValue;
 use utils::topo::TopologicalSorter;
 
+use crate::runtime::jsonata;
 use crate::runtime::model::{RedPropertyType, Variant};
 use crate::*;
 
@@ -189,10 +190,19 @@ impl EnvStoreBuilder {
                     .to_bytes()
                     .ok_or(EdgelinkError::BadArgument("value"))
                     .with_context(|| format!("Expected an array of bytes, got: {:?}", value))?;
-                Ok(Variant::Bytes(bytes))
+                Ok(Variant::Bytes(bytes.into()))
             }
 
-            RedPropertyType::Jsonata => todo!(),
+            RedPropertyType::Jsonata => {
+                let expr = jsonata::Expression::parse(value)?;
+                if !expr.context_keys().is_empty() {
+                    return Err(EdgelinkError::NotSupported("reading the context in environment variables".into()))
+                        .with_context(|| format!("Cannot evaluate the JSONata expression: '{}'", value));
+                }
+                let env = |name: &str| self.get_existed(name);
+                let bindings = jsonata::Bindings::new().with_env(&env);
+                Ok(expr.evaluate(&Variant::Null, &bindings)?.unwrap_or(Variant::Null))
+            }
 
             RedPropertyType::Env => match self.normalized_and_get_existed(value) {
                 Some(ev) => Ok(ev),
@@ -314,6 +324,11 @@ mod tests {
                 "value": "barbar",
                 "type": "str"
             },
+            {
+                "name": "NEXT_AGE",
+                "value": "$env('AGE') + 1",
+                "type": "jsonata"
+            },
         ]);
         let flow = EnvStoreBuilder::default().with_parent(&global).load_json(&json).build();
 
@@ -346,5 +361,6 @@ mod tests {
         assert_eq!(node.evalute_env("PARENT_BAR").unwrap().as_str().unwrap(), "barbar");
         assert_eq!(node.evalute_env("AGE").unwrap().as_str().unwrap(), "100");
         assert_eq!(node.evalute_env("FILE_SIZE").unwrap().as_i64().unwrap(), 123);
+        assert_eq!(node.evalute_env("NEXT_AGE").unwrap().as_i64().unwrap(), 42);
     }
 }
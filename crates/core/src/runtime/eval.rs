@@ -5,6 +5,7 @@ use serde::Deserialize;
 use smallvec::SmallVec;
 
 use crate::runtime::flow::*;
+use crate::runtime::jsonata;
 use crate::runtime::model::*;
 use crate::runtime::nodes::*;
 use crate::utils;
@@ -121,7 +122,7 @@ pub async fn evaluate_node_property(
 
         RedPropertyType::Bool => Ok(Variant::Bool(value.trim_ascii().parse::<bool>()?)),
 
-        RedPropertyType::Jsonata => todo!(),
+        RedPropertyType::Jsonata => evaluate_jsonata(value, node, flow, msg).await,
 
         RedPropertyType::Env => match evaluate_env_property(value, node, flow) {
             Some(ev) => Ok(ev),
@@ -131,6 +132,68 @@ pub async fn evaluate_node_property(
     }
 }
 
+/// Evaluates a JSONata expression against the message, an `undefined` result becomes `null`.
+///
+/// The context values read by `$flowContext()` and `$globalContext()` are read before the evaluation.
+pub async fn evaluate_jsonata(
+    source: &str,
+    node: Option<&dyn FlowNodeBehavior>,
+    flow: Option<&Flow>,
+    msg: Option<&Msg>,
+) -> crate::Result<Variant> {
+    let expr = jsonata::Expression::parse(source)?;
+    let context_values = read_jsonata_context(&expr, node, flow, msg).await?;
+    let input = msg.map(|x| x.as_variant().clone()).unwrap_or(Variant::Null);
+    let result = evaluate_jsonata_expression(&expr, &input, &[], &context_values, node, flow)?;
+    Ok(result.unwrap_or(Variant::Null))
+}
+
+/// Reads the context values used by the expression, see `jsonata::Expression::context_keys()`.
+pub async fn read_jsonata_context(
+    expr: &jsonata::Expression,
+    node: Option<&dyn FlowNodeBehavior>,
+    flow: Option<&Flow>,
+    msg: Option<&Msg>,
+) -> crate::Result<Vec<(jsonata::ContextKey, Variant)>> {
+    let msg_env = msg.map(|m| SmallVec::from([PropexEnv::ExtRef("msg", m.as_variant())])).unwrap_or_default();
+    let mut context_values = Vec::with_capacity(expr.context_keys().len());
+    for key in expr.context_keys() {
+        let ctx = match key.scope {
+            jsonata::ContextScope::Flow => flow.cloned().or(node.and_then(|n| n.flow())).map(|f| f.context().clone()),
+            jsonata::ContextScope::Global => {
+                flow.and_then(|f| f.engine()).or(node.and_then(|n| n.engine())).map(|e| e.context().clone())
+            }
+        };
+        let ctx = ctx.ok_or(EdgelinkError::BadArgument("flow,node"))?;
+        if let Some(value) = ctx.try_get_one(key.store.as_deref(), &key.key, &msg_env).await? {
+            context_values.push((key.clone(), value));
+        }
+    }
+    Ok(context_values)
+}
+
+/// Evaluates a parsed JSONata expression against `input` with the extra `variables`, `None` means `undefined`.
+///
+/// The `context_values` are read by `read_jsonata_context()`, so they can be read once for many evaluations.
+pub fn evaluate_jsonata_expression(
+    expr: &jsonata::Expression,
+    input: &Variant,
+    variables: &[(&str, Variant)],
+    context_values: &[(jsonata::ContextKey, Variant)],
+    node: Option<&dyn FlowNodeBehavior>,
+    flow: Option<&Flow>,
+) -> crate::Result<Option<Variant>> {
+    let env = |name: &str| evaluate_env_property(name, node, flow);
+    let mut bindings = jsonata::Bindings::new().with_env(&env);
+    for (name, value) in variables.iter() {
+        bindings = bindings.bind(name, value.clone());
+    }
+    for (key, value) in context_values.iter() {
+        bindings.set_context_value(key, value.clone());
+    }
+    expr.evaluate(input, &bindings)
+}
+
 /// Evaluates a property variant according to its type.
 pub fn evaluate_node_property_variant<'a>(
     value: &'a Variant,
@@ -191,7 +254,17 @@ pub fn evaluate_node_property_variant<'a>(
 
         (RedPropertyType::Bool, Variant::String(s)) => Cow::Owned(Variant::Bool(s.trim_ascii().parse::<bool>()?)),
 
-        (RedPropertyType::Jsonata, _) => todo!(),
+        (RedPropertyType::Jsonata, Variant::String(s)) => {
+            let expr = jsonata::Expression::parse(s)?;
+            if !expr.context_keys().is_empty() {
+                return Err(EdgelinkError::NotSupported("reading the context here".into()))
+                    .with_context(|| format!("Cannot evaluate the JSONata expression `{}`", s));
+            }
+            let env = |name: &str| evaluate_env_property(name, node, flow);
+            let bindings = jsonata::Bindings::new().with_env(&env);
+            let input = msg.map(|x| x.as_variant().clone()).unwrap_or(Variant::Null);
+            Cow::Owned(expr.evaluate(&input, &bindings)?.unwrap_or(Variant::Null))
+        }
 
         (RedPropertyType::Env, Variant::String(s)) => match evaluate_env_property(s, node, flow) {
             Some(ev) => Cow::Owned(ev),
@@ -211,5 +284,24 @@ pub fn evaluate_node_property_variant<'a>(
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+
+    #[tokio::test]
+    async fn jsonata_property_should_fail_instead_of_panicking() {
+        let res = evaluate_node_property("$.a +", RedPropertyType::Jsonata, None, None, None).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn jsonata_property_should_be_evaluated_against_the_msg() {
+        let msg = Msg::deserialize(serde_json::json!({"payload": {"a": 1, "b": 2}})).unwrap();
+        let res = evaluate_node_property("payload.a + payload.b", RedPropertyType::Jsonata, None, None, Some(&msg))
+            .await
+            .unwrap();
+        assert_eq!(res, Variant::from(3));
+
+        let res =
+            evaluate_node_property("payload.missing", RedPropertyType::Jsonata, None, None, Some(&msg)).await.unwrap();
+        assert_eq!(res, Variant::Null);
+    }
 }
@@ -1,7 +1,10 @@
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::{Arc, Weak};
 
 use common_nodes::catch::{CatchNode, CatchNodeScope};
+use common_nodes::status::StatusNode;
 use dashmap::DashMap;
 use itertools::Itertools;
 use serde::Deserialize;
@@ -19,7 +22,7 @@ use crate::runtime::model::json::*;
 use crate::runtime::model::*;
 use crate::runtime::nodes::*;
 use crate::runtime::registry::Registry;
-use crate::EdgelinkError;
+use crate::{EdgelinkError, ErrorContext};
 
 const NODE_MSG_CHANNEL_CAPACITY: usize = 32;
 
@@ -28,6 +31,11 @@ pub type FlowNodeTask = tokio::task::JoinHandle<()>;
 #[derive(Debug, Clone, Deserialize)]
 pub struct FlowArgs {
     pub node_msg_queue_capacity: usize,
+
+    /// A message caught this many times for the errors of the same node is dropped, so a `catch` node wired back
+    /// into the failing node cannot loop forever.
+    #[serde(default = "FlowArgs::default_max_catch_count")]
+    pub max_catch_count: usize,
 }
 
 impl FlowArgs {
@@ -43,9 +51,15 @@ impl FlowArgs {
     }
 }
 
+impl FlowArgs {
+    fn default_max_catch_count() -> usize {
+        10
+    }
+}
+
 impl Default for FlowArgs {
     fn default() -> Self {
-        Self { node_msg_queue_capacity: 16 }
+        Self { node_msg_queue_capacity: 16, max_catch_count: Self::default_max_catch_count() }
     }
 }
 
@@ -71,13 +85,23 @@ pub enum FlowKind {
     Subflow,
 }
 
+/// The task of a node spawned in `node_tasks`.
+#[derive(Debug)]
+struct RunningNode {
+    stop_token: CancellationToken,
+    /// Cancelled once the task has ended, even if it panicked.
+    stopped: CancellationToken,
+}
+
 #[derive(Debug)]
 struct InnerFlow {
     id: ElementId,
     parent: Option<ElementId>,
     label: String,
     disabled: bool,
-    _args: FlowArgs,
+    preserve_order: bool,
+    msg_seq: AtomicU64,
+    args: FlowArgs,
     ordering: usize,
     type_str: &'static str,
 
@@ -89,8 +113,10 @@ struct InnerFlow {
     pub(crate) nodes: DashMap<ElementId, Arc<dyn FlowNodeBehavior>>,
     pub(crate) complete_nodes_map: DashMap<ElementId, Vec<Arc<dyn FlowNodeBehavior>>>,
     pub(crate) catch_nodes: std::sync::RwLock<Vec<Arc<dyn FlowNodeBehavior>>>,
+    pub(crate) status_nodes: std::sync::RwLock<Vec<Arc<dyn FlowNodeBehavior>>>,
     pub(crate) _context: RwLock<Variant>,
     pub(crate) node_tasks: Mutex<JoinSet<()>>,
+    running_nodes: std::sync::Mutex<HashMap<ElementId, RunningNode>>,
 
     subflow_state: Option<SubflowState>,
 
@@ -150,25 +176,78 @@ impl Flow {
     async fn start_nodes(&self, stop_token: CancellationToken) -> crate::Result<()> {
         let nodes_ordering =
             self.inner.nodes.iter().sorted_by(|a, b| a.ordering().cmp(&b.ordering())).map(|x| x.value().clone());
+        let nodes_to_start: Vec<Arc<dyn FlowNodeBehavior>> = nodes_ordering
+            .filter(|node| {
+                if node.is_effectively_disabled() {
+                    log::warn!("------ Skipping disabled node {}.", node);
+                }
+                !node.is_effectively_disabled()
+            })
+            .collect();
 
-        for node in nodes_ordering.into_iter() {
-            if node.get_node().disabled {
-                log::warn!("------ Skipping disabled node {}.", node);
-                continue;
-            }
+        // Every node gets ready before any of them runs
+        for node in nodes_to_start.iter() {
+            node.on_starting().await.with_context(|| format!("Failed to start the node {}", node))?;
+        }
 
-            // Start the async-task of each flow node
-            log::info!("------ Starting node {}...", node,);
+        for node in nodes_to_start.into_iter() {
+            self.spawn_node_task(node, &stop_token).await;
+        }
 
-            let child_stop_token = stop_token.clone();
-            node.on_starting().await;
+        Ok(())
+    }
+
+    async fn spawn_node_task(&self, node: Arc<dyn FlowNodeBehavior>, stop_token: &CancellationToken) {
+        let node_stop_token = stop_token.child_token();
+        let stopped = CancellationToken::new();
+        let stopped_guard = stopped.clone().drop_guard();
+        let running = RunningNode { stop_token: node_stop_token.clone(), stopped };
+        self.inner.running_nodes.lock().expect("running_nodes lock").insert(node.id(), running);
+
+        let msg_tracer = self.engine().and_then(|x| x.msg_tracer());
+        let id_generator: Arc<dyn IdGenerator> = match self.engine().and_then(|x| x.msg_id_seed()) {
+            Some(seed) => Arc::new(SeededIdGenerator::new(seed ^ u64::from(node.id()))),
+            None => Arc::new(RandomIdGenerator),
+        };
+        if node.is_effectively_disabled() {
+            // Keep draining the messages of the disabled node, so the senders will not be blocked by a full channel
+            log::info!("------ Draining disabled node {}...", node);
             self.inner.node_tasks.lock().await.spawn(async move {
+                let _stopped = stopped_guard;
+                while node.get_node().msg_rx.recv_msg(node_stop_token.clone()).await.is_ok() {
+                    //
+                }
+            });
+        } else {
+            // Start the async-task of each flow node
+            log::info!("------ Starting node {}...", node);
+            let task = trace::with_tracer(msg_tracer, async move {
+                let _stopped = stopped_guard;
                 let node_ref = node.as_ref();
-                let _ = node.clone().run(child_stop_token.child_token()).await;
+                let _ = node.clone().run(node_stop_token).await;
                 log::info!("------ {} has been stopped.", node_ref,);
             });
+            self.inner.node_tasks.lock().await.spawn(Msg::with_id_generator(id_generator, task));
         }
+    }
 
+    /// Stops the task of the node and starts it again according to its current disabled state.
+    ///
+    /// The old task is awaited first, so it has finished the message in process and released the node before the new
+    /// one starts. The node instance is kept, so its state and context survive the restart.
+    pub(crate) async fn restart_node(&self, node: &Arc<dyn FlowNodeBehavior>) -> crate::Result<()> {
+        if self.is_disabled() || self.inner.stop_token.is_cancelled() {
+            return Ok(());
+        }
+        let running = self.inner.running_nodes.lock().expect("running_nodes lock").remove(&node.id());
+        if let Some(running) = running {
+            running.stop_token.cancel();
+            running.stopped.cancelled().await;
+        }
+        if !node.is_effectively_disabled() {
+            node.on_starting().await.with_context(|| format!("Failed to start the node {}", node))?;
+        }
+        self.spawn_node_task(node.clone(), &self.inner.stop_token).await;
         Ok(())
     }
 
@@ -246,8 +325,10 @@ impl Flow {
             engine: engine.downgrade(),
             label: flow_config.label.clone(),
             disabled: flow_config.disabled,
+            preserve_order: flow_config.rest.get("preserveOrder").and_then(|x| x.as_bool()).unwrap_or(false),
+            msg_seq: AtomicU64::new(0),
             ordering: flow_config.ordering,
-            _args: args.clone(),
+            args: args.clone(),
             type_str: match flow_kind {
                 FlowKind::GlobalFlow => "flow",
                 FlowKind::Subflow => "subflow",
@@ -256,8 +337,10 @@ impl Flow {
             nodes: DashMap::new(),
             complete_nodes_map: DashMap::new(),
             catch_nodes: std::sync::RwLock::new(Vec::new()),
+            status_nodes: std::sync::RwLock::new(Vec::new()),
             _context: RwLock::new(Variant::empty_object()),
             node_tasks: Mutex::new(JoinSet::new()),
+            running_nodes: std::sync::Mutex::new(HashMap::new()),
 
             subflow_state: match flow_kind {
                 FlowKind::Subflow => Some(SubflowState::new(engine, &flow_config, &args)?),
@@ -270,7 +353,7 @@ impl Flow {
         };
         let flow = Flow { inner: Arc::new(inner_flow) };
 
-        flow.populate_groups(&flow_config)?;
+        flow.populate_groups(&flow_config, engine)?;
         flow.populate_nodes(&flow_config, reg.as_ref(), engine)?;
 
         if let Some(subflow_state) = &flow.inner.subflow_state {
@@ -280,7 +363,7 @@ impl Flow {
         Ok(flow)
     }
 
-    fn populate_groups(&self, flow_config: &RedFlowConfig) -> crate::Result<()> {
+    fn populate_groups(&self, flow_config: &RedFlowConfig, engine: &Engine) -> crate::Result<()> {
         if !self.inner.groups.is_empty() {
             self.inner.groups.clear();
         }
@@ -294,10 +377,11 @@ impl Flow {
                     &self.inner.groups.get(parent_id).map(|x| x.value().clone()).ok_or(
                         EdgelinkError::InvalidOperation(format!("cannot found parent group id `{}`", parent_id)),
                     )?,
+                    engine,
                 )?,
 
                 // Root group
-                None => Group::new_flow_group(gc, self)?,
+                None => Group::new_flow_group(gc, self, engine)?,
             };
             self.inner.groups.insert(group.id(), group);
         }
@@ -305,6 +389,11 @@ impl Flow {
     }
 
     fn populate_nodes(&self, flow_config: &RedFlowConfig, reg: &dyn Registry, engine: &Engine) -> crate::Result<()> {
+        // Create the channels of all nodes first, so a node can be wired to the nodes built after it, e.g. in loops.
+        let mut channels: HashMap<ElementId, (MsgSender, MsgReceiver)> =
+            flow_config.nodes.iter().map(|x| (x.id, tokio::sync::mpsc::channel(NODE_MSG_CHANNEL_CAPACITY))).collect();
+        let senders: HashMap<ElementId, MsgSender> = channels.iter().map(|(id, (tx, _))| (*id, tx.clone())).collect();
+
         // Adding nodes
         for node_config in flow_config.nodes.iter() {
             let meta_node = if let Some(meta_node) = reg.get(&node_config.type_name) {
@@ -323,7 +412,11 @@ impl Flow {
 
             let node = match meta_node.factory {
                 NodeFactory::Flow(factory) => {
-                    let mut node_state = self.new_flow_node_state(meta_node, node_config, engine).map_err(|e| {
+                    let channel = channels
+                        .remove(&node_config.id)
+                        .unwrap_or_else(|| tokio::sync::mpsc::channel(NODE_MSG_CHANNEL_CAPACITY));
+                    let node_state = self.new_flow_node_state(meta_node, node_config, engine, channel, &senders);
+                    let mut node_state = node_state.map_err(|e| {
                         log::error!("Failed to create flow node(id='{}'): {:?}", node_config.id, e);
                         e
                     })?;
@@ -343,7 +436,14 @@ impl Flow {
                                             subflow_state.tx_ports.read().expect("read subflow tx_ports lock");
                                         tx_ports_lock[subflow_port_index].clone()
                                     };
-                                    let node_wire = PortWire { msg_sender: subflow_tx_port.msg_tx.clone() };
+                                    // The messages are forwarded by the instance node of the subflow
+                                    let node_wire = PortWire::new(
+                                        flow_config.subflow_node_id.unwrap_or(flow_config.id),
+                                        subflow_tx_port.msg_tx.clone(),
+                                    );
+                                    if let Some(instance_node) = &subflow_state.instance_node {
+                                        node_wire.set_target_node(instance_node);
+                                    }
                                     node_port.wires.push(node_wire)
                                 } else {
                                     return Err(EdgelinkError::BadFlowsJson(format!(
@@ -378,7 +478,7 @@ impl Flow {
             };
 
             let arc_node: Arc<dyn FlowNodeBehavior> = Arc::from(node);
-            arc_node.on_loaded();
+            arc_node.on_loaded().with_context(|| format!("Failed to load the node {}", arc_node))?;
             self.inner.nodes.insert(node_config.id, arc_node.clone());
 
             log::debug!("------ {} has been loaded!", arc_node);
@@ -408,6 +508,37 @@ impl Flow {
             });
         }
 
+        // The `complete` nodes watching the nodes of other flows are held by the engine, a node only notifies the
+        // `complete` nodes of its own flow and the ones of the engine
+        let foreign_ids: Vec<ElementId> = self
+            .inner
+            .complete_nodes_map
+            .iter()
+            .map(|x| *x.key())
+            .filter(|x| !self.inner.nodes.contains_key(x))
+            .collect();
+        if !foreign_ids.is_empty() {
+            let engine = self.engine().ok_or(EdgelinkError::InvalidOperation(
+                "The engine has gone, cannot register the `complete` nodes watching other flows".into(),
+            ))?;
+            for src_id in foreign_ids.into_iter() {
+                if let Some((src_id, complete_nodes)) = self.inner.complete_nodes_map.remove(&src_id) {
+                    for complete_node in complete_nodes.into_iter() {
+                        engine.register_global_complete_node(src_id, complete_node);
+                    }
+                }
+            }
+        }
+
+        // All the nodes in this flow have been built, resolve the targets of their wires
+        for node in self.get_all_flow_nodes().iter() {
+            for wire in node.get_node().ports.iter().flat_map(|x| x.wires.iter()) {
+                if let Some(target) = self.inner.nodes.get(&wire.target_node_id) {
+                    wire.set_target_node(target.value());
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -419,11 +550,24 @@ impl Flow {
         match node.get_node().type_str {
             "complete" => self.register_complete_node(node, node_config)?,
 
+            "catch" | "status" if is_global_scoped(node.as_ref()) => {
+                let engine = self.engine().ok_or(EdgelinkError::InvalidOperation(format!(
+                    "The engine has gone, cannot register the global scoped node: {}",
+                    node.id()
+                )))?;
+                engine.register_global_scoped_node(node.clone());
+            }
+
             "catch" => {
                 let mut catch_nodes = self.inner.catch_nodes.write().expect("`catch_nodes` write lock");
                 catch_nodes.push(node.clone());
             }
 
+            "status" => {
+                let mut status_nodes = self.inner.status_nodes.write().expect("`status_nodes` write lock");
+                status_nodes.push(node.clone());
+            }
+
             // ignore normal nodes
             &_ => {}
         }
@@ -463,24 +607,71 @@ impl Flow {
         self.inner.subflow_state.is_some()
     }
 
+    /// Returns `true` if the flow is in the "preserve order" mode, it is opt-in by the `preserveOrder`
+    /// property of the flow.
+    ///
+    /// In this mode messages are tagged with a monotonic sequence number (`msg._seq`) when they leave
+    /// a node for the first time, so the reconvergence points like `join` can restore their order.
+    pub fn is_order_preserved(&self) -> bool {
+        self.inner.preserve_order
+    }
+
+    pub fn next_msg_seq(&self) -> u64 {
+        self.inner.msg_seq.fetch_add(1, AtomicOrdering::Relaxed)
+    }
+
     pub fn get_all_flow_nodes(&self) -> Vec<Arc<dyn FlowNodeBehavior>> {
         self.inner.nodes.iter().map(|x| x.value().clone()).collect()
     }
 
+    /// Returns the wires of the nodes in this flow as `(source node, port, target node)`, in the node ordering.
+    ///
+    /// A subflow also returns the edges from its instance node to the nodes wired to its input, which come first.
+    pub fn wiring_graph(&self) -> Vec<(ElementId, usize, ElementId)> {
+        let mut edges = Vec::new();
+        if let Some(subflow_state) = &self.inner.subflow_state {
+            if let Some(instance_id) = subflow_state.instance_node.as_ref().map(|x| x.id()) {
+                let in_nodes = subflow_state.in_nodes.read().expect("read in_nodes lock");
+                edges.extend(in_nodes.iter().map(|x| (instance_id, 0, x.id())));
+            }
+        }
+        let node_edges =
+            self.inner.nodes.iter().map(|x| x.value().clone()).sorted_by_key(|x| x.ordering()).flat_map(|node| {
+                let source_id = node.id();
+                node.get_node()
+                    .ports
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(port, x)| x.wires.iter().map(move |wire| (source_id, port, wire.target_node_id)))
+                    .collect::<Vec<_>>()
+            });
+        edges.extend(node_edges);
+        edges
+    }
+
     pub fn get_node_by_id(&self, id: &ElementId) -> Option<Arc<dyn FlowNodeBehavior>> {
         self.inner.nodes.get(id).map(|x| x.value().clone())
     }
 
+    /// Finds the node by its name in this flow, the name is case-sensitive.
     pub fn get_node_by_name(&self, name: &str) -> crate::Result<Option<Arc<dyn FlowNodeBehavior>>> {
-        let iter = self.inner.nodes.iter().filter(|val| val.name() == name);
-        let nfound = iter.clone().count();
-        if nfound == 1 {
-            Ok(iter.clone().next().map(|x| x.clone()))
-        } else if nfound == 0 {
-            Ok(None)
-        } else {
-            Err(EdgelinkError::InvalidOperation(format!("There are multiple node with name '{}'", name)).into())
-        }
+        unique_node_by_name(name, self.inner.nodes.iter().map(|x| x.value().clone()))
+    }
+
+    /// Like `get_node_by_name()` but only the nodes of the type `type_str` are considered.
+    pub fn get_node_by_type_and_name(
+        &self,
+        type_str: &str,
+        name: &str,
+    ) -> crate::Result<Option<Arc<dyn FlowNodeBehavior>>> {
+        unique_node_by_name(
+            name,
+            self.inner.nodes.iter().map(|x| x.value().clone()).filter(|x| x.type_str() == type_str),
+        )
+    }
+
+    pub fn get_group(&self, id: &ElementId) -> Option<Group> {
+        self.inner.groups.get(id).map(|x| x.value().clone())
     }
 
     pub fn engine(&self) -> Option<Engine> {
@@ -498,6 +689,11 @@ impl Flow {
     pub async fn start(&self) -> crate::Result<()> {
         // let mut state = self.shared.state.write().await;
 
+        if self.is_disabled() {
+            log::warn!("---- Skipping disabled flow (id={}).", self.id());
+            return Ok(());
+        }
+
         if self.is_subflow() {
             log::info!("---- Starting Subflow (id={})...", self.id());
         } else {
@@ -542,15 +738,19 @@ impl Flow {
         Ok(())
     }
 
+    /// Notifies the `complete` nodes of this flow watching the node first, then the ones of other flows.
     pub async fn notify_node_uow_completed(&self, emitter_id: &ElementId, msg: MsgHandle, cancel: CancellationToken) {
-        if let Some(complete_nodes) = self.inner.complete_nodes_map.get(emitter_id) {
-            for complete_node in complete_nodes.iter() {
-                let to_send = msg.deep_clone(true).await;
-                match complete_node.inject_msg(to_send, cancel.child_token()).await {
-                    Ok(()) => {}
-                    Err(err) => {
-                        log::warn!("Failed to inject msg in notify_node_completed(): {}", err.to_string());
-                    }
+        // Never hold the map while injecting
+        let mut complete_nodes = self.inner.complete_nodes_map.get(emitter_id).map(|x| x.clone()).unwrap_or_default();
+        if let Some(engine) = self.engine() {
+            complete_nodes.extend(engine.global_complete_nodes(emitter_id));
+        }
+        for complete_node in complete_nodes.iter() {
+            let to_send = msg.deep_clone(true).await;
+            match complete_node.inject_msg(to_send, cancel.child_token()).await {
+                Ok(()) => {}
+                Err(err) => {
+                    log::warn!("Failed to inject msg in notify_node_completed(): {}", err.to_string());
                 }
             }
         }
@@ -590,26 +790,28 @@ impl Flow {
         meta_node: &MetaNode,
         node_config: &RedFlowNodeConfig,
         engine: &Engine,
+        channel: (MsgSender, MsgReceiver),
+        flow_senders: &HashMap<ElementId, MsgSender>,
     ) -> crate::Result<FlowNode> {
         let mut ports = Vec::new();
-        let (tx_root, rx) = tokio::sync::mpsc::channel(NODE_MSG_CHANNEL_CAPACITY);
+        let (tx_root, rx) = channel;
         // Convert the Node-RED wires elements to ours
         for red_port in node_config.wires.iter() {
             let mut wires = Vec::new();
             for nid in red_port.node_ids.iter() {
-                // First we find the node in this flow
-                let node_in_flow = self.inner.nodes.get(nid).map(|x| x.value().clone());
-                // Next we find the node in the entire engine, otherwise there is an error
-                let node_in_engine = engine.find_flow_node_by_id(nid);
-                let node_entry = node_in_flow.or(node_in_engine).ok_or(EdgelinkError::InvalidOperation(format!(
-                    "[flow:{}] Referenced node not found [this_node.id='{}' this_node.name='{}', referenced_node.id='{}']",
-                    self.name(), node_config.id, node_config.name, nid
-                )))?;
-                let tx = node_entry.get_node().msg_tx.to_owned();
-                let pw = PortWire {
-                    // target_node_id: *nid,
-                    // target_node: Arc::downgrade(node_entry),
-                    msg_sender: tx,
+                // First we find the node in this flow, built or not, its target node is set after the flow was loaded
+                let pw = match flow_senders.get(nid) {
+                    Some(tx) => PortWire::new(*nid, tx.clone()),
+                    // Next we find the node in the entire engine, otherwise there is an error
+                    None => {
+                        let target = engine.find_flow_node_by_id(nid).ok_or(EdgelinkError::InvalidOperation(format!(
+                            "[flow:{}] Referenced node not found [this_node.id='{}' this_node.name='{}', referenced_node.id='{}']",
+                            self.name(), node_config.id, node_config.name, nid
+                        )))?;
+                        let pw = PortWire::new(*nid, target.get_node().msg_tx.to_owned());
+                        pw.set_target_node(&target);
+                        pw
+                    }
                 };
                 wires.push(pw);
             }
@@ -647,7 +849,10 @@ impl Flow {
                 ("NR_NODE_PATH".into(), Variant::String(format!("{}/{}", self.get_path(), node_config.id))),
             ])
             .build();
-        let context = engine.get_context_manager().new_context(&self.inner.context, node_config.id.to_string());
+        // Scoped by the node ID only, so a persistent store rebinds the node to the same data after the restarts.
+        // The parent is the group of the node if any, reached by the `$parent.` keys.
+        let parent_context = group.as_ref().map(|g| g.context()).unwrap_or_else(|| self.inner.context.clone());
+        let context = engine.get_context_manager().new_context(&parent_context, node_config.id.to_string());
 
         Ok(FlowNode {
             id: node_config.id,
@@ -661,11 +866,16 @@ impl Flow {
             msg_rx: MsgReceiverHolder::new(rx),
             ports,
             group: group.map(|g| g.downgrade()),
+            flow_id: node_config.z,
+            group_id: node_config.g,
+            status: std::sync::Mutex::new(NodeStatus::default()),
             envs,
             context,
             on_received: MsgEventSender::new(1),
             on_completed: MsgEventSender::new(1),
             on_error: MsgEventSender::new(1),
+            clone_stats: Default::default(),
+            received_tapped: Default::default(),
         })
     }
 
@@ -679,6 +889,26 @@ impl Flow {
     ) -> crate::Result<bool> {
         let reporting_node = if let Some(rn) = reporting_node { rn } else { node };
 
+        // Count the catches of the message for the errors of the same node, like Node-RED does
+        let mut count = 1;
+        if let Some(ref msg) = msg {
+            let msg_guard = msg.read().await;
+            if msg_guard.get_nav("error.source.id").and_then(|x| x.as_str()) == Some(node.id().to_string().as_str()) {
+                count += msg_guard.get_nav("error.source.count").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+                if count >= self.inner.args.max_catch_count {
+                    log::warn!(
+                        "[{}:{}] Message exceeded maximum number of catches, dropped: {}",
+                        node.type_str(),
+                        node.name(),
+                        log_message
+                    );
+                    drop(msg_guard);
+                    self.handle_uncaught_error(node, log_message, Some(msg.clone()));
+                    return Ok(false);
+                }
+            }
+        }
+
         // TODO: use SmallVec
         let mut candidates = Vec::new();
         {
@@ -690,13 +920,13 @@ impl Flow {
                     && reporting_node.group().is_none()
                 {
                     // Catch node inside a group, reporting node not in a group - skip it
-                    return Ok(true);
+                    continue;
                 }
 
                 if let CatchNodeScope::Nodes(ref scope) = catch_node.scope {
                     // Catch node has a scope set and it doesn't include the reporting node
                     if !scope.contains(&reporting_node.id()) {
-                        return Ok(true);
+                        continue;
                     }
                 }
                 let mut distance: usize = 0;
@@ -723,7 +953,7 @@ impl Flow {
                     {
                         // This catch node is in a group, but not in the same hierachy
                         // the reporting node is in
-                        return Ok(true);
+                        continue;
                     }
                 }
                 candidates.push((distance, catch_node_behavior.clone()))
@@ -741,27 +971,142 @@ impl Flow {
                 }
                 handled_by_uncaught = true;
             }
-            let mut error_msg = if let Some(ref msg) = msg {
-                let msg_lock = msg.read().await;
-                msg_lock.clone()
-            } else {
-                Msg::default()
-            };
-            let error_object = Variant::from(serde_json::json!({
-                "message": log_message.to_string(),
-                "source": {
-                    "id": node.id(),
-                    "type": node.type_str().to_string(),
-                    "name": node.name(),
-                    "count": 1, // TODO
-                }
-            }));
-            error_msg.set("error".into(), error_object);
-            let error_msg = MsgHandle::new(error_msg);
+            let error_msg = Self::make_error_msg(node, log_message, msg.as_ref(), count).await;
             catch_node.inject_msg(error_msg, cancel.clone()).await?;
 
             handled = true;
         }
+
+        // The engine scoped `catch` nodes only get the errors no `catch` node of this flow caught, and like above the
+        // `uncaught` ones only get the errors the others did not catch
+        if !handled {
+            if let Some(engine) = self.engine() {
+                let (uncaught, caught): (Vec<_>, Vec<_>) = engine
+                    .global_catch_nodes()
+                    .into_iter()
+                    .partition(|x| x.as_any().downcast_ref::<CatchNode>().expect("CatchNode").uncaught);
+                for catch_nodes in [caught, uncaught] {
+                    if handled {
+                        break;
+                    }
+                    for catch_node in catch_nodes.iter() {
+                        let error_msg = Self::make_error_msg(node, log_message, msg.as_ref(), count).await;
+                        catch_node.inject_msg(error_msg, cancel.clone()).await?;
+                        handled = true;
+                    }
+                }
+            }
+        }
+
+        if !handled {
+            self.handle_uncaught_error(node, log_message, msg);
+        }
+        Ok(handled)
+    }
+
+    async fn make_error_msg(
+        node: &dyn FlowNodeBehavior,
+        log_message: &str,
+        msg: Option<&MsgHandle>,
+        count: usize,
+    ) -> MsgHandle {
+        let mut error_msg = if let Some(msg) = msg { msg.read().await.clone() } else { Msg::default() };
+        let error_object = Variant::from(serde_json::json!({
+            "message": log_message.to_string(),
+            "source": {
+                "id": node.id(),
+                "type": node.type_str().to_string(),
+                "name": node.name(),
+                "count": count,
+            }
+        }));
+        error_msg.set("error".into(), error_object);
+        MsgHandle::new(error_msg)
+    }
+
+    /// Hands the error that no `catch` node handled over to the dead-letter sink of the engine.
+    fn handle_uncaught_error(&self, node: &dyn FlowNodeBehavior, log_message: &str, msg: Option<MsgHandle>) {
+        if let Some(engine) = self.engine() {
+            engine.notify_uncaught_error(&node.id(), log_message, msg);
+        }
+    }
+}
+
+impl Flow {
+    /// Delivers the status of the node to the `status` nodes watching it, returns `true` if any one received it.
+    pub async fn handle_status(
+        &self,
+        node: &dyn FlowNodeBehavior,
+        status: &NodeStatus,
+        cancel: CancellationToken,
+    ) -> crate::Result<bool> {
+        let mut status_nodes = self.inner.status_nodes.read().expect("`status_nodes` read lock").clone();
+        if let Some(engine) = self.engine() {
+            status_nodes.extend(engine.global_status_nodes());
+        }
+        let mut handled = false;
+        for status_node_behavior in status_nodes.iter() {
+            let status_node = status_node_behavior.as_any().downcast_ref::<StatusNode>().expect("StatusNode");
+            let in_scope = match status_node.scope {
+                CatchNodeScope::All | CatchNodeScope::Global => true,
+                CatchNodeScope::Group => {
+                    status_node.group().is_some_and(|g| node.group().is_some_and(|ng| ng.id() == g.id()))
+                }
+                CatchNodeScope::Nodes(ref scope) => scope.contains(&node.id()),
+            };
+            // A status node never watches itself
+            if !in_scope || status_node.id() == node.id() {
+                continue;
+            }
+
+            let mut status_object = Variant::from(serde_json::to_value(status)?);
+            if let Some(obj) = status_object.as_object_mut() {
+                obj.insert(
+                    "source".into(),
+                    Variant::from(serde_json::json!({
+                        "id": node.id(),
+                        "type": node.type_str().to_string(),
+                        "name": node.name(),
+                    })),
+                );
+            }
+            let mut status_msg = Msg::default();
+            status_msg.set("status".into(), status_object);
+            status_node.inject_msg(MsgHandle::new(status_msg), cancel.clone()).await?;
+            handled = true;
+        }
         Ok(handled)
     }
 }
+
+/// Returns `true` for the `catch` and `status` nodes watching the nodes of all flows, they are held by the engine.
+fn is_global_scoped(node: &dyn FlowNodeBehavior) -> bool {
+    let scope = if let Some(catch_node) = node.as_any().downcast_ref::<CatchNode>() {
+        &catch_node.scope
+    } else if let Some(status_node) = node.as_any().downcast_ref::<StatusNode>() {
+        &status_node.scope
+    } else {
+        return false;
+    };
+    *scope == CatchNodeScope::Global
+}
+
+/// Selects the only node named `name`, returns an error listing the IDs of the nodes if the name is ambiguous.
+pub(crate) fn unique_node_by_name(
+    name: &str,
+    nodes: impl Iterator<Item = Arc<dyn FlowNodeBehavior>>,
+) -> crate::Result<Option<Arc<dyn FlowNodeBehavior>>> {
+    let mut found: Vec<Arc<dyn FlowNodeBehavior>> = nodes.filter(|x| x.name() == name).collect();
+    match found.len() {
+        0 => Ok(None),
+        1 => Ok(found.pop()),
+        _ => {
+            let ids = found.iter().map(|x| format!("'{}'", x.id())).sorted().join(", ");
+            Err(EdgelinkError::InvalidOperation(format!(
+                "There are multiple nodes named '{}' (ids: {}), use the node ID instead",
+                name, ids
+            ))
+            .into())
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Weak;
 
+use super::context::Context;
+use super::engine::Engine;
 use super::env::*;
 use super::flow::*;
 use super::model::json::*;
@@ -51,7 +54,7 @@ impl FlowsElement for Group {
     }
 
     fn is_disabled(&self) -> bool {
-        self.inner.disabled
+        self.inner.disabled.load(Ordering::Relaxed)
     }
 
     fn get_path(&self) -> String {
@@ -59,19 +62,27 @@ impl FlowsElement for Group {
     }
 }
 
+/// The group-scoped context, the parent of the contexts of its nodes and subgroups.
+impl ContextHolder for Group {
+    fn context(&self) -> Arc<Context> {
+        self.inner.context.clone()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum GroupParent {
     Flow(WeakFlow),
     Group(WeakGroup),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct InnerGroup {
     pub id: ElementId,
     pub name: String,
-    pub disabled: bool,
+    pub disabled: AtomicBool,
     pub parent: GroupParent,
     pub envs: Envs,
+    pub context: Arc<Context>,
 }
 
 impl Group {
@@ -79,32 +90,63 @@ impl Group {
         WeakGroup { inner: Arc::downgrade(&self.inner) }
     }
 
-    pub(crate) fn new_flow_group(config: &RedGroupConfig, flow: &Flow) -> crate::Result<Self> {
+    pub(crate) fn new_flow_group(config: &RedGroupConfig, flow: &Flow, engine: &Engine) -> crate::Result<Self> {
         let envs_builder = EnvStoreBuilder::default().with_parent(flow.get_envs());
+        let context = engine.get_context_manager().new_context(&flow.context(), config.id.to_string());
 
         let inner = InnerGroup {
             id: config.id,
             name: config.name.clone(),
-            disabled: config.disabled,
+            disabled: AtomicBool::new(config.disabled),
             parent: GroupParent::Flow(flow.downgrade()),
             envs: build_envs(envs_builder, config),
+            context,
         };
         Ok(Self { inner: Arc::new(inner) })
     }
 
-    pub(crate) fn new_subgroup(config: &RedGroupConfig, parent: &Group) -> crate::Result<Self> {
+    pub(crate) fn new_subgroup(config: &RedGroupConfig, parent: &Group, engine: &Engine) -> crate::Result<Self> {
         let envs_builder = EnvStoreBuilder::default().with_parent(&parent.inner.envs);
+        let context = engine.get_context_manager().new_context(&parent.inner.context, config.id.to_string());
 
         let inner = InnerGroup {
             id: config.id,
             name: config.name.clone(),
-            disabled: config.disabled,
+            disabled: AtomicBool::new(config.disabled),
             parent: GroupParent::Group(parent.downgrade()),
             envs: build_envs(envs_builder, config),
+            context,
         };
         Ok(Self { inner: Arc::new(inner) })
     }
 
+    /// Returns `true` if this group or any of its parent groups is disabled.
+    pub fn is_disabled_recursively(&self) -> bool {
+        if self.is_disabled() {
+            return true;
+        }
+        match self.inner.parent {
+            GroupParent::Group(ref parent) => parent.upgrade().is_some_and(|x| x.is_disabled_recursively()),
+            GroupParent::Flow(_) => false,
+        }
+    }
+
+    /// Only changes the flag, see `Engine::set_group_enabled()` for starting and stopping the nodes.
+    pub(crate) fn set_disabled(&self, disabled: bool) {
+        self.inner.disabled.store(disabled, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if this group is the group `id` or one of its subgroups at any depth.
+    pub fn is_within(&self, id: &ElementId) -> bool {
+        if self.inner.id == *id {
+            return true;
+        }
+        match self.inner.parent {
+            GroupParent::Group(ref parent) => parent.upgrade().is_some_and(|x| x.is_within(id)),
+            GroupParent::Flow(_) => false,
+        }
+    }
+
     pub fn get_parent(&self) -> &GroupParent {
         &self.inner.parent
     }
@@ -129,3 +171,43 @@ fn build_envs(mut envs_builder: EnvStoreBuilder, config: &RedGroupConfig) -> Env
         ])
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_nodes_should_see_the_env_and_context_of_their_group() {
+        let grouped = r#"
+            context.set('$parent.count', 1);
+            context.set('$parent.$parent.count', 2);
+            context.set('$parent.$parent.$parent.count', 3);
+            msg.payload = [env.get('GREETING'), context.get('$parent.count'), flow.get('count'), global.get('count')];
+            return msg;
+        "#;
+        let ungrouped = "msg.outside = [env.get('GREETING'), context.get('$parent.count')]; return msg;";
+        let flows_json = json!([
+            {"id": "100", "type": "tab", "env": [{"name": "GREETING", "value": "from flow", "type": "str"}]},
+            {"id": "200", "z": "100", "type": "group", "nodes": ["1"],
+                "env": [{"name": "GREETING", "value": "from group", "type": "str"}]},
+            {"id": "1", "z": "100", "g": "200", "type": "function", "func": grouped, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "function", "func": ungrouped, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"topic": "t"}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+
+        let payload = msgs[0]["payload"].as_array().unwrap();
+        assert_eq!(payload[0].as_str(), Some("from group"));
+        assert_eq!(payload.iter().skip(1).map(|x| x.as_i64()).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3)]);
+
+        // Outside of the group, the parent of a node is the flow
+        let outside = msgs[0]["outside"].as_array().unwrap();
+        assert_eq!(outside[0].as_str(), Some("from flow"));
+        assert_eq!(outside[1].as_i64(), Some(2));
+    }
+}
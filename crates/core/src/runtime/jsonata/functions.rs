@@ -0,0 +1,353 @@
+//! The built-in functions of JSONata.
+
+use super::*;
+
+/// Converts the value to string like `$string()`, the strings are kept as they are and the others become JSON.
+pub(super) fn to_string(v: &Variant) -> Result<String, JsonataError> {
+    match v {
+        Variant::String(s) => Ok(s.clone()),
+        Variant::Number(n) => match n.as_f64() {
+            Some(f) => Ok(number(f)?.to_json_value().to_string()),
+            None => Ok(n.to_string()),
+        },
+        Variant::Regexp(_) => Ok(String::new()),
+        other => serde_json::to_string(other).map_err(|e| eval_error(e.to_string())),
+    }
+}
+
+fn to_number(name: &str, v: &Variant) -> Result<f64, JsonataError> {
+    v.as_f64().ok_or_else(|| eval_error(format!("The argument of `${}()` must be a number", name)))
+}
+
+fn to_str<'v>(name: &str, v: &'v Variant) -> Result<&'v str, JsonataError> {
+    v.as_str().ok_or_else(|| eval_error(format!("The argument of `${}()` must be a string", name)))
+}
+
+fn numbers(name: &str, value: Value) -> Result<Vec<f64>, JsonataError> {
+    value.into_items().iter().map(|x| to_number(name, x)).collect()
+}
+
+/// Returns the characters `[start, start + length)` like `$substring()`, a negative start counts from the end.
+fn substring(s: &str, start: f64, length: Option<f64>) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let len = chars.len() as f64;
+    let start = if start < 0.0 { (len + start).max(0.0) } else { start.min(len) };
+    let end = match length {
+        Some(length) => (start + length.max(0.0)).min(len),
+        None => len,
+    };
+    chars[start as usize..end.max(start) as usize].iter().collect()
+}
+
+pub(super) fn call(name: &str, args: Vec<Value>, bindings: &Bindings) -> Result<Value, JsonataError> {
+    let n = args.len();
+    let mut args = args.into_iter();
+    // The functions of JSONata return `undefined` for an `undefined` argument, except the aggregations and tests
+    let mut arg = || args.next().unwrap_or(Value::Undefined);
+    macro_rules! item {
+        ($value:expr) => {
+            match $value.into_variant() {
+                Some(v) => v,
+                None => return Ok(Value::Undefined),
+            }
+        };
+    }
+    let check = |min: usize, max: usize| {
+        if n < min || n > max {
+            Err(eval_error(format!("Wrong number of the arguments of `${}()`: {}", name, n)))
+        } else {
+            Ok(())
+        }
+    };
+
+    let result = match name {
+        // The aggregations
+        "sum" => {
+            check(1, 1)?;
+            let sum = numbers(name, arg())?.iter().sum::<f64>();
+            number(sum)?
+        }
+        "count" => {
+            check(0, 1)?;
+            Variant::from(arg().into_items().len() as i64)
+        }
+        "max" | "min" | "average" => {
+            check(1, 1)?;
+            let values = numbers(name, arg())?;
+            if values.is_empty() {
+                return Ok(Value::Undefined);
+            }
+            let result = match name {
+                "max" => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                "min" => values.iter().copied().fold(f64::INFINITY, f64::min),
+                _ => values.iter().sum::<f64>() / values.len() as f64,
+            };
+            number(result)?
+        }
+
+        // The casts and tests
+        "string" => {
+            check(1, 1)?;
+            Variant::String(to_string(&item!(arg()))?)
+        }
+        "number" => {
+            check(1, 1)?;
+            let v = item!(arg());
+            let parsed = match &v {
+                Variant::Number(_) => v.as_f64(),
+                Variant::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+                Variant::String(s) => s.trim().parse::<f64>().ok().filter(|x| x.is_finite()),
+                _ => None,
+            };
+            match parsed {
+                Some(x) => number(x)?,
+                None => return Err(eval_error(format!("Unable to cast the value to a number: {:?}", v))),
+            }
+        }
+        "boolean" => {
+            check(1, 1)?;
+            Variant::Bool(boolean(&item!(arg())))
+        }
+        "not" => {
+            check(1, 1)?;
+            Variant::Bool(!boolean(&item!(arg())))
+        }
+        "exists" => {
+            check(1, 1)?;
+            Variant::Bool(!arg().is_undefined())
+        }
+
+        // The strings
+        "length" => {
+            check(1, 1)?;
+            let v = item!(arg());
+            Variant::from(to_str(name, &v)?.chars().count() as i64)
+        }
+        "substring" => {
+            check(2, 3)?;
+            let v = item!(arg());
+            let start = to_number(name, &item!(arg()))?;
+            let length = match arg().into_variant() {
+                Some(x) => Some(to_number(name, &x)?),
+                None => None,
+            };
+            Variant::String(substring(to_str(name, &v)?, start, length))
+        }
+        "substringBefore" | "substringAfter" => {
+            check(2, 2)?;
+            let v = item!(arg());
+            let s = to_str(name, &v)?;
+            let chars = item!(arg());
+            let chars = to_str(name, &chars)?;
+            let result = match s.find(chars) {
+                Some(pos) if name == "substringBefore" => &s[..pos],
+                Some(pos) => &s[pos + chars.len()..],
+                None => s,
+            };
+            Variant::from(result)
+        }
+        "uppercase" | "lowercase" | "trim" => {
+            check(1, 1)?;
+            let v = item!(arg());
+            let s = to_str(name, &v)?;
+            let result = match name {
+                "uppercase" => s.to_uppercase(),
+                "lowercase" => s.to_lowercase(),
+                // Like JSONata, the whitespaces inside are normalised to a single space
+                _ => s.split_whitespace().collect::<Vec<_>>().join(" "),
+            };
+            Variant::String(result)
+        }
+        "contains" => {
+            check(2, 2)?;
+            let v = item!(arg());
+            let s = to_str(name, &v)?;
+            let pattern = item!(arg());
+            match pattern {
+                Variant::Regexp(_) => Variant::Bool(pattern.regex_test(s)),
+                Variant::String(_) => Variant::Bool(predicate::contains(&v, &pattern)),
+                _ => return Err(eval_error("The pattern of `$contains()` must be a string or a regex")),
+            }
+        }
+        "startsWith" | "endsWith" => {
+            check(2, 2)?;
+            let v = item!(arg());
+            to_str(name, &v)?;
+            let affix = item!(arg());
+            to_str(name, &affix)?;
+            if name == "startsWith" {
+                Variant::Bool(predicate::starts_with(&v, &affix))
+            } else {
+                Variant::Bool(predicate::ends_with(&v, &affix))
+            }
+        }
+        "replace" => {
+            check(3, 3)?;
+            let v = item!(arg());
+            let s = to_str(name, &v)?;
+            let pattern = item!(arg());
+            let replacement = item!(arg());
+            let replacement = to_str(name, &replacement)?;
+            match &pattern {
+                Variant::Regexp(_) => {
+                    Variant::from(pattern.regex_replace(s, replacement, true).expect("a regex").into_owned())
+                }
+                Variant::String(p) if !p.is_empty() => Variant::String(s.replace(p.as_str(), replacement)),
+                _ => return Err(eval_error("The pattern of `$replace()` must be a non-empty string or a regex")),
+            }
+        }
+        "split" => {
+            check(2, 3)?;
+            let v = item!(arg());
+            let s = to_str(name, &v)?;
+            let separator = item!(arg());
+            let limit = match arg().into_variant() {
+                Some(x) => to_number(name, &x)?.max(0.0) as usize,
+                None => usize::MAX,
+            };
+            let parts: Vec<Variant> = match &separator {
+                Variant::String(sep) if sep.is_empty() => s.chars().map(|c| Variant::String(c.to_string())).collect(),
+                Variant::String(sep) => s.split(sep.as_str()).map(Variant::from).collect(),
+                Variant::Regexp(re) => re.split(s).map(Variant::from).collect(),
+                _ => return Err(eval_error("The separator of `$split()` must be a string or a regex")),
+            };
+            Variant::Array(parts.into_iter().take(limit).collect())
+        }
+        "join" => {
+            check(1, 2)?;
+            let items = arg().into_items();
+            let separator = match arg().into_variant() {
+                Some(x) => to_str(name, &x)?.to_string(),
+                None => String::new(),
+            };
+            let strings = items.iter().map(|x| to_str(name, x).map(str::to_string)).collect::<Result<Vec<_>, _>>()?;
+            Variant::String(strings.join(&separator))
+        }
+
+        // The arrays and objects
+        "append" => {
+            check(2, 2)?;
+            let (a, b) = (arg(), arg());
+            match (a.is_undefined(), b.is_undefined()) {
+                (true, _) => return Ok(b),
+                (_, true) => return Ok(a),
+                _ => {
+                    let mut items = a.into_items();
+                    items.extend(b.into_items());
+                    Variant::Array(items)
+                }
+            }
+        }
+        "reverse" => {
+            check(1, 1)?;
+            let mut items = Value::Item(item!(arg())).into_items();
+            items.reverse();
+            Variant::Array(items)
+        }
+        "distinct" => {
+            check(1, 1)?;
+            let mut distinct: Vec<Variant> = Vec::new();
+            for item in arg().into_items() {
+                if !distinct.iter().any(|x| predicate::equals(x, &item, false)) {
+                    distinct.push(item);
+                }
+            }
+            Variant::Array(distinct)
+        }
+        "keys" => {
+            check(1, 1)?;
+            let mut keys: Vec<Variant> = Vec::new();
+            for item in arg().into_items() {
+                if let Variant::Object(obj) = item {
+                    for key in obj.keys() {
+                        if !keys.iter().any(|x| x.as_str() == Some(key.as_str())) {
+                            keys.push(Variant::from(key.as_str()));
+                        }
+                    }
+                }
+            }
+            return Ok(Value::sequence(keys));
+        }
+        "lookup" => {
+            check(2, 2)?;
+            let objects = arg().into_items();
+            let key = item!(arg());
+            let key = to_str(name, &key)?;
+            let found = objects.iter().filter_map(|x| x.as_object().and_then(|obj| obj.get(key)).cloned());
+            return Ok(Value::sequence(found.collect()));
+        }
+
+        // The numbers
+        "abs" | "floor" | "ceil" | "sqrt" => {
+            check(1, 1)?;
+            let x = to_number(name, &item!(arg()))?;
+            let result = match name {
+                "abs" => x.abs(),
+                "floor" => x.floor(),
+                "ceil" => x.ceil(),
+                _ if x < 0.0 => return Err(eval_error("The argument of `$sqrt()` must not be negative")),
+                _ => x.sqrt(),
+            };
+            number(result)?
+        }
+        "round" => {
+            check(1, 2)?;
+            let x = to_number(name, &item!(arg()))?;
+            let precision = match arg().into_variant() {
+                Some(p) => to_number(name, &p)? as i32,
+                None => 0,
+            };
+            // Rounds half to even like JSONata, the decimal point is shifted in the decimal notation so `1.255`
+            // is not rounded as `125.49999999999999`
+            let shift = |x: f64, exponent: i32| format!("{}e{}", x, exponent).parse::<f64>().unwrap_or(f64::NAN);
+            let scaled = shift(x, precision);
+            let rounded = scaled.round();
+            let rounded = if (scaled - scaled.trunc()).abs() == 0.5 && rounded % 2.0 != 0.0 {
+                rounded - scaled.signum()
+            } else {
+                rounded
+            };
+            number(shift(rounded, -precision))?
+        }
+        "power" => {
+            check(2, 2)?;
+            let base = to_number(name, &item!(arg()))?;
+            let exponent = to_number(name, &item!(arg()))?;
+            number(base.powf(exponent))?
+        }
+
+        // The date and time
+        "millis" => {
+            check(0, 0)?;
+            Variant::from(crate::utils::time::unix_now())
+        }
+        "now" => {
+            check(0, 0)?;
+            Variant::String(crate::utils::time::iso_now())
+        }
+
+        // The environment and the context
+        "env" => {
+            check(1, 1)?;
+            let key = item!(arg());
+            let key = to_str(name, &key)?;
+            match bindings.env.and_then(|env| env(key)) {
+                Some(v) => v,
+                None => return Ok(Value::Undefined),
+            }
+        }
+        "flowContext" | "globalContext" => {
+            let values = if name == "flowContext" { &bindings.flow_context } else { &bindings.global_context };
+            let scope = if name == "flowContext" { ContextScope::Flow } else { ContextScope::Global };
+            let key = item!(arg()).as_str().map(str::to_string).unwrap_or_default();
+            let store = arg().into_variant().and_then(|x| x.as_str().map(str::to_string));
+            match values.get(&ContextKey { scope, key, store }) {
+                Some(v) => v.clone(),
+                None => return Ok(Value::Undefined),
+            }
+        }
+
+        _ => return Err(eval_error(format!("Unknown function `${}()`", name))),
+    };
+    Ok(Value::Item(result))
+}
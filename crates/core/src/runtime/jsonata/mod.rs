@@ -0,0 +1,819 @@
+//! A JSONata evaluator for the `jsonata` property values of the nodes, e.g. the `change`, `switch` and `inject`
+//! nodes, and the reduce mode of the `join` node.
+//!
+//! It covers the subset of JSONata used by the flows in practice:
+//! - the paths with the predicates and the wildcard, like `payload.items[price > 10].name` and `$.a[-1]`;
+//! - the literals, the array and object constructors and the ranges like `[1..5]`;
+//! - the arithmetic, comparison, boolean, `in` and string concatenation `&` operators;
+//! - the conditions `?:`, the blocks `( ...; ... )`, the variable bindings `:=` and the chaining `~>`;
+//! - the built-in functions listed in `functions.rs`.
+//!
+//! The lambdas, the higher-order functions, the sorting and grouping operators are not supported and fail to parse
+//! or evaluate. As in Node-RED, `$flowContext()` and `$globalContext()` read the context, but their keys must be
+//! string literals: the context is read before the evaluation since the stores are asynchronous, see
+//! `Expression::context_keys()`.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::runtime::model::*;
+
+mod functions;
+mod parser;
+
+#[derive(Error, Debug)]
+pub enum JsonataError {
+    #[error("Syntax error at {position}: {message}")]
+    Syntax { position: usize, message: String },
+
+    #[error("{0}")]
+    Evaluation(String),
+}
+
+fn eval_error(message: impl Into<String>) -> JsonataError {
+    JsonataError::Evaluation(message.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn of(op: &str) -> Option<Self> {
+        let op = match op {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            "%" => BinaryOp::Rem,
+            "&" => BinaryOp::Concat,
+            "=" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "<" => BinaryOp::Lt,
+            "<=" => BinaryOp::Le,
+            ">" => BinaryOp::Gt,
+            ">=" => BinaryOp::Ge,
+            "in" => BinaryOp::In,
+            "and" => BinaryOp::And,
+            "or" => BinaryOp::Or,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn binding_power(self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 60,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Concat => 50,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge | BinaryOp::In => {
+                40
+            }
+            BinaryOp::And => 30,
+            BinaryOp::Or => 25,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Concat => "&",
+            BinaryOp::Eq => "=",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::In => "in",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    node: Node,
+    predicates: Vec<Node>,
+}
+
+impl Step {
+    fn new(node: Node) -> Self {
+        Step { node, predicates: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(Variant),
+    /// A field of the context object, only as a step of a path.
+    Name(String),
+    Wildcard,
+    Variable(String),
+    Path(Vec<Step>),
+    Negate(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Condition(Box<Node>, Box<Node>, Option<Box<Node>>),
+    Array(Vec<Node>),
+    Range(Box<Node>, Box<Node>),
+    Object(Vec<(Node, Node)>),
+    Block(Vec<Node>),
+    Bind(String, Box<Node>),
+    Call(String, Vec<Node>),
+}
+
+/// The result of an evaluation, like JSONata it tells `undefined` from `null` and the sequences from the arrays.
+#[derive(Debug, Clone)]
+enum Value {
+    Undefined,
+    Item(Variant),
+    /// The results of a path, flattened, a singleton sequence is the item itself.
+    Sequence(Vec<Variant>),
+}
+
+impl Value {
+    fn sequence(mut items: Vec<Variant>) -> Self {
+        match items.len() {
+            0 => Value::Undefined,
+            1 => Value::Item(items.pop().expect("one item")),
+            _ => Value::Sequence(items),
+        }
+    }
+
+    fn is_undefined(&self) -> bool {
+        matches!(self, Value::Undefined)
+    }
+
+    fn into_variant(self) -> Option<Variant> {
+        match self {
+            Value::Undefined => None,
+            Value::Item(v) => Some(v),
+            Value::Sequence(items) => Some(Variant::Array(items)),
+        }
+    }
+
+    fn as_variant(&self) -> Option<std::borrow::Cow<'_, Variant>> {
+        match self {
+            Value::Undefined => None,
+            Value::Item(v) => Some(std::borrow::Cow::Borrowed(v)),
+            Value::Sequence(items) => Some(std::borrow::Cow::Owned(Variant::Array(items.clone()))),
+        }
+    }
+
+    /// The items of the sequence or the array, a single item otherwise.
+    fn into_items(self) -> Vec<Variant> {
+        match self {
+            Value::Undefined => Vec::new(),
+            Value::Item(Variant::Array(items)) | Value::Sequence(items) => items,
+            Value::Item(v) => vec![v],
+        }
+    }
+}
+
+/// Casts the value to boolean like the `$boolean()` function of JSONata.
+pub fn boolean(v: &Variant) -> bool {
+    match v {
+        Variant::Null => false,
+        Variant::Bool(b) => *b,
+        Variant::Number(n) => n.as_f64().is_some_and(|x| x != 0.0),
+        Variant::String(s) => !s.is_empty(),
+        Variant::Array(arr) => arr.iter().any(boolean),
+        Variant::Object(obj) => !obj.is_empty(),
+        Variant::Bytes(_) | Variant::Date(_) | Variant::Regexp(_) => true,
+    }
+}
+
+fn value_boolean(v: &Value) -> bool {
+    match v {
+        Value::Undefined => false,
+        Value::Item(v) => boolean(v),
+        Value::Sequence(items) => items.iter().any(boolean),
+    }
+}
+
+/// Converts a number to `Variant`, the integral values become integers like the numbers of JS in JSON.
+fn number(value: f64) -> Result<Variant, JsonataError> {
+    if !value.is_finite() {
+        return Err(eval_error("The number is out of range"));
+    }
+    // 2^53, the integers beyond cannot be told from their neighbours
+    if value.fract() == 0.0 && value.abs() < 9007199254740992.0 {
+        Ok(Variant::from(value as i64))
+    } else {
+        Ok(Variant::from(value))
+    }
+}
+
+fn numeric(value: Numeric) -> Result<Variant, JsonataError> {
+    match value {
+        Numeric::Float(f) => number(f),
+        integer => Ok(Variant::from(integer)),
+    }
+}
+
+/// Looks up an environment variable for `$env()`.
+pub type EnvLookup<'a> = &'a (dyn Fn(&str) -> Option<Variant> + Sync);
+
+/// The variables and the context values visible to an expression.
+#[derive(Default)]
+pub struct Bindings<'a> {
+    variables: HashMap<String, Variant>,
+    flow_context: HashMap<ContextKey, Variant>,
+    global_context: HashMap<ContextKey, Variant>,
+    env: Option<EnvLookup<'a>>,
+}
+
+impl<'a> Bindings<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds the variable `$name`.
+    pub fn bind(mut self, name: &str, value: Variant) -> Self {
+        self.variables.insert(name.to_string(), value);
+        self
+    }
+
+    /// Sets the environment variables read by `$env()`.
+    pub fn with_env(mut self, env: EnvLookup<'a>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Sets a value read by `$flowContext()` or `$globalContext()`, see `Expression::context_keys()`.
+    pub fn set_context_value(&mut self, key: &ContextKey, value: Variant) {
+        match key.scope {
+            ContextScope::Flow => self.flow_context.insert(key.clone(), value),
+            ContextScope::Global => self.global_context.insert(key.clone(), value),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextScope {
+    Flow,
+    Global,
+}
+
+/// A context value read by `$flowContext(key, store)` or `$globalContext(key, store)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContextKey {
+    pub scope: ContextScope,
+    pub key: String,
+    pub store: Option<String>,
+}
+
+/// A parsed JSONata expression, parse it once and evaluate it for every message.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+    context_keys: Vec<ContextKey>,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> crate::Result<Self> {
+        let invalid = |e: JsonataError| {
+            crate::EdgelinkError::InvalidOperation(format!("Invalid JSONata expression `{}`: {}", source, e))
+        };
+        let root = parser::parse(source).map_err(invalid)?;
+        let mut context_keys = Vec::new();
+        collect_context_keys(&root, &mut context_keys).map_err(invalid)?;
+        Ok(Expression { source: source.to_string(), root, context_keys })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The context values read by the expression, to be read into the `Bindings` before the evaluation.
+    pub fn context_keys(&self) -> &[ContextKey] {
+        &self.context_keys
+    }
+
+    /// Evaluates the expression against `input`, the `$` and `$$` of the expression; `None` means `undefined`.
+    pub fn evaluate(&self, input: &Variant, bindings: &Bindings) -> crate::Result<Option<Variant>> {
+        let evaluator = Evaluator { root: input, bindings };
+        let mut frame = Frame { locals: HashMap::new(), parent: None };
+        let result = evaluator.eval(&self.root, input, &mut frame).map_err(|e| {
+            crate::EdgelinkError::InvalidOperation(format!(
+                "Failed to evaluate the JSONata expression `{}`: {}",
+                self.source, e
+            ))
+        })?;
+        Ok(result.into_variant())
+    }
+}
+
+fn collect_context_keys(node: &Node, keys: &mut Vec<ContextKey>) -> Result<(), JsonataError> {
+    let mut children: Vec<&Node> = Vec::new();
+    match node {
+        Node::Literal(_) | Node::Name(_) | Node::Wildcard | Node::Variable(_) => {}
+        Node::Path(steps) => {
+            for step in steps.iter() {
+                children.push(&step.node);
+                children.extend(step.predicates.iter());
+            }
+        }
+        Node::Negate(x) | Node::Bind(_, x) => children.push(x),
+        Node::Binary(_, a, b) | Node::Range(a, b) => children.extend([a.as_ref(), b.as_ref()]),
+        Node::Condition(a, b, c) => {
+            children.extend([a.as_ref(), b.as_ref()]);
+            children.extend(c.as_deref());
+        }
+        Node::Array(items) | Node::Block(items) => children.extend(items.iter()),
+        Node::Object(pairs) => children.extend(pairs.iter().flat_map(|(k, v)| [k, v])),
+        Node::Call(name, args) => {
+            let scope = match name.as_str() {
+                "flowContext" => Some(ContextScope::Flow),
+                "globalContext" => Some(ContextScope::Global),
+                _ => None,
+            };
+            match (scope, args.as_slice()) {
+                (Some(scope), [Node::Literal(Variant::String(key))]) => {
+                    keys.push(ContextKey { scope, key: key.clone(), store: None })
+                }
+                (Some(scope), [Node::Literal(Variant::String(key)), Node::Literal(Variant::String(store))]) => {
+                    keys.push(ContextKey { scope, key: key.clone(), store: Some(store.clone()) })
+                }
+                (Some(_), _) => {
+                    return Err(eval_error(format!("The arguments of `${}()` must be string literals", name)));
+                }
+                (None, _) => children.extend(args.iter()),
+            }
+        }
+    }
+    for child in children {
+        collect_context_keys(child, keys)?;
+    }
+    Ok(())
+}
+
+/// The variables bound by `:=` in a block.
+struct Frame<'f> {
+    locals: HashMap<String, Variant>,
+    parent: Option<&'f Frame<'f>>,
+}
+
+impl Frame<'_> {
+    fn lookup(&self, name: &str) -> Option<&Variant> {
+        self.locals.get(name).or_else(|| self.parent.and_then(|x| x.lookup(name)))
+    }
+}
+
+struct Evaluator<'e> {
+    root: &'e Variant,
+    bindings: &'e Bindings<'e>,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, node: &Node, context: &Variant, frame: &mut Frame) -> Result<Value, JsonataError> {
+        let value = match node {
+            Node::Literal(v) => Value::Item(v.clone()),
+
+            Node::Name(name) => match context {
+                Variant::Object(obj) => obj.get(name.as_str()).cloned().map(Value::Item).unwrap_or(Value::Undefined),
+                // The steps map over the arrays
+                Variant::Array(items) => {
+                    let mut results = Vec::new();
+                    for item in items.iter() {
+                        match self.eval(node, item, frame)? {
+                            Value::Undefined => {}
+                            Value::Item(Variant::Array(x)) | Value::Sequence(x) => results.extend(x),
+                            Value::Item(x) => results.push(x),
+                        }
+                    }
+                    Value::sequence(results)
+                }
+                _ => Value::Undefined,
+            },
+
+            Node::Wildcard => match context {
+                Variant::Object(obj) => {
+                    let mut results = Vec::new();
+                    for v in obj.values() {
+                        match v {
+                            Variant::Array(items) => results.extend(items.iter().cloned()),
+                            other => results.push(other.clone()),
+                        }
+                    }
+                    Value::sequence(results)
+                }
+                Variant::Array(_) => Value::Item(context.clone()),
+                _ => Value::Undefined,
+            },
+
+            Node::Variable(name) => match name.as_str() {
+                "" => Value::Item(context.clone()),
+                "$" => Value::Item(self.root.clone()),
+                _ => match frame.lookup(name).or_else(|| self.bindings.variables.get(name)) {
+                    Some(v) => Value::Item(v.clone()),
+                    None => Value::Undefined,
+                },
+            },
+
+            Node::Path(steps) => self.eval_path(steps, context, frame)?,
+
+            Node::Negate(operand) => match self.eval(operand, context, frame)? {
+                Value::Undefined => Value::Undefined,
+                Value::Item(Variant::Number(n)) => match n.as_f64() {
+                    Some(n) => Value::Item(number(-n)?),
+                    None => return Err(eval_error("Cannot negate the number")),
+                },
+                _ => return Err(eval_error("Cannot negate a non-numeric value")),
+            },
+
+            Node::Binary(op, left, right) => self.eval_binary(*op, left, right, context, frame)?,
+
+            Node::Condition(condition, then, otherwise) => {
+                if value_boolean(&self.eval(condition, context, frame)?) {
+                    self.eval(then, context, frame)?
+                } else if let Some(otherwise) = otherwise {
+                    self.eval(otherwise, context, frame)?
+                } else {
+                    Value::Undefined
+                }
+            }
+
+            Node::Array(items) => {
+                let mut array = Vec::new();
+                for item in items.iter() {
+                    match item {
+                        Node::Range(start, end) => array.extend(self.eval_range(start, end, context, frame)?),
+                        // A nested array constructor stays an array, the other arrays are flattened
+                        Node::Array(_) => {
+                            array.extend(self.eval(item, context, frame)?.into_variant());
+                        }
+                        _ => array.extend(self.eval(item, context, frame)?.into_items()),
+                    }
+                }
+                Value::Item(Variant::Array(array))
+            }
+
+            Node::Range(start, end) => Value::Item(Variant::Array(self.eval_range(start, end, context, frame)?)),
+
+            Node::Object(pairs) => {
+                let mut obj = VariantObjectMap::new();
+                for (key, value) in pairs.iter() {
+                    let key = match self.eval(key, context, frame)?.into_variant() {
+                        Some(Variant::String(key)) => key,
+                        other => return Err(eval_error(format!("The key of an object must be a string: {:?}", other))),
+                    };
+                    if let Some(value) = self.eval(value, context, frame)?.into_variant() {
+                        obj.insert(key.into(), value);
+                    }
+                }
+                Value::Item(Variant::Object(obj))
+            }
+
+            Node::Block(items) => {
+                let mut block_frame = Frame { locals: HashMap::new(), parent: Some(&*frame) };
+                let mut result = Value::Undefined;
+                for item in items.iter() {
+                    result = self.eval(item, context, &mut block_frame)?;
+                }
+                result
+            }
+
+            Node::Bind(name, value) => {
+                let value = self.eval(value, context, frame)?;
+                if let Some(v) = value.as_variant() {
+                    frame.locals.insert(name.clone(), v.into_owned());
+                }
+                value
+            }
+
+            Node::Call(name, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    values.push(self.eval(arg, context, frame)?);
+                }
+                functions::call(name, values, self.bindings)?
+            }
+        };
+        Ok(value)
+    }
+
+    fn eval_path(&self, steps: &[Step], context: &Variant, frame: &mut Frame) -> Result<Value, JsonataError> {
+        // The field names map over the context array, the other expressions see it as a whole
+        let mut inputs = match (&steps[0].node, context) {
+            (Node::Name(_) | Node::Wildcard, Variant::Array(items)) => items.clone(),
+            _ => vec![context.clone()],
+        };
+        let mut last = Value::Undefined;
+        for (index, step) in steps.iter().enumerate() {
+            let is_last = index + 1 == steps.len();
+            let mut results = Vec::new();
+            for input in inputs.iter() {
+                let mut result = self.eval(&step.node, input, frame)?;
+                for predicate in step.predicates.iter() {
+                    result = self.filter(result, predicate, frame)?;
+                }
+                if !result.is_undefined() {
+                    results.push(result);
+                }
+            }
+            // The only array of the last step is kept as it is
+            if is_last && results.len() == 1 && matches!(results[0], Value::Item(Variant::Array(_))) {
+                return Ok(results.pop().expect("one result"));
+            }
+            let flattened: Vec<Variant> = results.into_iter().flat_map(|x| x.into_items()).collect();
+            if flattened.is_empty() {
+                return Ok(Value::Undefined);
+            }
+            if is_last {
+                last = Value::sequence(flattened);
+            } else {
+                inputs = flattened;
+            }
+        }
+        Ok(last)
+    }
+
+    fn filter(&self, value: Value, predicate: &Node, frame: &mut Frame) -> Result<Value, JsonataError> {
+        let items = value.into_items();
+        let mut kept = Vec::new();
+        // A numeric literal is an index, negative from the end
+        if let Node::Literal(Variant::Number(n)) = predicate {
+            let index = n.as_f64().unwrap_or(0.0).floor();
+            let index = if index < 0.0 { items.len() as f64 + index } else { index };
+            if index >= 0.0 {
+                kept.extend(items.into_iter().nth(index as usize));
+            }
+            return Ok(Value::sequence(kept));
+        }
+        let len = items.len();
+        for (index, item) in items.into_iter().enumerate() {
+            let result = self.eval(predicate, &item, frame)?;
+            let is_kept = match result {
+                Value::Item(Variant::Number(ref n)) => {
+                    let i = n.as_f64().unwrap_or(0.0).floor();
+                    let i = if i < 0.0 { len as f64 + i } else { i };
+                    i == index as f64
+                }
+                ref other => value_boolean(other),
+            };
+            if is_kept {
+                kept.push(item);
+            }
+        }
+        Ok(Value::sequence(kept))
+    }
+
+    fn eval_range(
+        &self,
+        start: &Node,
+        end: &Node,
+        context: &Variant,
+        frame: &mut Frame,
+    ) -> Result<Vec<Variant>, JsonataError> {
+        let start = self.eval(start, context, frame)?.into_variant();
+        let end = self.eval(end, context, frame)?.into_variant();
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Ok(Vec::new()),
+        };
+        match (start.as_i64(), end.as_i64()) {
+            (Some(start), Some(end)) => {
+                if end.saturating_sub(start) > 10_000_000 {
+                    return Err(eval_error("The range is too large"));
+                }
+                Ok((start..=end).map(Variant::from).collect())
+            }
+            _ => Err(eval_error("The bounds of a range must be integers")),
+        }
+    }
+
+    fn eval_binary(
+        &self,
+        op: BinaryOp,
+        left: &Node,
+        right: &Node,
+        context: &Variant,
+        frame: &mut Frame,
+    ) -> Result<Value, JsonataError> {
+        // The boolean operators short-circuit
+        match op {
+            BinaryOp::And => {
+                let result = value_boolean(&self.eval(left, context, frame)?)
+                    && value_boolean(&self.eval(right, context, frame)?);
+                return Ok(Value::Item(Variant::Bool(result)));
+            }
+            BinaryOp::Or => {
+                let result = value_boolean(&self.eval(left, context, frame)?)
+                    || value_boolean(&self.eval(right, context, frame)?);
+                return Ok(Value::Item(Variant::Bool(result)));
+            }
+            _ => {}
+        }
+
+        let lhs = self.eval(left, context, frame)?.into_variant();
+        let rhs = self.eval(right, context, frame)?.into_variant();
+        let value = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                let (lhs, rhs) = match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => (lhs, rhs),
+                    _ => return Ok(Value::Undefined),
+                };
+                if !lhs.is_number() || !rhs.is_number() {
+                    return Err(eval_error(format!(
+                        "The operands of the {} operator must evaluate to numbers",
+                        op.symbol()
+                    )));
+                }
+                let result = match op {
+                    BinaryOp::Add => lhs.add(&rhs),
+                    BinaryOp::Sub => lhs.sub(&rhs),
+                    BinaryOp::Mul => lhs.mul(&rhs),
+                    BinaryOp::Div => lhs.div(&rhs),
+                    _ => {
+                        let (a, b) = (lhs.as_f64().unwrap_or(f64::NAN), rhs.as_f64().unwrap_or(f64::NAN));
+                        Some(Numeric::Float(a % b))
+                    }
+                };
+                numeric(result.expect("both are numbers"))?
+            }
+            BinaryOp::Concat => {
+                let lhs = lhs.map(|x| functions::to_string(&x)).transpose()?.unwrap_or_default();
+                let rhs = rhs.map(|x| functions::to_string(&x)).transpose()?.unwrap_or_default();
+                Variant::String(lhs + &rhs)
+            }
+            BinaryOp::Eq | BinaryOp::Ne => {
+                let equal = match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => predicate::equals(&lhs, &rhs, false),
+                    _ => return Ok(Value::Item(Variant::Bool(false))),
+                };
+                Variant::Bool(if op == BinaryOp::Eq { equal } else { !equal })
+            }
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                let (lhs, rhs) = match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => (lhs, rhs),
+                    _ => return Ok(Value::Item(Variant::Bool(false))),
+                };
+                let ordering = match (&lhs, &rhs) {
+                    (Variant::Number(_), Variant::Number(_)) | (Variant::String(_), Variant::String(_)) => {
+                        predicate::compare(&lhs, &rhs)
+                    }
+                    _ => {
+                        return Err(eval_error(format!(
+                            "The operands of the {} operator must be both numbers or both strings",
+                            op.symbol()
+                        )));
+                    }
+                };
+                let result = match ordering {
+                    Some(ordering) => match op {
+                        BinaryOp::Lt => ordering.is_lt(),
+                        BinaryOp::Le => ordering.is_le(),
+                        BinaryOp::Gt => ordering.is_gt(),
+                        _ => ordering.is_ge(),
+                    },
+                    None => false,
+                };
+                Variant::Bool(result)
+            }
+            BinaryOp::In => {
+                let (lhs, rhs) = match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => (lhs, rhs),
+                    _ => return Ok(Value::Item(Variant::Bool(false))),
+                };
+                let found = Value::Item(rhs).into_items().iter().any(|x| predicate::equals(&lhs, x, false));
+                Variant::Bool(found)
+            }
+            BinaryOp::And | BinaryOp::Or => unreachable!(),
+        };
+        Ok(Value::Item(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(source: &str, input: serde_json::Value) -> Option<serde_json::Value> {
+        let expr = Expression::parse(source).unwrap();
+        expr.evaluate(&Variant::from(input), &Bindings::new()).unwrap().map(|x| x.to_json_value())
+    }
+
+    #[test]
+    fn it_should_navigate_the_paths() {
+        let input = json!({
+            "payload": {"a": 1, "b": 2, "items": [{"name": "x", "price": 5}, {"name": "y", "price": 15}]},
+            "topic": "t"
+        });
+        assert_eq!(eval("payload.a + payload.b", input.clone()), Some(json!(3)));
+        assert_eq!(eval("$.payload.a", input.clone()), Some(json!(1)));
+        assert_eq!(eval("payload.items.name", input.clone()), Some(json!(["x", "y"])));
+        assert_eq!(eval("payload.items[price > 10].name", input.clone()), Some(json!("y")));
+        assert_eq!(eval("payload.items[-1].price", input.clone()), Some(json!(15)));
+        assert_eq!(eval("payload.items[0]", input.clone()), Some(json!({"name": "x", "price": 5})));
+        assert_eq!(eval("payload.missing", input.clone()), None);
+        assert_eq!(eval("payload.missing + 1", input.clone()), None);
+        assert_eq!(eval("`topic` & '/' & payload.items[1].name", input.clone()), Some(json!("t/y")));
+        assert_eq!(eval("payload.*", json!({"payload": {"a": 1, "b": [2, 3]}})), Some(json!([1, 2, 3])));
+        // The only array is kept as it is
+        assert_eq!(eval("payload", json!({"payload": [1]})), Some(json!([1])));
+        assert_eq!(eval("$$.payload[0]", json!({"payload": [7, 8]})), Some(json!(7)));
+    }
+
+    #[test]
+    fn it_should_evaluate_the_operators() {
+        assert_eq!(eval("7 / 2", json!({})), Some(json!(3.5)));
+        assert_eq!(eval("6 / 2", json!({})), Some(json!(3)));
+        assert_eq!(eval("0.1 * 10", json!({})), Some(json!(1)));
+        assert_eq!(eval("-a % 4", json!({"a": 7})), Some(json!(-3)));
+        assert_eq!(eval("a = 1 and b != 'x'", json!({"a": 1.0, "b": "y"})), Some(json!(true)));
+        assert_eq!(eval("'b' in ['a', 'b']", json!({})), Some(json!(true)));
+        assert_eq!(eval("a > 1 ? 'big' : 'small'", json!({"a": 0})), Some(json!("small")));
+        assert_eq!(eval("a > 1 ? 'big'", json!({"a": 0})), None);
+        assert_eq!(eval("[1..3, 5]", json!({})), Some(json!([1, 2, 3, 5])));
+        assert_eq!(eval("{'sum': a + 1, 'none': missing}", json!({"a": 1})), Some(json!({"sum": 2})));
+        assert_eq!(eval("($x := a * 2; $y := $x + 1; $y)", json!({"a": 2})), Some(json!(5)));
+        assert_eq!(eval("'abc' < 'abd'", json!({})), Some(json!(true)));
+
+        let expr = Expression::parse("a + 'x'").unwrap();
+        assert!(expr.evaluate(&Variant::from(json!({"a": 1})), &Bindings::new()).is_err());
+        let expr = Expression::parse("1 / 0").unwrap();
+        assert!(expr.evaluate(&Variant::empty_object(), &Bindings::new()).is_err());
+    }
+
+    #[test]
+    fn it_should_call_the_functions() {
+        let input = json!({"payload": {"values": [1, 2, 3.5], "text": "  Hello   World  "}});
+        assert_eq!(eval("$sum(payload.values)", input.clone()), Some(json!(6.5)));
+        assert_eq!(eval("$count(payload.values)", input.clone()), Some(json!(3)));
+        assert_eq!(eval("$max(payload.values)", input.clone()), Some(json!(3.5)));
+        assert_eq!(eval("$average([1, 2])", input.clone()), Some(json!(1.5)));
+        assert_eq!(eval("$max([])", input.clone()), None);
+        assert_eq!(eval("$trim(payload.text)", input.clone()), Some(json!("Hello World")));
+        assert_eq!(eval("$uppercase($substring($trim(payload.text), -5))", input.clone()), Some(json!("WORLD")));
+        assert_eq!(eval("$substringBefore('a-b-c', '-')", input.clone()), Some(json!("a")));
+        assert_eq!(eval("$substringAfter('a-b-c', '-')", input.clone()), Some(json!("b-c")));
+        assert_eq!(eval("$split('a,b,c', ',', 2)", input.clone()), Some(json!(["a", "b"])));
+        assert_eq!(eval("$join(['a', 'b'], '+')", input.clone()), Some(json!("a+b")));
+        assert_eq!(eval("$contains('abc', 'bc')", input.clone()), Some(json!(true)));
+        assert_eq!(eval("$contains('abc', /^A/i)", input.clone()), Some(json!(true)));
+        assert_eq!(eval("$replace('a1b22', /[0-9]+/, '#')", input.clone()), Some(json!("a#b#")));
+        assert_eq!(eval("$string(1.5) & $string(true) & $string([1])", input.clone()), Some(json!("1.5true[1]")));
+        assert_eq!(eval("$number('12') + 1", input.clone()), Some(json!(13)));
+        assert_eq!(eval("$exists(payload.none)", input.clone()), Some(json!(false)));
+        assert_eq!(eval("$round(2.5) + $round(3.5) + $round(1.255, 2)", input.clone()), Some(json!(7.26)));
+        assert_eq!(eval("$keys({'a': 1, 'b': 2})", input.clone()), Some(json!(["a", "b"])));
+        assert_eq!(eval("$distinct([1, 1.0, 2]) ~> $reverse()", input.clone()), Some(json!([2, 1])));
+        assert_eq!(eval("$append(1, [2, 3])", input.clone()), Some(json!([1, 2, 3])));
+        assert!(eval("$millis()", input.clone()).unwrap().is_i64());
+
+        let expr = Expression::parse("$nope()").unwrap();
+        assert!(expr.evaluate(&Variant::Null, &Bindings::new()).is_err());
+        let expr = Expression::parse("$sum('a')").unwrap();
+        assert!(expr.evaluate(&Variant::Null, &Bindings::new()).is_err());
+    }
+
+    #[test]
+    fn it_should_read_the_bindings() {
+        let env = |name: &str| if name == "HOST" { Some(Variant::from("localhost")) } else { None };
+        let mut bindings = Bindings::new().bind("A", Variant::from(10)).with_env(&env);
+        let expr = Expression::parse("$A + $flowContext('count') & '@' & $env('HOST')").unwrap();
+        assert_eq!(
+            expr.context_keys(),
+            &[ContextKey { scope: ContextScope::Flow, key: "count".to_string(), store: None }]
+        );
+        bindings.set_context_value(&expr.context_keys()[0], Variant::from(5));
+        assert_eq!(expr.evaluate(&Variant::Null, &bindings).unwrap(), Some(Variant::from("15@localhost")));
+
+        assert!(Expression::parse("$flowContext(payload)").is_err());
+    }
+
+    #[test]
+    fn test_boolean_coercion() {
+        assert!(!boolean(&Variant::Null));
+        assert!(!boolean(&Variant::from(0)));
+        assert!(boolean(&Variant::from(-1)));
+        assert!(!boolean(&Variant::from("")));
+        assert!(boolean(&Variant::from("false")));
+        assert!(!boolean(&Variant::from(json!([0, "", false]))));
+        assert!(boolean(&Variant::from(json!([0, 1]))));
+        assert!(!boolean(&Variant::from(json!({}))));
+        assert!(boolean(&Variant::from(json!({"a": null}))));
+    }
+}
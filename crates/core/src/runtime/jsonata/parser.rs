@@ -0,0 +1,455 @@
+//! The tokenizer and the Pratt parser of the JSONata expressions.
+
+use super::*;
+
+/// The deepest nesting of the expressions, so an untrusted expression cannot overflow the stack.
+const MAX_NESTING_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Variant),
+    Str(String),
+    /// A name or a keyword like `and`, `true`.
+    Name(String),
+    /// A name quoted by backticks, never a keyword.
+    QuotedName(String),
+    /// `$name`, the empty name is the context `$` and `$` is the root `$$`.
+    Variable(String),
+    Regex(String, String),
+    Op(&'static str),
+    End,
+}
+
+const OPERATORS: &[&str] = &[
+    "..", ":=", "~>", "!=", "<=", ">=", ".", "[", "]", "{", "}", "(", ")", ",", ":", ";", "?", "+", "-", "*", "/", "%",
+    "&", "=", "<", ">",
+];
+
+fn syntax_error(position: usize, message: impl Into<String>) -> JsonataError {
+    JsonataError::Syntax { position, message: message.into() }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, JsonataError> {
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(pos, c)) = chars.peek() {
+        // A slash following an operand is the division, otherwise it starts a regular expression
+        let after_operand = matches!(
+            tokens.last(),
+            Some((
+                Token::Number(_)
+                    | Token::Str(_)
+                    | Token::Name(_)
+                    | Token::QuotedName(_)
+                    | Token::Variable(_)
+                    | Token::Op(")" | "]" | "}"),
+                _
+            ))
+        );
+        if c.is_whitespace() {
+            chars.next();
+        } else if source[pos..].starts_with("/*") {
+            let end = source[pos + 2..].find("*/").ok_or_else(|| syntax_error(pos, "Unterminated comment"))?;
+            let end = pos + 2 + end + 2;
+            while chars.peek().is_some_and(|&(i, _)| i < end) {
+                chars.next();
+            }
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, x)) if x == c => break,
+                    Some((i, '\\')) => match chars.next() {
+                        Some((_, 'n')) => s.push('\n'),
+                        Some((_, 't')) => s.push('\t'),
+                        Some((_, 'r')) => s.push('\r'),
+                        Some((_, 'b')) => s.push('\u{8}'),
+                        Some((_, 'f')) => s.push('\u{c}'),
+                        Some((_, 'u')) => {
+                            let hex: String = (0..4).filter_map(|_| chars.next().map(|x| x.1)).collect();
+                            let ch = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| syntax_error(i, "Invalid unicode escape"))?;
+                            s.push(ch);
+                        }
+                        Some((_, x)) => s.push(x),
+                        None => return Err(syntax_error(pos, "Unterminated string")),
+                    },
+                    Some((_, x)) => s.push(x),
+                    None => return Err(syntax_error(pos, "Unterminated string")),
+                }
+            }
+            tokens.push((Token::Str(s), pos));
+        } else if c == '`' {
+            chars.next();
+            let end = source[pos + 1..].find('`').ok_or_else(|| syntax_error(pos, "Unterminated quoted name"))?;
+            let name = &source[pos + 1..pos + 1 + end];
+            while chars.peek().is_some_and(|&(i, _)| i <= pos + 1 + end) {
+                chars.next();
+            }
+            tokens.push((Token::QuotedName(name.to_string()), pos));
+        } else if c.is_ascii_digit() {
+            let rest = &source[pos..];
+            let mut len = rest.find(|x: char| !x.is_ascii_digit()).unwrap_or(rest.len());
+            let mut is_integer = true;
+            // The `..` of the ranges is not a decimal point
+            if rest[len..].starts_with('.') && rest[len + 1..].starts_with(|x: char| x.is_ascii_digit()) {
+                is_integer = false;
+                len += 1;
+                len += rest[len..].find(|x: char| !x.is_ascii_digit()).unwrap_or(rest.len() - len);
+            }
+            if rest[len..].starts_with(['e', 'E']) {
+                let mut exp_len = 1;
+                if rest[len + 1..].starts_with(['+', '-']) {
+                    exp_len += 1;
+                }
+                let digits =
+                    rest[len + exp_len..].find(|x: char| !x.is_ascii_digit()).unwrap_or(rest.len() - len - exp_len);
+                if digits > 0 {
+                    is_integer = false;
+                    len += exp_len + digits;
+                }
+            }
+            let text = &rest[..len];
+            let number = match text.parse::<i64>() {
+                Ok(i) if is_integer => Variant::from(i),
+                _ => text
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|x| x.is_finite())
+                    .map(Variant::from)
+                    .ok_or_else(|| syntax_error(pos, format!("Invalid number '{}'", text)))?,
+            };
+            while chars.peek().is_some_and(|&(i, _)| i < pos + len) {
+                chars.next();
+            }
+            tokens.push((Token::Number(number), pos));
+        } else if c == '$' {
+            chars.next();
+            let rest = &source[pos + 1..];
+            let len = if rest.starts_with('$') {
+                1
+            } else {
+                rest.find(|x: char| !(x.is_alphanumeric() || x == '_')).unwrap_or(rest.len())
+            };
+            while chars.peek().is_some_and(|&(i, _)| i <= pos + len) {
+                chars.next();
+            }
+            tokens.push((Token::Variable(rest[..len].to_string()), pos));
+        } else if c == '/' && !after_operand {
+            chars.next();
+            let mut pattern = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '/')) => break,
+                    Some((_, '\\')) => {
+                        pattern.push('\\');
+                        match chars.next() {
+                            Some((_, x)) => pattern.push(x),
+                            None => return Err(syntax_error(pos, "Unterminated regular expression")),
+                        }
+                    }
+                    Some((_, x)) => pattern.push(x),
+                    None => return Err(syntax_error(pos, "Unterminated regular expression")),
+                }
+            }
+            let mut flags = String::new();
+            while let Some(&(_, x)) = chars.peek().filter(|x| x.1.is_ascii_alphabetic()) {
+                flags.push(x);
+                chars.next();
+            }
+            tokens.push((Token::Regex(pattern, flags), pos));
+        } else if c.is_alphabetic() || c == '_' {
+            let rest = &source[pos..];
+            let len = rest.find(|x: char| !(x.is_alphanumeric() || x == '_')).unwrap_or(rest.len());
+            while chars.peek().is_some_and(|&(i, _)| i < pos + len) {
+                chars.next();
+            }
+            tokens.push((Token::Name(rest[..len].to_string()), pos));
+        } else if let Some(op) = OPERATORS.iter().find(|x| source[pos..].starts_with(**x)) {
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push((Token::Op(op), pos));
+        } else {
+            return Err(syntax_error(pos, format!("Unexpected character '{}'", c)));
+        }
+    }
+    tokens.push((Token::End, source.len()));
+    Ok(tokens)
+}
+
+/// The binding power of the token following an operand, 0 if it cannot continue the expression.
+fn left_binding_power(token: &Token) -> u8 {
+    match token {
+        Token::Op(".") => 75,
+        Token::Op("[" | "(") => 80,
+        Token::Op("*" | "/" | "%") => 60,
+        Token::Op("+" | "-" | "&") => 50,
+        Token::Op("=" | "!=" | "<" | "<=" | ">" | ">=" | "~>") => 40,
+        Token::Name(x) if x == "in" => 40,
+        Token::Name(x) if x == "and" => 30,
+        Token::Name(x) if x == "or" => 25,
+        Token::Op("?") => 20,
+        Token::Op(":=") => 10,
+        _ => 0,
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<(), JsonataError> {
+        match self.next() {
+            (Token::Op(x), _) if x == op => Ok(()),
+            (token, pos) => Err(syntax_error(pos, format!("Expected '{}', got {:?}", op, token))),
+        }
+    }
+
+    /// Parses the items separated by commas until the closing `op`.
+    fn list(&mut self, close: &'static str) -> Result<Vec<Node>, JsonataError> {
+        let mut items = Vec::new();
+        if self.peek() != &Token::Op(close) {
+            loop {
+                items.push(self.expression(0)?);
+                if self.peek() != &Token::Op(",") {
+                    break;
+                }
+                self.next();
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+
+    fn expression(&mut self, rbp: u8) -> Result<Node, JsonataError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(syntax_error(self.position(), "The expression is nested too deeply"));
+        }
+        let token = self.next();
+        let mut left = self.prefix(token)?;
+        while rbp < left_binding_power(self.peek()) {
+            let token = self.next();
+            left = self.infix(token, left)?;
+        }
+        self.depth -= 1;
+        Ok(left)
+    }
+
+    fn prefix(&mut self, (token, pos): (Token, usize)) -> Result<Node, JsonataError> {
+        let node = match token {
+            Token::Number(n) => Node::Literal(n),
+            Token::Str(s) => Node::Literal(Variant::String(s)),
+            Token::Regex(pattern, flags) => {
+                let re = Variant::regex_from(&pattern, &flags).map_err(|e| syntax_error(pos, format!("{:#}", e)))?;
+                Node::Literal(re)
+            }
+            Token::Name(name) => match name.as_str() {
+                "true" => Node::Literal(Variant::Bool(true)),
+                "false" => Node::Literal(Variant::Bool(false)),
+                "null" => Node::Literal(Variant::Null),
+                _ => Node::Path(vec![Step::new(Node::Name(name))]),
+            },
+            Token::QuotedName(name) => Node::Path(vec![Step::new(Node::Name(name))]),
+            Token::Variable(name) => Node::Variable(name),
+            Token::Op("*") => Node::Path(vec![Step::new(Node::Wildcard)]),
+            Token::Op("-") => Node::Negate(Box::new(self.expression(70)?)),
+            Token::Op("(") => {
+                let mut items = Vec::new();
+                while self.peek() != &Token::Op(")") {
+                    items.push(self.expression(0)?);
+                    if self.peek() != &Token::Op(";") {
+                        break;
+                    }
+                    self.next();
+                }
+                self.expect(")")?;
+                Node::Block(items)
+            }
+            Token::Op("[") => {
+                let mut items = Vec::new();
+                if self.peek() != &Token::Op("]") {
+                    loop {
+                        let item = self.expression(0)?;
+                        if self.peek() == &Token::Op("..") {
+                            self.next();
+                            let end = self.expression(0)?;
+                            items.push(Node::Range(Box::new(item), Box::new(end)));
+                        } else {
+                            items.push(item);
+                        }
+                        if self.peek() != &Token::Op(",") {
+                            break;
+                        }
+                        self.next();
+                    }
+                }
+                self.expect("]")?;
+                Node::Array(items)
+            }
+            Token::Op("{") => {
+                let mut pairs = Vec::new();
+                if self.peek() != &Token::Op("}") {
+                    loop {
+                        let key = self.expression(0)?;
+                        self.expect(":")?;
+                        let value = self.expression(0)?;
+                        pairs.push((key, value));
+                        if self.peek() != &Token::Op(",") {
+                            break;
+                        }
+                        self.next();
+                    }
+                }
+                self.expect("}")?;
+                Node::Object(pairs)
+            }
+            token => return Err(syntax_error(pos, format!("Unexpected token {:?}", token))),
+        };
+        Ok(node)
+    }
+
+    fn infix(&mut self, (token, pos): (Token, usize), left: Node) -> Result<Node, JsonataError> {
+        let node = match token {
+            Token::Op(".") => {
+                let right = self.expression(75)?;
+                let mut steps = match left {
+                    Node::Path(steps) => steps,
+                    other => vec![Step::new(other)],
+                };
+                match right {
+                    Node::Path(right_steps) => steps.extend(right_steps),
+                    other => steps.push(Step::new(other)),
+                }
+                Node::Path(steps)
+            }
+            Token::Op("[") => {
+                if self.peek() == &Token::Op("]") {
+                    return Err(syntax_error(pos, "The empty predicate `[]` is not supported"));
+                }
+                let predicate = self.expression(0)?;
+                self.expect("]")?;
+                let mut steps = match left {
+                    Node::Path(steps) => steps,
+                    other => vec![Step::new(other)],
+                };
+                steps.last_mut().expect("a path has steps").predicates.push(predicate);
+                Node::Path(steps)
+            }
+            Token::Op("(") => match left {
+                Node::Variable(name) => Node::Call(name, self.list(")")?),
+                _ => return Err(syntax_error(pos, "Only the functions like `$sum()` can be called")),
+            },
+            Token::Op("?") => {
+                let then = self.expression(0)?;
+                let otherwise = if self.peek() == &Token::Op(":") {
+                    self.next();
+                    Some(Box::new(self.expression(0)?))
+                } else {
+                    None
+                };
+                Node::Condition(Box::new(left), Box::new(then), otherwise)
+            }
+            Token::Op("~>") => match self.expression(40)? {
+                Node::Call(name, mut args) => {
+                    args.insert(0, left);
+                    Node::Call(name, args)
+                }
+                _ => return Err(syntax_error(pos, "The right side of `~>` must be a function call")),
+            },
+            Token::Op(":=") => match left {
+                Node::Variable(name) if !name.is_empty() && name != "$" => {
+                    Node::Bind(name, Box::new(self.expression(9)?))
+                }
+                _ => return Err(syntax_error(pos, "The left side of `:=` must be a variable")),
+            },
+            Token::Op(op) => {
+                let op = BinaryOp::of(op).ok_or_else(|| syntax_error(pos, format!("Unexpected operator '{}'", op)))?;
+                let right = self.expression(op.binding_power())?;
+                Node::Binary(op, Box::new(left), Box::new(right))
+            }
+            Token::Name(name) => {
+                let op = BinaryOp::of(&name).ok_or_else(|| syntax_error(pos, format!("Unexpected name '{}'", name)))?;
+                let right = self.expression(op.binding_power())?;
+                Node::Binary(op, Box::new(left), Box::new(right))
+            }
+            token => return Err(syntax_error(pos, format!("Unexpected token {:?}", token))),
+        };
+        Ok(node)
+    }
+}
+
+pub(super) fn parse(source: &str) -> Result<Node, JsonataError> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0, depth: 0 };
+    if parser.peek() == &Token::End {
+        return Err(syntax_error(0, "The expression is empty"));
+    }
+    let node = parser.expression(0)?;
+    match parser.next() {
+        (Token::End, _) => Ok(node),
+        (token, pos) => Err(syntax_error(pos, format!("Unexpected token {:?}", token))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_paths_and_operators() {
+        let node = parse("payload.items[0].price * 2").unwrap();
+        match node {
+            Node::Binary(BinaryOp::Mul, left, right) => {
+                match *left {
+                    Node::Path(ref steps) => {
+                        assert_eq!(steps.len(), 3);
+                        assert_eq!(steps[1].predicates.len(), 1);
+                    }
+                    ref other => panic!("Not a path: {:?}", other),
+                }
+                assert!(matches!(*right, Node::Literal(Variant::Number(_))));
+            }
+            other => panic!("Not a multiplication: {:?}", other),
+        }
+
+        assert!(
+            matches!(parse("$sum(payload) ~> $string()").unwrap(), Node::Call(ref x, ref args) if x == "string" && args.len() == 1)
+        );
+        assert!(matches!(parse("a and b or c").unwrap(), Node::Binary(BinaryOp::Or, _, _)));
+        assert!(matches!(parse("[1..3]").unwrap(), Node::Array(ref x) if matches!(x[0], Node::Range(_, _))));
+        assert!(matches!(parse("a / 2").unwrap(), Node::Binary(BinaryOp::Div, _, _)));
+        assert!(matches!(parse("/ab+c/i").unwrap(), Node::Literal(Variant::Regexp(_))));
+    }
+
+    #[test]
+    fn it_should_reject_the_bad_syntax() {
+        for source in ["", "a.", "(a", "[1, 2", "{\"a\" 1}", "'abc", "a b", "1 +", "$x := ", "a ? ", "`a", "#"] {
+            assert!(parse(source).is_err(), "{}", source);
+        }
+        let deep = format!("{}1{}", "(".repeat(1000), ")".repeat(1000));
+        assert!(parse(&deep).is_err());
+    }
+}
@@ -4,10 +4,15 @@ pub mod env;
 pub mod eval;
 pub mod flow;
 pub mod group;
+pub mod jsonata;
 pub mod model;
 pub mod nodes;
 pub mod registry;
 pub mod subflow;
+pub mod validation;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[cfg(feature = "js")]
 pub mod js;
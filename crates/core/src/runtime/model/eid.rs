@@ -2,6 +2,7 @@ use std::fmt;
 use std::hash::Hash;
 use std::ops::BitXor;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::utils;
 use crate::*;
@@ -30,13 +31,32 @@ impl Default for ElementId {
 }
 
 impl FromStr for ElementId {
-    type Err = std::num::ParseIntError;
+    type Err = ParseElementIdError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(ElementId(u64::from_str_radix(s, 16)?))
+        Self::from_hex(s)
     }
 }
 
+/// The error of parsing an invalid `ElementId`, see `ElementId::from_hex()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseElementIdError {
+    id: String,
+}
+
+impl fmt::Display for ParseElementIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid element ID '{}': expected up to 16 hex digits like 'a1b2c3d4e5f60718', or the legacy form like \
+             'a1b2c3d4.e5f607'",
+            self.id
+        )
+    }
+}
+
+impl std::error::Error for ParseElementIdError {}
+
 impl From<u64> for ElementId {
     fn from(value: u64) -> Self {
         ElementId(value)
@@ -66,6 +86,29 @@ impl ElementId {
         Self(id)
     }
 
+    /// Parses the ID of Node-RED, the canonical form is 16 hex digits like `a1b2c3d4e5f60718`.
+    ///
+    /// The shorter IDs are zero-extended, and the legacy IDs like `a1b2c3d4.e5f607` generated by Node-RED before 1.0
+    /// are accepted by dropping the dot.
+    pub fn from_hex(s: &str) -> Result<Self, ParseElementIdError> {
+        let err = || ParseElementIdError { id: s.to_string() };
+        let digits = match s.split_once('.') {
+            Some((high, low)) if !high.is_empty() && !low.is_empty() => [high, low],
+            Some(_) => return Err(err()),
+            None => [s, ""],
+        };
+        let len = digits[0].len() + digits[1].len();
+        if len == 0 || len > 16 || !digits.iter().all(|x| x.bytes().all(|c| c.is_ascii_hexdigit())) {
+            return Err(err());
+        }
+        u64::from_str_radix(&digits.concat(), 16).map(ElementId).map_err(|_| err())
+    }
+
+    /// Formats the ID in the canonical form of Node-RED, the same as `Display`.
+    pub fn to_hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+
     pub fn to_chars(&self) -> [char; 16] {
         let hex_string = format!("{:016x}", self.0); // 格式化为16位十六进制字符串
         let mut char_array = ['0'; 16]; // 初始化一个字符数组
@@ -86,6 +129,50 @@ impl ElementId {
     }
 }
 
+/// Generates the IDs of the messages, see `Msg::generate_id()`.
+pub trait IdGenerator: Send + Sync + fmt::Debug {
+    fn next_id(&self) -> ElementId;
+}
+
+/// The default generator of the unpredictable IDs, the same as `ElementId::new()`.
+#[derive(Debug, Default)]
+pub struct RandomIdGenerator;
+
+impl IdGenerator for RandomIdGenerator {
+    fn next_id(&self) -> ElementId {
+        ElementId::new()
+    }
+}
+
+/// Generates a reproducible sequence of IDs from the seed, for the snapshot tests.
+#[derive(Debug)]
+pub struct SeededIdGenerator {
+    state: AtomicU64,
+}
+
+impl SeededIdGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { state: AtomicU64::new(seed) }
+    }
+}
+
+impl IdGenerator for SeededIdGenerator {
+    fn next_id(&self) -> ElementId {
+        // SplitMix64
+        const GAMMA: u64 = 0x9e3779b97f4a7c15;
+        loop {
+            let mut z = self.state.fetch_add(GAMMA, Ordering::Relaxed).wrapping_add(GAMMA);
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            // The empty ID is reserved
+            if z != 0 {
+                return ElementId(z);
+            }
+        }
+    }
+}
+
 impl serde::Serialize for ElementId {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -109,7 +196,7 @@ impl<'de> serde::de::Visitor<'de> for ElementIdVisitor {
     where
         E: serde::de::Error,
     {
-        ElementId::from_str(value).map_err(|_| E::custom("failed to parse ElementId"))
+        ElementId::from_hex(value).map_err(E::custom)
     }
 }
 
@@ -121,3 +208,41 @@ impl<'de> serde::Deserialize<'de> for ElementId {
         deserializer.deserialize_str(ElementIdVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_it_should_round_trip_node_red_ids() {
+        for id in ["a1b2c3d4e5f60718", "4e9b7a0d5c3f2e1a", "0000000000000001", "ffffffffffffffff", "8f3a2c1d0e9b7a65"] {
+            let eid = ElementId::from_hex(id).unwrap();
+            assert_eq!(eid.to_hex(), id);
+            assert_eq!(eid.to_string(), id);
+            assert_eq!(serde_json::to_value(eid).unwrap(), serde_json::json!(id));
+            assert_eq!(serde_json::from_value::<ElementId>(serde_json::json!(id)).unwrap(), eid);
+        }
+        assert_eq!(ElementId::from_hex("A1B2C3D4E5F60718").unwrap().to_hex(), "a1b2c3d4e5f60718");
+        assert_eq!(ElementId::from_hex("100").unwrap(), ElementId::from(0x100));
+    }
+
+    #[test]
+    fn test_it_should_parse_legacy_ids() {
+        assert_eq!(ElementId::from_hex("a1b2c3d4.e5f607").unwrap().to_hex(), "00a1b2c3d4e5f607");
+        assert_eq!(ElementId::from_hex("1.2").unwrap(), ElementId::from(0x12));
+    }
+
+    #[test]
+    fn test_it_should_reject_invalid_ids() {
+        for id in ["", "xyz", "+1", "-1", "a1b2c3d4e5f607189", "a1b2c3d4.", ".e5f607", "a.b.c", "a1b2 c3d4"] {
+            let err = ElementId::from_hex(id).unwrap_err();
+            assert!(err.to_string().starts_with(&format!("Invalid element ID '{}'", id)), "{}", err);
+        }
+        let err = serde_json::from_value::<ElementId>(serde_json::json!("not-an-id")).unwrap_err();
+        assert!(err.to_string().contains("Invalid element ID 'not-an-id'"));
+
+        let flows = serde_json::json!([{"id": "a1b2c3d4e5f6071g", "type": "tab"}]);
+        let err = crate::runtime::model::json::deser::load_flows_json_value(flows).unwrap_err();
+        assert!(err.to_string().contains("Invalid element ID 'a1b2c3d4e5f6071g'"), "{}", err);
+    }
+}
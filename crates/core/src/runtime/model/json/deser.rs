@@ -9,12 +9,15 @@ use serde::Deserializer;
 use serde_json::Map as JsonMap;
 use serde_json::Value as JsonValue;
 
-use crate::runtime::model::ElementId;
+use crate::runtime::model::{ElementId, IdGenerator, SeededIdGenerator};
 use crate::text::json::{option_value_equals_str, EMPTY_ARRAY};
 use crate::EdgelinkError;
 
 use super::*;
 
+// The IDs are only parsed by the helpers, re-exported for the existing users of this module
+pub use super::helpers::{parse_red_id_str, parse_red_id_value};
+
 pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows> {
     let mut preprocessed = preprocess_subflows(root_jv)?;
     preprocess_merge_subflow_env(&mut preprocessed)?;
@@ -33,6 +36,10 @@ pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows>
 
     for jobject in all_values.iter() {
         if let Some(obj) = jobject.as_object() {
+            // An ID in a wrong format is an error, rather than an element silently missing
+            if let Some(JsonValue::String(id)) = obj.get("id") {
+                ElementId::from_hex(id).map_err(|e| EdgelinkError::BadFlowsJson(e.to_string()))?;
+            }
             if let (Some(ele_id), Some(type_value)) = (
                 obj.get("id").and_then(parse_red_id_value),
                 obj.get("type").and_then(|x| x.as_str()).map(|x| parse_red_type_value(x)),
@@ -88,7 +95,8 @@ pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows>
                             flow_nodes.insert(ele_id, jobject.clone());
                         }
                         None => {
-                            let global_config: RedGlobalNodeConfig = serde_json::from_value(jobject.clone())?;
+                            let mut global_config: RedGlobalNodeConfig = serde_json::from_value(jobject.clone())?;
+                            global_config.ordering = global_nodes.len();
                             global_nodes.push(global_config);
                         }
                     },
@@ -207,132 +215,155 @@ fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<JsonValue> {
     }
 
     let mut new_elements = Vec::new();
-    let mut id_map: HashMap<String, String> = HashMap::new();
+
+    // The children of every subflow instance get their own IDs, so the wires and the `scope` of
+    // `catch`/`complete`/`status` nodes must be remapped per instance, or sibling instances would be mixed up.
+    let mut global_id_map: HashMap<String, String> = HashMap::new();
+    let mut instance_elements = Vec::with_capacity(subflow_packs.len());
 
     for pack in subflow_packs.iter() {
-        let subflow_new_id = ElementId::new();
+        // Derived from the instance, so the children keep their IDs, and thus their contexts, across the restarts
+        let instance_id = pack.instance["id"]
+            .as_str()
+            .and_then(parse_red_id_str)
+            .ok_or(EdgelinkError::BadFlowsJson(format!("Bad ID of the subflow instance: {}", pack.instance["id"])))?;
+        let subflow_new_id = SeededIdGenerator::new(instance_id.into()).next_id();
+        let mut elements = Vec::with_capacity(pack.children.len() + 1);
+        let mut id_map: HashMap<String, String> = HashMap::new();
 
         // "subflow" element
         {
             let mut new_subflow = pack.subflow.clone();
             new_subflow["id"] = JsonValue::String(subflow_new_id.to_string());
-            id_map.insert(pack.subflow_id.to_string(), new_subflow["id"].as_str().unwrap().to_string());
-            new_elements.push(new_subflow);
+            id_map.insert(pack.subflow_id.to_string(), subflow_new_id.to_string());
+            elements.push(new_subflow);
         }
 
         // the fixed subflow instance node
-        {
-            let mut new_instance = pack.instance.clone();
-            new_instance["type"] = JsonValue::String(format!("subflow:{}", subflow_new_id));
-            new_elements.push(new_instance);
-        }
+        let mut new_instance = pack.instance.clone();
+        new_instance["type"] = JsonValue::String(format!("subflow:{}", subflow_new_id));
 
         // The children elements in the subflow
         for old_child in pack.children.iter() {
             let mut new_child = (*old_child).clone();
             new_child["id"] = generate_new_xored_id_value(subflow_new_id, old_child["id"].as_str().unwrap())?;
             id_map.insert(old_child["id"].as_str().unwrap().to_string(), new_child["id"].as_str().unwrap().to_string());
-            new_elements.push(new_child);
+            elements.push(new_child);
         }
+
+        global_id_map.extend(id_map.iter().map(|(k, v)| (k.clone(), v.clone())));
+        instance_elements.push((id_map, new_instance, elements));
     }
 
     // Remap all known properties of the new elements
-    for node in new_elements.iter_mut() {
-        let node = node.as_object_mut().unwrap();
+    let global_lookup = |id: &str| global_id_map.get(id).cloned();
+    for (id_map, mut new_instance, elements) in instance_elements.into_iter() {
+        // The instance node lives in the parent flow, so it cannot be remapped by its own instance
+        remap_element_ids(&mut new_instance, &global_lookup);
+        new_elements.push(new_instance);
 
-        if let Some(JsonValue::String(pvalue)) = node.get_mut("z") {
-            if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                *pvalue = new_id.to_string();
-            }
+        let lookup = |id: &str| id_map.get(id).or_else(|| global_id_map.get(id)).cloned();
+        for mut node in elements.into_iter() {
+            remap_element_ids(&mut node, &lookup);
+            new_elements.push(node);
         }
+    }
 
-        if let Some(JsonValue::String(pvalue)) = node.get_mut("g") {
-            if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                *pvalue = new_id.to_string();
-            }
+    new_elements.extend(elements.iter().filter(|x| !elements_to_delete.contains(x)).cloned());
+
+    Ok(JsonValue::Array(new_elements))
+}
+
+fn remap_element_ids(node: &mut JsonValue, lookup: &impl Fn(&str) -> Option<String>) {
+    let node = node.as_object_mut().unwrap();
+
+    if let Some(JsonValue::String(pvalue)) = node.get_mut("z") {
+        if let Some(new_id) = lookup(pvalue.as_str()) {
+            *pvalue = new_id.to_string();
         }
+    }
 
-        // Replace the nested flow instance `type` property
-        if let Some(JsonValue::String(pvalue)) = node.get_mut("type") {
-            if let Some(("subflow", old_id)) = pvalue.split_once(':') {
-                if let Some(new_id) = id_map.get(old_id) {
-                    *pvalue = format!("subflow:{}", new_id);
-                }
-            }
+    if let Some(JsonValue::String(pvalue)) = node.get_mut("g") {
+        if let Some(new_id) = lookup(pvalue.as_str()) {
+            *pvalue = new_id.to_string();
         }
+    }
 
-        // Node with `wires` property
-        if let Some(wires) = node.get_mut("wires").and_then(|x| x.as_array_mut()) {
-            for wire in wires {
-                let wire = wire.as_array_mut().unwrap();
-                for id in wire {
-                    if let JsonValue::String(pvalue) = id {
-                        if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                            *pvalue = new_id.to_string();
-                        }
-                    }
-                }
+    // Replace the nested flow instance `type` property
+    if let Some(JsonValue::String(pvalue)) = node.get_mut("type") {
+        if let Some(("subflow", old_id)) = pvalue.split_once(':') {
+            if let Some(new_id) = lookup(old_id) {
+                *pvalue = format!("subflow:{}", new_id);
             }
         }
+    }
 
-        // Node with `scope` property
-        // TODO CHECK TYPE: complete/catch/status
-        if let Some(scope) = node.get_mut("scope").and_then(|x| x.as_array_mut()) {
-            for id in scope {
+    // Node with `wires` property
+    if let Some(wires) = node.get_mut("wires").and_then(|x| x.as_array_mut()) {
+        for wire in wires {
+            let wire = wire.as_array_mut().unwrap();
+            for id in wire {
                 if let JsonValue::String(pvalue) = id {
-                    if let Some(new_id) = id_map.get(pvalue.as_str()) {
+                    if let Some(new_id) = lookup(pvalue.as_str()) {
                         *pvalue = new_id.to_string();
                     }
                 }
             }
         }
+    }
 
-        // Node with `links` property
-        if let Some(links) = node.get_mut("links").and_then(|x| x.as_array_mut()) {
-            for id in links {
-                if let JsonValue::String(pvalue) = id {
-                    if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                        *pvalue = new_id.to_string();
-                    }
+    // Node with `scope` property
+    // TODO CHECK TYPE: complete/catch/status
+    if let Some(scope) = node.get_mut("scope").and_then(|x| x.as_array_mut()) {
+        for id in scope {
+            if let JsonValue::String(pvalue) = id {
+                if let Some(new_id) = lookup(pvalue.as_str()) {
+                    *pvalue = new_id.to_string();
                 }
             }
         }
+    }
 
-        // Replace the `in` property
-        if let Some(JsonValue::Array(in_props)) = node.get_mut("in") {
-            for in_item in in_props.iter_mut() {
-                for wires_item in in_item["wires"].as_array_mut().unwrap().iter_mut() {
-                    if let Some(JsonValue::String(pvalue)) = wires_item.get_mut("id") {
-                        if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                            *pvalue = new_id.to_string();
-                        }
-                    }
+    // Node with `links` property
+    if let Some(links) = node.get_mut("links").and_then(|x| x.as_array_mut()) {
+        for id in links {
+            if let JsonValue::String(pvalue) = id {
+                if let Some(new_id) = lookup(pvalue.as_str()) {
+                    *pvalue = new_id.to_string();
                 }
             }
         }
+    }
 
-        // Replace the `out` property
-        if let Some(JsonValue::Array(out_props)) = node.get_mut("out") {
-            for out_item in out_props.iter_mut() {
-                for wires_item in out_item["wires"].as_array_mut().unwrap().iter_mut() {
-                    if let Some(JsonValue::String(pvalue)) = wires_item.get_mut("id") {
-                        if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                            *pvalue = new_id.to_string();
-                        }
+    // Replace the `in` property
+    if let Some(JsonValue::Array(in_props)) = node.get_mut("in") {
+        for in_item in in_props.iter_mut() {
+            for wires_item in in_item["wires"].as_array_mut().unwrap().iter_mut() {
+                if let Some(JsonValue::String(pvalue)) = wires_item.get_mut("id") {
+                    if let Some(new_id) = lookup(pvalue.as_str()) {
+                        *pvalue = new_id.to_string();
                     }
                 }
             }
         }
     }
 
-    new_elements.extend(elements.iter().filter(|x| !elements_to_delete.contains(x)).cloned());
-
-    Ok(JsonValue::Array(new_elements))
+    // Replace the `out` property
+    if let Some(JsonValue::Array(out_props)) = node.get_mut("out") {
+        for out_item in out_props.iter_mut() {
+            for wires_item in out_item["wires"].as_array_mut().unwrap().iter_mut() {
+                if let Some(JsonValue::String(pvalue)) = wires_item.get_mut("id") {
+                    if let Some(new_id) = lookup(pvalue.as_str()) {
+                        *pvalue = new_id.to_string();
+                    }
+                }
+            }
+        }
+    }
 }
 
 fn generate_new_xored_id_value(subflow_id: ElementId, old_id: &str) -> crate::Result<JsonValue> {
-    let old_id =
-        parse_red_id_str(old_id).ok_or(EdgelinkError::BadFlowsJson(format!("Cannot parse id: '{}'", old_id)))?;
+    let old_id = ElementId::from_hex(old_id).map_err(|e| EdgelinkError::BadFlowsJson(e.to_string()))?;
     Ok(JsonValue::String((subflow_id ^ old_id).to_string()))
 }
 
@@ -343,14 +374,6 @@ pub fn parse_red_type_value(t: &str) -> RedTypeValue {
     }
 }
 
-pub fn parse_red_id_str(id_str: &str) -> Option<ElementId> {
-    id_str.parse().ok()
-}
-
-pub fn parse_red_id_value(id_value: &serde_json::Value) -> Option<ElementId> {
-    id_value.as_str().and_then(|s| s.parse().ok())
-}
-
 pub trait RedFlowJsonObject {
     fn get_flow_dependencies(&self, elements: &[JsonValue]) -> HashSet<ElementId>;
     fn get_subflow_dependencies(&self, elements: &[JsonValue]) -> HashSet<ElementId>;
@@ -552,154 +575,127 @@ impl RedPropertyType {
     }
 }
 
-pub fn str_to_option_u64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+// The lenient helpers of the node configs, Node-RED stores many numbers and booleans as strings.
+// Every one of them treats `null` and an empty (or blank) string as a missing value.
+
+/// Parses a number, or a string of a number, returns `None` for `null` and blank strings.
+fn lenient_parse<T, E>(value: JsonValue, expected: &'static str) -> Result<Option<T>, E>
 where
-    D: Deserializer<'de>,
+    T: std::str::FromStr,
+    E: de::Error,
 {
-    let value: Option<String> = Option::deserialize(deserializer)?;
     match value {
-        Some(s) => {
-            if s.is_empty() {
-                Ok(None)
-            } else {
-                s.parse::<u64>()
-                    .map(Some)
-                    .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&s), &"An invalid u64"))
-            }
-        }
-        None => Ok(None),
+        JsonValue::Null => Ok(None),
+        JsonValue::Number(n) => n
+            .to_string()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Other(&n.to_string()), &expected)),
+        JsonValue::String(s) if s.trim().is_empty() => Ok(None),
+        JsonValue::String(s) => {
+            s.trim().parse::<T>().map(Some).map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&s), &expected))
+        }
+        other => Err(de::Error::invalid_type(unexpected_json(&other), &expected)),
     }
 }
 
-pub fn str_to_option_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+fn unexpected_json(value: &JsonValue) -> de::Unexpected<'_> {
+    match value {
+        JsonValue::Null => de::Unexpected::Unit,
+        JsonValue::Bool(b) => de::Unexpected::Bool(*b),
+        JsonValue::Number(_) => de::Unexpected::Other("number"),
+        JsonValue::String(s) => de::Unexpected::Str(s),
+        JsonValue::Array(_) => de::Unexpected::Seq,
+        JsonValue::Object(_) => de::Unexpected::Map,
+    }
+}
+
+pub fn str_to_option_u64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    struct F64Visitor;
-
-    impl<'de> de::Visitor<'de> for F64Visitor {
-        type Value = Option<f64>;
-
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("a float, a string containing a float, or an empty string")
-        }
-
-        fn visit_f64<E>(self, value: f64) -> Result<Option<f64>, E>
-        where
-            E: de::Error,
-        {
-            Ok(Some(value))
-        }
-
-        fn visit_str<E>(self, value: &str) -> Result<Option<f64>, E>
-        where
-            E: de::Error,
-        {
-            if value.trim().is_empty() {
-                Ok(None)
-            } else {
-                value.parse::<f64>().map(Some).map_err(de::Error::custom)
-            }
-        }
-
-        fn visit_string<E>(self, value: String) -> Result<Option<f64>, E>
-        where
-            E: de::Error,
-        {
-            self.visit_str(&value)
-        }
-
-        fn visit_u64<E>(self, value: u64) -> Result<Option<f64>, E>
-        where
-            E: de::Error,
-        {
-            Ok(Some(value as f64))
-        }
+    lenient_parse(JsonValue::deserialize(deserializer)?, "an u64 or a string of it")
+}
 
-        fn visit_i64<E>(self, value: i64) -> Result<Option<f64>, E>
-        where
-            E: de::Error,
-        {
-            Ok(Some(value as f64))
-        }
-    }
+pub fn str_to_option_u16<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    lenient_parse(JsonValue::deserialize(deserializer)?, "an u16 or a string of it")
+}
 
-    deserializer.deserialize_any(F64Visitor)
+pub fn str_to_option_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    lenient_parse(JsonValue::deserialize(deserializer)?, "a float, a string containing a float, or an empty string")
 }
 
+/// A missing value is `NaN`, e.g. the bounds of the `range` node.
 pub fn deser_f64_or_string_nan<'de, D>(deserializer: D) -> Result<f64, D::Error>
 where
     D: Deserializer<'de>,
 {
-    struct F64Visitor;
-
-    impl<'de> de::Visitor<'de> for F64Visitor {
-        type Value = f64;
-
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("a float, a string containing a float, or an empty string")
-        }
-
-        fn visit_f64<E>(self, value: f64) -> Result<f64, E>
-        where
-            E: de::Error,
-        {
-            Ok(value)
-        }
-
-        fn visit_str<E>(self, value: &str) -> Result<f64, E>
-        where
-            E: de::Error,
-        {
-            if value.trim().is_empty() {
-                Ok(f64::NAN)
-            } else {
-                value.parse::<f64>().map_err(de::Error::custom)
-            }
-        }
+    Ok(str_to_option_f64(deserializer)?.unwrap_or(f64::NAN))
+}
 
-        fn visit_string<E>(self, value: String) -> Result<f64, E>
-        where
-            E: de::Error,
-        {
-            self.visit_str(&value)
-        }
+/// Parses a boolean, or the string `"true"` or `"false"`.
+pub fn str_to_option_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::Bool(b) => Ok(Some(b)),
+        JsonValue::String(s) if s.trim().is_empty() => Ok(None),
+        JsonValue::String(s) => match s.trim() {
+            "true" => Ok(Some(true)),
+            "false" => Ok(Some(false)),
+            _ => Err(de::Error::invalid_value(de::Unexpected::Str(&s), &"a boolean or a string of it")),
+        },
+        JsonValue::Null => Ok(None),
+        other => Err(de::Error::invalid_type(unexpected_json(&other), &"a boolean or a string of it")),
+    }
+}
 
-        fn visit_u64<E>(self, value: u64) -> Result<f64, E>
-        where
-            E: de::Error,
-        {
-            Ok(value as f64)
-        }
+/// Like `str_to_option_bool()`, but a missing value is `false`, e.g. the `checkall` of the switch node.
+pub fn deser_bool_or_string<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(str_to_option_bool(deserializer)?.unwrap_or(false))
+}
 
-        fn visit_i64<E>(self, value: i64) -> Result<f64, E>
-        where
-            E: de::Error,
-        {
-            Ok(value as f64)
-        }
+/// Keeps the numbers and booleans as their text, a missing value is an empty string.
+pub fn deser_string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::String(s) => Ok(s),
+        JsonValue::Number(n) => Ok(n.to_string()),
+        JsonValue::Bool(b) => Ok(b.to_string()),
+        JsonValue::Null => Ok(String::new()),
+        other => Err(de::Error::invalid_type(unexpected_json(&other), &"a string or a number")),
     }
+}
 
-    deserializer.deserialize_any(F64Visitor)
+/// Like Node-RED, a missing or bad count is `0`.
+pub fn deser_usize_or_zero<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = JsonValue::deserialize(deserializer)?;
+    Ok(lenient_parse::<u64, D::Error>(value, "an integer").ok().flatten().unwrap_or(0) as usize)
 }
 
-pub fn str_to_option_u16<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+/// Like Node-RED, a missing, bad or zero length is `1`.
+pub fn deser_usize_lossy<'de, D>(deserializer: D) -> Result<usize, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let value: Option<String> = Option::deserialize(deserializer)?;
-    match value {
-        Some(s) => {
-            if s.is_empty() {
-                Ok(None)
-            } else {
-                s.parse::<u16>()
-                    .map(Some)
-                    .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&s), &"An invalid u16"))
-            }
-        }
-        None => Ok(None),
-    }
+    let value = JsonValue::deserialize(deserializer)?;
+    let n = lenient_parse::<u64, D::Error>(value, "an integer").ok().flatten();
+    Ok(n.filter(|x| *x > 0).map(|x| x as usize).unwrap_or(1))
 }
 
 pub fn str_to_ipaddr<'de, D>(deserializer: D) -> Result<Option<IpAddr>, D::Error>
@@ -778,3 +774,80 @@ fn merge_env(target_envs: &mut JsonValue, ref_envs: &JsonValue) -> crate::Result
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct LenientConfig {
+        #[serde(default, deserialize_with = "str_to_option_u64")]
+        count: Option<u64>,
+
+        #[serde(default, deserialize_with = "str_to_option_u16")]
+        port: Option<u16>,
+
+        #[serde(default, deserialize_with = "str_to_option_f64")]
+        timeout: Option<f64>,
+
+        #[serde(default, deserialize_with = "str_to_option_bool")]
+        enabled: Option<bool>,
+
+        #[serde(default, deserialize_with = "deser_bool_or_string")]
+        checkall: bool,
+
+        #[serde(default, deserialize_with = "deser_usize_or_zero")]
+        size: usize,
+    }
+
+    #[test]
+    fn test_lenient_config_should_parse_string_encoded_values() {
+        let config = LenientConfig::deserialize(json!({
+            "count": "42",
+            "port": " 1880 ",
+            "timeout": "0.5",
+            "enabled": "true",
+            "checkall": "false",
+            "size": "3"
+        }))
+        .unwrap();
+        assert_eq!(config.count, Some(42));
+        assert_eq!(config.port, Some(1880));
+        assert_eq!(config.timeout, Some(0.5));
+        assert_eq!(config.enabled, Some(true));
+        assert!(!config.checkall);
+        assert_eq!(config.size, 3);
+
+        let config = LenientConfig::deserialize(json!({
+            "count": 42, "port": 1880, "timeout": 2, "enabled": false, "checkall": true, "size": 3
+        }))
+        .unwrap();
+        assert_eq!((config.count, config.port, config.timeout), (Some(42), Some(1880), Some(2.0)));
+        assert_eq!((config.enabled, config.checkall, config.size), (Some(false), true, 3));
+    }
+
+    #[test]
+    fn test_lenient_config_should_treat_empty_strings_as_missing() {
+        let config = LenientConfig::deserialize(json!({
+            "count": "", "port": " ", "timeout": "", "enabled": "", "checkall": "", "size": ""
+        }))
+        .unwrap();
+        assert_eq!((config.count, config.port, config.timeout, config.enabled), (None, None, None, None));
+        assert!(!config.checkall);
+        assert_eq!(config.size, 0);
+
+        let config = LenientConfig::deserialize(json!({"count": null, "timeout": null, "enabled": null})).unwrap();
+        assert_eq!((config.count, config.timeout, config.enabled), (None, None, None));
+    }
+
+    #[test]
+    fn test_lenient_config_should_reject_bad_values() {
+        assert!(LenientConfig::deserialize(json!({"count": "many"})).is_err());
+        assert!(LenientConfig::deserialize(json!({"port": "70000"})).is_err());
+        assert!(LenientConfig::deserialize(json!({"count": -1})).is_err());
+        assert!(LenientConfig::deserialize(json!({"enabled": "yes"})).is_err());
+        assert!(LenientConfig::deserialize(json!({"checkall": "yes"})).is_err());
+        assert!(LenientConfig::deserialize(json!({"timeout": [1]})).is_err());
+    }
+}
@@ -1,9 +1,33 @@
 use crate::runtime::model::*;
 
+/// Parses the ID of a Node-RED element, the single implementation used by the flows loader and the nodes.
 pub fn parse_red_id_str(id_str: &str) -> Option<ElementId> {
-    id_str.parse().ok()
+    ElementId::from_hex(id_str).ok()
 }
 
 pub fn parse_red_id_value(id_value: &serde_json::Value) -> Option<ElementId> {
-    id_value.as_str().and_then(|s| s.parse().ok())
+    id_value.as_str().and_then(parse_red_id_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_it_should_parse_red_ids() {
+        let cases = [
+            ("a1b2c3d4e5f60718", Some(ElementId::from(0xa1b2c3d4e5f60718))),
+            ("a1b2c3d4.e5f607", Some(ElementId::from(0xa1b2c3d4e5f607))),
+            ("n1", None),
+            ("", None),
+            // The `subflow:` prefix belongs to the types, an ID with a suffix is not an ID
+            ("a1b2c3d4e5f60718:0011", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_red_id_str(input), expected, "{:?}", input);
+            assert_eq!(parse_red_id_value(&json!(input)), expected, "{:?}", input);
+        }
+        assert_eq!(parse_red_id_value(&json!(4)), None);
+    }
 }
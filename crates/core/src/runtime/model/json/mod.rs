@@ -31,7 +31,7 @@ pub struct RedGroupConfig {
     #[serde(default)]
     pub name: String,
 
-    #[serde(default)]
+    #[serde(default, alias = "d")]
     pub disabled: bool,
 
     #[serde(default, deserialize_with = "deser::deser_red_id_vec")]
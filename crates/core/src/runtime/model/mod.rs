@@ -1,5 +1,5 @@
 use std::any::Any;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, Weak};
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
@@ -46,17 +46,35 @@ pub trait ContextHolder: FlowsElement + Sync + Send {
 
 #[derive(Debug)]
 pub struct PortWire {
-    // pub target_node_id: ElementId,
-    // pub target_node: Weak<dyn FlowNodeBehavior>,
+    /// The node receiving the messages, the instance node if the wire is an output of a subflow.
+    pub target_node_id: ElementId,
+
+    /// Set once the target node has been built, it is weak since the nodes can be wired in loops.
+    pub target_node: OnceLock<Weak<dyn FlowNodeBehavior>>,
+
     pub msg_sender: tokio::sync::mpsc::Sender<MsgHandle>,
 }
 
 impl PortWire {
+    pub fn new(target_node_id: ElementId, msg_sender: MsgSender) -> Self {
+        PortWire { target_node_id, target_node: OnceLock::new(), msg_sender }
+    }
+
+    /// Returns the target node, `None` if it has not been resolved yet or has been dropped.
+    pub fn target_node(&self) -> Option<Arc<dyn FlowNodeBehavior>> {
+        self.target_node.get().and_then(|x| x.upgrade())
+    }
+
+    pub(crate) fn set_target_node(&self, node: &Arc<dyn FlowNodeBehavior>) {
+        let _ = self.target_node.set(Arc::downgrade(node));
+    }
+
     pub async fn tx(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
         tokio::select! {
 
             send_result = self.msg_sender.send(msg) =>  send_result.map_err(|e|
-                crate::EdgelinkError::InvalidOperation(format!("Failed to transmit message: {}", e)).into()),
+                crate::EdgelinkError::InvalidOperation(
+                    format!("Failed to transmit message to the node(id='{}'): {}", self.target_node_id, e)).into()),
 
             _ = cancel.cancelled() =>
                 Err(crate::EdgelinkError::TaskCancelled.into()),
@@ -0,0 +1,820 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::de;
+use serde::ser::SerializeMap;
+use tokio::sync::RwLock;
+
+#[cfg(feature = "js")]
+mod js {
+    pub use rquickjs::{prelude::*, *};
+}
+
+use crate::runtime::model::json::deser::parse_red_id_str;
+use crate::runtime::model::*;
+use crate::{EdgelinkError, ErrorContext};
+
+pub mod protocol;
+pub mod redact;
+pub mod trace;
+
+pub mod wellknown {
+    pub const MSG_ID_PROPERTY: &str = "_msgid";
+    pub const LINK_SOURCE_PROPERTY: &str = "_linkSource";
+    pub const MSG_SEQ_PROPERTY: &str = "_seq";
+    pub const PAYLOAD_PROPERTY: &str = "payload";
+    pub const TOPIC_PROPERTY: &str = "topic";
+
+    /// The per-topic state key of the messages without a topic.
+    pub const NO_TOPIC_KEY: &str = "_no_topic";
+}
+
+/// Computes the key of the per-topic state from the value of a topic property.
+///
+/// A missing or empty topic maps to `wellknown::NO_TOPIC_KEY`, numbers map to their textual form just like
+/// the property keys in JS.
+pub fn topic_key(topic: Option<&Variant>) -> Cow<'_, str> {
+    match topic {
+        Some(Variant::String(s)) if !s.is_empty() => Cow::Borrowed(s.as_str()),
+        Some(Variant::Number(n)) => Cow::Owned(n.to_string()),
+        _ => Cow::Borrowed(wellknown::NO_TOPIC_KEY),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub port: usize,
+    pub msg: MsgHandle,
+}
+
+pub type MsgBody = BTreeMap<VariantKey, Variant>;
+
+#[derive(Debug, Clone)]
+pub struct MsgHandle {
+    inner: Arc<RwLock<Msg>>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct LinkCallStackEntry {
+    pub id: ElementId,
+    pub link_call_node_id: ElementId,
+}
+
+/// A single property change between two messages, the `path` is a navigation property expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropChange {
+    Added { path: String, value: Variant },
+    Removed { path: String },
+    Changed { path: String, old: Variant, new: Variant },
+}
+
+impl PropChange {
+    pub fn path(&self) -> &str {
+        match self {
+            PropChange::Added { path, .. } | PropChange::Removed { path } | PropChange::Changed { path, .. } => path,
+        }
+    }
+}
+
+tokio::task_local! {
+    static MSG_ID_GENERATOR: Arc<dyn IdGenerator>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Msg {
+    body: Variant,
+    pub link_call_stack: Option<Vec<LinkCallStackEntry>>,
+}
+
+impl Default for Msg {
+    fn default() -> Self {
+        Msg { body: Variant::empty_object(), link_call_stack: None }
+    }
+}
+
+impl Msg {
+    /// Wraps the body as is, the `_linkSource` in it is not taken as the link call stack.
+    pub fn with_body(body: MsgBody) -> Self {
+        Msg { body: Variant::Object(body), link_call_stack: None }
+    }
+
+    /// Returns a cheap estimate of the memory used by this message in bytes, see `Variant::approx_size()`.
+    pub fn approx_size(&self) -> usize {
+        self.body.approx_size()
+    }
+
+    /// Returns the approximate bytes copied by a deep clone of this message, see `Variant::approx_copied_size()`.
+    pub fn approx_copied_size(&self) -> usize {
+        self.body.approx_copied_size()
+    }
+
+    pub fn id(&self) -> Option<ElementId> {
+        self.body
+            .as_object()
+            .unwrap()
+            .get(wellknown::MSG_ID_PROPERTY)
+            .and_then(|x| x.as_str())
+            .and_then(parse_red_id_str)
+    }
+
+    pub fn set_id(&mut self, id: ElementId) {
+        let uid: u64 = id.into();
+        self.body.as_object_mut().unwrap().insert(wellknown::MSG_ID_PROPERTY.into(), Variant::from(uid));
+    }
+
+    /// Generates a new message ID with the generator of the current task, see `Msg::with_id_generator()`.
+    pub fn generate_id() -> ElementId {
+        MSG_ID_GENERATOR.try_with(|x| x.next_id()).unwrap_or_else(|_| ElementId::new())
+    }
+
+    /// Runs the future with the generator used by `Msg::generate_id()`, the tasks spawned by it are not affected.
+    pub async fn with_id_generator<F: std::future::Future>(generator: Arc<dyn IdGenerator>, f: F) -> F::Output {
+        MSG_ID_GENERATOR.scope(generator, f).await
+    }
+
+    pub fn generate_id_variant() -> Variant {
+        let uid: u64 = Msg::generate_id().into();
+        Variant::from(uid)
+    }
+
+    pub fn as_variant(&self) -> &Variant {
+        &self.body
+    }
+
+    pub fn as_variant_mut(&mut self) -> &mut Variant {
+        &mut self.body
+    }
+
+    pub fn as_variant_object(&self) -> &VariantObjectMap {
+        self.body.as_object().unwrap()
+    }
+
+    pub fn as_variant_object_mut(&mut self) -> &mut VariantObjectMap {
+        self.body.as_object_mut().unwrap()
+    }
+
+    pub fn contains(&self, prop: &str) -> bool {
+        self.body.as_object().unwrap().contains_property(prop)
+    }
+
+    pub fn get(&self, prop: &str) -> Option<&Variant> {
+        self.body.as_object().unwrap().get_property(prop)
+    }
+
+    pub fn get_mut(&mut self, prop: &str) -> Option<&mut Variant> {
+        self.body.as_object_mut().unwrap().get_property_mut(prop)
+    }
+
+    /// Get the value of a navigation property
+    ///
+    /// The first level of the property expression for 'msg' must be a string, which means it must be
+    /// `msg[msg.topic]` `msg['aaa']` or `msg.aaa`, and not `msg[12]`
+    pub fn get_nav(&self, expr: &str) -> Option<&Variant> {
+        let value = self.body.as_object().unwrap().get_nav_property(expr, &[PropexEnv::ThisRef("msg")]);
+        if trace::is_enabled() {
+            trace::trace_get(expr, value);
+        }
+        value
+    }
+
+    pub fn get_nav_mut(&mut self, expr: &str) -> Option<&mut Variant> {
+        self.body.as_object_mut().unwrap().get_nav_property_mut(expr, &[PropexEnv::ThisRef("msg")])
+    }
+
+    pub fn get_nav_stripped_mut(&mut self, expr: &str) -> Option<&mut Variant> {
+        let trimmed_expr = expr.trim_ascii();
+        if let Some(stripped_expr) = trimmed_expr.strip_prefix("msg.") {
+            self.get_nav_mut(stripped_expr)
+        } else {
+            self.get_nav_mut(trimmed_expr)
+        }
+    }
+
+    pub fn get_nav_stripped(&self, expr: &str) -> Option<&Variant> {
+        let trimmed_expr = expr.trim_ascii();
+        if let Some(stripped_expr) = trimmed_expr.strip_prefix("msg.") {
+            self.get_nav(stripped_expr)
+        } else {
+            self.get_nav(trimmed_expr)
+        }
+    }
+
+    pub fn set(&mut self, prop: String, value: Variant) {
+        self.body.as_object_mut().unwrap().set_property(prop, value)
+    }
+
+    pub fn set_nav(&mut self, expr: &str, value: Variant, create_missing: bool) -> crate::Result<()> {
+        if trace::is_enabled() {
+            let old = self.body.as_object().unwrap().get_nav_property(expr, &[PropexEnv::ThisRef("msg")]);
+            trace::trace_set(expr, old, &value);
+        }
+        self.body.set_nav(expr, value, create_missing, &[PropexEnv::ThisRef("msg")])
+    }
+
+    pub fn set_nav_stripped(&mut self, expr: &str, value: Variant, create_missing: bool) -> crate::Result<()> {
+        let trimmed_expr = expr.trim_ascii();
+        if let Some(stripped_expr) = trimmed_expr.strip_prefix("msg.") {
+            self.set_nav(stripped_expr, value, create_missing)
+        } else {
+            self.set_nav(trimmed_expr, value, create_missing)
+        }
+    }
+
+    pub fn remove(&mut self, prop: &str) -> Option<Variant> {
+        self.body.as_object_mut().unwrap().remove_property(prop)
+    }
+
+    pub fn remove_nav(&mut self, prop: &str) -> Option<Variant> {
+        self.body.as_object_mut().unwrap().remove_nav_property(prop, &[PropexEnv::ThisRef("msg")])
+    }
+
+    /// Gets `msg.topic` if it is a string.
+    pub fn topic(&self) -> Option<&str> {
+        self.get(wellknown::TOPIC_PROPERTY).and_then(|x| x.as_str())
+    }
+
+    pub fn set_topic(&mut self, topic: String) {
+        self.set(wellknown::TOPIC_PROPERTY.to_string(), Variant::String(topic))
+    }
+
+    /// Gets the per-topic state key of this message, see `topic_key()`.
+    pub fn topic_key(&self) -> Cow<'_, str> {
+        topic_key(self.get(wellknown::TOPIC_PROPERTY))
+    }
+}
+
+impl Msg {
+    pub fn push_link_source(&mut self, lse: LinkCallStackEntry) {
+        if let Some(link_source) = &mut self.link_call_stack {
+            link_source.push(lse);
+        } else {
+            self.link_call_stack = Some(vec![lse]);
+        }
+    }
+
+    /// Like `push_link_source()` but fails if the message is already `max_depth` link calls deep.
+    pub fn try_push_link_source(&mut self, lse: LinkCallStackEntry, max_depth: usize) -> crate::Result<()> {
+        let depth = self.link_call_depth();
+        if depth >= max_depth {
+            return Err(EdgelinkError::OutOfRange).with_context(|| {
+                format!("The link calls are nested too deep: {} levels, the limit is {}", depth + 1, max_depth)
+            });
+        }
+        self.push_link_source(lse);
+        Ok(())
+    }
+
+    /// Returns the number of the pending link calls of this message.
+    pub fn link_call_depth(&self) -> usize {
+        self.link_call_stack.as_ref().map(|x| x.len()).unwrap_or(0)
+    }
+
+    pub fn pop_link_source(&mut self) -> Option<LinkCallStackEntry> {
+        if let Some(link_source) = &mut self.link_call_stack {
+            let p = link_source.pop();
+            if link_source.is_empty() {
+                self.link_call_stack = None
+            }
+            p
+        } else {
+            None
+        }
+    }
+}
+
+impl Msg {
+    /// Computes the property changes required to turn this message into `other`.
+    ///
+    /// Nested objects are reported at the leaf paths and arrays are compared element-wise.
+    pub fn diff(&self, other: &Msg) -> Vec<PropChange> {
+        let mut changes = Vec::new();
+        diff_object(&mut changes, "", self.as_variant_object(), other.as_variant_object());
+        changes
+    }
+
+    /// Compares the bodies of the messages deeply, the `_msgid` and `_linkSource` are ignored.
+    pub fn eq_ignoring_id(&self, other: &Msg) -> bool {
+        fn is_ignored(key: &str) -> bool {
+            key == wellknown::MSG_ID_PROPERTY || key == wellknown::LINK_SOURCE_PROPERTY
+        }
+        let lhs = self.as_variant_object().iter().filter(|(k, _)| !is_ignored(k));
+        let rhs = other.as_variant_object().iter().filter(|(k, _)| !is_ignored(k));
+        lhs.eq(rhs)
+    }
+
+    /// Hashes the body of the message like `Msg::eq_ignoring_id()` compares it.
+    pub fn hash_ignoring_id<H: std::hash::Hasher>(&self, state: &mut H) {
+        use std::hash::Hash;
+
+        for (key, value) in self.as_variant_object().iter() {
+            if key != wellknown::MSG_ID_PROPERTY && key != wellknown::LINK_SOURCE_PROPERTY {
+                key.hash(state);
+                value.hash(state);
+            }
+        }
+    }
+
+    /// Applies the changes produced by `Msg::diff()`.
+    pub fn apply_patch(&mut self, changes: &[PropChange]) -> crate::Result<()> {
+        for change in changes.iter() {
+            match change {
+                PropChange::Added { path, value } | PropChange::Changed { path, new: value, .. } => {
+                    self.body.set_nav(path, value.clone(), true, &[PropexEnv::ThisRef("msg")])?;
+                }
+                PropChange::Removed { path } => {
+                    if self.remove_nav(path).is_none() {
+                        return Err(
+                            EdgelinkError::InvalidOperation(format!("Cannot remove the property `{}`", path)).into()
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn diff_prop_path(parent: &str, key: &str) -> String {
+    let mut chars = key.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    match (parent.is_empty(), is_identifier) {
+        (true, true) => key.to_string(),
+        (false, true) => format!("{}.{}", parent, key),
+        (_, false) => format!("{}[\"{}\"]", parent, key.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+fn diff_object(changes: &mut Vec<PropChange>, path: &str, from: &VariantObjectMap, to: &VariantObjectMap) {
+    for (key, from_value) in from.iter() {
+        let child_path = diff_prop_path(path, key);
+        match to.get(key) {
+            Some(to_value) => diff_variant(changes, child_path, from_value, to_value),
+            None => changes.push(PropChange::Removed { path: child_path }),
+        }
+    }
+    for (key, to_value) in to.iter() {
+        if !from.contains_key(key) {
+            changes.push(PropChange::Added { path: diff_prop_path(path, key), value: to_value.clone() });
+        }
+    }
+}
+
+fn diff_variant(changes: &mut Vec<PropChange>, path: String, from: &Variant, to: &Variant) {
+    match (from, to) {
+        (Variant::Object(from_map), Variant::Object(to_map)) => diff_object(changes, &path, from_map, to_map),
+        (Variant::Array(from_arr), Variant::Array(to_arr)) => {
+            for (i, (from_item, to_item)) in from_arr.iter().zip(to_arr.iter()).enumerate() {
+                diff_variant(changes, format!("{}[{}]", path, i), from_item, to_item);
+            }
+            for (i, to_item) in to_arr.iter().enumerate().skip(from_arr.len()) {
+                changes.push(PropChange::Added { path: format!("{}[{}]", path, i), value: to_item.clone() });
+            }
+            // Remove from the tail, so the indices of the remaining elements stay valid
+            for i in (to_arr.len()..from_arr.len()).rev() {
+                changes.push(PropChange::Removed { path: format!("{}[{}]", path, i) });
+            }
+        }
+        _ if from != to => changes.push(PropChange::Changed { path, old: from.clone(), new: to.clone() }),
+        _ => {}
+    }
+}
+
+impl Index<&str> for Msg {
+    type Output = Variant;
+
+    fn index(&self, key: &str) -> &Self::Output {
+        &self.body.as_object().unwrap()[key]
+    }
+}
+
+impl IndexMut<&str> for Msg {
+    fn index_mut(&mut self, key: &str) -> &mut Self::Output {
+        self.body.as_object_mut().unwrap().entry(key.into()).or_default()
+    }
+}
+
+impl Msg {
+    /// Converts to a `serde_json::Value` directly, the same as `serde_json::to_value()` including the `_linkSource`
+    /// property, see `Variant::to_json_value()`.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let mut map = serde_json::Map::new();
+        let link_source = match self.link_call_stack {
+            Some(ref stack) => serde_json::to_value(stack).unwrap_or_default(),
+            None => serde_json::Value::Null,
+        };
+        map.insert(wellknown::LINK_SOURCE_PROPERTY.to_string(), link_source);
+        for (k, v) in self.body.as_object().unwrap().iter() {
+            map.insert(k.to_string(), v.to_json_value());
+        }
+        serde_json::Value::Object(map)
+    }
+}
+
+impl serde::Serialize for Msg {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry(wellknown::LINK_SOURCE_PROPERTY, &self.link_call_stack)?;
+        for (k, v) in self.body.as_object().unwrap().iter() {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Msg {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MsgVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for MsgVisitor {
+            type Value = Msg;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct Msg")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Msg, V::Error>
+            where
+                V: serde::de::MapAccess<'de>,
+            {
+                let mut link_call_stack = None;
+                let mut body = MsgBody::new();
+
+                while let Some(key) = map.next_key::<VariantKey>()? {
+                    match key.as_str() {
+                        wellknown::LINK_SOURCE_PROPERTY => {
+                            if link_call_stack.is_some() {
+                                return Err(de::Error::duplicate_field(wellknown::LINK_SOURCE_PROPERTY));
+                            }
+                            link_call_stack = Some(map.next_value()?);
+                        }
+                        _ => {
+                            let value = map.next_value()?;
+                            body.insert(key, value);
+                        }
+                    }
+                }
+
+                Ok(Msg { body: Variant::Object(body), link_call_stack })
+            }
+        }
+
+        deserializer.deserialize_map(MsgVisitor)
+    }
+}
+
+#[cfg(feature = "js")]
+impl<'js> js::FromJs<'js> for Msg {
+    fn from_js(ctx: &js::Ctx<'js>, jv: js::Value<'js>) -> js::Result<Msg> {
+        let mut link_call_stack: Option<Vec<LinkCallStackEntry>> = None;
+        match jv.type_of() {
+            js::Type::Object => {
+                if let Some(jo) = jv.as_object() {
+                    let mut body = MsgBody::new();
+                    // TODO _msgid check
+                    for result in jo.props::<String, js::Value>() {
+                        match result {
+                            Ok((ref k, v)) => match k.as_str() {
+                                wellknown::MSG_ID_PROPERTY if v.is_string() => {
+                                    // covert
+                                    let uid_str: String = v.get()?;
+                                    let uid: u64 =
+                                        ElementId::from_str(uid_str.as_str()).map(|x| x.into()).map_err(|e| {
+                                            js::Error::FromJs {
+                                                from: "String",
+                                                to: "ElementID",
+                                                message: Some(format!("Failed to convert msg id '{}': {}", uid_str, e)),
+                                            }
+                                        })?;
+                                    body.insert(k.into(), Variant::from(uid));
+                                }
+                                wellknown::LINK_SOURCE_PROPERTY => {
+                                    if let Some(bytes) =
+                                        v.as_object().and_then(|x| x.as_array_buffer()).and_then(|x| x.as_bytes())
+                                    {
+                                        link_call_stack =
+                                            bincode::deserialize(bytes).map_err(|_| js::Error::FromJs {
+                                                from: wellknown::LINK_SOURCE_PROPERTY,
+                                                to: "link_call_stack",
+                                                message: Some(
+                                                    "Failed to deserialize `_linkSource` property".to_string(),
+                                                ),
+                                            })?;
+                                    }
+                                }
+                                _ => {
+                                    body.insert(k.into(), Variant::from_js(ctx, v)?);
+                                }
+                            },
+                            Err(e) => {
+                                log::error!("Error occurred: {:?}", e);
+                                unreachable!();
+                            }
+                        }
+                    }
+                    Ok(Msg { link_call_stack, body: Variant::Object(body) })
+                } else {
+                    Err(js::Error::FromJs { from: "JS object", to: "Variant::Object", message: None })
+                }
+            }
+            _ => Err(js::Error::FromJs { from: "Unsupported JS type", to: "", message: None }),
+        }
+    }
+}
+
+#[cfg(feature = "js")]
+impl<'js> js::IntoJs<'js> for Msg {
+    fn into_js(self, ctx: &js::Ctx<'js>) -> js::Result<js::Value<'js>> {
+        let msg_id = self.id();
+        let jsv = self.body.into_js(ctx)?;
+        let obj = jsv.as_object().unwrap();
+        if let Some(msg_id) = msg_id {
+            let msgid_atom = wellknown::MSG_ID_PROPERTY.into_js(ctx)?;
+            obj.set(msgid_atom, msg_id.to_string().into_js(ctx))?;
+        }
+        {
+            let link_source_atom = wellknown::LINK_SOURCE_PROPERTY.into_js(ctx)?;
+            let link_source_bytes = bincode::serialize(&self.link_call_stack).map_err(|e| js::Error::IntoJs {
+                from: "Msg._linkSource",
+                to: "js._linkSource",
+                message: Some(e.to_string()),
+            })?;
+            let link_source_buffer = js::ArrayBuffer::new(ctx.clone(), link_source_bytes)?;
+            obj.set(link_source_atom, link_source_buffer)?;
+        }
+        Ok(jsv)
+    }
+}
+
+impl Default for MsgHandle {
+    fn default() -> Self {
+        let msg = Msg {
+            body: Variant::Object(BTreeMap::from([
+                (wellknown::MSG_ID_PROPERTY.into(), Msg::generate_id_variant()),
+                ("payload".into(), Variant::Null),
+            ])),
+            link_call_stack: None,
+        };
+        MsgHandle::new(msg)
+    }
+}
+
+impl MsgHandle {
+    pub fn new(inner: Msg) -> Self {
+        MsgHandle { inner: (Arc::new(RwLock::new(inner))) }
+    }
+
+    pub fn with_body(body: MsgBody) -> Self {
+        let msg = Msg { link_call_stack: None, body: Variant::Object(body) };
+        MsgHandle::new(msg)
+    }
+
+    pub fn with_payload(payload: Variant) -> Self {
+        let msg = Msg {
+            link_call_stack: None,
+            body: Variant::Object(BTreeMap::from([
+                (wellknown::MSG_ID_PROPERTY.into(), Msg::generate_id_variant()),
+                ("payload".into(), payload),
+            ])),
+        };
+        MsgHandle::new(msg)
+    }
+
+    pub async fn read(&self) -> tokio::sync::RwLockReadGuard<Msg> {
+        self.inner.read().await
+    }
+
+    /// Reads the message without waiting, fails if it is being written.
+    pub fn try_read(&self) -> Result<tokio::sync::RwLockReadGuard<Msg>, tokio::sync::TryLockError> {
+        self.inner.try_read()
+    }
+
+    pub async fn write(&self) -> tokio::sync::RwLockWriteGuard<Msg> {
+        self.inner.write().await
+    }
+
+    pub async fn deep_clone(&self, new_id: bool) -> Self {
+        let mut inner = self.inner.read().await.clone();
+        if new_id {
+            inner.set_id(Msg::generate_id());
+        }
+        MsgHandle::new(inner)
+    }
+
+    pub async fn unwrap(self) -> Msg {
+        let inner_lock = Arc::try_unwrap(self.inner).expect("only one reference");
+        inner_lock.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_json_value_should_match_the_serializer() {
+        let mut msg = Msg::deserialize(json!({
+            "payload": {"a": [1, 2.5, "three", null], "b": true},
+            "topic": "foo",
+            "_msgid": "0000000000000001"
+        }))
+        .unwrap();
+        msg["bytes"] = Variant::Bytes(vec![0, 127, 255].into());
+        msg["date"] = Variant::Date(std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123));
+        assert_eq!(msg.to_json_value(), serde_json::to_value(&msg).unwrap());
+
+        msg.link_call_stack =
+            Some(vec![LinkCallStackEntry { id: ElementId::from(1), link_call_node_id: ElementId::from(2) }]);
+        let json_value = msg.to_json_value();
+        assert_eq!(json_value, serde_json::to_value(&msg).unwrap());
+        assert!(json_value[wellknown::LINK_SOURCE_PROPERTY].is_array());
+    }
+
+    #[test]
+    fn test_eq_ignoring_id() {
+        let msg1 =
+            Msg::deserialize(json!({"payload": {"a": [1, 2]}, "topic": "foo", "_msgid": "0000000000000001"})).unwrap();
+        let mut msg2 =
+            Msg::deserialize(json!({"payload": {"a": [1, 2]}, "topic": "foo", "_msgid": "0000000000000002"})).unwrap();
+        msg2.link_call_stack =
+            Some(vec![LinkCallStackEntry { id: ElementId::from(1), link_call_node_id: ElementId::from(2) }]);
+        assert!(msg1.eq_ignoring_id(&msg2));
+        assert!(msg1.eq_ignoring_id(&Msg::deserialize(json!({"payload": {"a": [1, 2]}, "topic": "foo"})).unwrap()));
+
+        msg2.set_nav("payload.a[1]", Variant::from(3), false).unwrap();
+        assert!(!msg1.eq_ignoring_id(&msg2));
+        assert!(!msg1.eq_ignoring_id(&Msg::deserialize(json!({"payload": {"a": [1, 2]}})).unwrap()));
+    }
+
+    #[test]
+    fn test_topic_helpers() {
+        let mut msg = Msg::deserialize(json!({"payload": 1, "topic": "foo"})).unwrap();
+        assert_eq!(msg.topic(), Some("foo"));
+        assert_eq!(msg.topic_key(), "foo");
+
+        msg.set_topic("bar".to_string());
+        assert_eq!(msg.topic(), Some("bar"));
+        assert_eq!(msg.topic_key(), "bar");
+
+        msg.remove("topic");
+        assert_eq!(msg.topic(), None);
+        assert_eq!(msg.topic_key(), wellknown::NO_TOPIC_KEY);
+    }
+
+    #[test]
+    fn test_topic_key_should_map_absent_topics_to_the_same_key() {
+        assert_eq!(topic_key(None), wellknown::NO_TOPIC_KEY);
+        assert_eq!(topic_key(Some(&Variant::from(""))), wellknown::NO_TOPIC_KEY);
+        assert_eq!(topic_key(Some(&Variant::Null)), wellknown::NO_TOPIC_KEY);
+        assert_eq!(topic_key(Some(&Variant::from(json!({"a": 1})))), wellknown::NO_TOPIC_KEY);
+        assert_eq!(topic_key(Some(&Variant::from(42))), "42");
+        assert_eq!(topic_key(Some(&Variant::from("t"))), "t");
+    }
+
+    #[test]
+    fn test_get_nested_nav_property() {
+        let jv = json!({"payload": "newValue", "lookup": {"a": 1, "b": 2}, "topic": "b"});
+        let msg = Msg::deserialize(jv).unwrap();
+        {
+            assert!(msg.contains("lookup"));
+            assert!(msg.contains("topic"));
+            assert_eq!(*msg.get_nav("lookup[msg.topic]").unwrap(), Variant::from(2));
+        }
+    }
+
+    #[test]
+    fn test_get_nested_nav_property_mut() {
+        let jv = json!({"payload": "newValue", "lookup": {"a": 1, "b": 2}, "topic": "b"});
+        let mut msg = Msg::deserialize(jv).unwrap();
+        {
+            assert!(msg.contains("lookup"));
+            assert!(msg.contains("topic"));
+            let b = msg.get_nav_mut("lookup[msg.topic]").unwrap();
+            *b = Variant::from(1701);
+            assert_eq!(*msg.get_nav("lookup.b").unwrap(), Variant::from(1701));
+        }
+    }
+
+    #[test]
+    fn test_set_deep_msg_property() {
+        let jv = json!( {"foo": {"bar": "foo"}, "name": "hello"});
+        let mut msg = Msg::deserialize(jv).unwrap();
+        {
+            let old_foo = msg.get("foo").unwrap();
+            assert!(old_foo.is_object());
+            assert_eq!(old_foo.as_object().unwrap()["bar"].as_str().unwrap(), "foo");
+        }
+        msg.set("name".into(), "world".into());
+        assert_eq!(msg.get("name").unwrap().as_str().unwrap(), "world");
+
+        msg.set_nav("foo.bar", "changed2".into(), false).unwrap();
+        assert_eq!(msg.get("foo").unwrap().as_object().unwrap().get("bar").unwrap().as_str().unwrap(), "changed2");
+
+        assert!(msg.set_nav("foo.new_field", "new_value".into(), false).is_err());
+
+        assert!(msg.set_nav("foo.new_new_field", "new_new_value".into(), true).is_ok());
+
+        assert_eq!(
+            msg.get("foo").unwrap().as_object().unwrap().get("new_new_field").unwrap().as_str().unwrap(),
+            "new_new_value"
+        );
+    }
+
+    #[test]
+    fn test_set_nav_should_append_to_arrays() {
+        let mut msg = Msg::deserialize(json!({"payload": {}, "list": [1]})).unwrap();
+
+        msg.set_nav("payload.items[]", "a".into(), true).unwrap();
+        msg.set_nav("payload.items[]", "b".into(), true).unwrap();
+        assert_eq!(msg.get_nav("payload.items").unwrap(), &Variant::from(json!(["a", "b"])));
+
+        // Appending to an existing array does not create anything
+        msg.set_nav("list[]", 2.into(), false).unwrap();
+        assert_eq!(msg.get_nav("list").unwrap(), &Variant::from(json!([1, 2])));
+        assert!(msg.set_nav("missing[]", 1.into(), false).is_err());
+        assert!(msg.get_nav("list[]").is_none());
+    }
+
+    #[test]
+    fn test_diff_and_apply_patch() {
+        let from = Msg::deserialize(json!({
+            "payload": {"a": 1, "b": {"c": "foo", "d": true}},
+            "topic": "t1",
+            "arr": [1, 2, 3],
+            "removed": "bye"
+        }))
+        .unwrap();
+        let to = Msg::deserialize(json!({
+            "payload": {"a": 1, "b": {"c": "bar", "d": true, "e": null}},
+            "topic": "t1",
+            "arr": [1, 5],
+            "added": [1]
+        }))
+        .unwrap();
+
+        let changes = from.diff(&to);
+        assert_eq!(
+            changes,
+            vec![
+                PropChange::Changed { path: "arr[1]".into(), old: Variant::from(2), new: Variant::from(5) },
+                PropChange::Removed { path: "arr[2]".into() },
+                PropChange::Changed { path: "payload.b.c".into(), old: "foo".into(), new: "bar".into() },
+                PropChange::Added { path: "payload.b.e".into(), value: Variant::Null },
+                PropChange::Removed { path: "removed".into() },
+                PropChange::Added { path: "added".into(), value: Variant::Array(vec![Variant::from(1)]) },
+            ]
+        );
+
+        let mut patched = from.clone();
+        patched.apply_patch(&changes).unwrap();
+        assert_eq!(patched.as_variant(), to.as_variant());
+        assert!(patched.diff(&to).is_empty());
+    }
+
+    #[test]
+    fn test_diff_and_apply_patch_with_quoted_keys() {
+        let from =
+            Msg::deserialize(json!({"payload": {"it's": 1, "say \"hi\"": 2, "c:\\dir\\": 3, "both'\"": 4}})).unwrap();
+        let to =
+            Msg::deserialize(json!({"payload": {"it's": 5, "say \"hi\"": 6, "c:\\dir\\": 7, "both'\"": 8}})).unwrap();
+        let mut patched = from.clone();
+        patched.apply_patch(&from.diff(&to)).unwrap();
+        assert_eq!(patched.as_variant(), to.as_variant());
+
+        // The removal of an element out of the array is an error but not a panic
+        let mut msg = Msg::deserialize(json!({"arr": [1, 2]})).unwrap();
+        assert!(msg.apply_patch(&[PropChange::Removed { path: "arr[5]".into() }]).is_err());
+        assert_eq!(msg.get("arr"), Some(&Variant::from(json!([1, 2]))));
+    }
+
+    #[test]
+    fn should_be_ok_with_empty_object_variant() {
+        let jv = json!({});
+        let mut msg = Msg::deserialize(jv).unwrap();
+
+        msg.set_nav("foo.bar", "changed2".into(), true).unwrap();
+        assert!(msg.contains("foo"));
+        assert_eq!(msg.get("foo").unwrap().as_object().unwrap().get("bar").unwrap().as_str().unwrap(), "changed2");
+
+        assert!(msg.set_nav("foo.new_field", "new_value".into(), false).is_err());
+
+        assert!(msg.set_nav("foo.new_new_field", "new_new_value".into(), true).is_ok());
+
+        assert_eq!(
+            msg.get("foo").unwrap().as_object().unwrap().get("new_new_field").unwrap().as_str().unwrap(),
+            "new_new_value"
+        );
+    }
+}
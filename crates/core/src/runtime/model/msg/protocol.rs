@@ -0,0 +1,139 @@
+//! Typed accessors of the protocol metadata carried by the messages, e.g. `msg.headers` of HTTP or `msg.qos` of MQTT.
+//!
+//! The protocol nodes should use these instead of the nav-properties, so they agree on the names and types.
+
+use crate::runtime::model::*;
+use crate::EdgelinkError;
+
+pub const HEADERS_PROPERTY: &str = "headers";
+pub const QOS_PROPERTY: &str = "qos";
+pub const RETAIN_PROPERTY: &str = "retain";
+pub const STATUS_CODE_PROPERTY: &str = "statusCode";
+
+/// The MQTT quality of service, stored as a number in `msg.qos`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QoS {
+    #[default]
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl TryFrom<u64> for QoS {
+    type Error = EdgelinkError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(QoS::AtMostOnce),
+            1 => Ok(QoS::AtLeastOnce),
+            2 => Ok(QoS::ExactlyOnce),
+            _ => Err(EdgelinkError::OutOfRange),
+        }
+    }
+}
+
+impl Msg {
+    /// Gets `msg.headers` if it is an object.
+    pub fn headers(&self) -> Option<&VariantObjectMap> {
+        self.get(HEADERS_PROPERTY).and_then(|x| x.as_object())
+    }
+
+    /// Gets a header by its name, case-insensitively like HTTP does.
+    pub fn header(&self, name: &str) -> Option<&Variant> {
+        self.headers().and_then(|headers| {
+            headers.get(name).or_else(|| headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v))
+        })
+    }
+
+    /// Sets a header, replacing the existing one with the same name in any case.
+    ///
+    /// `msg.headers` will be created or replaced if it is not an object.
+    pub fn set_header(&mut self, name: &str, value: Variant) {
+        if self.headers().is_none() {
+            self.set(HEADERS_PROPERTY.to_string(), Variant::empty_object());
+        }
+        let headers = self.get_mut(HEADERS_PROPERTY).and_then(|x| x.as_object_mut()).expect("msg.headers");
+        headers.retain(|k, _| !k.eq_ignore_ascii_case(name));
+        headers.insert(name.into(), value);
+    }
+
+    /// Removes a header by its name case-insensitively, returns the removed value.
+    pub fn remove_header(&mut self, name: &str) -> Option<Variant> {
+        let headers = self.get_mut(HEADERS_PROPERTY).and_then(|x| x.as_object_mut())?;
+        let key = headers.keys().find(|k| k.eq_ignore_ascii_case(name)).cloned()?;
+        headers.remove(&key)
+    }
+
+    /// Gets `msg.qos`, `None` if it is missing or not a valid QoS level.
+    pub fn qos(&self) -> Option<QoS> {
+        self.get(QOS_PROPERTY).and_then(|x| x.as_u64()).and_then(|x| QoS::try_from(x).ok())
+    }
+
+    pub fn set_qos(&mut self, qos: QoS) {
+        self.set(QOS_PROPERTY.to_string(), Variant::from(qos as u64))
+    }
+
+    /// Gets `msg.retain`, a missing or non-boolean value means `false`.
+    pub fn retain(&self) -> bool {
+        self.get(RETAIN_PROPERTY).and_then(|x| x.as_bool()).unwrap_or(false)
+    }
+
+    pub fn set_retain(&mut self, retain: bool) {
+        self.set(RETAIN_PROPERTY.to_string(), Variant::Bool(retain))
+    }
+
+    /// Gets `msg.statusCode` of HTTP.
+    pub fn status_code(&self) -> Option<u16> {
+        self.get(STATUS_CODE_PROPERTY).and_then(|x| x.as_u64()).and_then(|x| u16::try_from(x).ok())
+    }
+
+    pub fn set_status_code(&mut self, status_code: u16) {
+        self.set(STATUS_CODE_PROPERTY.to_string(), Variant::from(status_code as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_headers_should_round_trip_with_the_body() {
+        let mut msg = Msg::deserialize(json!({"payload": 1, "headers": {"Content-Type": "text/plain"}})).unwrap();
+        assert_eq!(msg.header("content-type").and_then(|x| x.as_str()), Some("text/plain"));
+
+        msg.set_header("content-type", Variant::from("application/json"));
+        msg.set_header("X-Count", Variant::from(2));
+        assert_eq!(msg.headers().unwrap().len(), 2);
+        assert_eq!(msg.headers().unwrap()["content-type"].as_str(), Some("application/json"));
+        assert_eq!(msg.get_nav("headers['X-Count']").and_then(|x| x.as_i64()), Some(2));
+
+        assert_eq!(msg.remove_header("x-count").and_then(|x| x.as_i64()), Some(2));
+        assert!(msg.header("X-Count").is_none());
+    }
+
+    #[test]
+    fn test_set_header_should_replace_non_object_headers() {
+        let mut msg = Msg::deserialize(json!({"payload": 1, "headers": "bad"})).unwrap();
+        assert!(msg.headers().is_none());
+        msg.set_header("Host", Variant::from("localhost"));
+        assert_eq!(msg.header("host").and_then(|x| x.as_str()), Some("localhost"));
+    }
+
+    #[test]
+    fn test_mqtt_and_http_metadata() {
+        let mut msg = Msg::deserialize(json!({"payload": 1, "qos": 3})).unwrap();
+        assert_eq!(msg.qos(), None);
+        assert!(!msg.retain());
+
+        msg.set_qos(QoS::ExactlyOnce);
+        msg.set_retain(true);
+        msg.set_status_code(404);
+        assert_eq!(msg["qos"].as_u64(), Some(2));
+        assert_eq!(msg.qos(), Some(QoS::ExactlyOnce));
+        assert!(msg.retain());
+        assert_eq!(msg.status_code(), Some(404));
+    }
+}
@@ -0,0 +1,141 @@
+//! Masks the sensitive properties of the messages in the logs, like `payload.password` or `headers.authorization`.
+//!
+//! Only the texts written by the `debug` node and the property trace are redacted, the messages themselves are never
+//! changed. Every engine has its own `Redactor`, set from `runtime.engine.redact_msg_properties`; a property is
+//! masked along with everything inside it.
+
+use std::borrow::Cow;
+
+use crate::runtime::model::propex::{self, PropexSegment};
+use crate::runtime::model::*;
+use crate::*;
+
+/// The text replacing the redacted values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// The redacted property paths, split into their keys.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    paths: Vec<Vec<String>>,
+}
+
+impl Redactor {
+    /// Parses the redacted property paths, e.g. `payload.password` or `msg.headers["authorization"]`.
+    pub fn new<S: AsRef<str>>(paths: &[S]) -> crate::Result<Self> {
+        let mut parsed = Vec::with_capacity(paths.len());
+        for path in paths.iter().map(AsRef::as_ref) {
+            let keys = parse_path(path)
+                .filter(|x| !x.is_empty())
+                .ok_or(EdgelinkError::BadArgument("paths"))
+                .with_context(|| format!("Bad redacted property path: '{}'", path))?;
+            parsed.push(keys);
+        }
+        Ok(Redactor { paths: parsed })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.paths.is_empty()
+    }
+
+    /// Returns the whole message with the redacted properties masked.
+    pub fn redact_msg<'a>(&self, msg: &'a Msg) -> Cow<'a, Variant> {
+        self.redact_value("", msg.as_variant())
+    }
+
+    /// Masks the redacted properties in the `value` found at the property `path` of a message, the value is only
+    /// cloned if anything in it has to be masked.
+    pub fn redact_value<'a>(&self, path: &str, value: &'a Variant) -> Cow<'a, Variant> {
+        if self.paths.is_empty() {
+            return Cow::Borrowed(value);
+        }
+        // The dynamic paths like `a[msg.topic]` cannot be matched
+        let Some(prefix) = parse_path(path) else {
+            return Cow::Borrowed(value);
+        };
+
+        let mut redacted = Cow::Borrowed(value);
+        for keys in self.paths.iter() {
+            if prefix.starts_with(keys) {
+                return Cow::Owned(Variant::String(REDACTED.into()));
+            }
+            if let Some(rest) = keys.strip_prefix(prefix.as_slice()) {
+                if lookup(redacted.as_ref(), rest).is_some() {
+                    if let Some(target) = lookup_mut(redacted.to_mut(), rest) {
+                        *target = Variant::String(REDACTED.into());
+                    }
+                }
+            }
+        }
+        redacted
+    }
+}
+
+/// Splits the property expression into the keys, `None` if it has dynamic segments.
+fn parse_path(expr: &str) -> Option<Vec<String>> {
+    let expr = expr.trim_ascii();
+    let expr = expr.strip_prefix("msg.").unwrap_or(expr);
+    if expr.is_empty() {
+        return Some(Vec::new());
+    }
+    let segs = propex::parse(expr).ok()?;
+    segs.iter()
+        .map(|seg| match seg {
+            PropexSegment::Property(name) => Some(name.to_string()),
+            PropexSegment::Index(index) => Some(index.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn lookup<'a>(value: &'a Variant, keys: &[String]) -> Option<&'a Variant> {
+    keys.iter().try_fold(value, |current, key| match current {
+        Variant::Object(obj) => obj.get(key.as_str()),
+        Variant::Array(arr) => key.parse::<usize>().ok().and_then(|i| arr.get(i)),
+        _ => None,
+    })
+}
+
+fn lookup_mut<'a>(value: &'a mut Variant, keys: &[String]) -> Option<&'a mut Variant> {
+    keys.iter().try_fold(value, |current, key| match current {
+        Variant::Object(obj) => obj.get_mut(key.as_str()),
+        Variant::Array(arr) => key.parse::<usize>().ok().and_then(|i| arr.get_mut(i)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_it_should_mask_by_paths_without_changing_the_msg() {
+        let redactor = Redactor::new(&["payload.password", "headers['authorization']"][..]).unwrap();
+        let msg = Msg::deserialize(json!({
+            "payload": {"user": "admin", "password": "hunter2"},
+            "headers": {"authorization": "Bearer secret", "accept": "*/*"}
+        }))
+        .unwrap();
+
+        let redacted = redactor.redact_msg(&msg);
+        assert_eq!(redacted.get_nav("payload.password", &[]).and_then(|x| x.as_str()), Some(REDACTED));
+        assert_eq!(redacted.get_nav("payload.user", &[]).and_then(|x| x.as_str()), Some("admin"));
+        assert_eq!(redacted.get_nav("headers.authorization", &[]).and_then(|x| x.as_str()), Some(REDACTED));
+        assert_eq!(msg.get_nav("payload.password").and_then(|x| x.as_str()), Some("hunter2"));
+
+        // By the path of the logged property
+        let payload = msg.get("payload").unwrap();
+        assert!(!redactor.redact_value("payload", payload).to_display_string().contains("hunter2"));
+        let password = msg.get_nav("payload.password").unwrap();
+        assert_eq!(redactor.redact_value("msg.payload.password", password).as_str(), Some(REDACTED));
+        assert!(matches!(redactor.redact_value("headers.accept", &Variant::from("*/*")), Cow::Borrowed(_)));
+
+        // Nothing is masked without paths
+        assert!(!Redactor::default().is_enabled());
+        assert!(matches!(Redactor::default().redact_msg(&msg), Cow::Borrowed(_)));
+
+        assert!(Redactor::new(&["payload[msg.topic]"][..]).is_err());
+    }
+}
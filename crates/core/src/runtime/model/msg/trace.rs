@@ -0,0 +1,221 @@
+//! Opt-in tracing of the nav-property accesses on the messages, for debugging the flows.
+//!
+//! The accesses are logged at the `trace` level with the `TRACE_TARGET` target. Every engine with tracing enabled
+//! owns a `MsgTracer`, which is in effect for the tasks of its nodes, see `with_tracer()`. Tracing is off by default,
+//! and a disabled trace costs a single task-local lookup per access.
+
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+use super::redact::Redactor;
+use crate::runtime::model::*;
+
+pub const TRACE_TARGET: &str = "edgelink::msg_trace";
+
+/// The values longer than this in their JSON form are truncated in the trace.
+pub const MAX_TRACED_VALUE_LEN: usize = 128;
+
+/// A traced access of a message property, the values are truncated JSON texts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropAccess {
+    Get { path: String, value: Option<String> },
+    Set { path: String, old: Option<String>, new: String },
+}
+
+impl PropAccess {
+    pub fn path(&self) -> &str {
+        match self {
+            PropAccess::Get { path, .. } | PropAccess::Set { path, .. } => path,
+        }
+    }
+}
+
+pub type TraceSink = Arc<dyn Fn(&PropAccess) + Send + Sync>;
+
+tokio::task_local! {
+    static MSG_TRACER: Option<Arc<MsgTracer>>;
+}
+
+/// Traces the property accesses of the messages in the tasks of an engine, the redacted properties are masked.
+pub struct MsgTracer {
+    redactor: Arc<Redactor>,
+    sink: RwLock<Option<TraceSink>>,
+}
+
+impl std::fmt::Debug for MsgTracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MsgTracer").field("redactor", &self.redactor).finish_non_exhaustive()
+    }
+}
+
+impl MsgTracer {
+    pub fn new(redactor: Arc<Redactor>) -> Self {
+        MsgTracer { redactor, sink: RwLock::new(None) }
+    }
+
+    /// Also delivers the traced accesses to `sink`, e.g. to collect them in an embedding application or a test.
+    pub fn set_sink(&self, sink: Option<TraceSink>) {
+        *self.sink.write().expect("trace sink") = sink;
+    }
+
+    fn emit(&self, access: PropAccess) {
+        match &access {
+            PropAccess::Get { path, value } => {
+                log::trace!(target: TRACE_TARGET, "get msg.{} = {}", path, value.as_deref().unwrap_or("undefined"))
+            }
+            PropAccess::Set { path, old, new } => {
+                let old = old.as_deref().unwrap_or("undefined");
+                log::trace!(target: TRACE_TARGET, "set msg.{}: {} -> {}", path, old, new)
+            }
+        }
+        let sink = self.sink.read().expect("trace sink").clone();
+        if let Some(sink) = sink {
+            sink(&access);
+        }
+    }
+
+    /// The redacted properties are masked, the serialization stops once the text is too long.
+    fn format_value(&self, path: &str, value: &Variant) -> String {
+        let value = self.redactor.redact_value(path, value);
+        let mut writer = TruncatingWriter { buf: Vec::new(), truncated: false };
+        let text = match serde_json::to_writer(&mut writer, value.as_ref()) {
+            Ok(()) => String::from_utf8(writer.buf).unwrap_or_default(),
+            Err(_) if writer.truncated => {
+                let mut text = match String::from_utf8(writer.buf) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        // Cut in the middle of a character
+                        let valid = e.utf8_error().valid_up_to();
+                        let mut bytes = e.into_bytes();
+                        bytes.truncate(valid);
+                        String::from_utf8(bytes).unwrap_or_default()
+                    }
+                };
+                text.push_str("...");
+                return text;
+            }
+            Err(_) => value.to_display_string(),
+        };
+        truncate(text)
+    }
+}
+
+/// Runs the future with the tracer of the message accesses, `None` disables the trace. Like
+/// `Msg::with_id_generator()`, the tasks spawned by it are not affected.
+pub async fn with_tracer<F: std::future::Future>(tracer: Option<Arc<MsgTracer>>, f: F) -> F::Output {
+    MSG_TRACER.scope(tracer, f).await
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    MSG_TRACER.try_with(|x| x.is_some()).unwrap_or(false)
+}
+
+pub(crate) fn trace_get(path: &str, value: Option<&Variant>) {
+    let _ = MSG_TRACER.try_with(|tracer| {
+        if let Some(tracer) = tracer {
+            let value = value.map(|x| tracer.format_value(path, x));
+            tracer.emit(PropAccess::Get { path: path.to_string(), value });
+        }
+    });
+}
+
+pub(crate) fn trace_set(path: &str, old: Option<&Variant>, new: &Variant) {
+    let _ = MSG_TRACER.try_with(|tracer| {
+        if let Some(tracer) = tracer {
+            let (old, new) = (old.map(|x| tracer.format_value(path, x)), tracer.format_value(path, new));
+            tracer.emit(PropAccess::Set { path: path.to_string(), old, new });
+        }
+    });
+}
+
+/// Keeps the first `MAX_TRACED_VALUE_LEN` bytes, then fails the write to stop the serializer.
+struct TruncatingWriter {
+    buf: Vec<u8>,
+    truncated: bool,
+}
+
+impl Write for TruncatingWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let room = MAX_TRACED_VALUE_LEN - self.buf.len();
+        if data.len() > room {
+            self.buf.extend_from_slice(&data[..room]);
+            self.truncated = true;
+            return Err(std::io::Error::other("The traced value is too long"));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn truncate(mut text: String) -> String {
+    if text.len() > MAX_TRACED_VALUE_LEN {
+        let mut end = MAX_TRACED_VALUE_LEN;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_it_should_record_the_property_accesses() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let cloned = recorded.clone();
+        let redactor = Arc::new(Redactor::new(&["traced.secret"][..]).unwrap());
+        let tracer = Arc::new(MsgTracer::new(redactor));
+        tracer.set_sink(Some(Arc::new(move |access: &PropAccess| cloned.lock().unwrap().push(access.clone()))));
+
+        let mut msg = Msg::deserialize(json!({"payload": 1, "traced": {"a": 1, "secret": "hunter2"}})).unwrap();
+        MSG_TRACER.sync_scope(Some(tracer), || {
+            assert!(is_enabled());
+            msg.set_nav("traced.a", Variant::from(2), false).unwrap();
+            msg.set_nav_stripped("msg.traced.b", Variant::from("x".repeat(1000)), true).unwrap();
+            assert_eq!(msg.get_nav("traced.a").and_then(|x| x.as_i64()), Some(2));
+            assert!(msg.get_nav("traced.secret").is_some());
+        });
+
+        // Outside of the scope
+        assert!(!is_enabled());
+        msg.set_nav("traced.a", Variant::from(3), false).unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 4);
+        assert_eq!(recorded[0], PropAccess::Set { path: "traced.a".into(), old: Some("1".into()), new: "2".into() });
+        match &recorded[1] {
+            PropAccess::Set { path, old, new } => {
+                assert_eq!(path, "traced.b");
+                assert!(old.is_none());
+                assert_eq!(new.len(), MAX_TRACED_VALUE_LEN + 3);
+                assert!(new.ends_with("..."));
+            }
+            other => panic!("Unexpected access: {:?}", other),
+        }
+        assert_eq!(recorded[2], PropAccess::Get { path: "traced.a".into(), value: Some("2".into()) });
+        assert_eq!(recorded[3], PropAccess::Get { path: "traced.secret".into(), value: Some("\"[REDACTED]\"".into()) });
+    }
+
+    #[test]
+    fn test_it_should_truncate_multibyte_values_at_char_boundaries() {
+        let tracer = MsgTracer::new(Arc::new(Redactor::default()));
+        let text = tracer.format_value("payload", &Variant::from("é".repeat(200)));
+        assert!(text.ends_with("..."));
+        assert!(text.len() <= MAX_TRACED_VALUE_LEN + 3);
+        assert!(text.trim_end_matches("...").trim_start_matches('"').chars().all(|x| x == 'é'));
+    }
+}
@@ -10,12 +10,11 @@ use crate::text::nom_parsers;
 
 use nom::{
     branch::alt,
-    bytes::complete::take_while1,
     character::complete::{char, digit1, multispace0},
     combinator::{all_consuming, map_res, opt},
-    error::{context, ParseError, VerboseError},
+    error::{context, ErrorKind, ParseError, VerboseError},
     multi::{fold_many0, many1},
-    sequence::{delimited, preceded},
+    sequence::{delimited, pair, preceded},
     IResult, Parser,
 };
 
@@ -36,6 +35,8 @@ pub enum PropexSegment<'a> {
     Index(usize),
     Property(Cow<'a, str>), // Use a reference to a string slice
     Nested(Vec<PropexSegment<'a>>),
+    /// `[]`, the position after the last element of an array, only valid as the target to set.
+    Append,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -86,6 +87,7 @@ impl<'a> PartialEq for PropexSegment<'a> {
             (PropexSegment::Index(i1), PropexSegment::Index(i2)) => i1 == i2,
             (PropexSegment::Property(p1), PropexSegment::Property(p2)) => p1 == p2,
             (PropexSegment::Nested(n1), PropexSegment::Nested(n2)) => n1 == n2,
+            (PropexSegment::Append, PropexSegment::Append) => true,
             _ => false,
         }
     }
@@ -105,6 +107,7 @@ impl<'a> Display for PropexSegment<'a> {
                 }
                 write!(f, "]")
             }
+            PropexSegment::Append => write!(f, "[]"),
         }
     }
 }
@@ -136,15 +139,39 @@ fn parse_usize(input: &str) -> IResult<&str, usize, VerboseError<&str>> {
     context("usize", map_res(digit1, |s: &str| s.parse::<usize>())).parse(input)
 }
 
-fn string_literal<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+/// A quoted string, the quote and the backslash can be escaped by a backslash and other backslashes are kept as-is.
+fn string_literal<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Cow<'a, str>, E> {
     let (input, quote) = alt((char('"'), char('\'')))(input)?;
-    let (input, content) = take_while1(|c| c != quote)(input)?; // empty string is not allowed
-    let (input, _) = char(quote)(input)?;
-    Ok((input, content))
+    let mut unescaped: Option<String> = None;
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            // empty string is not allowed
+            if i == 0 {
+                return Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::TakeWhile1)));
+            }
+            let content = match unescaped {
+                Some(s) => Cow::Owned(s),
+                None => Cow::Borrowed(&input[..i]),
+            };
+            return Ok((&input[i + c.len_utf8()..], content));
+        }
+        if c == '\\' {
+            if let Some(escaped) = input[i + 1..].chars().next().filter(|x| *x == quote || *x == '\\') {
+                unescaped.get_or_insert_with(|| input[..i].to_string()).push(escaped);
+                chars.next();
+                continue;
+            }
+        }
+        if let Some(s) = unescaped.as_mut() {
+            s.push(c);
+        }
+    }
+    Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Char)))
 }
 
 fn first_string_literal_property(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
-    token(string_literal).map(|x| PropexSegment::Property(Cow::Borrowed(x))).parse(i)
+    token(string_literal).map(PropexSegment::Property).parse(i)
 }
 
 fn first_direct_property(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
@@ -167,9 +194,7 @@ fn first_property(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
 
 /// `['prop']` or `["prop"]`
 fn quoted_index_property(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
-    delimited(token(char('[')), string_literal, token(char(']')))
-        .map(|x| PropexSegment::Property(Cow::Borrowed(x)))
-        .parse(i)
+    delimited(token(char('[')), string_literal, token(char(']'))).map(PropexSegment::Property).parse(i)
 }
 
 /// `.property`
@@ -192,6 +217,7 @@ fn subproperty(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
             direct_numbers_index,       // `a.123`
             quoted_index_property,      // `a["b"]`
             bracket_index,              // `a[123]`
+            append_index,               // `a[]`
             nested,                     // `a[b.c]`
         )),
     )
@@ -204,6 +230,11 @@ fn bracket_index(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
         .parse(i)
 }
 
+/// `[]`
+fn append_index(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
+    context("append_index", pair(token(char('[')), token(char(']')))).map(|_| PropexSegment::Append).parse(i)
+}
+
 fn nested(i: &str) -> IResult<&str, PropexSegment, VerboseError<&str>> {
     let (i, _) = token(char('[')).parse(i)?;
     let (i, first) = first_direct_property.parse(i)?;
@@ -282,6 +313,22 @@ mod tests {
         assert_eq!(PropexSegment::Index(123), parsed);
     }
 
+    #[test]
+    fn parse_escaped_string_literals_should_be_ok() {
+        let segs = parse(r#"a['it\'s']["say \"hi\""]['c:\dir\\']"#).unwrap();
+        assert_eq!(
+            segs.as_slice(),
+            &[
+                PropexSegment::Property(Cow::Borrowed("a")),
+                PropexSegment::Property(Cow::Owned("it's".into())),
+                PropexSegment::Property(Cow::Owned("say \"hi\"".into())),
+                PropexSegment::Property(Cow::Owned("c:\\dir\\".into())),
+            ]
+        );
+        assert!(parse("a['']").is_err());
+        assert!(parse("a['abc\\']").is_err());
+    }
+
     #[test]
     fn parse_propex_should_be_ok() {
         let expr1 = r#"test1.hello.world['aaa'][333]["bb"].name_of"#;
@@ -313,6 +360,18 @@ mod tests {
         assert_eq!(PropexSegment::Property(Cow::Borrowed("name_of")), segs[7]);
     }
 
+    #[test]
+    fn parse_propex_with_append_index() {
+        let segs = parse("payload.items[]").unwrap();
+        assert_eq!(3, segs.len());
+        assert_eq!(PropexSegment::Property(Cow::Borrowed("payload")), segs[0]);
+        assert_eq!(PropexSegment::Property(Cow::Borrowed("items")), segs[1]);
+        assert_eq!(PropexSegment::Append, segs[2]);
+        assert_eq!("[]", segs[2].to_string());
+
+        assert!(parse("[]").is_err());
+    }
+
     #[test]
     fn parse_propex_with_nested_propex() {
         let expr1 = r#"['test1'].msg.payload[msg["topic"][0]].str[123]"#;
@@ -537,7 +596,6 @@ mod tests {
         // Failures:
         assert!(parse(r#"a'b'.c"#).is_err(), r#"fail a'b'.c"#);
         assert!(parse(r#"a['b'.c"#).is_err(), r#"fail a['b'.c"#);
-        assert!(parse(r#"a[]"#).is_err(), r#"fail a[]"#);
         assert!(parse(r#"a]"#).is_err(), r#"fail a]"#);
         assert!(parse(r#"a["#).is_err(), r#"fail a["#);
         assert!(parse(r#"a[0d]"#).is_err(), r#"fail a[0d]"#);
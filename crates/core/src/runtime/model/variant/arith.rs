@@ -0,0 +1,178 @@
+//! The arithmetic of the numeric `Variant`s, used by the JSONata expressions.
+//!
+//! Like the numbers of JS: the integers stay integers while the result fits in `i64` or `u64`, an overflow or a
+//! fractional quotient promotes them to rational `f64`s. The results are `Numeric`s, so a division by zero gives
+//! the IEEE `NaN` or `±Infinity`; only converting such a result into a `Variant` makes it `Null`, the same as the
+//! `NaN` and `Infinity` of JS in JSON.
+
+use super::*;
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Integer(i128),
+    Rational(f64),
+}
+
+impl Operand {
+    fn of(value: &Variant) -> Option<Self> {
+        let number = value.as_number()?;
+        if let Some(i) = number.as_i64() {
+            Some(Operand::Integer(i as i128))
+        } else if let Some(u) = number.as_u64() {
+            Some(Operand::Integer(u as i128))
+        } else {
+            number.as_f64().map(Operand::Rational)
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        match self {
+            Operand::Integer(i) => i as f64,
+            Operand::Rational(f) => f,
+        }
+    }
+}
+
+/// The result of the arithmetic, unlike `Variant::Number` it also holds the `NaN` and `±Infinity` of IEEE 754.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Numeric {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+impl Numeric {
+    fn integer(value: i128) -> Self {
+        if let Ok(i) = i64::try_from(value) {
+            Numeric::Int(i)
+        } else if let Ok(u) = u64::try_from(value) {
+            Numeric::UInt(u)
+        } else {
+            Numeric::Float(value as f64)
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Numeric::Int(i) => i as f64,
+            Numeric::UInt(u) => u as f64,
+            Numeric::Float(f) => f,
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.as_f64().is_finite()
+    }
+}
+
+/// The `NaN` and `±Infinity` become `Null`, as JSON has no such numbers.
+impl From<Numeric> for Variant {
+    fn from(value: Numeric) -> Self {
+        match value {
+            Numeric::Int(i) => Variant::from(i),
+            Numeric::UInt(u) => Variant::from(u),
+            Numeric::Float(f) => Variant::from(f),
+        }
+    }
+}
+
+impl Variant {
+    /// Returns `self + rhs`, `None` if any of them is not a number.
+    pub fn add(&self, rhs: &Variant) -> Option<Numeric> {
+        self.arith(rhs, i128::checked_add, |a, b| a + b)
+    }
+
+    /// Returns `self - rhs`, `None` if any of them is not a number.
+    pub fn sub(&self, rhs: &Variant) -> Option<Numeric> {
+        self.arith(rhs, i128::checked_sub, |a, b| a - b)
+    }
+
+    /// Returns `self * rhs`, `None` if any of them is not a number.
+    pub fn mul(&self, rhs: &Variant) -> Option<Numeric> {
+        self.arith(rhs, i128::checked_mul, |a, b| a * b)
+    }
+
+    /// Returns `self / rhs`, `None` if any of them is not a number.
+    ///
+    /// The quotient of two integers is only an integer if the division is exact. Like JS, a division by zero is
+    /// `±Infinity`, or `NaN` for `0 / 0`.
+    pub fn div(&self, rhs: &Variant) -> Option<Numeric> {
+        match (Operand::of(self)?, Operand::of(rhs)?) {
+            (Operand::Integer(a), Operand::Integer(b)) if b != 0 && a % b == 0 => Some(Numeric::integer(a / b)),
+            (a, b) => Some(Numeric::Float(a.to_f64() / b.to_f64())),
+        }
+    }
+
+    fn arith(
+        &self,
+        rhs: &Variant,
+        integer_op: fn(i128, i128) -> Option<i128>,
+        rational_op: fn(f64, f64) -> f64,
+    ) -> Option<Numeric> {
+        let value = match (Operand::of(self)?, Operand::of(rhs)?) {
+            (Operand::Integer(a), Operand::Integer(b)) => match integer_op(a, b) {
+                Some(x) => Numeric::integer(x),
+                None => Numeric::Float(rational_op(a as f64, b as f64)),
+            },
+            (a, b) => Numeric::Float(rational_op(a.to_f64(), b.to_f64())),
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_should_stay_integers() {
+        assert_eq!(Variant::from(2).add(&Variant::from(3)), Some(Numeric::Int(5)));
+        assert_eq!(Variant::from(2).sub(&Variant::from(3)), Some(Numeric::Int(-1)));
+        assert_eq!(Variant::from(-4).mul(&Variant::from(3)), Some(Numeric::Int(-12)));
+        assert_eq!(Variant::from(12).div(&Variant::from(4)), Some(Numeric::Int(3)));
+        assert!(Variant::from(Variant::from(12).div(&Variant::from(4)).unwrap()).is_i64());
+
+        // Beyond `i64` but still in `u64`
+        let sum = Variant::from(i64::MAX).add(&Variant::from(1)).unwrap();
+        assert_eq!(sum, Numeric::UInt(i64::MAX as u64 + 1));
+        assert_eq!(Variant::from(sum).as_u64(), Some(i64::MAX as u64 + 1));
+    }
+
+    #[test]
+    fn it_should_promote_to_rational() {
+        let quotient = Variant::from(7).div(&Variant::from(2)).unwrap();
+        assert_eq!(quotient, Numeric::Float(3.5));
+        assert!(Variant::from(quotient).is_f64());
+
+        assert_eq!(Variant::from(1).add(&Variant::from(0.5)).map(|x| x.as_f64()), Some(1.5));
+        assert_eq!(Variant::from(0.1).mul(&Variant::from(10)).map(|x| x.as_f64()), Some(1.0));
+
+        // Overflows
+        let product = Variant::from(u64::MAX).mul(&Variant::from(2)).unwrap();
+        assert_eq!(product, Numeric::Float(u64::MAX as f64 * 2.0));
+        let difference = Variant::from(i64::MIN).sub(&Variant::from(1)).unwrap();
+        assert_eq!(difference, Numeric::Float(i64::MIN as f64 - 1.0));
+    }
+
+    #[test]
+    fn division_by_zero_should_follow_ieee() {
+        assert_eq!(Variant::from(1).div(&Variant::from(0)), Some(Numeric::Float(f64::INFINITY)));
+        assert_eq!(Variant::from(-1.5).div(&Variant::from(0.0)), Some(Numeric::Float(f64::NEG_INFINITY)));
+        assert_eq!(Variant::from(-1).div(&Variant::from(-0.0)), Some(Numeric::Float(f64::INFINITY)));
+        let nan = Variant::from(0).div(&Variant::from(0)).unwrap();
+        assert!(nan.as_f64().is_nan());
+        assert!(!nan.is_finite());
+        assert_eq!(Variant::from(0).div(&Variant::from(3)), Some(Numeric::Int(0)));
+
+        // JSON has no such numbers
+        assert_eq!(Variant::from(nan), Variant::Null);
+        assert_eq!(Variant::from(Numeric::Float(f64::INFINITY)), Variant::Null);
+    }
+
+    #[test]
+    fn non_numbers_should_not_be_computed() {
+        assert_eq!(Variant::from("1").add(&Variant::from(1)), None);
+        assert_eq!(Variant::from(1).mul(&Variant::Null), None);
+        assert_eq!(Variant::Bool(true).sub(&Variant::Bool(false)), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! The conversion between a sequence of `Variant::Object` and an Arrow `RecordBatch`, one column per property.
+//!
+//! The type of a column is inferred from all rows, the integers are widened to floats if any row has a float and to
+//! unsigned ones if any row is above `i64::MAX`. Arrow makes no difference between a missing property and a `null`, both are restored as a missing property.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type, TimestampMillisecondType, UInt64Type};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array, NullArray, RecordBatch, RecordBatchOptions,
+    StringArray, TimestampMillisecondArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+
+use crate::runtime::model::*;
+use crate::*;
+
+/// Converts the objects into a `RecordBatch`, the columns are sorted by the property names.
+///
+/// Fails if an element is not an object, a property has values of different types or a nested value.
+pub fn variants_to_record_batch(objects: &[Variant]) -> crate::Result<RecordBatch> {
+    let mut rows = Vec::with_capacity(objects.len());
+    for object in objects.iter() {
+        rows.push(
+            object
+                .as_object()
+                .ok_or(EdgelinkError::BadArgument("objects"))
+                .with_context(|| format!("Only the objects can be converted into a record batch, got: {:?}", object))?,
+        );
+    }
+
+    let mut column_types: BTreeMap<&str, DataType> = BTreeMap::new();
+    for row in rows.iter() {
+        for (name, value) in row.iter() {
+            let inferred = infer_data_type(name, value)?;
+            let column_type = column_types.entry(name).or_insert(DataType::Null);
+            *column_type = merge_data_types(name, column_type, &inferred)?;
+        }
+    }
+
+    let mut fields = Vec::with_capacity(column_types.len());
+    let mut columns = Vec::with_capacity(column_types.len());
+    for (name, data_type) in column_types.into_iter() {
+        let values: Vec<Option<&Variant>> = rows.iter().map(|row| row.get(name).filter(|x| !x.is_null())).collect();
+        columns.push(build_column(name, &data_type, &values)?);
+        fields.push(Field::new(name, data_type, true));
+    }
+
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    Ok(RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), columns, &options)?)
+}
+
+/// Converts the messages into a `RecordBatch` like `variants_to_record_batch()`, including the `_msgid`.
+pub fn msgs_to_record_batch(msgs: &[Msg]) -> crate::Result<RecordBatch> {
+    let objects: Vec<Variant> = msgs.iter().map(|x| x.as_variant().clone()).collect();
+    variants_to_record_batch(&objects)
+}
+
+/// Converts every row of the batch back into an object, the `null` values are omitted.
+pub fn record_batch_to_variants(batch: &RecordBatch) -> crate::Result<Vec<Variant>> {
+    let mut rows = vec![VariantObjectMap::new(); batch.num_rows()];
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns().iter()) {
+        for (index, row) in rows.iter_mut().enumerate() {
+            if let Some(value) = column_value(field.name(), column, index)? {
+                row.insert(field.name().into(), value);
+            }
+        }
+    }
+    Ok(rows.into_iter().map(Variant::Object).collect())
+}
+
+/// Converts every row of the batch back into a message.
+pub fn record_batch_to_msgs(batch: &RecordBatch) -> crate::Result<Vec<Msg>> {
+    Ok(record_batch_to_variants(batch)?
+        .into_iter()
+        .map(|x| match x {
+            Variant::Object(body) => Msg::with_body(body),
+            _ => unreachable!(),
+        })
+        .collect())
+}
+
+fn infer_data_type(name: &str, value: &Variant) -> crate::Result<DataType> {
+    Ok(match value {
+        Variant::Null => DataType::Null,
+        Variant::Bool(_) => DataType::Boolean,
+        Variant::Number(n) if n.is_i64() => DataType::Int64,
+        Variant::Number(n) if n.is_u64() => DataType::UInt64,
+        Variant::Number(_) => DataType::Float64,
+        Variant::String(_) => DataType::Utf8,
+        Variant::Bytes(_) => DataType::Binary,
+        Variant::Date(_) => DataType::Timestamp(TimeUnit::Millisecond, None),
+        Variant::Regexp(_) | Variant::Array(_) | Variant::Object(_) => {
+            return Err(EdgelinkError::BadArgument("objects"))
+                .with_context(|| format!("The property '{}' has a value not supported by the columns", name))
+        }
+    })
+}
+
+fn merge_data_types(name: &str, lhs: &DataType, rhs: &DataType) -> crate::Result<DataType> {
+    match (lhs, rhs) {
+        (DataType::Null, x) | (x, DataType::Null) => Ok(x.clone()),
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => Ok(DataType::Float64),
+        // The negative integers are rejected by `build_column()`
+        (DataType::Int64, DataType::UInt64) | (DataType::UInt64, DataType::Int64) => Ok(DataType::UInt64),
+        (x, y) if x == y => Ok(x.clone()),
+        (x, y) => Err(EdgelinkError::BadArgument("objects"))
+            .with_context(|| format!("The property '{}' has the values of different types: {} and {}", name, x, y)),
+    }
+}
+
+fn build_column(name: &str, data_type: &DataType, values: &[Option<&Variant>]) -> crate::Result<ArrayRef> {
+    Ok(match data_type {
+        DataType::Boolean => {
+            Arc::new(BooleanArray::from(values.iter().map(|x| x.and_then(|v| v.as_bool())).collect::<Vec<_>>()))
+        }
+        DataType::Int64 => {
+            Arc::new(Int64Array::from(values.iter().map(|x| x.and_then(|v| v.as_i64())).collect::<Vec<_>>()))
+        }
+        DataType::UInt64 => {
+            let column =
+                values.iter().map(|x| x.map(|v| v.as_u64().ok_or(v)).transpose()).collect::<Result<Vec<_>, _>>();
+            match column {
+                Ok(column) => Arc::new(UInt64Array::from(column)),
+                Err(value) => {
+                    return Err(EdgelinkError::BadArgument("objects")).with_context(|| {
+                        format!("The property '{}' has integers above i64::MAX and the negative {:?}", name, value)
+                    })
+                }
+            }
+        }
+        DataType::Float64 => {
+            Arc::new(Float64Array::from(values.iter().map(|x| x.and_then(|v| v.as_f64())).collect::<Vec<_>>()))
+        }
+        DataType::Utf8 => {
+            Arc::new(StringArray::from(values.iter().map(|x| x.and_then(|v| v.as_str())).collect::<Vec<_>>()))
+        }
+        DataType::Binary => Arc::new(BinaryArray::from(
+            values.iter().map(|x| x.and_then(|v| v.as_bytes())).collect::<Vec<Option<&[u8]>>>(),
+        )),
+        DataType::Timestamp(TimeUnit::Millisecond, None) => Arc::new(TimestampMillisecondArray::from(
+            values
+                .iter()
+                .map(|x| match x {
+                    Some(Variant::Date(time)) => Some(to_millis(*time)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        _ => Arc::new(NullArray::new(values.len())),
+    })
+}
+
+fn column_value(name: &str, column: &ArrayRef, index: usize) -> crate::Result<Option<Variant>> {
+    if column.is_null(index) {
+        return Ok(None);
+    }
+    Ok(Some(match column.data_type() {
+        DataType::Boolean => Variant::Bool(column.as_boolean().value(index)),
+        DataType::Int64 => Variant::from(column.as_primitive::<Int64Type>().value(index)),
+        DataType::UInt64 => Variant::from(column.as_primitive::<UInt64Type>().value(index)),
+        DataType::Float64 => Variant::from(column.as_primitive::<Float64Type>().value(index)),
+        DataType::Utf8 => Variant::String(column.as_string::<i32>().value(index).to_string()),
+        DataType::Binary => Variant::Bytes(column.as_binary::<i32>().value(index).to_vec().into()),
+        DataType::Timestamp(TimeUnit::Millisecond, None) => {
+            Variant::Date(from_millis(column.as_primitive::<TimestampMillisecondType>().value(index)))
+        }
+        DataType::Null => return Ok(None),
+        x => {
+            return Err(EdgelinkError::BadArgument("batch"))
+                .with_context(|| format!("The column '{}' has an unsupported type: {}", name, x))
+        }
+    }))
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+fn from_millis(ms: i64) -> SystemTime {
+    if ms >= 0 {
+        UNIX_EPOCH + Duration::from_millis(ms as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_millis(ms.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_it_should_round_trip_a_batch_of_objects() {
+        let date = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let mut objects: Vec<Variant> = vec![
+            Variant::from(json!({"id": 1, "name": "foo", "value": 1, "ok": true})),
+            Variant::from(json!({"id": 2, "name": "bar", "value": 2.5})),
+            Variant::from(json!({"id": 3, "name": null, "value": null, "ok": false})),
+        ];
+        objects[0].as_object_mut().unwrap().insert("raw".into(), Variant::Bytes(vec![0, 255].into()));
+        objects[2].as_object_mut().unwrap().insert("at".into(), Variant::Date(date));
+
+        let batch = variants_to_record_batch(&objects).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let schema = batch.schema();
+        let types: Vec<(&str, &DataType)> =
+            schema.fields().iter().map(|x| (x.name().as_str(), x.data_type())).collect();
+        assert_eq!(
+            types,
+            vec![
+                ("at", &DataType::Timestamp(TimeUnit::Millisecond, None)),
+                ("id", &DataType::Int64),
+                ("name", &DataType::Utf8),
+                ("ok", &DataType::Boolean),
+                ("raw", &DataType::Binary),
+                ("value", &DataType::Float64),
+            ]
+        );
+        assert_eq!(batch.column(5).null_count(), 1);
+
+        let restored = record_batch_to_variants(&batch).unwrap();
+        let mut expected = vec![
+            Variant::from(json!({"id": 1, "name": "foo", "value": 1.0, "ok": true})),
+            Variant::from(json!({"id": 2, "name": "bar", "value": 2.5})),
+            Variant::from(json!({"id": 3, "ok": false})),
+        ];
+        expected[0].as_object_mut().unwrap().insert("raw".into(), Variant::Bytes(vec![0, 255].into()));
+        expected[2].as_object_mut().unwrap().insert("at".into(), Variant::Date(date));
+        assert_eq!(restored, expected);
+    }
+
+    #[test]
+    fn test_it_should_round_trip_msgs() {
+        let msgs: Vec<Msg> = (0..3)
+            .map(|x| Msg::deserialize(json!({"_msgid": format!("000000000000000{}", x), "payload": x})).unwrap())
+            .collect();
+        let batch = msgs_to_record_batch(&msgs).unwrap();
+        let restored = record_batch_to_msgs(&batch).unwrap();
+        assert_eq!(restored.len(), 3);
+        for (lhs, rhs) in msgs.iter().zip(restored.iter()) {
+            assert!(lhs.eq_ignoring_id(rhs));
+            assert_eq!(lhs.get("_msgid"), rhs.get("_msgid"));
+        }
+    }
+
+    #[test]
+    fn test_it_should_keep_the_integers_above_i64_max() {
+        let objects = vec![Variant::from(json!({"a": 1})), Variant::from(json!({"a": u64::MAX}))];
+        let batch = variants_to_record_batch(&objects).unwrap();
+        assert_eq!(batch.schema().field(0).data_type(), &DataType::UInt64);
+        assert_eq!(record_batch_to_variants(&batch).unwrap(), objects);
+
+        let objects = vec![Variant::from(json!({"a": -1})), Variant::from(json!({"a": u64::MAX}))];
+        assert!(variants_to_record_batch(&objects).is_err());
+        let objects = vec![Variant::from(json!({"a": 0.5})), Variant::from(json!({"a": u64::MAX}))];
+        assert!(variants_to_record_batch(&objects).is_err());
+    }
+
+    #[test]
+    fn test_it_should_reject_inconsistent_columns() {
+        let objects = vec![Variant::from(json!({"a": 1})), Variant::from(json!({"a": "1"}))];
+        let err = variants_to_record_batch(&objects).unwrap_err();
+        assert!(format!("{:#}", err).contains("different types"));
+
+        assert!(variants_to_record_batch(&[Variant::from(json!({"a": [1]}))]).is_err());
+        assert!(variants_to_record_batch(&[Variant::from(1)]).is_err());
+    }
+}
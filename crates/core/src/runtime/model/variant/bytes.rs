@@ -0,0 +1,114 @@
+use core::fmt::{self, Debug};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The buffer of `Variant::Bytes`, shared by reference between the clones.
+///
+/// Cloning a message fanned out to several wires only bumps the reference count, the buffer is copied on the first
+/// mutation of a clone that is still shared (copy-on-write). The `Vec` is kept inside the `Arc`, so wrapping an
+/// existing buffer does not copy it either.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SharedBytes(Arc<Vec<u8>>);
+
+impl SharedBytes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the buffer for mutation, copying it first if it is shared with other clones.
+    pub fn make_mut(&mut self) -> &mut Vec<u8> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// Returns the buffer, copying it only if it is shared with other clones.
+    pub fn into_vec(self) -> Vec<u8> {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| shared.as_ref().clone())
+    }
+
+    /// Returns `true` if both share the same buffer.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.0, &other.0)
+    }
+
+    /// Returns `true` if the buffer is shared with other clones.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+}
+
+impl Deref for SharedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Debug for SharedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.0.as_slice(), f)
+    }
+}
+
+impl From<Vec<u8>> for SharedBytes {
+    fn from(vec: Vec<u8>) -> Self {
+        Self(Arc::new(vec))
+    }
+}
+
+impl From<&[u8]> for SharedBytes {
+    fn from(slice: &[u8]) -> Self {
+        Self(Arc::new(slice.to_vec()))
+    }
+}
+
+impl From<SharedBytes> for Vec<u8> {
+    fn from(bytes: SharedBytes) -> Self {
+        bytes.into_vec()
+    }
+}
+
+impl PartialEq<[u8]> for SharedBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl PartialEq<Vec<u8>> for SharedBytes {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self.0.as_ref() == other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_bytes_should_copy_on_write() {
+        let origin = SharedBytes::from(vec![1, 2, 3]);
+        let mut cloned = origin.clone();
+        assert!(SharedBytes::ptr_eq(&origin, &cloned));
+        assert!(origin.is_shared());
+
+        cloned.make_mut().push(4);
+        assert!(!SharedBytes::ptr_eq(&origin, &cloned));
+        assert_eq!(origin, vec![1, 2, 3]);
+        assert_eq!(cloned, vec![1, 2, 3, 4]);
+
+        // The unique buffer is taken without copying
+        let ptr = cloned.as_ptr();
+        let taken = cloned.into_vec();
+        assert_eq!(taken.as_ptr(), ptr);
+    }
+}
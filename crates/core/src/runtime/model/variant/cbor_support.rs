@@ -0,0 +1,163 @@
+use ciborium::value::{Integer, Value};
+
+use super::ser::{date_from_epoch_secs, date_to_epoch_parts};
+use super::*;
+
+/// The CBOR tag of the date/time as a RFC 3339 string (RFC 8949).
+const CBOR_DATE_TIME_TAG: u64 = 0;
+
+/// The CBOR tag of the date/time as the seconds since the Unix epoch (RFC 8949).
+const CBOR_EPOCH_TIME_TAG: u64 = 1;
+
+impl Variant {
+    /// Encodes the `Variant` into CBOR.
+    ///
+    /// The bytes are kept as a byte string and the dates become the epoch-based date/time of the tag 1.
+    pub fn to_cbor(&self) -> crate::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(&self.to_cbor_value(), &mut buf)
+            .map_err(|e| EdgelinkError::InvalidOperation(format!("Cannot encode the CBOR: {}", e)))?;
+        Ok(buf)
+    }
+
+    /// Decodes a `Variant` from CBOR, the dates of the tags 0 and 1 become `Variant::Date` and the other tags are
+    /// dropped with their contents kept.
+    pub fn from_cbor(data: &[u8]) -> crate::Result<Variant> {
+        let value: Value = ciborium::from_reader(data)
+            .map_err(|e| EdgelinkError::InvalidOperation(format!("Cannot decode the CBOR: {}", e)))?;
+        Variant::from_cbor_value(value)
+    }
+
+    /// Converts the `Variant` into a CBOR value of `ciborium`.
+    pub fn to_cbor_value(&self) -> Value {
+        match self {
+            Variant::Null => Value::Null,
+            Variant::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    Value::Integer(i.into())
+                } else if let Some(u) = n.as_u64() {
+                    Value::Integer(u.into())
+                } else {
+                    Value::Float(n.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            Variant::String(s) => Value::Text(s.clone()),
+            Variant::Bool(b) => Value::Bool(*b),
+            Variant::Bytes(bytes) => Value::Bytes(bytes.to_vec()),
+            Variant::Regexp(re) => Value::Text(re.as_str().to_string()),
+            Variant::Date(d) => {
+                let (secs, nanos) = date_to_epoch_parts(d);
+                let time = if nanos == 0 {
+                    Value::Integer(secs.into())
+                } else {
+                    Value::Float(secs as f64 + nanos as f64 / 1e9)
+                };
+                Value::Tag(CBOR_EPOCH_TIME_TAG, Box::new(time))
+            }
+            Variant::Array(arr) => Value::Array(arr.iter().map(Variant::to_cbor_value).collect()),
+            Variant::Object(map) => {
+                Value::Map(map.iter().map(|(k, v)| (Value::Text(k.to_string()), v.to_cbor_value())).collect())
+            }
+        }
+    }
+
+    /// Converts a CBOR value of `ciborium` into a `Variant`, the keys of the maps must be strings.
+    pub fn from_cbor_value(value: Value) -> crate::Result<Variant> {
+        let var = match value {
+            Value::Null => Variant::Null,
+            Value::Bool(b) => Variant::Bool(b),
+            Value::Integer(i) => cbor_integer_to_variant(i)?,
+            Value::Float(f) => serde_json::Number::from_f64(f)
+                .map(Variant::Number)
+                .ok_or(EdgelinkError::OutOfRange)
+                .with_context(|| format!("Cannot convert the CBOR float `{}`", f))?,
+            Value::Text(s) => Variant::String(s),
+            Value::Bytes(bytes) => Variant::Bytes(bytes.into()),
+            Value::Tag(tag, inner) => cbor_tagged_to_variant(tag, *inner)?,
+            Value::Array(arr) => {
+                Variant::Array(arr.into_iter().map(Variant::from_cbor_value).collect::<Result<_, _>>()?)
+            }
+            Value::Map(entries) => Variant::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| match k {
+                        Value::Text(k) => Ok((k.into(), Variant::from_cbor_value(v)?)),
+                        other => Err(EdgelinkError::NotSupported(format!("The CBOR map key `{:?}`", other)).into()),
+                    })
+                    .collect::<crate::Result<VariantObjectMap>>()?,
+            ),
+            other => return Err(EdgelinkError::NotSupported(format!("The CBOR value `{:?}`", other)).into()),
+        };
+        Ok(var)
+    }
+}
+
+fn cbor_integer_to_variant(i: Integer) -> crate::Result<Variant> {
+    let i = i128::from(i);
+    if let Ok(v) = i64::try_from(i) {
+        Ok(Variant::from(v))
+    } else if let Ok(v) = u64::try_from(i) {
+        Ok(Variant::from(v))
+    } else {
+        Err(EdgelinkError::OutOfRange).with_context(|| format!("Cannot convert the CBOR integer `{}`", i))
+    }
+}
+
+fn cbor_tagged_to_variant(tag: u64, inner: Value) -> crate::Result<Variant> {
+    match (tag, inner) {
+        (CBOR_DATE_TIME_TAG, Value::Text(s)) => {
+            let dt = chrono::DateTime::parse_from_rfc3339(&s)
+                .with_context(|| format!("Bad CBOR date/time string: `{}`", s))?;
+            Ok(Variant::Date(dt.with_timezone(&chrono::Utc).into()))
+        }
+        (CBOR_EPOCH_TIME_TAG, Value::Integer(secs)) => {
+            let secs =
+                i64::try_from(secs).map_err(|_| EdgelinkError::OutOfRange).context("Bad CBOR epoch-based date/time")?;
+            Ok(Variant::Date(date_from_epoch_secs(secs, 0)))
+        }
+        (CBOR_EPOCH_TIME_TAG, Value::Float(secs)) if secs.is_finite() && secs.abs() < i64::MAX as f64 => {
+            // Rounded to microseconds, the precision of a double around the current dates
+            let nanos = ((secs - secs.floor()) * 1e6).round() as u32 * 1000;
+            Ok(Variant::Date(date_from_epoch_secs(secs.floor() as i64, nanos)))
+        }
+        (CBOR_DATE_TIME_TAG | CBOR_EPOCH_TIME_TAG, other) => {
+            Err(EdgelinkError::InvalidOperation(format!("Bad CBOR date/time: `{:?}`", other)).into())
+        }
+        (_, inner) => Variant::from_cbor_value(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dates_should_round_trip_through_cbor() {
+        let dates = [
+            UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            UNIX_EPOCH - Duration::from_millis(86_400_250),
+        ];
+        for date in dates {
+            let mut var = Variant::empty_object();
+            var.as_object_mut().unwrap().insert("date".into(), Variant::Date(date));
+            let decoded = Variant::from_cbor(&var.to_cbor().unwrap()).unwrap();
+            assert_eq!(decoded, var);
+        }
+    }
+
+    #[test]
+    fn from_cbor_should_decode_the_tagged_dates() {
+        let value = Value::Tag(CBOR_DATE_TIME_TAG, Box::new(Value::Text("2023-11-14T22:13:20Z".into())));
+        let var = Variant::from_cbor_value(value).unwrap();
+        assert_eq!(var, Variant::Date(UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+
+        // The unknown tags are dropped
+        let var = Variant::from_cbor_value(Value::Tag(32, Box::new(Value::Text("http://a".into())))).unwrap();
+        assert_eq!(var, Variant::from("http://a"));
+
+        assert!(Variant::from_cbor_value(Value::Tag(CBOR_EPOCH_TIME_TAG, Box::new(Value::Null))).is_err());
+        assert!(Variant::from_cbor_value(Value::Map(vec![(Value::Integer(1.into()), Value::Null)])).is_err());
+    }
+}
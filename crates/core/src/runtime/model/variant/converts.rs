@@ -96,7 +96,7 @@ impl From<char> for Variant {
 impl From<&[(String, Variant)]> for Variant {
     #[inline]
     fn from(value: &[(String, Variant)]) -> Self {
-        let map: VariantObjectMap = value.iter().map(|x| (x.0.clone(), x.1.clone())).collect();
+        let map: VariantObjectMap = value.iter().map(|x| (x.0.as_str().into(), x.1.clone())).collect();
         Variant::Object(map)
     }
 }
@@ -104,14 +104,14 @@ impl From<&[(String, Variant)]> for Variant {
 impl<const N: usize> From<[(&str, Variant); N]> for Variant {
     #[inline]
     fn from(value: [(&str, Variant); N]) -> Self {
-        let map: VariantObjectMap = value.iter().map(|x| (x.0.to_string(), x.1.clone())).collect();
+        let map: VariantObjectMap = value.iter().map(|x| (x.0.into(), x.1.clone())).collect();
         Variant::Object(map)
     }
 }
 
 impl From<&[u8]> for Variant {
     fn from(array: &[u8]) -> Self {
-        Variant::Bytes(array.to_vec())
+        Variant::Bytes(array.to_vec().into())
     }
 }
 
@@ -154,7 +154,7 @@ impl From<serde_json::Value> for Variant {
             serde_json::Value::String(string) => Variant::String(string.to_owned()),
             serde_json::Value::Array(array) => Variant::Array(array.iter().map(Variant::from).collect()),
             serde_json::Value::Object(object) => {
-                let new_map: VariantObjectMap = object.iter().map(|(k, v)| (k.to_owned(), Variant::from(v))).collect();
+                let new_map: VariantObjectMap = object.iter().map(|(k, v)| (k.into(), Variant::from(v))).collect();
                 Variant::Object(new_map)
             }
         }
@@ -170,7 +170,7 @@ impl From<&serde_json::Value> for Variant {
             serde_json::Value::String(string) => Variant::String(string.clone()),
             serde_json::Value::Array(array) => Variant::Array(array.iter().map(Variant::from).collect()),
             serde_json::Value::Object(object) => {
-                let new_map: VariantObjectMap = object.iter().map(|(k, v)| (k.clone(), Variant::from(v))).collect();
+                let new_map: VariantObjectMap = object.iter().map(|(k, v)| (k.into(), Variant::from(v))).collect();
                 Variant::Object(new_map)
             }
         }
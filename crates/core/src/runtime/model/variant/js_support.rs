@@ -27,7 +27,7 @@ impl<'js> js::FromJs<'js> for Variant {
                 if let Some(arr) = jv.as_array() {
                     if let Some(buf) = arr.as_typed_array::<u8>() {
                         match buf.as_bytes() {
-                            Some(bytes) => Ok(Variant::Bytes(bytes.to_vec())),
+                            Some(bytes) => Ok(Variant::Bytes(bytes.to_vec().into())),
                             None => {
                                 Err(js::Error::FromJs { from: "TypedArray<u8>", to: "Variant::Bytes", message: None })
                             }
@@ -58,19 +58,27 @@ impl<'js> js::FromJs<'js> for Variant {
                         let st = jv.get::<SystemTime>()?;
                         Ok(Variant::Date(st))
                     } else if jo.is_instance_of(regexp_ctor) {
-                        let to_string_fn: js::Function = jo.get("toString")?;
-                        let re_str: String = to_string_fn.call((js::function::This(jv),))?;
-                        match Regex::new(re_str.as_str()) {
-                            Ok(re) => Ok(Variant::Regexp(re)),
-                            Err(_) => Err(js::Error::FromJs {
+                        let source: String = jo.get("source")?;
+                        let flags: String = jo.get("flags")?;
+                        match Variant::regex_from(&source, &flags) {
+                            Ok(re) => Ok(re),
+                            Err(e) => Err(js::Error::FromJs {
                                 from: "JS object",
                                 to: "Variant::Regexp",
-                                message: Some(format!("Failed to create Regex from: '{}'", re_str)),
+                                message: Some(format!("Failed to create Regex from: '/{}/{}': {}", source, flags, e)),
                             }),
                         }
+                    } else if let Some(buf) = jo.as_typed_array::<u8>() {
+                        // `Buffer` and `Uint8Array`
+                        match buf.as_bytes() {
+                            Some(bytes) => Ok(Variant::Bytes(bytes.to_vec().into())),
+                            None => {
+                                Err(js::Error::FromJs { from: "TypedArray<u8>", to: "Variant::Bytes", message: None })
+                            }
+                        }
                     } else if let Some(buf) = jo.as_array_buffer() {
                         match buf.as_bytes() {
-                            Some(bytes) => Ok(Variant::Bytes(bytes.to_vec())),
+                            Some(bytes) => Ok(Variant::Bytes(bytes.to_vec().into())),
                             None => Err(js::Error::FromJs { from: "ArrayBuffer", to: "Variant::Bytes", message: None }),
                         }
                     } else {
@@ -78,7 +86,7 @@ impl<'js> js::FromJs<'js> for Variant {
                         for result in jo.props::<String, js::Value>() {
                             match result {
                                 Ok((ref k, v)) => {
-                                    map.insert(k.clone(), Variant::from_js(_ctx, v)?);
+                                    map.insert(k.into(), Variant::from_js(_ctx, v)?);
                                 }
                                 Err(e) => {
                                     log::error!("Unknown fatal error: {}", e);
@@ -108,7 +116,16 @@ impl<'js> js::IntoJs<'js> for Variant {
 
             Variant::Bool(b) => b.into_js(ctx),
 
-            Variant::Bytes(bytes) => Ok(js::ArrayBuffer::new(ctx.clone(), bytes)?.into_value()),
+            Variant::Bytes(bytes) => {
+                // Prefer the `Buffer` provided by the function node prelude, then fall back to `Uint8Array`
+                let global = ctx.globals();
+                let ctor = match global.get::<_, Option<Constructor>>("Buffer")? {
+                    Some(buffer_ctor) => buffer_ctor,
+                    None => global.get("Uint8Array")?,
+                };
+                let array_buffer = js::ArrayBuffer::new(ctx.clone(), bytes)?;
+                ctor.construct((array_buffer,))
+            }
 
             Variant::Number(num) => {
                 if let Some(f) = num.as_f64() {
@@ -124,7 +141,13 @@ impl<'js> js::IntoJs<'js> for Variant {
 
             Variant::Null => Ok(js::Value::new_null(ctx.clone())),
 
-            Variant::Object(map) => map.into_js(ctx),
+            Variant::Object(map) => {
+                let obj = js::Object::new(ctx.clone())?;
+                for (k, v) in map {
+                    obj.set(k.as_str(), v)?;
+                }
+                Ok(obj.into_value())
+            }
 
             Variant::String(s) => s.into_js(ctx),
 
@@ -133,7 +156,8 @@ impl<'js> js::IntoJs<'js> for Variant {
             Variant::Regexp(re) => {
                 let global = ctx.globals();
                 let regexp_ctor: Constructor = global.get("RegExp")?;
-                regexp_ctor.construct((re.as_str(),))
+                let (source, flags) = crate::text::regex::to_js_regex(&re);
+                regexp_ctor.construct((source, flags))
             }
         }
     }
@@ -184,4 +208,28 @@ mod tests {
             assert_eq!(v, vec![Variant::from(1), Variant::from(2), Variant::from(3)]);
         });
     }
+
+    #[test]
+    fn variant_bytes_should_round_trip_through_js() {
+        let js_rt = js::Runtime::new().unwrap();
+        let ctx = js::Context::full(&js_rt).unwrap();
+
+        let bytes = Variant::Bytes(vec![0x00, 0x7f, 0x80, 0xff].into());
+
+        ctx.with(|ctx| {
+            let globs = ctx.globals();
+            globs.set("foo", bytes.clone().into_js(&ctx).unwrap()).unwrap();
+
+            let is_u8_array: bool = ctx.eval("foo instanceof Uint8Array").unwrap();
+            assert!(is_u8_array);
+            let len: usize = ctx.eval("foo.length").unwrap();
+            assert_eq!(len, 4);
+
+            let v: Variant = ctx.eval("foo").unwrap();
+            assert_eq!(v, bytes);
+
+            let v: Variant = ctx.eval("foo.subarray(1, 3)").unwrap();
+            assert_eq!(v, Variant::Bytes(vec![0x7f, 0x80].into()));
+        });
+    }
 }
@@ -0,0 +1,238 @@
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde::Serialize;
+
+use super::*;
+
+/// The longer keys are rarely repeated, they are never interned.
+const MAX_INTERNED_KEY_LEN: usize = 64;
+
+/// The interner stops taking new keys past this, so the keys made of the data cannot grow it forever.
+const MAX_INTERNED_KEYS: usize = 16 * 1024;
+
+static INTERNING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+static INTERNED_KEYS: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+
+/// The key of `VariantObjectMap` and `MsgBody`, an immutable shared string.
+///
+/// It compares, orders and hashes exactly like its `str`, so the maps can be looked up by `&str`. When the
+/// interning is enabled by `VariantKey::set_interning()`, the short keys are taken from a process-wide pool and the
+/// same key in many messages shares a single buffer.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VariantKey(Arc<str>);
+
+impl VariantKey {
+    pub fn new(s: &str) -> Self {
+        if s.len() <= MAX_INTERNED_KEY_LEN && INTERNING_ENABLED.load(Ordering::Relaxed) {
+            return Self::intern(s);
+        }
+        VariantKey(Arc::from(s))
+    }
+
+    fn intern(s: &str) -> Self {
+        let mut pool = INTERNED_KEYS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(key) = pool.get(s) {
+            return VariantKey(key.clone());
+        }
+        let key: Arc<str> = Arc::from(s);
+        if pool.len() < MAX_INTERNED_KEYS {
+            pool.insert(key.clone());
+        }
+        VariantKey(key)
+    }
+
+    /// Enables or disables the interning of the keys created from now on, it is disabled by default.
+    ///
+    /// Disabling it also empties the pool, the keys already created keep their buffers.
+    pub fn set_interning(enabled: bool) {
+        INTERNING_ENABLED.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            if let Some(pool) = INTERNED_KEYS.get() {
+                pool.lock().unwrap_or_else(|e| e.into_inner()).clear();
+            }
+        }
+    }
+
+    pub fn is_interning() -> bool {
+        INTERNING_ENABLED.load(Ordering::Relaxed)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for VariantKey {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for VariantKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for VariantKey {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for VariantKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for VariantKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl From<&str> for VariantKey {
+    fn from(s: &str) -> Self {
+        VariantKey::new(s)
+    }
+}
+
+impl From<&String> for VariantKey {
+    fn from(s: &String) -> Self {
+        VariantKey::new(s)
+    }
+}
+
+impl From<String> for VariantKey {
+    fn from(s: String) -> Self {
+        if s.len() <= MAX_INTERNED_KEY_LEN && INTERNING_ENABLED.load(Ordering::Relaxed) {
+            return Self::intern(&s);
+        }
+        VariantKey(Arc::from(s))
+    }
+}
+
+impl From<&VariantKey> for VariantKey {
+    fn from(key: &VariantKey) -> Self {
+        key.clone()
+    }
+}
+
+impl From<VariantKey> for String {
+    fn from(key: VariantKey) -> Self {
+        key.0.to_string()
+    }
+}
+
+impl PartialEq<str> for VariantKey {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for VariantKey {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for VariantKey {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl Serialize for VariantKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for VariantKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct KeyVisitor;
+
+        impl de::Visitor<'_> for KeyVisitor {
+            type Value = VariantKey;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a string key")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<VariantKey, E>
+            where
+                E: de::Error,
+            {
+                Ok(VariantKey::new(value))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<VariantKey, E>
+            where
+                E: de::Error,
+            {
+                Ok(VariantKey::from(value))
+            }
+        }
+
+        deserializer.deserialize_str(KeyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+
+    fn hash_of<T: Hash + ?Sized>(v: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn keys_should_compare_and_hash_like_their_strings() {
+        let (a, b) = (VariantKey::from("alpha"), VariantKey::from("beta".to_string()));
+        assert_eq!(a, "alpha");
+        assert!(a < b);
+        assert_eq!(hash_of(&a), hash_of("alpha"));
+
+        let mut map = VariantObjectMap::new();
+        map.insert("beta".into(), Variant::from(2));
+        map.insert("alpha".into(), Variant::from(1));
+        assert_eq!(map.get("alpha"), Some(&Variant::from(1)));
+        assert_eq!(map.keys().map(VariantKey::as_str).collect::<Vec<_>>(), vec!["alpha", "beta"]);
+    }
+
+    #[test]
+    fn interned_keys_should_share_the_buffers() {
+        VariantKey::set_interning(true);
+        let json = r#"[{"temperature": 1, "humidity": 2}, {"temperature": 3, "humidity": 4}]"#;
+        let var: Variant = serde_json::from_str(json).unwrap();
+        let keys: Vec<&VariantKey> =
+            var.as_array().unwrap().iter().flat_map(|x| x.as_object().unwrap().keys()).collect();
+        let interned = Arc::ptr_eq(&keys[0].0, &keys[2].0) && Arc::ptr_eq(&keys[1].0, &keys[3].0);
+        let long = "k".repeat(MAX_INTERNED_KEY_LEN + 1);
+        let long_interned = Arc::ptr_eq(&VariantKey::new(&long).0, &VariantKey::new(&long).0);
+        VariantKey::set_interning(false);
+
+        assert!(interned);
+        assert!(!long_interned);
+        // The interning does not change the values
+        assert_eq!(var, Variant::from(serde_json::from_str::<serde_json::Value>(json).unwrap()));
+    }
+}
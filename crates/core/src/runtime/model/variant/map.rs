@@ -2,7 +2,7 @@ use std::borrow::Cow;
 
 use super::*;
 
-pub type VariantObjectMap = BTreeMap<String, Variant>;
+pub type VariantObjectMap = BTreeMap<VariantKey, Variant>;
 
 pub trait VariantObject {
     fn contains_property(&self, prop: &str) -> bool;
@@ -60,7 +60,7 @@ impl VariantObject for VariantObjectMap {
 
     /// Set the value of a direct property.
     fn set_property(&mut self, prop: String, value: Variant) {
-        let _ = self.insert(prop, value);
+        let _ = self.insert(prop.into(), value);
     }
 
     /// Set the value of a navigation property.
@@ -100,13 +100,13 @@ impl VariantObject for VariantObjectMap {
                 let var = match next_seg {
                     // the next level property is an object
                     Some(PropexSegment::Property(_)) => Variant::empty_object(),
-                    Some(PropexSegment::Index(_)) => Variant::empty_array(),
+                    Some(PropexSegment::Index(_) | PropexSegment::Append) => Variant::empty_array(),
                     _ => {
                         return Err(crate::EdgelinkError::BadArgument("expr"))
                             .with_context(|| format!("Not allowed to set first property: '{}'", first_prop_name));
                     }
                 };
-                self.insert(first_prop_name.to_string(), var);
+                self.insert(VariantKey::new(first_prop_name), var);
                 self.get_property_mut(first_prop_name).unwrap()
             }
             (None, _, _) => {
@@ -125,7 +125,10 @@ impl VariantObject for VariantObjectMap {
                 *pv = value;
                 Ok(())
             }
-            None if create_missing => first_prop.set_segs_property(&segs[1..], value, true).map_err(Into::into),
+            // Appending to an existing array needs nothing to be created
+            None if create_missing || segs.last() == Some(&PropexSegment::Append) => {
+                first_prop.set_segs_property(&segs[1..], value, create_missing)
+            }
             None => Err(crate::EdgelinkError::InvalidOperation(
                 "Unable to set property: missing intermediate segments".into(),
             )
@@ -221,7 +224,10 @@ impl VariantObject for VariantObjectMap {
                     (Variant::Object(tail_map), PropexSegment::Property(tail_seg)) => {
                         tail_map.remove(tail_seg.as_ref())
                     }
-                    (Variant::Array(tail_arr), PropexSegment::Index(tail_index)) => Some(tail_arr.remove(*tail_index)),
+                    (Variant::Array(tail_arr), PropexSegment::Index(tail_index)) => {
+                        tail_arr.get(*tail_index)?;
+                        Some(tail_arr.remove(*tail_index))
+                    }
                     _ => None,
                 }
             }
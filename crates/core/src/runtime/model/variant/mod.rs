@@ -1,6 +1,7 @@
 use core::fmt::{self, Debug};
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use regex::Regex;
@@ -17,12 +18,26 @@ use super::propex::PropexSegment;
 #[cfg(feature = "js")]
 mod js_support;
 
+#[cfg(feature = "arrow")]
+pub mod arrow_support;
+
+mod arith;
 mod array;
+mod bytes;
+mod cbor_support;
 mod converts;
+mod key;
 mod map;
+mod msgpack_support;
 mod ser;
+mod toml_support;
+
+pub mod predicate;
 
+pub use self::arith::Numeric;
 pub use self::array::*;
+pub use self::bytes::*;
+pub use self::key::VariantKey;
 pub use self::map::*;
 
 #[derive(Debug, Clone)]
@@ -40,6 +55,21 @@ pub trait PropexEnvSliceExt<'a> {
 #[derive(Debug, Clone)]
 pub struct UndefinableVariant(pub Option<Variant>);
 
+/// How arrays are handled by `Variant::merge()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayMergeMode {
+    /// The array of the other value replaces the existing one.
+    #[default]
+    Replace,
+
+    /// The items of the other array are appended to the existing one.
+    Concat,
+
+    /// The items are merged by their index.
+    Index,
+}
+
 /// A versatile enum that can represent various types of data.
 ///
 /// This enum is designed to be a flexible container for different kinds of data,
@@ -83,8 +113,8 @@ pub enum Variant {
     /// Represents a regular expression string.
     Regexp(Regex),
 
-    /// Represents a sequence of bytes.
-    Bytes(Vec<u8>),
+    /// Represents a sequence of bytes, shared between the clones until mutated.
+    Bytes(SharedBytes),
 
     /// Represents an array of `Variant` values.
     Array(Vec<Variant>),
@@ -112,6 +142,23 @@ impl PartialEq for Variant {
 
 impl Eq for Variant {}
 
+impl Hash for Variant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Variant::Null => {}
+            Variant::Number(a) => a.hash(state),
+            Variant::String(a) => a.hash(state),
+            Variant::Bool(a) => a.hash(state),
+            Variant::Date(a) => a.hash(state),
+            Variant::Regexp(a) => a.as_str().hash(state),
+            Variant::Bytes(a) => a.hash(state),
+            Variant::Array(a) => a.hash(state),
+            Variant::Object(a) => a.hash(state),
+        }
+    }
+}
+
 impl Variant {
     pub fn empty_string() -> Variant {
         Variant::String("".into())
@@ -143,7 +190,7 @@ impl Variant {
                         return Err(EdgelinkError::NotSupported("Invalid byte JSON value type".to_owned()).into());
                     }
                 }
-                Ok(Variant::Bytes(bytes))
+                Ok(Variant::Bytes(bytes.into()))
             }
             serde_json::Value::String(string) => Ok(Variant::from(string.as_bytes())),
             _ => Err(EdgelinkError::NotSupported("Invalid byte JSON Value".to_owned()).into()),
@@ -179,7 +226,7 @@ impl Variant {
                 return Err(EdgelinkError::InvalidOperation("Invalid Variant type".into()).into());
             }
         }
-        Ok(Variant::Bytes(bytes))
+        Ok(Variant::Bytes(bytes.into()))
     }
 
     pub fn is_bytes(&self) -> bool {
@@ -196,7 +243,7 @@ impl Variant {
 
     pub fn to_bytes(&self) -> Option<Vec<u8>> {
         match self {
-            Variant::Bytes(ref bytes) => Some(bytes.clone()),
+            Variant::Bytes(ref bytes) => Some(bytes.to_vec()),
             Variant::String(ref s) => Some(s.bytes().collect()),
             Variant::Array(ref arr) => {
                 let mut bytes = Vec::with_capacity(arr.len());
@@ -210,16 +257,17 @@ impl Variant {
         }
     }
 
+    /// The buffer is copied first if it is shared with other clones.
     pub fn as_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
         match self {
-            Variant::Bytes(ref mut bytes) => Some(bytes),
+            Variant::Bytes(ref mut bytes) => Some(bytes.make_mut()),
             _ => None,
         }
     }
 
     pub fn into_bytes(self) -> Result<Vec<u8>, Self> {
         match self {
-            Variant::Bytes(vec) => Ok(vec),
+            Variant::Bytes(bytes) => Ok(bytes.into_vec()),
             other => Err(other),
         }
     }
@@ -401,6 +449,28 @@ impl Variant {
         }
     }
 
+    /// Creates a `Variant::Regexp` from a JavaScript pattern and flags, see `text::regex::build_js_regex()`.
+    pub fn regex_from(pattern: &str, flags: &str) -> crate::Result<Variant> {
+        Ok(Variant::Regexp(crate::text::regex::build_js_regex(pattern, flags)?))
+    }
+
+    /// Tests the text like `RegExp.prototype.test()`, always `false` if this is not a regular expression.
+    pub fn regex_test(&self, text: &str) -> bool {
+        self.as_regexp().is_some_and(|re| re.is_match(text))
+    }
+
+    /// Replaces the first match, or all matches like the `g` flag of JS, `None` if this is not a regular expression.
+    ///
+    /// The replacement refers to the capture groups as `$1` or `${name}` like the `regex` crate does.
+    pub fn regex_replace<'t>(&self, text: &'t str, replacement: &str, all: bool) -> Option<Cow<'t, str>> {
+        let re = self.as_regexp()?;
+        if all {
+            Some(re.replace_all(text, replacement))
+        } else {
+            Some(re.replace(text, replacement))
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
             Variant::Null => 0,
@@ -424,11 +494,121 @@ impl Variant {
         }
     }
 
+    /// Returns a cheap estimate of the memory used by this value in bytes, including its children.
+    ///
+    /// Only the payload of the heap allocations is counted, not their capacity or allocator overhead.
+    pub fn approx_size(&self) -> usize {
+        let heap_size = match self {
+            Variant::String(s) => s.len(),
+            Variant::Bytes(bytes) => bytes.len(),
+            Variant::Regexp(re) => re.as_str().len(),
+            Variant::Array(array) => array.iter().map(|x| x.approx_size()).sum(),
+            Variant::Object(object) => object.iter().map(|(k, v)| k.len() + v.approx_size()).sum(),
+            Variant::Null | Variant::Number(_) | Variant::Bool(_) | Variant::Date(_) => 0,
+        };
+        std::mem::size_of::<Variant>() + heap_size
+    }
+
+    /// Like `approx_size()`, but only counts the bytes copied by `clone()`.
+    pub fn approx_copied_size(&self) -> usize {
+        let heap_size = match self {
+            Variant::String(s) => s.len(),
+            Variant::Array(array) => array.iter().map(|x| x.approx_copied_size()).sum(),
+            Variant::Object(object) => object.iter().map(|(k, v)| k.len() + v.approx_copied_size()).sum(),
+            // Both the buffers and the compiled regexps are reference counted
+            Variant::Bytes(_) | Variant::Regexp(_) => 0,
+            Variant::Null | Variant::Number(_) | Variant::Bool(_) | Variant::Date(_) => 0,
+        };
+        std::mem::size_of::<Variant>() + heap_size
+    }
+
+    /// Returns the keys of an object as an array of strings, like `Object.keys()` in JS.
+    pub fn object_keys(&self) -> Option<Variant> {
+        self.as_object().map(|obj| Variant::Array(obj.keys().map(|k| Variant::from(k.as_str())).collect()))
+    }
+
+    /// Returns the values of an object as an array, like `Object.values()` in JS.
+    pub fn object_values(&self) -> Option<Variant> {
+        self.as_object().map(|obj| Variant::Array(obj.values().cloned().collect()))
+    }
+
+    /// Returns the `[key, value]` pairs of an object as an array, like `Object.entries()` in JS.
+    pub fn object_entries(&self) -> Option<Variant> {
+        self.as_object().map(|obj| {
+            Variant::Array(
+                obj.iter().map(|(k, v)| Variant::Array(vec![Variant::from(k.as_str()), v.clone()])).collect(),
+            )
+        })
+    }
+
+    /// Builds an object from an array of `[key, value]` pairs, the inverse of `object_entries()`.
+    pub fn from_entries(entries: &[Variant]) -> crate::Result<Variant> {
+        let mut map = VariantObjectMap::new();
+        for entry in entries.iter() {
+            match entry.as_array().map(|x| x.as_slice()) {
+                Some([key, value]) => {
+                    let key = match key {
+                        Variant::String(s) => s.clone(),
+                        Variant::Number(n) => n.to_string(),
+                        _ => {
+                            return Err(EdgelinkError::InvalidOperation(format!(
+                                "The key of the entry must be a string or a number, got: {:?}",
+                                key
+                            ))
+                            .into())
+                        }
+                    };
+                    map.insert(key.into(), value.clone());
+                }
+                _ => {
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "The entry must be a `[key, value]` array, got: {:?}",
+                        entry
+                    ))
+                    .into())
+                }
+            }
+        }
+        Ok(Variant::Object(map))
+    }
+
+    /// Deep-merges `other` into this value.
+    ///
+    /// Objects are merged key by key recursively, arrays are merged as specified by `arrays`,
+    /// any other value of `other` replaces this value.
+    pub fn merge(&mut self, other: Variant, arrays: ArrayMergeMode) {
+        match (self, other) {
+            (Variant::Object(this), Variant::Object(other)) => {
+                for (key, value) in other.into_iter() {
+                    match this.get_mut(&key) {
+                        Some(existed) => existed.merge(value, arrays),
+                        None => {
+                            this.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (Variant::Array(this), Variant::Array(other)) if arrays != ArrayMergeMode::Replace => match arrays {
+                ArrayMergeMode::Concat => this.extend(other),
+                _ => {
+                    for (i, value) in other.into_iter().enumerate() {
+                        match this.get_mut(i) {
+                            Some(existed) => existed.merge(value, arrays),
+                            None => this.push(value),
+                        }
+                    }
+                }
+            },
+            (this, other) => *this = other,
+        }
+    }
+
     pub fn get_seg(&self, pseg: &PropexSegment) -> Option<&Variant> {
         match pseg {
             PropexSegment::Index(index) => self.get_array_item(*index),
             PropexSegment::Property(prop) => self.as_object()?.get_property(prop),
             PropexSegment::Nested(_) => None, // TODO log debug
+            PropexSegment::Append => None,
         }
     }
 
@@ -437,6 +617,7 @@ impl Variant {
             PropexSegment::Index(index) => self.get_array_item_mut(*index),
             PropexSegment::Property(prop) => self.as_object_mut()?.get_property_mut(prop),
             PropexSegment::Nested(_) => None, // TODO log debug
+            PropexSegment::Append => None,
         }
     }
 
@@ -489,6 +670,7 @@ impl Variant {
                 }
             }
             Variant::Bytes(ref mut this_bytes) => {
+                let this_bytes = this_bytes.make_mut();
                 if let Some(existed) = this_bytes.get_mut(index) {
                     *existed = value.as_u8().ok_or(EdgelinkError::InvalidOperation("Bad casting".into()))?;
                     Ok(())
@@ -515,6 +697,14 @@ impl Variant {
                 Ok(())
             }
             PropexSegment::Nested(_nested) => unreachable!(),
+            PropexSegment::Append => {
+                let len = match self {
+                    Variant::Array(arr) => arr.len(),
+                    Variant::Bytes(bytes) => bytes.len(),
+                    _ => return Err(EdgelinkError::InvalidOperation("Bad type".into()).into()),
+                };
+                self.set_array_item(len, value)
+            }
         }
     }
 
@@ -544,7 +734,7 @@ impl Variant {
             (Some(prop), _, _) => prop,
             (None, true, 1) => {
                 // Only one level of the property
-                self.as_object_mut().unwrap().insert(first_prop_name.to_string(), value);
+                self.as_object_mut().unwrap().insert(VariantKey::new(first_prop_name), value);
                 return Ok(());
             }
             (None, true, _) => {
@@ -552,13 +742,13 @@ impl Variant {
                 let var = match next_seg {
                     // the next level property is an object
                     Some(PropexSegment::Property(_)) => Variant::empty_object(),
-                    Some(PropexSegment::Index(_)) => Variant::empty_array(),
+                    Some(PropexSegment::Index(_) | PropexSegment::Append) => Variant::empty_array(),
                     _ => {
                         return Err(crate::EdgelinkError::BadArgument("segs"))
                             .with_context(|| format!("Not allowed to set first property: '{}'", first_prop_name));
                     }
                 };
-                self.as_object_mut().unwrap().insert(first_prop_name.to_string(), var);
+                self.as_object_mut().unwrap().insert(VariantKey::new(first_prop_name), var);
                 self.get_nav_mut(first_prop_name, &[]).unwrap()
             }
             (None, _, _) => {
@@ -577,7 +767,10 @@ impl Variant {
                 *pv = value;
                 Ok(())
             }
-            None if create_missing => first_prop.set_segs_property(&segs[1..], value, true).map_err(Into::into),
+            // Appending to an existing array needs nothing to be created
+            None if create_missing || segs.last() == Some(&PropexSegment::Append) => {
+                first_prop.set_segs_property(&segs[1..], value, create_missing)
+            }
             None => Err(crate::EdgelinkError::InvalidOperation(
                 "Unable to set property: missing intermediate segments".into(),
             )
@@ -650,6 +843,87 @@ impl Debug for Variant {
     }
 }
 
+/// Renders a `Variant` in a human-readable form like the debug sidebar of Node-RED.
+///
+/// Strings are quoted, long buffers are summarized and nested arrays/objects are indented.
+pub struct VariantDisplay<'a>(&'a Variant);
+
+const DISPLAY_MAX_BYTES: usize = 16;
+const DISPLAY_MAX_DEPTH: usize = 32;
+const DISPLAY_INDENT: &str = "  ";
+
+impl VariantDisplay<'_> {
+    fn fmt_value(var: &Variant, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        match var {
+            Variant::Null => f.write_str("null"),
+            Variant::Bool(b) => write!(f, "{}", b),
+            Variant::Number(n) => write!(f, "{}", n),
+            Variant::String(s) => write!(f, "{:?}", s),
+            Variant::Date(d) => {
+                let dt: chrono::DateTime<chrono::Utc> = (*d).into();
+                f.write_str(&dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            }
+            Variant::Regexp(re) => write!(f, "/{}/", re.as_str()),
+            Variant::Bytes(bytes) => {
+                write!(f, "buffer[{}]", bytes.len())?;
+                for b in bytes.iter().take(DISPLAY_MAX_BYTES) {
+                    write!(f, " {:02x}", b)?;
+                }
+                if bytes.len() > DISPLAY_MAX_BYTES {
+                    f.write_str(" …")?;
+                }
+                Ok(())
+            }
+            Variant::Array(arr) => {
+                write!(f, "array[{}]", arr.len())?;
+                if depth >= DISPLAY_MAX_DEPTH {
+                    return if arr.is_empty() { Ok(()) } else { f.write_str(" …") };
+                }
+                for (i, item) in arr.iter().enumerate() {
+                    Self::fmt_child(&format!("[{}]", i), item, f, depth + 1)?;
+                }
+                Ok(())
+            }
+            Variant::Object(map) => {
+                f.write_str("object")?;
+                if depth >= DISPLAY_MAX_DEPTH {
+                    return if map.is_empty() { Ok(()) } else { f.write_str(" …") };
+                }
+                for (k, v) in map.iter() {
+                    Self::fmt_child(k, v, f, depth + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn fmt_child(key: &str, var: &Variant, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        f.write_str("\n")?;
+        for _ in 0..depth {
+            f.write_str(DISPLAY_INDENT)?;
+        }
+        write!(f, "{}: ", key)?;
+        Self::fmt_value(var, f, depth)
+    }
+}
+
+impl fmt::Display for VariantDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Self::fmt_value(self.0, f, 0)
+    }
+}
+
+impl Variant {
+    /// Returns a wrapper that implements `Display` for this variant.
+    pub fn display(&self) -> VariantDisplay<'_> {
+        VariantDisplay(self)
+    }
+
+    pub fn to_display_string(&self) -> String {
+        self.display().to_string()
+    }
+}
+
 impl<'a> PropexEnvSliceExt<'a> for &'a [PropexEnv<'a>] {
     fn find(&self, seg: &str, this: &'a Variant) -> Option<&'a Variant> {
         for s in self.iter() {
@@ -716,6 +990,71 @@ mod tests {
     use super::*;
     use serde_json::*;
 
+    #[test]
+    fn variant_regex_should_match_case_insensitively() {
+        let re = Variant::regex_from("^foo(\\d+)", "i").unwrap();
+        assert!(re.is_regexp());
+        assert!(re.regex_test("FOO42"));
+        assert!(!re.regex_test("bar"));
+        assert_eq!(re.regex_replace("Foo1 foo2", "x$1", false).as_deref(), Some("x1 foo2"));
+        assert!(!Variant::from("^foo").regex_test("foo"));
+
+        let re = Variant::regex_from("o", "g").unwrap();
+        assert_eq!(re.regex_replace("foo", "0", true).as_deref(), Some("f00"));
+        assert!(Variant::regex_from("[", "").is_err());
+    }
+
+    #[test]
+    fn variant_entries_should_round_trip() {
+        let var = Variant::from(json!({"b": 2, "a": [1, 2], "c": {"d": null}}));
+        let entries = var.object_entries().unwrap();
+        assert_eq!(entries, Variant::from(json!([["a", [1, 2]], ["b", 2], ["c", {"d": null}]])));
+        assert_eq!(Variant::from_entries(entries.as_array().unwrap()).unwrap(), var);
+
+        assert_eq!(var.object_keys().unwrap(), Variant::from(json!(["a", "b", "c"])));
+        assert_eq!(var.object_values().unwrap(), Variant::from(json!([[1, 2], 2, {"d": null}])));
+        assert!(Variant::from(1).object_entries().is_none());
+        assert!(Variant::from_entries(&[Variant::from("a")]).is_err());
+    }
+
+    #[test]
+    fn variant_merge_should_keep_existing_keys() {
+        let mut var = Variant::from(json!({"a": 1, "b": {"c": 3}, "e": [1, {"x": 1}]}));
+        var.merge(Variant::from(json!({"b": {"d": 4}, "e": [5, {"y": 2}]})), ArrayMergeMode::Index);
+        assert_eq!(var, Variant::from(json!({"a": 1, "b": {"c": 3, "d": 4}, "e": [5, {"x": 1, "y": 2}]})));
+
+        let mut var = Variant::from(json!({"e": [1, 2]}));
+        var.merge(Variant::from(json!({"e": [3]})), ArrayMergeMode::Concat);
+        assert_eq!(var, Variant::from(json!({"e": [1, 2, 3]})));
+
+        let mut var = Variant::from(json!({"e": [1, 2]}));
+        var.merge(Variant::from(json!({"e": [3]})), ArrayMergeMode::Replace);
+        assert_eq!(var, Variant::from(json!({"e": [3]})));
+    }
+
+    #[test]
+    fn variant_display_should_be_node_red_like() {
+        let mut var = Variant::from(json!({
+            "payload": "hello",
+            "count": 3,
+            "nested": {"flag": true, "items": [1, null]}
+        }));
+        var.as_object_mut().unwrap().insert("buf".into(), Variant::Bytes((0u8..20).collect::<Vec<u8>>().into()));
+        var.as_object_mut().unwrap().insert("small".into(), Variant::Bytes(vec![0xde, 0xad].into()));
+
+        let expected = r#"object
+  buf: buffer[20] 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f …
+  count: 3
+  nested: object
+    flag: true
+    items: array[2]
+      [0]: 1
+      [1]: null
+  payload: "hello"
+  small: buffer[2] de ad"#;
+        assert_eq!(var.to_display_string(), expected);
+    }
+
     #[test]
     fn variant_clone_should_be_ok() {
         let var1 = Variant::Array(vec![
@@ -0,0 +1,253 @@
+use rmp::{encode, Marker};
+
+use super::ser::{date_from_epoch_secs, date_to_epoch_parts};
+use super::*;
+
+/// The MessagePack extension type of the timestamps.
+const MSGPACK_TIMESTAMP_EXT_TYPE: i8 = -1;
+
+impl Variant {
+    /// Encodes the `Variant` into MessagePack.
+    ///
+    /// The bytes are kept as a bin and the dates become the 96-bit timestamps, the only ones covering the dates
+    /// before the epoch.
+    pub fn to_msgpack(&self) -> crate::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        write_msgpack(&mut buf, self)?;
+        Ok(buf)
+    }
+
+    /// Decodes a `Variant` from MessagePack, the timestamps become `Variant::Date` and the other extension types are
+    /// not supported.
+    pub fn from_msgpack(data: &[u8]) -> crate::Result<Variant> {
+        let mut rd = data;
+        let var = read_msgpack(&mut rd)?;
+        if !rd.is_empty() {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "{} bytes left after the MessagePack value",
+                rd.len()
+            ))
+            .into());
+        }
+        Ok(var)
+    }
+}
+
+fn write_msgpack(buf: &mut Vec<u8>, var: &Variant) -> crate::Result<()> {
+    match var {
+        Variant::Null => encode::write_nil(buf)?,
+        Variant::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                encode::write_sint(buf, i)?;
+            } else if let Some(u) = n.as_u64() {
+                encode::write_uint(buf, u)?;
+            } else {
+                encode::write_f64(buf, n.as_f64().unwrap_or(f64::NAN))?;
+            }
+        }
+        Variant::String(s) => encode::write_str(buf, s)?,
+        Variant::Bool(b) => encode::write_bool(buf, *b)?,
+        Variant::Bytes(bytes) => encode::write_bin(buf, bytes)?,
+        Variant::Regexp(re) => encode::write_str(buf, re.as_str())?,
+        Variant::Date(d) => {
+            let (secs, nanos) = date_to_epoch_parts(d);
+            encode::write_ext_meta(buf, 12, MSGPACK_TIMESTAMP_EXT_TYPE)?;
+            buf.extend_from_slice(&nanos.to_be_bytes());
+            buf.extend_from_slice(&secs.to_be_bytes());
+        }
+        Variant::Array(arr) => {
+            encode::write_array_len(buf, msgpack_len(arr.len())?)?;
+            for item in arr {
+                write_msgpack(buf, item)?;
+            }
+        }
+        Variant::Object(map) => {
+            encode::write_map_len(buf, msgpack_len(map.len())?)?;
+            for (k, v) in map {
+                encode::write_str(buf, k)?;
+                write_msgpack(buf, v)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn msgpack_len(len: usize) -> crate::Result<u32> {
+    u32::try_from(len).map_err(|_| EdgelinkError::OutOfRange).context("Too many items for MessagePack")
+}
+
+/// Takes `n` bytes from the input, the lengths are checked against the remaining input before anything is allocated.
+fn take<'a>(rd: &mut &'a [u8], n: usize) -> crate::Result<&'a [u8]> {
+    if rd.len() < n {
+        return Err(EdgelinkError::InvalidOperation("Unexpected end of the MessagePack data".into()).into());
+    }
+    let (head, tail) = rd.split_at(n);
+    *rd = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(rd: &mut &[u8]) -> crate::Result<[u8; N]> {
+    Ok(take(rd, N)?.try_into().expect("The length has been checked"))
+}
+
+fn read_len(rd: &mut &[u8], marker: Marker) -> crate::Result<usize> {
+    let len = match marker {
+        Marker::FixStr(n) | Marker::FixArray(n) | Marker::FixMap(n) => n as usize,
+        Marker::Str8 | Marker::Bin8 | Marker::Ext8 => u8::from_be_bytes(take_array(rd)?) as usize,
+        Marker::Str16 | Marker::Bin16 | Marker::Ext16 | Marker::Array16 | Marker::Map16 => {
+            u16::from_be_bytes(take_array(rd)?) as usize
+        }
+        _ => u32::from_be_bytes(take_array(rd)?) as usize,
+    };
+    Ok(len)
+}
+
+fn read_msgpack(rd: &mut &[u8]) -> crate::Result<Variant> {
+    let marker = Marker::from_u8(take_array::<1>(rd)?[0]);
+    let var = match marker {
+        Marker::Null => Variant::Null,
+        Marker::True => Variant::Bool(true),
+        Marker::False => Variant::Bool(false),
+        Marker::FixPos(n) => Variant::from(n as u64),
+        Marker::FixNeg(n) => Variant::from(n as i64),
+        Marker::U8 => Variant::from(u8::from_be_bytes(take_array(rd)?) as u64),
+        Marker::U16 => Variant::from(u16::from_be_bytes(take_array(rd)?) as u64),
+        Marker::U32 => Variant::from(u32::from_be_bytes(take_array(rd)?) as u64),
+        Marker::U64 => Variant::from(u64::from_be_bytes(take_array(rd)?)),
+        Marker::I8 => Variant::from(i8::from_be_bytes(take_array(rd)?) as i64),
+        Marker::I16 => Variant::from(i16::from_be_bytes(take_array(rd)?) as i64),
+        Marker::I32 => Variant::from(i32::from_be_bytes(take_array(rd)?) as i64),
+        Marker::I64 => Variant::from(i64::from_be_bytes(take_array(rd)?)),
+        Marker::F32 | Marker::F64 => {
+            let f = if marker == Marker::F32 {
+                f32::from_be_bytes(take_array(rd)?) as f64
+            } else {
+                f64::from_be_bytes(take_array(rd)?)
+            };
+            serde_json::Number::from_f64(f)
+                .map(Variant::Number)
+                .ok_or(EdgelinkError::OutOfRange)
+                .with_context(|| format!("Cannot convert the MessagePack float `{}`", f))?
+        }
+        Marker::FixStr(_) | Marker::Str8 | Marker::Str16 | Marker::Str32 => {
+            let len = read_len(rd, marker)?;
+            let s = std::str::from_utf8(take(rd, len)?).context("Bad MessagePack string")?;
+            Variant::String(s.to_string())
+        }
+        Marker::Bin8 | Marker::Bin16 | Marker::Bin32 => {
+            let len = read_len(rd, marker)?;
+            Variant::Bytes(take(rd, len)?.to_vec().into())
+        }
+        Marker::FixArray(_) | Marker::Array16 | Marker::Array32 => {
+            let len = read_len(rd, marker)?;
+            // Every item takes one byte at least
+            let mut arr = Vec::with_capacity(len.min(rd.len()));
+            for _ in 0..len {
+                arr.push(read_msgpack(rd)?);
+            }
+            Variant::Array(arr)
+        }
+        Marker::FixMap(_) | Marker::Map16 | Marker::Map32 => {
+            let len = read_len(rd, marker)?;
+            let mut map = VariantObjectMap::new();
+            for _ in 0..len {
+                let key = match read_msgpack(rd)? {
+                    Variant::String(key) => key,
+                    other => {
+                        return Err(
+                            EdgelinkError::NotSupported(format!("The MessagePack map key `{:?}`", other)).into()
+                        );
+                    }
+                };
+                map.insert(key.into(), read_msgpack(rd)?);
+            }
+            Variant::Object(map)
+        }
+        Marker::FixExt1
+        | Marker::FixExt2
+        | Marker::FixExt4
+        | Marker::FixExt8
+        | Marker::FixExt16
+        | Marker::Ext8
+        | Marker::Ext16
+        | Marker::Ext32 => {
+            let len = match marker {
+                Marker::FixExt1 => 1,
+                Marker::FixExt2 => 2,
+                Marker::FixExt4 => 4,
+                Marker::FixExt8 => 8,
+                Marker::FixExt16 => 16,
+                _ => read_len(rd, marker)?,
+            };
+            let ext_type = i8::from_be_bytes(take_array(rd)?);
+            let data = take(rd, len)?;
+            if ext_type != MSGPACK_TIMESTAMP_EXT_TYPE {
+                return Err(
+                    EdgelinkError::NotSupported(format!("The MessagePack extension type `{}`", ext_type)).into()
+                );
+            }
+            decode_msgpack_timestamp(data).map(Variant::Date).ok_or_else(|| {
+                EdgelinkError::InvalidOperation(format!("Bad MessagePack timestamp of {} bytes", data.len()))
+            })?
+        }
+        Marker::Reserved => {
+            return Err(EdgelinkError::InvalidOperation("The reserved MessagePack marker".into()).into());
+        }
+    };
+    Ok(var)
+}
+
+/// Decodes the 32, 64 and 96-bit timestamps of MessagePack.
+fn decode_msgpack_timestamp(data: &[u8]) -> Option<SystemTime> {
+    match data.len() {
+        4 => Some(date_from_epoch_secs(u32::from_be_bytes(data.try_into().ok()?) as i64, 0)),
+        8 => {
+            let value = u64::from_be_bytes(data.try_into().ok()?);
+            Some(date_from_epoch_secs((value & 0x3_ffff_ffff) as i64, (value >> 34) as u32))
+        }
+        12 => {
+            let nanos = u32::from_be_bytes(data[..4].try_into().ok()?);
+            let secs = i64::from_be_bytes(data[4..].try_into().ok()?);
+            Some(date_from_epoch_secs(secs, nanos))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dates_should_round_trip_through_msgpack() {
+        let dates = [
+            UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            UNIX_EPOCH - Duration::from_millis(86_400_250),
+        ];
+        for date in dates {
+            let mut var = Variant::empty_object();
+            var.as_object_mut().unwrap().insert("date".into(), Variant::Date(date));
+            let decoded = Variant::from_msgpack(&var.to_msgpack().unwrap()).unwrap();
+            assert_eq!(decoded, var);
+        }
+    }
+
+    #[test]
+    fn from_msgpack_should_decode_the_32_bit_timestamps() {
+        let data = [0xd6, 0xff, 0x65, 0x53, 0xf1, 0x00];
+        let var = Variant::from_msgpack(&data).unwrap();
+        assert_eq!(var, Variant::Date(UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+    }
+
+    #[test]
+    fn from_msgpack_should_reject_the_bad_data() {
+        // Another extension type
+        assert!(Variant::from_msgpack(&[0xd4, 0x01, 0x00]).is_err());
+        // An array claiming more items than the data
+        assert!(Variant::from_msgpack(&[0xdd, 0xff, 0xff, 0xff, 0xff]).is_err());
+        // Trailing bytes
+        assert!(Variant::from_msgpack(&[0xc0, 0xc0]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! The predicates to compare `Variant`s, shared by the nodes like `switch`, `change` and `template`.
+//!
+//! The coercions follow the loose operators of JS: numbers, numeric strings and booleans are compared by their
+//! numeric values, everything else by the `Variant` equality.
+
+use std::cmp::Ordering;
+
+use super::*;
+
+/// Compares two values like the loose comparison operators of JS, `None` if they are not comparable.
+pub fn compare(a: &Variant, b: &Variant) -> Option<Ordering> {
+    match (a, b) {
+        (Variant::String(a), Variant::String(b)) => Some(a.cmp(b)),
+        (Variant::Bool(a), Variant::Bool(b)) => Some(a.cmp(b)),
+        (Variant::Null, Variant::Null) => Some(Ordering::Equal),
+        (
+            Variant::Number(_) | Variant::String(_) | Variant::Bool(_),
+            Variant::Number(_) | Variant::String(_) | Variant::Bool(_),
+        ) => to_js_number(a)?.partial_cmp(&to_js_number(b)?),
+        _ => {
+            if a == b {
+                Some(Ordering::Equal)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Tests the equality, numbers are always compared by their values so `1` equals `1.0`.
+///
+/// With `coerce` the numeric strings and booleans are converted to numbers first, like the `==` of JS.
+pub fn equals(a: &Variant, b: &Variant, coerce: bool) -> bool {
+    match (a, b) {
+        (Variant::Number(x), Variant::Number(y)) => x.as_f64() == y.as_f64(),
+        _ if coerce => compare(a, b) == Some(Ordering::Equal),
+        _ => a == b,
+    }
+}
+
+/// Tests the membership of `needle`:
+/// - a substring of a string, the needle is converted to string first;
+/// - an element of an array by the `Variant` equality;
+/// - a key of an object;
+/// - a byte of a buffer.
+pub fn contains(haystack: &Variant, needle: &Variant) -> bool {
+    match haystack {
+        Variant::String(s) => to_js_string(needle).is_some_and(|x| s.contains(x.as_str())),
+        Variant::Array(arr) => arr.contains(needle),
+        Variant::Object(obj) => to_js_string(needle).is_some_and(|x| obj.contains_key(x.as_str())),
+        Variant::Bytes(bytes) => needle.as_u8().is_some_and(|x| bytes.contains(&x)),
+        _ => false,
+    }
+}
+
+/// Tests whether the string starts with `prefix`, the prefix is converted to string first.
+pub fn starts_with(a: &Variant, prefix: &Variant) -> bool {
+    match (a, to_js_string(prefix)) {
+        (Variant::String(s), Some(prefix)) => s.starts_with(prefix.as_str()),
+        _ => false,
+    }
+}
+
+/// Tests whether the string ends with `suffix`, the suffix is converted to string first.
+pub fn ends_with(a: &Variant, suffix: &Variant) -> bool {
+    match (a, to_js_string(suffix)) {
+        (Variant::String(s), Some(suffix)) => s.ends_with(suffix.as_str()),
+        _ => false,
+    }
+}
+
+/// Converts the value to number like `Number()` of JS, only for numbers, strings and booleans.
+pub fn to_js_number(v: &Variant) -> Option<f64> {
+    match v {
+        Variant::Number(n) => n.as_f64(),
+        Variant::String(s) if s.trim().is_empty() => Some(0.0),
+        Variant::String(s) => s.trim().parse::<f64>().ok(),
+        Variant::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Converts the value to string like `String()` of JS, only for numbers, strings and booleans.
+pub fn to_js_string(v: &Variant) -> Option<String> {
+    match v {
+        Variant::String(s) => Some(s.clone()),
+        Variant::Number(n) => Some(n.to_string()),
+        Variant::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn v(jv: serde_json::Value) -> Variant {
+        Variant::from(jv)
+    }
+
+    #[test]
+    fn test_equals() {
+        assert!(equals(&v(json!(1)), &v(json!(1.0)), false));
+        assert!(equals(&v(json!("a")), &v(json!("a")), false));
+        assert!(equals(&v(json!([1, {"a": null}])), &v(json!([1, {"a": null}])), false));
+        assert!(!equals(&v(json!(1)), &v(json!("1")), false));
+        assert!(!equals(&v(json!(true)), &v(json!(1)), false));
+
+        assert!(equals(&v(json!(1)), &v(json!("1")), true));
+        assert!(equals(&v(json!(" 2.5 ")), &v(json!(2.5)), true));
+        assert!(equals(&v(json!(true)), &v(json!(1)), true));
+        assert!(equals(&v(json!(null)), &v(json!(null)), true));
+        assert!(!equals(&v(json!("a")), &v(json!(0)), true));
+        assert!(!equals(&v(json!(null)), &v(json!(0)), true));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(&v(json!(2)), &v(json!("10"))), Some(Ordering::Less));
+        assert_eq!(compare(&v(json!("2")), &v(json!("10"))), Some(Ordering::Greater));
+        assert_eq!(compare(&v(json!(false)), &v(json!(true))), Some(Ordering::Less));
+        assert_eq!(compare(&v(json!([1])), &v(json!(1))), None);
+        assert_eq!(compare(&v(json!("1")), &v(json!(true))), Some(Ordering::Equal));
+        assert_eq!(compare(&v(json!("")), &v(json!(false))), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn test_compare_should_be_symmetric() {
+        let scalars = [
+            json!(null),
+            json!(0),
+            json!(1),
+            json!(2.5),
+            json!(""),
+            json!("0"),
+            json!("1"),
+            json!(" 2.5 "),
+            json!("a"),
+            json!(true),
+            json!(false),
+        ];
+        for a in scalars.iter().map(|x| v(x.clone())) {
+            for b in scalars.iter().map(|x| v(x.clone())) {
+                assert_eq!(compare(&a, &b), compare(&b, &a).map(Ordering::reverse), "{:?} <=> {:?}", a, b);
+                assert_eq!(equals(&a, &b, true), equals(&b, &a, true), "{:?} == {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(contains(&v(json!("hello world")), &v(json!("lo w"))));
+        assert!(contains(&v(json!("a1b")), &v(json!(1))));
+        assert!(!contains(&v(json!("hello")), &v(json!(null))));
+
+        assert!(contains(&v(json!([1, "a", {"b": 2}])), &v(json!({"b": 2}))));
+        assert!(contains(&v(json!([1, "a"])), &v(json!("a"))));
+        assert!(!contains(&v(json!([1, "a"])), &v(json!("1"))));
+
+        assert!(contains(&v(json!({"a": 1, "2": null})), &v(json!("a"))));
+        assert!(contains(&v(json!({"a": 1, "2": null})), &v(json!(2))));
+        assert!(!contains(&v(json!({"a": 1})), &v(json!(1))));
+
+        assert!(contains(&Variant::Bytes(vec![1, 2, 3].into()), &v(json!(2))));
+        assert!(!contains(&Variant::Bytes(vec![1, 2, 3].into()), &v(json!(4))));
+        assert!(!contains(&v(json!(123)), &v(json!(2))));
+    }
+
+    #[test]
+    fn test_starts_and_ends_with() {
+        assert!(starts_with(&v(json!("hello")), &v(json!("he"))));
+        assert!(starts_with(&v(json!("123")), &v(json!(12))));
+        assert!(!starts_with(&v(json!("hello")), &v(json!("lo"))));
+        assert!(!starts_with(&v(json!(123)), &v(json!("1"))));
+
+        assert!(ends_with(&v(json!("hello")), &v(json!("lo"))));
+        assert!(ends_with(&v(json!("v1.0")), &v(json!(".0"))));
+        assert!(!ends_with(&v(json!("hello")), &v(json!("he"))));
+        assert!(!ends_with(&v(json!(["a"])), &v(json!("a"))));
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use serde::ser::Serialize;
 
 use super::*;
@@ -12,18 +14,17 @@ impl Serialize for Variant {
             Variant::Number(v) => v.serialize(serializer),
             Variant::String(v) => serializer.serialize_str(v),
             Variant::Bool(v) => serializer.serialize_bool(*v),
+            // Binary formats like CBOR and MessagePack keep the bytes as-is
+            Variant::Bytes(v) if !serializer.is_human_readable() => serializer.serialize_bytes(v),
             Variant::Bytes(v) => {
                 let mut seq = serializer.serialize_seq(Some(v.len()))?;
-                for item in v {
+                for item in v.iter() {
                     seq.serialize_element(item)?;
                 }
                 seq.end()
             }
             Variant::Regexp(v) => serializer.serialize_str(v.as_str()),
-            Variant::Date(v) => {
-                let ts = v.duration_since(UNIX_EPOCH).map_err(serde::ser::Error::custom)?;
-                serializer.serialize_u64(ts.as_millis() as u64)
-            }
+            Variant::Date(v) => serializer.serialize_i64(date_to_epoch_millis(v)),
             Variant::Array(v) => {
                 let mut seq = serializer.serialize_seq(Some(v.len()))?;
                 for item in v {
@@ -42,6 +43,40 @@ impl Serialize for Variant {
     }
 }
 
+impl Variant {
+    /// Converts to a `serde_json::Value` directly, the same as `serde_json::to_value()` but without the serializer.
+    ///
+    /// The bytes become an array of numbers and the dates the milliseconds since the Unix epoch, negative before the
+    /// epoch like `Date.getTime()` of JS. It cannot fail, just like the JSON serialization.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        match self {
+            Variant::Null => serde_json::Value::Null,
+            Variant::Number(v) => serde_json::Value::Number(v.clone()),
+            Variant::String(v) => serde_json::Value::String(v.clone()),
+            Variant::Bool(v) => serde_json::Value::Bool(*v),
+            Variant::Bytes(v) => serde_json::Value::Array(v.iter().map(|x| serde_json::Value::from(*x)).collect()),
+            Variant::Regexp(v) => serde_json::Value::String(v.as_str().to_string()),
+            Variant::Date(v) => serde_json::Value::from(date_to_epoch_millis(v)),
+            Variant::Array(v) => serde_json::Value::Array(v.iter().map(Variant::to_json_value).collect()),
+            Variant::Object(v) => {
+                serde_json::Value::Object(v.iter().map(|(k, v)| (k.to_string(), v.to_json_value())).collect())
+            }
+        }
+    }
+
+    /// Formats as the indented JSON of `to_json_value()`, with `indent` spaces per level, for the snapshots.
+    ///
+    /// The properties are sorted by their keys at every level, so the same value always gives the same string.
+    pub fn to_pretty_json_string(&self, indent: usize) -> String {
+        let indent = " ".repeat(indent);
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+        let mut buf = Vec::new();
+        let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+        self.to_json_value().serialize(&mut ser).expect("Writing JSON into a `Vec` cannot fail");
+        String::from_utf8(buf).expect("The JSON must be UTF-8")
+    }
+}
+
 impl<'de> Deserialize<'de> for Variant {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -63,6 +98,20 @@ impl<'de> Deserialize<'de> for Variant {
                 Ok(Variant::Null)
             }
 
+            fn visit_none<E>(self) -> Result<Variant, E>
+            where
+                E: de::Error,
+            {
+                Ok(Variant::Null)
+            }
+
+            fn visit_some<D>(self, deserializer: D) -> Result<Variant, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                Deserialize::deserialize(deserializer)
+            }
+
             fn visit_bool<E>(self, value: bool) -> Result<Variant, E>
             where
                 E: de::Error,
@@ -102,7 +151,7 @@ impl<'de> Deserialize<'de> for Variant {
             where
                 E: de::Error,
             {
-                Ok(Variant::Bytes(value.to_vec()))
+                Ok(Variant::Bytes(value.to_vec().into()))
             }
 
             fn visit_seq<A>(self, mut seq: A) -> Result<Variant, A::Error>
@@ -131,3 +180,123 @@ impl<'de> Deserialize<'de> for Variant {
         deserializer.deserialize_any(VariantVisitor)
     }
 }
+
+/// Splits the date into the seconds since the Unix epoch, negative before it, and the nanoseconds after them.
+pub(super) fn date_to_epoch_parts(date: &SystemTime) -> (i64, u32) {
+    match date.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            let d = e.duration();
+            match d.subsec_nanos() {
+                0 => (-(d.as_secs() as i64), 0),
+                nanos => (-(d.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// The whole milliseconds since the epoch, rounded down like the dates of JS.
+fn date_to_epoch_millis(date: &SystemTime) -> i64 {
+    let (secs, nanos) = date_to_epoch_parts(date);
+    secs * 1000 + (nanos / 1_000_000) as i64
+}
+
+pub(super) fn date_from_epoch_secs(secs: i64, nanos: u32) -> SystemTime {
+    let date = if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    };
+    date + Duration::from_nanos(nanos as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_complex_variant() -> Variant {
+        let mut var = Variant::from(json!({
+            "int": -123,
+            "uint": 18446744073709551615u64,
+            "float": 3.5,
+            "str": "hello",
+            "bool": true,
+            "null": null,
+            "array": [1, "two", [3.0, {"four": 4}]],
+            "object": {"nested": {"deep": [null, false]}}
+        }));
+        var.as_object_mut().unwrap().insert("bytes".into(), Variant::Bytes(vec![0, 1, 2, 254, 255].into()));
+        var
+    }
+
+    #[test]
+    fn variant_should_round_trip_through_cbor() {
+        let var = make_complex_variant();
+        let decoded = Variant::from_cbor(&var.to_cbor().unwrap()).unwrap();
+        assert_eq!(decoded, var);
+        assert!(decoded.as_object().unwrap()["bytes"].is_bytes());
+    }
+
+    #[test]
+    fn variant_should_round_trip_through_msgpack() {
+        let var = make_complex_variant();
+        let decoded = Variant::from_msgpack(&var.to_msgpack().unwrap()).unwrap();
+        assert_eq!(decoded, var);
+        assert!(decoded.as_object().unwrap()["bytes"].is_bytes());
+    }
+
+    #[test]
+    fn to_json_value_should_match_the_serializer_before_the_epoch() {
+        let dates = [
+            (UNIX_EPOCH - std::time::Duration::from_millis(86_400_250), -86_400_250),
+            (UNIX_EPOCH - std::time::Duration::from_micros(1500), -2),
+            (UNIX_EPOCH, 0),
+        ];
+        for (date, millis) in dates {
+            let var = Variant::Date(date);
+            assert_eq!(var.to_json_value(), json!(millis));
+            assert_eq!(serde_json::to_value(&var).unwrap(), json!(millis));
+        }
+    }
+
+    #[test]
+    fn to_json_value_should_match_the_serializer() {
+        let mut var = make_complex_variant();
+        let obj = var.as_object_mut().unwrap();
+        obj.insert("date".into(), Variant::Date(UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123)));
+        obj.insert("regexp".into(), Variant::Regexp(Regex::new("^a+b$").unwrap()));
+        obj.insert("empty".into(), Variant::Array(vec![Variant::empty_object(), Variant::Bytes(vec![].into())]));
+        assert_eq!(var.to_json_value(), serde_json::to_value(&var).unwrap());
+    }
+
+    #[test]
+    fn to_pretty_json_string_should_sort_the_keys_and_indent() {
+        let mut var = Variant::from(json!({"zeta": [1, {"b": true, "a": null}], "alpha": {"y": "s", "x": 2.5}}));
+        var.as_object_mut().unwrap().insert("mid".into(), Variant::Bytes(vec![7].into()));
+        let expected = r#"{
+    "alpha": {
+        "x": 2.5,
+        "y": "s"
+    },
+    "mid": [
+        7
+    ],
+    "zeta": [
+        1,
+        {
+            "a": null,
+            "b": true
+        }
+    ]
+}"#;
+        assert_eq!(var.to_pretty_json_string(4), expected);
+        assert_eq!(Variant::from(json!({"b": 1, "a": []})).to_pretty_json_string(2), "{\n  \"a\": [],\n  \"b\": 1\n}");
+    }
+
+    #[test]
+    fn bytes_should_stay_an_array_in_json() {
+        let var = Variant::Bytes(vec![1, 2, 3].into());
+        assert_eq!(serde_json::to_value(&var).unwrap(), json!([1, 2, 3]));
+    }
+}
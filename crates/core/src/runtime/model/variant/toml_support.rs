@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use super::*;
+
+impl Variant {
+    /// Converts a TOML value into a `Variant`.
+    ///
+    /// Offset and local datetimes become `Variant::Date` (local ones are treated as UTC), a bare date is taken
+    /// as midnight UTC and a bare time is kept as a string.
+    pub fn from_toml(tv: &toml::Value) -> crate::Result<Variant> {
+        let var = match tv {
+            toml::Value::String(s) => Variant::String(s.clone()),
+            toml::Value::Integer(i) => Variant::from(*i),
+            toml::Value::Float(f) => serde_json::Number::from_f64(*f)
+                .map(Variant::Number)
+                .ok_or(EdgelinkError::OutOfRange)
+                .with_context(|| format!("Cannot convert the TOML float `{}`", f))?,
+            toml::Value::Boolean(b) => Variant::Bool(*b),
+            toml::Value::Datetime(dt) => toml_datetime_to_variant(dt)?,
+            toml::Value::Array(arr) => Variant::Array(arr.iter().map(Variant::from_toml).collect::<Result<_, _>>()?),
+            toml::Value::Table(table) => Variant::Object(
+                table
+                    .iter()
+                    .map(|(k, v)| Ok((k.into(), Variant::from_toml(v)?)))
+                    .collect::<crate::Result<VariantObjectMap>>()?,
+            ),
+        };
+        Ok(var)
+    }
+
+    /// Converts the `Variant` into a TOML value, TOML has no null so `Variant::Null` cannot be converted.
+    pub fn to_toml(&self) -> crate::Result<toml::Value> {
+        let tv = match self {
+            Variant::Null => {
+                return Err(EdgelinkError::NotSupported("TOML does not support the null value".into()).into());
+            }
+            Variant::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    toml::Value::Integer(i)
+                } else if let Some(f) = n.as_f64() {
+                    toml::Value::Float(f)
+                } else {
+                    return Err(EdgelinkError::OutOfRange)
+                        .with_context(|| format!("Cannot convert the number `{}`", n));
+                }
+            }
+            Variant::String(s) => toml::Value::String(s.clone()),
+            Variant::Bool(b) => toml::Value::Boolean(*b),
+            Variant::Date(d) => {
+                let dt: chrono::DateTime<chrono::Utc> = (*d).into();
+                let s = dt.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+                toml::Value::Datetime(toml::value::Datetime::from_str(&s)?)
+            }
+            Variant::Regexp(re) => toml::Value::String(re.as_str().to_string()),
+            Variant::Bytes(bytes) => {
+                toml::Value::Array(bytes.iter().map(|x| toml::Value::Integer(*x as i64)).collect())
+            }
+            Variant::Array(arr) => toml::Value::Array(arr.iter().map(|x| x.to_toml()).collect::<Result<_, _>>()?),
+            Variant::Object(map) => toml::Value::Table(
+                map.iter().map(|(k, v)| Ok((k.to_string(), v.to_toml()?))).collect::<crate::Result<toml::Table>>()?,
+            ),
+        };
+        Ok(tv)
+    }
+}
+
+fn toml_datetime_to_variant(dt: &toml::value::Datetime) -> crate::Result<Variant> {
+    let s = dt.to_string();
+    match (dt.date, dt.time, dt.offset) {
+        (Some(_), Some(_), Some(_)) => {
+            let parsed = chrono::DateTime::parse_from_rfc3339(&s)?;
+            Ok(Variant::Date(parsed.with_timezone(&chrono::Utc).into()))
+        }
+        (Some(_), Some(_), None) => {
+            let parsed = chrono::NaiveDateTime::from_str(&s)?;
+            Ok(Variant::Date(parsed.and_utc().into()))
+        }
+        (Some(_), None, _) => {
+            let parsed = chrono::NaiveDate::from_str(&s)?;
+            Ok(Variant::Date(parsed.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().into()))
+        }
+        _ => Ok(Variant::String(s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variant_should_round_trip_through_toml() {
+        let toml_str = r#"
+            name = "edgelink"
+            port = 1883
+            ratio = 0.5
+            enabled = true
+            started = 1979-05-27T07:32:00Z
+
+            [[stores]]
+            name = "memory"
+            tags = ["a", "b"]
+
+            [[stores]]
+            name = "localfs"
+            dir = "/tmp"
+        "#;
+        let tv: toml::Value = toml::from_str(toml_str).unwrap();
+
+        let var = Variant::from_toml(&tv).unwrap();
+        assert!(matches!(var.get_nav("started", &[]), Some(Variant::Date(_))));
+        let stores = var.get_nav("stores", &[]).unwrap().as_array().unwrap();
+        assert_eq!(stores.len(), 2);
+        assert!(stores.iter().all(|x| x.is_object()));
+        assert_eq!(var.get_nav("stores[1].dir", &[]).unwrap().as_str(), Some("/tmp"));
+
+        assert_eq!(var.to_toml().unwrap(), tv);
+    }
+
+    #[test]
+    fn null_should_not_be_converted_to_toml() {
+        assert!(Variant::Null.to_toml().is_err());
+    }
+}
@@ -20,6 +20,8 @@ pub enum CatchNodeScope {
     All,
     Group,
     Nodes(Vec<ElementId>),
+    /// The nodes of all flows, handled by the engine only after no `catch` node of the failed flow caught the error.
+    Global,
 }
 
 impl CatchNodeScope {
@@ -90,6 +92,7 @@ impl<'de> Deserialize<'de> for CatchNodeScope {
             {
                 match value {
                     "group" => Ok(CatchNodeScope::Group),
+                    "global" => Ok(CatchNodeScope::Global),
                     _ => Err(serde::de::Error::invalid_value(serde::de::Unexpected::Str(value), &self)),
                 }
             }
@@ -106,3 +109,238 @@ impl<'de> Deserialize<'de> for CatchNodeScope {
         deserializer.deserialize_any(CatchNodeScopeVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A node outside of the built-in ones, reports an error for every message received.
+    #[derive(Debug)]
+    #[flow_node("test-report-error")]
+    struct ReportErrorNode {
+        base: FlowNode,
+    }
+
+    impl ReportErrorNode {
+        fn build(
+            _flow: &Flow,
+            state: FlowNode,
+            _config: &RedFlowNodeConfig,
+        ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+            Ok(Box::new(ReportErrorNode { base: state }))
+        }
+    }
+
+    #[async_trait]
+    impl FlowNodeBehavior for ReportErrorNode {
+        fn get_node(&self) -> &FlowNode {
+            &self.base
+        }
+
+        async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+            while !stop_token.is_cancelled() {
+                match self.recv_msg(stop_token.clone()).await {
+                    Ok(msg) => self.report_error("something went wrong".to_string(), msg, stop_token.clone()).await,
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_catch_errors_reported_by_any_node_type() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-report-error"},
+            {"id": "2", "z": "100", "type": "catch", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_str(), Some("foo"));
+        assert_eq!(msgs[0].get_nav("error.message").and_then(|x| x.as_str()), Some("something went wrong"));
+        assert_eq!(msgs[0].get_nav("error.source.type").and_then(|x| x.as_str()), Some("test-report-error"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_only_catch_errors_of_its_own_subflow_instance() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "subflow:200", "wires": [["5"]]},
+            {"id": "2", "z": "100", "type": "subflow:200", "wires": [["6"]]},
+            {"id": "5", "z": "100", "type": "change",
+                "rules": [{"t": "set", "p": "instance", "pt": "msg", "to": "A", "tot": "str"}], "wires": [["7"]]},
+            {"id": "6", "z": "100", "type": "change",
+                "rules": [{"t": "set", "p": "instance", "pt": "msg", "to": "B", "tot": "str"}], "wires": [["7"]]},
+            {"id": "7", "z": "100", "type": "junction", "wires": []},
+            // Subflow
+            {"id": "200", "type": "subflow", "name": "Subflow",
+                "in": [{"wires": [{"id": "3"}]}], "out": [{"wires": [{"id": "4", "port": 0}]}]},
+            {"id": "3", "z": "200", "type": "function",
+                "func": "if (msg.payload === 'bad') { throw new Error('boom'); } return null;", "wires": [[]]},
+            {"id": "4", "z": "200", "type": "catch", "scope": ["3"], "uncaught": false, "wires": []}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        let good = MsgHandle::new(Msg::deserialize(json!({"payload": "good"})).unwrap());
+        engine.inject_msg(&ElementId::from(2), good, cancel).await.unwrap();
+
+        // Collects every message until the timeout, the catch node of the other instance must not report it again
+        let bad = Msg::deserialize(json!({"payload": "bad"})).unwrap();
+        let timeout = std::time::Duration::from_millis(400);
+        let msgs = engine.inject_and_collect_at(&ElementId::from(1), &ElementId::from(7), bad, timeout).await.unwrap();
+        engine.stop().await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["instance"], Variant::from("A"));
+        assert!(msgs[0].contains("error"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_try_the_other_catch_nodes_after_a_scope_mismatch() {
+        // The catch nodes not watching the failed node come first, they must not stop the others
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-report-error"},
+            {"id": "9", "z": "100", "type": "test-report-error"},
+            {"id": "2", "z": "100", "type": "catch", "scope": ["9"], "wires": [["6"]]},
+            {"id": "300", "type": "group", "z": "100", "nodes": ["3"]},
+            {"id": "3", "z": "100", "g": "300", "type": "catch", "scope": "group", "wires": [["6"]]},
+            {"id": "4", "z": "100", "type": "catch", "scope": ["1"], "wires": [["6"]]},
+            {"id": "6", "z": "100", "type": "junction", "wires": []}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+        let msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+        let timeout = std::time::Duration::from_millis(400);
+        let msgs = engine.inject_and_collect_at(&ElementId::from(1), &ElementId::from(6), msg, timeout).await.unwrap();
+        engine.stop().await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].get_nav("error.source.id").and_then(|x| x.as_str()), Some("0000000000000001"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_break_the_catch_loop() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-report-error"},
+            {"id": "2", "z": "100", "type": "catch", "scope": ["1"], "wires": [["1"]]}
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.flow]
+                node_msg_queue_capacity = 16
+                max_catch_count = 3
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let mut tap = engine.tap_output(&"2".parse().unwrap()).unwrap();
+        engine.start().await.unwrap();
+
+        // Every new message starts counting from scratch
+        let cancel = CancellationToken::new();
+        for payload in ["foo", "bar"] {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": payload})).unwrap());
+            engine.inject_msg(&"1".parse().unwrap(), msg, cancel.clone()).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+        engine.stop().await.unwrap();
+
+        let mut caught = Vec::new();
+        while let Ok((_, msg)) = tap.try_recv() {
+            let count = msg.get_nav("error.source.count").and_then(|x| x.as_u64()).unwrap();
+            caught.push((msg["payload"].as_str().unwrap().to_string(), count));
+        }
+        let expected = [("foo", 1), ("foo", 2), ("bar", 1), ("bar", 2)];
+        assert_eq!(caught, expected.map(|(p, c)| (p.to_string(), c)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_catch_errors_of_other_flows_in_global_scope() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-report-error"},
+            {"id": "200", "type": "tab"},
+            {"id": "2", "z": "200", "type": "test-report-error"},
+            {"id": "3", "z": "200", "type": "catch", "scope": ["2"], "wires": [["5"]]},
+            {"id": "300", "type": "tab"},
+            {"id": "4", "z": "300", "type": "catch", "scope": "global", "wires": [["5"]]},
+            {"id": "5", "z": "300", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+            ["2", {"payload": "bar"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut tap = engine.tap_output(&"4".parse().unwrap()).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        let mut caught: Vec<_> = msgs
+            .iter()
+            .map(|x| (x["payload"].as_str().unwrap(), x.get_nav("error.source.id").and_then(|x| x.as_str())))
+            .collect();
+        caught.sort();
+        assert_eq!(caught, vec![("bar", Some("0000000000000002")), ("foo", Some("0000000000000001"))]);
+
+        // The error caught in its own flow never reaches the global scoped catch node
+        let mut global_caught = Vec::new();
+        while let Ok((_, msg)) = tap.try_recv() {
+            global_caught.push(msg["payload"].as_str().unwrap().to_string());
+        }
+        assert_eq!(global_caught, vec!["foo".to_string()]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_honor_uncaught_of_the_global_scoped_catch_nodes() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-report-error"},
+            {"id": "300", "type": "tab"},
+            {"id": "2", "z": "300", "type": "catch", "scope": "global", "uncaught": true, "wires": [["4"]]},
+            {"id": "3", "z": "300", "type": "catch", "scope": "global", "uncaught": false, "wires": [["4"]]},
+            {"id": "4", "z": "300", "type": "test-once"}
+        ]);
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": "foo"})).unwrap())];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut uncaught_tap = engine.tap_output(&"2".parse().unwrap()).unwrap();
+        let mut caught_tap = engine.tap_output(&"3".parse().unwrap()).unwrap();
+        assert_eq!(engine.global_catch_nodes().len(), 2);
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(uncaught_tap.try_recv().is_err());
+        assert_eq!(caught_tap.try_recv().unwrap().1["payload"].as_str(), Some("foo"));
+
+        // The flows are not loaded again when restarted, so they must be kept after stopping
+        assert_eq!(engine.global_catch_nodes().len(), 2);
+        engine.start().await.unwrap();
+        assert_eq!(engine.global_catch_nodes().len(), 2);
+        engine.stop().await.unwrap();
+        assert_eq!(engine.global_catch_nodes().len(), 2);
+    }
+}
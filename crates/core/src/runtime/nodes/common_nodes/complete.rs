@@ -47,3 +47,38 @@ impl FlowNodeBehavior for CompleteNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_watch_the_nodes_of_other_flows() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [[]]},
+            {"id": "2", "z": "100", "type": "complete", "scope": ["1"], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "200", "type": "tab"},
+            {"id": "4", "z": "200", "type": "complete", "scope": ["1"], "wires": [["5"]]},
+            {"id": "5", "z": "200", "type": "change", "rules": [
+                {"t": "set", "p": "other", "pt": "msg", "to": "true", "tot": "bool"}], "wires": [["6"]]},
+            {"id": "6", "z": "200", "type": "test-once"}
+        ]);
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": "foo"})).unwrap())];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut other_flow_tap = engine.tap_output(&ElementId::from(4)).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs.iter().all(|x| x["payload"].as_str() == Some("foo")));
+        assert_eq!(msgs.iter().filter(|x| x.contains("other")).count(), 1);
+
+        // The flow of the watched node notifies the `complete` node of the other flow exactly once
+        assert!(other_flow_tap.try_recv().is_ok());
+        assert!(other_flow_tap.try_recv().is_err());
+    }
+}
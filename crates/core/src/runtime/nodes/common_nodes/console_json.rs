@@ -28,9 +28,7 @@ impl FlowNodeBehavior for ConsoleJsonNode {
         while !stop_token.is_cancelled() {
             let cancel = stop_token.child_token();
             with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move {
-                let guard = msg.read().await;
-                let msg_to_ser = guard.clone();
-                let json_value = serde_json::to_value(&msg_to_ser)?;
+                let json_value = msg.read().await.to_json_value();
                 let json_text = serde_json::to_string(&json_value)?;
                 let mut stdout = io::stdout();
                 stdout.write_all(&[0x1e]).await?; // add `0x1E` character
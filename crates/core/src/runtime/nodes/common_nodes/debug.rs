@@ -2,11 +2,26 @@ use serde;
 use serde::Deserialize;
 use std::sync::Arc;
 
+use log4rs::append::rolling_file::policy::compound::roll::delete::DeleteRoller;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::roll::Roll;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::trigger::time::{TimeTrigger, TimeTriggerConfig};
+use log4rs::append::rolling_file::policy::compound::trigger::Trigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::append::Append;
+use log4rs::encode::pattern::PatternEncoder;
+
 use crate::runtime::flow::Flow;
 use crate::runtime::model::json::RedFlowNodeConfig;
+use crate::runtime::model::redact::Redactor;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
 
+const FILE_SINK_PATTERN: &str = "{d(%Y-%m-%dT%H:%M:%S%.3f%:z)} {m}{n}";
+const FILE_SINK_QUEUE_CAPACITY: usize = 1024;
+
 #[derive(Deserialize, Debug)]
 struct DebugNodeConfig {
     //#[serde(default)]
@@ -15,25 +30,121 @@ struct DebugNodeConfig {
     //target_type: String,
     #[serde(default)]
     complete: String,
+
+    /// Writes the debug output into a rolling log file.
+    #[serde(default, rename = "tofile")]
+    to_file: bool,
+
+    #[serde(default)]
+    file: String,
+
+    /// Rolls the file when it exceeds this size in bytes, ignored if `fileRotateInterval` was set.
+    #[serde(default = "default_file_max_size", rename = "fileMaxSize")]
+    file_max_size: u64,
+
+    /// The number of rolled files to keep, `0` means the rolled file will be deleted.
+    #[serde(default = "default_file_max_files", rename = "fileMaxFiles")]
+    file_max_files: u32,
+
+    /// Rolls the file by time, e.g. `"1 hour"` or `"1 day"`.
+    #[serde(default, rename = "fileRotateInterval")]
+    file_rotate_interval: Option<String>,
+}
+
+fn default_file_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_file_max_files() -> u32 {
+    5
 }
 
 #[derive(Debug)]
 #[flow_node("debug")]
 struct DebugNode {
     base: FlowNode,
-    _config: DebugNodeConfig,
+    config: DebugNodeConfig,
+    file_appender: Option<Arc<RollingFileAppender>>,
+    redactor: Arc<Redactor>,
 }
 
 impl DebugNode {
-    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+    fn build(flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
         let mut debug_config: DebugNodeConfig = DebugNodeConfig::deserialize(&config.rest)?;
         if debug_config.complete.is_empty() {
             debug_config.complete = "payload".to_string();
         }
 
-        let node = DebugNode { base: state, _config: debug_config };
+        let file_appender = if debug_config.to_file {
+            let appender = Self::build_file_appender(&debug_config)
+                .with_context(|| format!("Failed to open the debug file '{}'", debug_config.file))?;
+            Some(Arc::new(appender))
+        } else {
+            None
+        };
+
+        let redactor = flow.engine().map(|x| x.redactor()).unwrap_or_default();
+        let node = DebugNode { base: state, config: debug_config, file_appender, redactor };
         Ok(Box::new(node))
     }
+
+    fn build_file_appender(config: &DebugNodeConfig) -> crate::Result<RollingFileAppender> {
+        if config.file.is_empty() {
+            return Err(EdgelinkError::BadFlowsJson("The `file` of the debug node is required".into()).into());
+        }
+
+        let trigger: Box<dyn Trigger> = match &config.file_rotate_interval {
+            Some(interval) => {
+                let trigger_config = TimeTriggerConfig::deserialize(serde_json::json!({ "interval": interval }))
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad `fileRotateInterval`: {}", e)))?;
+                Box::new(TimeTrigger::new(trigger_config))
+            }
+            None => Box::new(SizeTrigger::new(config.file_max_size)),
+        };
+
+        let roller: Box<dyn Roll> = if config.file_max_files > 0 {
+            Box::new(FixedWindowRoller::builder().build(&format!("{}.{{}}", config.file), config.file_max_files)?)
+        } else {
+            Box::new(DeleteRoller::new())
+        };
+
+        let appender = RollingFileAppender::builder()
+            .encoder(Box::new(PatternEncoder::new(FILE_SINK_PATTERN)))
+            .build(&config.file, Box::new(CompoundPolicy::new(trigger, roller)))?;
+        Ok(appender)
+    }
+
+    fn format_entry(&self, msg: &Msg) -> String {
+        if self.config.complete == "true" {
+            format!("[debug:{}] msg : {}", self.name(), self.redactor.redact_msg(msg).display())
+        } else {
+            match msg.get_nav_stripped(&self.config.complete) {
+                Some(value) => {
+                    let value = self.redactor.redact_value(&self.config.complete, value);
+                    format!("[debug:{}] msg.{} : {}", self.name(), self.config.complete, value.display())
+                }
+                None => format!("[debug:{}] msg.{} : undefined", self.name(), self.config.complete),
+            }
+        }
+    }
+
+    /// Spawns the blocking writer of the file sink, so the message loop never waits for the disk.
+    fn spawn_file_writer(
+        appender: Arc<RollingFileAppender>,
+    ) -> (tokio::sync::mpsc::Sender<String>, tokio::task::JoinHandle<()>) {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(FILE_SINK_QUEUE_CAPACITY);
+        let handle = tokio::task::spawn_blocking(move || {
+            while let Some(entry) = rx.blocking_recv() {
+                let result = appender
+                    .append(&log::Record::builder().args(format_args!("{}", entry)).level(log::Level::Info).build());
+                if let Err(err) = result {
+                    log::error!("Failed to write the debug file: {:?}", err);
+                }
+            }
+            appender.flush();
+        });
+        (tx, handle)
+    }
 }
 
 #[async_trait]
@@ -43,21 +154,122 @@ impl FlowNodeBehavior for DebugNode {
     }
 
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let file_writer = self.file_appender.clone().map(Self::spawn_file_writer);
+        let file_tx = file_writer.as_ref().map(|(tx, _)| tx.clone());
+
         while !stop_token.is_cancelled() {
             if self.base.active {
-                match self.recv_msg(stop_token.child_token()).await {
-                    Ok(msg) => {
-                        let msg = msg.read().await;
-                        log::info!("[debug:{}] Message Received: \n{:#?}", self.name(), &msg)
+                let file_tx = file_tx.clone();
+                with_uow(self.as_ref(), stop_token.child_token(), |node, msg| async move {
+                    let msg = msg.read().await;
+                    if node.redactor.is_enabled() {
+                        log::info!("[debug:{}] Message Received: \n{:#?}", node.name(), node.redactor.redact_msg(&msg));
+                    } else {
+                        log::info!("[debug:{}] Message Received: \n{:#?}", node.name(), &msg);
                     }
-                    Err(ref err) => {
-                        log::error!("[debug:{}] Error: {:#?}", self.name(), err);
-                        break;
+                    if let Some(file_tx) = file_tx {
+                        file_tx.send(node.format_entry(&msg)).await.map_err(|_| {
+                            EdgelinkError::InvalidOperation("The debug file writer has been closed".into())
+                        })?;
                     }
-                }
+                    Ok(())
+                })
+                .await;
             } else {
                 stop_token.cancelled().await;
             }
         }
+
+        // Drain the pending entries before the node stops
+        drop(file_tx);
+        if let Some((tx, handle)) = file_writer {
+            drop(tx);
+            if let Err(err) = handle.await {
+                log::error!("[debug:{}] The file writer task failed: {:?}", self.name(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::model::redact;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_write_entries_to_file() {
+        let path = std::env::temp_dir().join(format!("edgelink-debug-{}.log", ElementId::new()));
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "debug", "name": "dbg", "tofile": true,
+                "file": path.to_str().unwrap(), "fileMaxSize": 1048576, "fileMaxFiles": 2},
+            {"id": "2", "z": "100", "type": "complete", "scope": ["1"], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "hello"}],
+            ["1", {"payload": 123}],
+            ["1", {"payload": {"a": true}}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].ends_with("[debug:dbg] msg.payload : \"hello\""), "{}", content);
+        assert!(lines[1].ends_with("[debug:dbg] msg.payload : 123"), "{}", content);
+        assert!(lines[2].ends_with("[debug:dbg] msg.payload : object"), "{}", content);
+        assert!(lines[3].ends_with("a: true"), "{}", content);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_redact_the_logged_properties() {
+        let path = std::env::temp_dir().join(format!("edgelink-debug-{}.log", ElementId::new()));
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "debug", "name": "dbg", "tofile": true, "complete": "true",
+                "file": path.to_str().unwrap(), "fileMaxSize": 1048576, "fileMaxFiles": 2},
+            {"id": "2", "z": "100", "type": "complete", "scope": ["1"], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": {"user": "admin", "password": "hunter2"}}],
+        ]);
+
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.engine]
+                redact_msg_properties = ["payload.password", "headers['authorization']"]
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].get_nav("payload.password").and_then(|x| x.as_str()), Some("hunter2"));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(!content.contains("hunter2"), "{}", content);
+        assert!(content.contains(redact::REDACTED), "{}", content);
+        assert!(content.contains("admin"), "{}", content);
     }
 }
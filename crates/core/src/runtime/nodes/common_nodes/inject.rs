@@ -1,4 +1,3 @@
-use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -9,6 +8,7 @@ use tokio_cron_scheduler::{Job, JobScheduler};
 use crate::runtime::eval;
 use crate::runtime::model::*;
 use crate::runtime::nodes::*;
+use crate::utils::async_util;
 use edgelink_macro::*;
 
 // const USER_INJECT_PROPS: &str = "__user_inject_props__";
@@ -73,7 +73,8 @@ impl InjectNode {
 
     async fn once_task(&self, stop_token: CancellationToken) -> crate::Result<()> {
         if let Some(once_delay_value) = self.config.once_delay {
-            crate::utils::async_util::delay(Duration::from_secs_f64(once_delay_value), stop_token.clone()).await?;
+            let clock = self.clock();
+            async_util::delay_on(clock.as_ref(), Duration::from_secs_f64(once_delay_value), stop_token.clone()).await?;
         }
 
         self.inject_msg(stop_token).await?;
@@ -136,8 +137,9 @@ impl InjectNode {
     }
 
     async fn repeat_task(&self, repeat_interval: f64, stop_token: CancellationToken) -> crate::Result<()> {
+        let clock = self.clock();
         while !stop_token.is_cancelled() {
-            crate::utils::async_util::delay(Duration::from_secs_f64(repeat_interval), stop_token.clone()).await?;
+            async_util::delay_on(clock.as_ref(), Duration::from_secs_f64(repeat_interval), stop_token.clone()).await?;
             self.inject_msg(stop_token.clone()).await?;
         }
         log::info!("The `repeat` task has been stopped.");
@@ -145,30 +147,31 @@ impl InjectNode {
     }
 
     async fn inject_msg(&self, stop_token: CancellationToken) -> crate::Result<()> {
-        // TODO msg.field1 references msg.field2
-        let mut msg_body: BTreeMap<String, Variant> = BTreeMap::new();
+        let mut msg = Msg::default();
+        msg.set(wellknown::MSG_ID_PROPERTY.to_string(), Variant::String(Msg::generate_id().to_string()));
+
+        // The properties are evaluated in order at inject time, so the context variables are always up to date
+        // and a property may refer to the previous ones by the `msg` type.
         for prop in self.config.props.iter() {
-            let k = prop.p.to_string();
-            let v = eval::evaluate_node_property(&prop.v, prop.vt, Some(self), self.flow().as_ref(), None).await?;
-            msg_body.insert(k, v);
+            let evaluated =
+                eval::evaluate_node_property(&prop.v, prop.vt, Some(self), self.flow().as_ref(), Some(&msg)).await;
+            let result = evaluated.and_then(|v| msg.set_nav_stripped(&prop.p, v, true)).with_context(|| {
+                format!("Failed to evaluate the property `msg.{}` of the inject node(id='{}')", prop.p, self.id())
+            });
+            if let Err(err) = result {
+                self.report_error(format!("{:#}", err), MsgHandle::new(msg), stop_token).await;
+                return Ok(());
+            }
         }
-        msg_body.insert(wellknown::MSG_ID_PROPERTY.to_string(), Variant::String(Msg::generate_id().to_string()));
 
-        let envelope = Envelope { port: 0, msg: MsgHandle::with_body(msg_body) };
+        let envelope = Envelope { port: 0, msg: MsgHandle::new(msg) };
 
         self.notify_uow_completed(envelope.msg.clone(), stop_token.clone()).await;
 
         self.fan_out_one(envelope, stop_token.clone()).await
     }
-}
 
-#[async_trait]
-impl FlowNodeBehavior for InjectNode {
-    fn get_node(&self) -> &FlowNode {
-        &self.base
-    }
-
-    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+    async fn schedule_task(self: Arc<Self>, stop_token: CancellationToken) {
         let mut is_executed = false;
         if self.config.once {
             is_executed = true;
@@ -190,10 +193,35 @@ impl FlowNodeBehavior for InjectNode {
         }
 
         if !is_executed {
-            log::warn!("The InjectNode(id='{}', name='{}') has no trigger.", self.id(), self.name());
+            log::debug!("The InjectNode(id='{}', name='{}') has no schedule, only triggered.", self.id(), self.name());
             stop_token.cancelled().await;
         }
     }
+
+    /// Every message received is a trigger, see `Engine::trigger_inject()`, the message itself is dropped and a new one
+    /// is made from the properties like the scheduled ones.
+    async fn trigger_task(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            if self.recv_msg(stop_token.clone()).await.is_err() {
+                break;
+            }
+            if let Err(e) = self.inject_msg(stop_token.clone()).await {
+                log::warn!("[inject:{}] Failed to inject the triggered message: {}", self.name(), e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for InjectNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        // The triggers like the button of the editor may fire it at any time, whatever the schedule is
+        tokio::join!(self.clone().trigger_task(stop_token.child_token()), self.schedule_task(stop_token));
+    }
 }
 
 fn handle_legacy_json(orig: &Value) -> Value {
@@ -238,6 +266,7 @@ fn handle_legacy_json(orig: &Value) -> Value {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn parse_red_property_triple_should_be_ok() {
@@ -256,4 +285,134 @@ mod tests {
         assert_eq!("timestamp", triples[0].p);
         assert_eq!(RedPropertyType::Date, triples[0].vt);
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_inject_payload_from_flow_context() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "inject", "once": true, "onceDelay": 0.05,
+                "props": [
+                    {"p": "payload", "v": "counter", "vt": "flow"},
+                    {"p": "topic", "v": "payload", "vt": "msg"},
+                    {"p": "flow.id", "v": "NR_FLOW_ID", "vt": "env"}
+                ], "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        // Set after loading, so the value must be read at inject time
+        let flow = engine.get_flow(&"100".parse().unwrap()).unwrap();
+        flow.context().set_one(None, "counter", Some(Variant::from(42)), &[]).await.unwrap();
+
+        let msgs = engine.run_once(1, std::time::Duration::from_secs_f64(0.4)).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from(42));
+        assert_eq!(msgs[0]["topic"], Variant::from(42));
+        assert_eq!(msgs[0].get_nav("flow.id"), Some(&Variant::from("0000000000000100")));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_inject_props_computed_by_jsonata() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "inject", "once": true, "onceDelay": 0.05,
+                "props": [
+                    {"p": "x", "v": "10", "vt": "num"},
+                    // Sees the props evaluated before it
+                    {"p": "y", "v": "x + 2", "vt": "jsonata"},
+                    {"p": "payload", "v": "$flowContext('counter') * y", "vt": "jsonata"}
+                ], "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let flow = engine.get_flow(&"100".parse().unwrap()).unwrap();
+        flow.context().set_one(None, "counter", Some(Variant::from(3)), &[]).await.unwrap();
+
+        let msgs = engine.run_once(1, std::time::Duration::from_secs_f64(0.4)).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["y"], Variant::from(12));
+        assert_eq!(msgs[0]["payload"], Variant::from(36));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_report_errors_of_props() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "inject", "once": true, "onceDelay": 0.05,
+                "props": [{"p": "payload", "v": "missing", "vt": "global"}], "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "catch", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once(1, std::time::Duration::from_secs_f64(0.4)).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(msgs[0].contains("error"));
+        assert!(!msgs[0].contains("payload"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_inject_when_triggered() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "inject",
+                "props": [
+                    {"p": "payload", "v": "counter", "vt": "flow"},
+                    {"p": "topic", "v": "manual", "vt": "str"},
+                    {"p": "copy", "v": "payload", "vt": "msg"}
+                ], "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let flow = engine.get_flow(&"100".parse().unwrap()).unwrap();
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        let cancel = CancellationToken::new();
+        for counter in 1..=2 {
+            flow.context().set_one(None, "counter", Some(Variant::from(counter)), &[]).await.unwrap();
+            engine.trigger_inject(&ElementId::from(1), cancel.clone()).await.unwrap();
+            let (_, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+            assert_eq!(msg["payload"], Variant::from(counter));
+            assert_eq!(msg["copy"], Variant::from(counter));
+            assert_eq!(msg["topic"].as_str(), Some("manual"));
+        }
+        assert!(engine.trigger_inject(&ElementId::from(2), cancel.clone()).await.is_err());
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_repeat_by_the_clock_of_engine() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "inject", "repeat": "10",
+                "props": [{"p": "payload", "v": "tick", "vt": "str"}], "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let clock = Arc::new(crate::utils::time::MockClock::new());
+        engine.set_clock(clock.clone());
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        for _ in 0..3 {
+            // Wait for the inject node to sleep on the mock clock
+            tokio::time::timeout(Duration::from_secs(1), async {
+                while clock.pending_sleeps() == 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            })
+            .await
+            .unwrap();
+            assert!(sink_rx.try_recv().is_err());
+
+            clock.advance(Duration::from_secs(10));
+            let (_, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+            assert_eq!(msg["payload"].as_str(), Some("tick"));
+        }
+        engine.stop().await.unwrap();
+    }
 }
@@ -7,6 +7,7 @@ use serde::Deserialize;
 use tokio::sync::Mutex;
 use tokio::task::JoinSet;
 
+use crate::runtime::engine::DEFAULT_MAX_LINK_CALL_DEPTH;
 use crate::runtime::flow::Flow;
 use crate::runtime::model::json::deser::parse_red_id_str;
 use crate::runtime::model::*;
@@ -99,10 +100,13 @@ impl LinkCallNode {
     }
 
     async fn forward_call_msg(&self, node: Arc<Self>, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let max_depth = self.engine().map(|x| x.max_link_call_depth()).unwrap_or(DEFAULT_MAX_LINK_CALL_DEPTH);
         let (entry_id, cloned_msg) = {
             let mut locked_msg = msg.write().await;
             let entry_id = ElementId::with_u64(self.event_id_atomic.fetch_add(1, Ordering::Relaxed));
-            locked_msg.push_link_source(LinkCallStackEntry { id: entry_id, link_call_node_id: self.id() });
+            // A runaway recursive chain ends here with an error, which the `catch` nodes can handle
+            locked_msg
+                .try_push_link_source(LinkCallStackEntry { id: entry_id, link_call_node_id: self.id() }, max_depth)?;
             (entry_id, msg.clone())
         };
         {
@@ -194,7 +198,7 @@ impl LinkCallNode {
     }
 
     async fn timeout_task(&self, event_id: ElementId) {
-        tokio::time::sleep(Duration::from_secs_f64(self.config.timeout.unwrap_or(30.0))).await;
+        self.clock().sleep(Duration::from_secs_f64(self.config.timeout.unwrap_or(30.0))).await;
         log::warn!("LinkCallNode: flow timed out, event_id={}", event_id);
         let mut mut_state = self.mut_state.lock().await;
         if let Some(event) = mut_state.msg_events.remove(&event_id) {
@@ -251,3 +255,50 @@ impl LinkCallNodeBehavior for LinkCallNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_terminate_recursive_link_calls_at_the_max_depth() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "link in", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "function", "func": "msg.payload += 1; return msg;", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "link call", "links": ["1"], "wires": [[]]},
+            {"id": "4", "z": "100", "type": "catch", "scope": null, "uncaught": false, "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.engine]
+                max_link_call_depth = 5
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        assert_eq!(engine.max_link_call_depth(), 5);
+
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": 0})).unwrap())];
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        // The function runs once before every link call, including the rejected sixth one
+        assert_eq!(msgs[0]["payload"].as_i64(), Some(6));
+        assert_eq!(msgs[0].link_call_depth(), 5);
+        let error = msgs[0].get_nav("error.message").and_then(|x| x.as_str()).unwrap();
+        assert!(error.contains("the limit is 5"), "{}", error);
+    }
+}
@@ -1,18 +1,30 @@
 use std::sync::Arc;
 
+use serde::Deserialize;
+
 use crate::runtime::flow::Flow;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
 
+#[derive(Debug, Default, Deserialize)]
+struct LinkInNodeConfig {
+    /// Keeps the last message for the late subscribers, an extension of EdgeLink.
+    #[serde(default)]
+    retain: bool,
+}
+
 #[derive(Debug)]
 #[flow_node("link in")]
 struct LinkInNode {
     base: FlowNode,
+    config: LinkInNodeConfig,
+    retained: MsgRetainer,
 }
 
 impl LinkInNode {
-    fn build(_flow: &Flow, state: FlowNode, _config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
-        let node = LinkInNode { base: state };
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let link_in_config = LinkInNodeConfig::deserialize(&config.rest)?;
+        let node = LinkInNode { base: state, config: link_in_config, retained: MsgRetainer::default() };
         Ok(Box::new(node))
     }
 }
@@ -27,11 +39,18 @@ impl FlowNodeBehavior for LinkInNode {
         while !stop_token.is_cancelled() {
             let cancel = stop_token.clone();
             with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                if node.config.retain {
+                    node.retained.retain(&msg).await;
+                }
                 node.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await
             })
             .await;
         }
     }
+
+    fn retained_msg(&self) -> Option<Msg> {
+        self.retained.get()
+    }
 }
 
 #[cfg(test)]
@@ -62,4 +81,44 @@ mod tests {
             engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
         assert_eq!(msgs.len(), 2);
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_deliver_the_retained_msg_to_late_subscribers() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "link in", "retain": true, "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "link in", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "change", "wires": [],
+                "rules": [{"t": "set", "p": "payload", "pt": "msg", "to": "changed", "tot": "str"}]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+
+        let cancel = CancellationToken::new();
+        for id in ["1", "2"] {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": "last value"})).unwrap());
+            engine.inject_msg(&id.parse().unwrap(), msg, cancel.clone()).await.unwrap();
+        }
+        let retaining = engine.find_flow_node_by_id(&"1".parse().unwrap()).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while retaining.retained_msg().is_none() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Every subscriber gets its own copy, unaffected by the downstream `change` node
+        for _ in 0..2 {
+            let mut tap = engine.tap_output(&"1".parse().unwrap()).unwrap();
+            let (port, msg) = tap.try_recv().unwrap();
+            assert_eq!(port, 0);
+            assert_eq!(msg["payload"].as_str(), Some("last value"));
+        }
+
+        // Opt-in only
+        let mut tap = engine.tap_output(&"2".parse().unwrap()).unwrap();
+        assert!(tap.try_recv().is_err());
+        engine.stop().await.unwrap();
+    }
 }
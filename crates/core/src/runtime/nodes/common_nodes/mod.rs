@@ -7,9 +7,11 @@ mod junction;
 pub(crate) mod link_call;
 mod link_in;
 mod link_out;
-mod status;
+mod schedule;
+mod sink;
+pub(crate) mod status;
 mod subflow;
-mod unknown;
+pub(crate) mod unknown;
 
 #[cfg(any(test, feature = "pymod"))]
 mod test_once;
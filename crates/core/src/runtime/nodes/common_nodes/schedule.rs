@@ -0,0 +1,377 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, LocalResult, NaiveDateTime, Offset, SecondsFormat, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use cron::{Schedule, TimeUnitSpec};
+use serde::Deserialize;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// Gives up finding the next instant after so many candidates, e.g. for an expression only matching the skipped
+/// local times.
+const MAX_CANDIDATES: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct ScheduleNodeConfig {
+    #[serde(default)]
+    crontab: String,
+
+    /// The IANA time zone of the expression like `Europe/Amsterdam`, the local time zone if empty
+    #[serde(default)]
+    timezone: String,
+
+    #[serde(default)]
+    payload: String,
+
+    #[serde(default = "default_payload_type", rename = "payloadType")]
+    payload_type: RedPropertyType,
+
+    #[serde(default)]
+    topic: String,
+}
+
+fn default_payload_type() -> RedPropertyType {
+    RedPropertyType::Date
+}
+
+#[derive(Debug)]
+#[flow_node("schedule")]
+struct ScheduleNode {
+    base: FlowNode,
+    config: ScheduleNodeConfig,
+    schedule: Schedule,
+    timezone: Option<Tz>,
+    /// Fires at a single time of the day, see `next_fire()`
+    fixed_time: bool,
+}
+
+impl ScheduleNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let schedule_config = ScheduleNodeConfig::deserialize(&config.rest)?;
+
+        // The classic 5 fields expressions have no seconds
+        let crontab = schedule_config.crontab.trim();
+        let crontab =
+            if crontab.split_whitespace().count() == 5 { format!("0 {}", crontab) } else { crontab.to_string() };
+        let schedule = Schedule::from_str(&crontab).map_err(|e| {
+            EdgelinkError::BadFlowsJson(format!("Bad cron expression '{}': {}", schedule_config.crontab, e))
+        })?;
+
+        let timezone = match schedule_config.timezone.trim() {
+            "" => None,
+            tz => Some(
+                Tz::from_str(tz).map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad time zone '{}': {}", tz, e)))?,
+            ),
+        };
+
+        let fixed_time =
+            schedule.seconds().count() == 1 && schedule.minutes().count() == 1 && schedule.hours().count() == 1;
+        let node = ScheduleNode { base: state, config: schedule_config, schedule, timezone, fixed_time };
+        Ok(Box::new(node))
+    }
+
+    fn next_fire(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.timezone {
+            Some(tz) => next_fire(&self.schedule, tz, after, self.fixed_time),
+            None => next_fire(&self.schedule, &chrono::Local, after, self.fixed_time),
+        }
+    }
+
+    fn format_time(&self, t: &DateTime<Utc>) -> String {
+        match &self.timezone {
+            Some(tz) => t.with_timezone(tz).to_rfc3339_opts(SecondsFormat::Secs, false),
+            None => t.with_timezone(&chrono::Local).to_rfc3339_opts(SecondsFormat::Secs, false),
+        }
+    }
+
+    async fn fire(&self, stop_token: CancellationToken) -> crate::Result<()> {
+        let payload = eval::evaluate_node_property(
+            &self.config.payload,
+            self.config.payload_type,
+            Some(self),
+            self.flow().as_ref(),
+            None,
+        )
+        .await?;
+        let mut msg = Msg::default();
+        msg.set_id(Msg::generate_id());
+        msg.set(wellknown::PAYLOAD_PROPERTY.into(), payload);
+        if !self.config.topic.is_empty() {
+            msg.set(wellknown::TOPIC_PROPERTY.into(), Variant::String(self.config.topic.clone()));
+        }
+        self.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(msg) }, stop_token).await
+    }
+
+    /// The wall time is read from the clock of the engine before every wait, so the schedule follows the
+    /// adjustments of the system time and a `MockClock` drives it in tests.
+    async fn schedule_task(&self, stop_token: CancellationToken) {
+        let clock = self.clock();
+        let mut last_fired: Option<DateTime<Utc>> = None;
+
+        while !stop_token.is_cancelled() {
+            // The wall time may be a little behind the instant just fired, which must not fire twice
+            let now = clock.now();
+            let after = last_fired.map_or(now, |x| x.max(now));
+            let Some(next) = self.next_fire(after) else {
+                log::warn!("[schedule:{}] The cron expression will never fire again", self.id());
+                self.set_status(NodeStatus::new("grey", "ring", "finished"), stop_token.clone()).await;
+                break;
+            };
+            let status_text = format!("next: {}", self.format_time(&next));
+            self.set_status(NodeStatus::new("blue", "dot", status_text), stop_token.clone()).await;
+
+            let wait = (next - now).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = stop_token.cancelled() => break,
+                _ = clock.sleep(wait) => {
+                    last_fired = Some(next);
+                    if let Err(err) = self.fire(stop_token.clone()).await {
+                        log::warn!("[schedule:{}] Failed to fire: {}", self.id(), err);
+                    }
+                }
+            }
+        }
+        stop_token.cancelled().await;
+    }
+}
+
+/// Returns the first instant strictly after `after` matched by the expression in the local time of `tz`.
+///
+/// The transitions of the daylight saving time are handled like cronie:
+/// - A local time skipped by the clocks going forward fires right after the gap, e.g. `02:30` fires at `03:30`;
+/// - A local time repeated by the clocks going back only fires once for a `fixed_time` expression, at its first
+///   occurrence. The other expressions like `0 */15 * * * *` keep firing through both passes of the repeated hour.
+fn next_fire<Z: TimeZone>(
+    schedule: &Schedule,
+    tz: &Z,
+    after: DateTime<Utc>,
+    fixed_time: bool,
+) -> Option<DateTime<Utc>> {
+    // Walks the wall clock times, the UTC has no DST so the expression is matched literally
+    let local_after = after.with_timezone(tz);
+    let next = next_fire_from(schedule, tz, after, local_after.naive_local(), fixed_time);
+    if fixed_time {
+        return next;
+    }
+
+    // In the first pass of a repeated hour, the wall times already passed come again in the second pass
+    match tz.from_local_datetime(&local_after.naive_local()) {
+        LocalResult::Ambiguous(earliest, latest) if earliest.offset().fix() == local_after.offset().fix() => {
+            let shift = earliest.offset().fix().local_minus_utc() - latest.offset().fix().local_minus_utc();
+            let second_pass = local_after.naive_local() - TimeDelta::seconds(shift as i64);
+            let repeated = next_fire_from(schedule, tz, after, second_pass, fixed_time);
+            match (next, repeated) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        _ => next,
+    }
+}
+
+/// Returns the first instant strictly after `after` of the wall clock times matched after `wall`.
+fn next_fire_from<Z: TimeZone>(
+    schedule: &Schedule,
+    tz: &Z,
+    after: DateTime<Utc>,
+    wall: NaiveDateTime,
+    fixed_time: bool,
+) -> Option<DateTime<Utc>> {
+    for candidate in schedule.after(&Utc.from_utc_datetime(&wall)).take(MAX_CANDIDATES) {
+        let naive = candidate.naive_utc();
+        let resolved = match tz.from_local_datetime(&naive) {
+            LocalResult::Single(t) => t.with_timezone(&Utc),
+            LocalResult::Ambiguous(earliest, latest) => {
+                let earliest = earliest.with_timezone(&Utc);
+                if fixed_time || earliest > after {
+                    earliest
+                } else {
+                    latest.with_timezone(&Utc)
+                }
+            }
+            LocalResult::None => {
+                // Shifted by the offset before the gap
+                let offset = tz.offset_from_utc_datetime(&(naive - TimeDelta::days(1))).fix();
+                Utc.from_utc_datetime(&(naive - TimeDelta::seconds(offset.local_minus_utc() as i64)))
+            }
+        };
+        if resolved > after {
+            return Some(resolved);
+        }
+    }
+    None
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ScheduleNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        self.schedule_task(stop_token.child_token()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::{Clock, MockClock};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn fires(expr: &str, tz: &str, after: &str, n: usize) -> Vec<DateTime<Utc>> {
+        let schedule = Schedule::from_str(expr).unwrap();
+        let fixed_time =
+            schedule.seconds().count() == 1 && schedule.minutes().count() == 1 && schedule.hours().count() == 1;
+        let tz = Tz::from_str(tz).unwrap();
+        let mut after = utc(after);
+        let mut result = Vec::new();
+        for _ in 0..n {
+            after = next_fire(&schedule, &tz, after, fixed_time).unwrap();
+            result.push(after);
+        }
+        result
+    }
+
+    #[test]
+    fn test_next_fire_should_follow_the_time_zone() {
+        assert_eq!(
+            fires("0 0 8 * * *", "Asia/Shanghai", "2024-06-01T00:00:00Z", 2),
+            vec![utc("2024-06-02T00:00:00Z"), utc("2024-06-03T00:00:00Z")]
+        );
+        assert_eq!(
+            fires("0 0 8 * * *", "Asia/Shanghai", "2024-06-01T23:59:59+08:00", 1),
+            vec![utc("2024-06-02T00:00:00Z")]
+        );
+    }
+
+    #[test]
+    fn test_next_fire_should_handle_dst_transitions() {
+        // 02:30 does not exist on 2024-03-10 in New York, it fires at 03:30 EDT
+        assert_eq!(
+            fires("0 30 2 * * *", "America/New_York", "2024-03-09T12:00:00-05:00", 3),
+            vec![utc("2024-03-10T03:30:00-04:00"), utc("2024-03-11T02:30:00-04:00"), utc("2024-03-12T02:30:00-04:00")]
+        );
+
+        // 01:30 happens twice on 2024-11-03 in New York, it only fires at the first one
+        assert_eq!(
+            fires("0 30 1 * * *", "America/New_York", "2024-11-02T12:00:00-04:00", 3),
+            vec![utc("2024-11-03T01:30:00-04:00"), utc("2024-11-04T01:30:00-05:00"), utc("2024-11-05T01:30:00-05:00")]
+        );
+
+        // An expression across the day still keeps the wall time after the transition
+        assert_eq!(
+            fires("0 0 12 * * *", "Europe/Amsterdam", "2024-03-30T13:00:00+01:00", 2),
+            vec![utc("2024-03-31T12:00:00+02:00"), utc("2024-04-01T12:00:00+02:00")]
+        );
+    }
+
+    #[test]
+    fn test_next_fire_should_repeat_the_wildcard_expressions_at_fall_back() {
+        // Both passes of 01:xx on 2024-11-03 in New York
+        assert_eq!(
+            fires("0 */15 * * * *", "America/New_York", "2024-11-03T01:20:00-04:00", 7),
+            vec![
+                utc("2024-11-03T01:30:00-04:00"),
+                utc("2024-11-03T01:45:00-04:00"),
+                utc("2024-11-03T01:00:00-05:00"),
+                utc("2024-11-03T01:15:00-05:00"),
+                utc("2024-11-03T01:30:00-05:00"),
+                utc("2024-11-03T01:45:00-05:00"),
+                utc("2024-11-03T02:00:00-05:00"),
+            ]
+        );
+        assert_eq!(
+            fires("*/10 * * * * *", "America/New_York", "2024-11-03T01:59:40-04:00", 4),
+            vec![
+                utc("2024-11-03T01:59:50-04:00"),
+                utc("2024-11-03T01:00:00-05:00"),
+                utc("2024-11-03T01:00:10-05:00"),
+                utc("2024-11-03T01:00:20-05:00"),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_fire_by_the_clock() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "schedule", "crontab": "*/10 * * * * *", "timezone": "Asia/Shanghai",
+                "payload": "tick", "payloadType": "str", "topic": "cron", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"},
+            {"id": "3", "z": "100", "type": "status", "scope": ["1"], "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "sink"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let clock = Arc::new(MockClock::new());
+        engine.set_clock(clock.clone());
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        // The next fire time is reported before sleeping
+        let (node_id, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(node_id, ElementId::from(4));
+        let mut last_next = parse_next_fire(&msg);
+        for _ in 0..2 {
+            wait_for_pending_sleeps(&clock, 1).await;
+            assert!(sink_rx.try_recv().is_err());
+
+            // Every period contains exactly one instant of the expression
+            clock.advance(Duration::from_secs(10));
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                received.push(tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap());
+            }
+            received.sort_by_key(|x| x.0);
+            assert_eq!(received[0].0, ElementId::from(2));
+            assert_eq!(received[0].1["payload"].as_str(), Some("tick"));
+            assert_eq!(received[0].1["topic"].as_str(), Some("cron"));
+
+            assert_eq!(received[1].0, ElementId::from(4));
+            let next = parse_next_fire(&received[1].1);
+            assert_eq!(next - last_next, TimeDelta::seconds(10));
+            last_next = next;
+        }
+
+        // The wall time is read again after firing, so the adjusted system time is followed
+        wait_for_pending_sleeps(&clock, 1).await;
+        clock.set_now(clock.now() + TimeDelta::hours(1));
+        clock.advance(Duration::from_secs(10));
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            received.push(tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap());
+        }
+        received.sort_by_key(|x| x.0);
+        let next = parse_next_fire(&received[1].1);
+        assert_eq!(next - last_next, TimeDelta::hours(1) + TimeDelta::seconds(10));
+        engine.stop().await.unwrap();
+    }
+
+    fn parse_next_fire(status_msg: &Msg) -> DateTime<Utc> {
+        let text = status_msg.get_nav("status.text").unwrap().as_str().unwrap();
+        let next = DateTime::parse_from_rfc3339(text.strip_prefix("next: ").unwrap()).unwrap();
+        assert_eq!(next.offset().local_minus_utc(), 8 * 3600);
+        assert_eq!(next.timestamp() % 10, 0);
+        next.with_timezone(&Utc)
+    }
+
+    async fn wait_for_pending_sleeps(clock: &MockClock, n: usize) {
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while clock.pending_sleeps() < n {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
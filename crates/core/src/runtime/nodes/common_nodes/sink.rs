@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Deserialize)]
+struct SinkNodeConfig {
+    /// Keeps the last message for the receivers created later.
+    #[serde(default)]
+    retain: bool,
+}
+
+/// Forwards every received message to the engine-level sink channel, see `Engine::sink_receiver()`.
+#[derive(Debug)]
+#[flow_node("sink")]
+struct SinkNode {
+    base: FlowNode,
+    config: SinkNodeConfig,
+    retained: MsgRetainer,
+}
+
+impl SinkNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let sink_config = SinkNodeConfig::deserialize(&config.rest)?;
+        let node = SinkNode { base: state, config: sink_config, retained: MsgRetainer::default() };
+        Ok(Box::new(node))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SinkNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let engine = node.engine().ok_or_else(|| {
+                    EdgelinkError::InvalidOperation("The engine of the sink node has been released".into())
+                })?;
+                if node.config.retain {
+                    node.retained.retain(&msg).await;
+                }
+                let msg = msg.read().await.clone();
+                if !engine.send_to_sink(&node.id(), msg) {
+                    log::debug!("[sink:{}] No receiver of the sink, dropped the message", node.name());
+                }
+                Ok(())
+            })
+            .await;
+        }
+    }
+
+    fn retained_msg(&self) -> Option<Msg> {
+        self.retained.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::runtime::model::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_forward_msgs_to_the_sink_channel() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "topic", "pt": "msg", "to": "sunk", "tot": "str"}]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        let cancel = tokio_util::sync::CancellationToken::new();
+        for i in 0..3 {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": i})).unwrap());
+            engine.inject_msg(&"1".parse().unwrap(), msg, cancel.clone()).await.unwrap();
+        }
+
+        for i in 0..3 {
+            let (node_id, msg) =
+                tokio::time::timeout(std::time::Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+            assert_eq!(node_id, "2".parse::<ElementId>().unwrap());
+            assert_eq!(msg["payload"].as_i64(), Some(i));
+            assert_eq!(msg["topic"].as_str(), Some("sunk"));
+        }
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_deliver_the_retained_msg_to_a_new_receiver() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sink", "retain": true},
+            {"id": "2", "z": "100", "type": "complete", "scope": ["1"], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "first"}],
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+
+        // No receiver when the message arrived, the new one still gets it
+        let mut sink_rx = engine.sink_receiver();
+        let (node_id, msg) = sink_rx.try_recv().unwrap();
+        assert_eq!(node_id, "1".parse::<ElementId>().unwrap());
+        assert_eq!(msg["payload"].as_str(), Some("first"));
+    }
+}
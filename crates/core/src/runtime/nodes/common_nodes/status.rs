@@ -1,17 +1,29 @@
 use std::sync::Arc;
 
+use serde::Deserialize;
+
+use super::catch::CatchNodeScope;
 use crate::runtime::flow::Flow;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
 
 #[flow_node("status")]
-struct StatusNode {
+#[derive(Debug)]
+pub struct StatusNode {
     base: FlowNode,
+    pub scope: CatchNodeScope,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StatusNodeConfig {
+    #[serde(default)]
+    scope: CatchNodeScope,
 }
 
 impl StatusNode {
-    fn build(_flow: &Flow, state: FlowNode, _config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
-        let node = StatusNode { base: state };
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let status_config = StatusNodeConfig::deserialize(&config.rest)?;
+        let node = StatusNode { base: state, scope: status_config.scope };
         Ok(Box::new(node))
     }
 }
@@ -33,3 +45,77 @@ impl FlowNodeBehavior for StatusNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Sets the status text of the node to the payload of every message received.
+    #[derive(Debug)]
+    #[flow_node("test-set-status")]
+    struct SetStatusNode {
+        base: FlowNode,
+    }
+
+    impl SetStatusNode {
+        fn build(
+            _flow: &Flow,
+            state: FlowNode,
+            _config: &RedFlowNodeConfig,
+        ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+            Ok(Box::new(SetStatusNode { base: state }))
+        }
+    }
+
+    #[async_trait]
+    impl FlowNodeBehavior for SetStatusNode {
+        fn get_node(&self) -> &FlowNode {
+            &self.base
+        }
+
+        async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+            while !stop_token.is_cancelled() {
+                match self.recv_msg(stop_token.clone()).await {
+                    Ok(msg) => {
+                        let text = msg.read().await["payload"].as_str().unwrap_or_default().to_string();
+                        self.set_status(NodeStatus::new("green", "dot", text), stop_token.clone()).await;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_watch_the_status_of_other_flows_in_global_scope() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-set-status"},
+            {"id": "2", "z": "100", "type": "status", "scope": null, "wires": [["6"]]},
+            {"id": "200", "type": "tab"},
+            {"id": "3", "z": "200", "type": "status", "scope": "global", "wires": [["5"]]},
+            {"id": "4", "z": "200", "type": "status", "scope": null, "wires": [["5"]]},
+            {"id": "5", "z": "200", "type": "test-once"},
+            {"id": "6", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": "running"})).unwrap())];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut global_tap = engine.tap_output(&ElementId::from(3)).unwrap();
+        let mut other_flow_tap = engine.tap_output(&ElementId::from(4)).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        for msg in msgs.iter() {
+            assert_eq!(msg.get_nav("status.text").and_then(|x| x.as_str()), Some("running"));
+            assert_eq!(msg.get_nav("status.source.id").and_then(|x| x.as_str()), Some("0000000000000001"));
+        }
+
+        // The global scoped node gets the status exactly once, the flow scoped node of the other flow never does
+        let (_, msg) = global_tap.try_recv().unwrap();
+        assert_eq!(msg.get_nav("status.text").and_then(|x| x.as_str()), Some("running"));
+        assert!(global_tap.try_recv().is_err());
+        assert!(other_flow_tap.try_recv().is_err());
+    }
+}
@@ -38,7 +38,7 @@ impl FlowNodeBehavior for SubflowNode {
             let cancel = stop_token.clone();
             with_uow(self.as_ref(), stop_token.clone(), |node, msg| async move {
                 if let Some(engine) = node.get_node().flow.upgrade().and_then(|f| f.engine()) {
-                    engine.inject_msg_to_flow(node.subflow_id, msg, cancel.clone()).await?;
+                    let _ = engine.inject_msg_to_flow(node.subflow_id, msg, cancel.clone()).await?;
                 }
 
                 Ok(())
@@ -27,7 +27,11 @@ impl FlowNodeBehavior for TestOnceNode {
             let engine = self.engine().expect("The engine cannot be released");
 
             match self.recv_msg(stop_token.clone()).await {
-                Ok(msg) => engine.recv_final_msg(msg).expect("Shoud send final msg to the engine"),
+                // Waits for the receiver if the channel is full, unless the engine is stopping
+                Ok(msg) => tokio::select! {
+                    result = engine.recv_final_msg(msg) => result.expect("Shoud send final msg to the engine"),
+                    _ = stop_token.cancelled() => break,
+                },
                 Err(e) => {
                     match e.downcast_ref::<EdgelinkError>() {
                         Some(EdgelinkError::TaskCancelled) => (),
@@ -9,8 +9,10 @@ const UNKNOWN_GLOBAL_NODE_TYPE: &str = "unknown.global";
 
 #[derive(Debug)]
 #[global_node("unknown.global")]
-struct UnknownGlobalNode {
+pub(crate) struct UnknownGlobalNode {
     base: GlobalNode,
+    /// The type in the flows JSON that could not be found in the registry.
+    pub(crate) type_name: String,
 }
 
 impl UnknownGlobalNode {
@@ -25,6 +27,7 @@ impl UnknownGlobalNode {
                 disabled: config.disabled,
                 context,
             },
+            type_name: config.type_name.clone(),
         };
         Ok(Box::new(node))
     }
@@ -37,13 +40,15 @@ impl GlobalNodeBehavior for UnknownGlobalNode {
 }
 
 #[flow_node("unknown.flow")]
-struct UnknownFlowNode {
+pub(crate) struct UnknownFlowNode {
     base: FlowNode,
+    /// The type in the flows JSON that could not be found in the registry.
+    pub(crate) type_name: String,
 }
 
 impl UnknownFlowNode {
-    fn build(_flow: &Flow, base: FlowNode, _config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
-        let node = UnknownFlowNode { base };
+    fn build(_flow: &Flow, base: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let node = UnknownFlowNode { base, type_name: config.type_name.clone() };
         Ok(Box::new(node))
     }
 }
@@ -53,6 +53,14 @@ struct Rule {
 
     #[serde(default, rename = "fromRE", with = "crate::text::regex::serde_optional_regex")]
     pub from_regex: Option<Regex>,
+
+    /// Merge an object value into the existing object property instead of replacing it.
+    #[serde(default, rename = "deepMerge")]
+    pub deep_merge: bool,
+
+    /// How arrays are handled when `deep_merge` is on.
+    #[serde(default, rename = "mergeArrays")]
+    pub merge_arrays: ArrayMergeMode,
     /*
     #[serde(default, rename = "dc")]
     pub deep_clone: bool,
@@ -86,8 +94,9 @@ impl FlowNodeBehavior for ChangeNode {
             with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
                 {
                     let mut msg_guard = msg.write().await;
-                    // We always relay the message, regardless of whether the rules are followed or not.
-                    node.apply_rules(&mut msg_guard).await;
+                    // We always relay the message, regardless of whether the rules are followed or not, unless a
+                    // JSONata expression fails like in Node-RED.
+                    node.apply_rules(&mut msg_guard).await?;
                 }
                 node.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await
             })
@@ -133,16 +142,22 @@ impl ChangeNode {
         Ok(result)
     }
 
-    async fn apply_rules(&self, msg: &mut Msg) {
+    async fn apply_rules(&self, msg: &mut Msg) -> crate::Result<()> {
         for rule in self.config.rules.iter() {
-            if let Err(err) = self.apply_rule(rule, msg).await {
+            let to_value = match self.get_to_value(rule, msg).await {
+                Ok(value) => Some(value),
+                // The failed JSONata expressions are reported to the `catch` nodes and the message is dropped
+                Err(err) if rule.tot == Some(RedPropertyType::Jsonata) => return Err(err),
+                Err(_) => None,
+            };
+            if let Err(err) = self.apply_rule(rule, msg, to_value).await {
                 log::warn!("Failed to apply rule: {}", err);
             }
         }
+        Ok(())
     }
 
-    async fn apply_rule(&self, rule: &Rule, msg: &mut Msg) -> crate::Result<()> {
-        let to_value = self.get_to_value(rule, msg).await.ok();
+    async fn apply_rule(&self, rule: &Rule, msg: &mut Msg, to_value: Option<Variant>) -> crate::Result<()> {
         match rule.t {
             RuleKind::Set => self.apply_rule_set(rule, msg, to_value).await,
             RuleKind::Change => self.apply_rule_change(rule, msg, to_value).await,
@@ -153,6 +168,18 @@ impl ChangeNode {
 
     async fn apply_rule_set(&self, rule: &Rule, msg: &mut Msg, to_value: Option<Variant>) -> crate::Result<()> {
         assert!(rule.t == RuleKind::Set);
+        let to_value = match to_value {
+            Some(value @ Variant::Object(_)) if rule.deep_merge => {
+                match eval::evaluate_node_property(&rule.p, rule.pt, Some(self), None, Some(msg)).await {
+                    Ok(mut current @ Variant::Object(_)) => {
+                        current.merge(value, rule.merge_arrays);
+                        Some(current)
+                    }
+                    _ => Some(value),
+                }
+            }
+            other => other,
+        };
         self.set_property(&rule.p, rule.pt, to_value, msg).await
     }
 
@@ -571,3 +598,64 @@ fn handle_legacy_json(n: Value) -> crate::Result<Value> {
     changed["rules"] = Value::Array(rules);
     Ok(changed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_set_the_values_computed_by_jsonata() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "payload", "pt": "msg", "to": "payload.a + payload.b", "tot": "jsonata"},
+                // Sees the message changed by the previous rule
+                {"t": "set", "p": "doubled", "pt": "msg", "to": "payload * 2", "tot": "jsonata"},
+                {"t": "set", "p": "label", "pt": "msg", "to": "$uppercase(topic) & '-' & $flowContext('suffix')",
+                    "tot": "jsonata"}
+            ], "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": {"a": 1, "b": 2.5}, "topic": "sum"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let flow = engine.get_flow(&"100".parse().unwrap()).unwrap();
+        flow.context().set_one(None, "suffix", Some(Variant::from("x")), &[]).await.unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from(3.5));
+        assert_eq!(msgs[0]["doubled"], Variant::from(7));
+        assert_eq!(msgs[0]["label"], Variant::from("SUM-x"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_report_the_failed_jsonata_expressions() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "payload", "pt": "msg", "to": "payload & '!'", "tot": "jsonata"},
+                {"t": "set", "p": "topic", "pt": "msg", "to": "$sum(payload)", "tot": "jsonata"}
+            ], "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "catch", "scope": null, "uncaught": false, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "a"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        // Only the catch node relays the message
+        assert_eq!(msgs.len(), 1);
+        let message = msgs[0].get_nav("error.message").and_then(|x| x.as_str()).unwrap();
+        assert!(message.contains("$sum"));
+        assert!(!msgs[0].contains("topic"));
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::json::deser::str_to_option_f64;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils::async_util;
+use crate::utils::time::Clock;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum PauseType {
+    #[serde(rename = "delay")]
+    Delay,
+
+    #[serde(rename = "delayv")]
+    DelayV,
+
+    #[serde(rename = "random")]
+    Random,
+
+    #[serde(rename = "rate")]
+    Rate,
+
+    #[serde(rename = "queue")]
+    Queue,
+
+    #[serde(rename = "timed")]
+    Timed,
+}
+
+#[derive(Debug, Deserialize)]
+struct DelayNodeConfig {
+    #[serde(rename = "pauseType")]
+    pause_type: PauseType,
+
+    #[serde(default, deserialize_with = "str_to_option_f64")]
+    timeout: Option<f64>,
+
+    #[serde(rename = "timeoutUnits", default = "default_time_units")]
+    timeout_units: String,
+
+    #[serde(default, deserialize_with = "str_to_option_f64")]
+    rate: Option<f64>,
+
+    #[serde(rename = "nbRateUnits", default, deserialize_with = "str_to_option_f64")]
+    nb_rate_units: Option<f64>,
+
+    #[serde(rename = "rateUnits", default = "default_time_units")]
+    rate_units: String,
+
+    #[serde(rename = "randomFirst", default, deserialize_with = "str_to_option_f64")]
+    random_first: Option<f64>,
+
+    #[serde(rename = "randomLast", default, deserialize_with = "str_to_option_f64")]
+    random_last: Option<f64>,
+
+    #[serde(rename = "randomUnits", default = "default_time_units")]
+    random_units: String,
+
+    /// Drops the messages arriving faster than the rate instead of queueing them.
+    #[serde(default)]
+    drop: bool,
+
+    /// With two outputs the dropped messages are sent to the second one.
+    #[serde(default = "default_outputs")]
+    outputs: usize,
+
+    /// The maximum length of the queue in the rate mode, `0` means unlimited.
+    #[serde(rename = "maxQueue", default)]
+    max_queue: usize,
+}
+
+fn default_time_units() -> String {
+    "seconds".to_string()
+}
+
+fn default_outputs() -> usize {
+    1
+}
+
+fn parse_time_unit(unit: &str) -> crate::Result<Duration> {
+    let secs = match unit {
+        "millisecond" | "milliseconds" => return Ok(Duration::from_millis(1)),
+        "second" | "seconds" => 1,
+        "minute" | "minutes" => 60,
+        "hour" | "hours" => 60 * 60,
+        "day" | "days" => 24 * 60 * 60,
+        _ => return Err(EdgelinkError::BadFlowsJson(format!("Unsupported time unit: '{}'", unit)).into()),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// Delays each message passing through the node or limits the rate at which they pass.
+#[derive(Debug)]
+#[flow_node("delay")]
+struct DelayNode {
+    base: FlowNode,
+    config: DelayNodeConfig,
+    delay: Duration,
+    random_range: (Duration, Duration),
+    rate_interval: Duration,
+    /// The length of the queue of the rate mode
+    queued: AtomicUsize,
+}
+
+impl DelayNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let delay_config = DelayNodeConfig::deserialize(&config.rest)?;
+        if matches!(delay_config.pause_type, PauseType::Queue | PauseType::Timed) {
+            return Err(EdgelinkError::NotSupported(format!(
+                "The `{:?}` pause type of the delay node is not supported yet",
+                delay_config.pause_type
+            ))
+            .into());
+        }
+
+        let delay = parse_time_unit(&delay_config.timeout_units)?.mul_f64(delay_config.timeout.unwrap_or(5.0));
+        let random_unit = parse_time_unit(&delay_config.random_units)?;
+        let random_first = random_unit.mul_f64(delay_config.random_first.unwrap_or(1.0));
+        let random_last = random_unit.mul_f64(delay_config.random_last.unwrap_or(5.0));
+
+        let rate = delay_config.rate.unwrap_or(1.0);
+        if rate <= 0.0 {
+            return Err(EdgelinkError::BadFlowsJson(format!("Invalid rate of the delay node: {}", rate)).into());
+        }
+        let rate_interval =
+            parse_time_unit(&delay_config.rate_units)?.mul_f64(delay_config.nb_rate_units.unwrap_or(1.0)).div_f64(rate);
+
+        let node = DelayNode {
+            base: state,
+            config: delay_config,
+            delay,
+            random_range: (random_first.min(random_last), random_first.max(random_last)),
+            rate_interval,
+            queued: AtomicUsize::new(0),
+        };
+        Ok(Box::new(node))
+    }
+
+    fn delay_of(&self, msg: &Msg) -> crate::Result<Duration> {
+        match self.config.pause_type {
+            PauseType::DelayV => match msg.get("delay") {
+                Some(Variant::Number(n)) => match n.as_f64() {
+                    Some(ms) if ms >= 0.0 && ms.is_finite() => Ok(Duration::from_secs_f64(ms / 1000.0)),
+                    _ => Err(EdgelinkError::InvalidOperation(format!("Invalid `msg.delay`: {}", n)).into()),
+                },
+                Some(other) => {
+                    Err(EdgelinkError::InvalidOperation(format!("`msg.delay` must be a number, got: {:?}", other))
+                        .into())
+                }
+                None => Ok(self.delay),
+            },
+            PauseType::Random => {
+                let (first, last) = self.random_range;
+                Ok(if first == last { first } else { rand::thread_rng().gen_range(first..=last) })
+            }
+            _ => Ok(self.delay),
+        }
+    }
+
+    /// Returns the number of messages waiting in the queue of the rate mode.
+    #[cfg(test)]
+    pub(crate) fn queued_len(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    async fn delay_loop(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let msg = match self.recv_msg(stop_token.clone()).await {
+                Ok(msg) => msg,
+                Err(err) => {
+                    if !matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::TaskCancelled)) {
+                        log::warn!("[delay:{}] {}", self.name(), err);
+                    }
+                    break;
+                }
+            };
+
+            let delay = {
+                let msg_guard = msg.read().await;
+                self.delay_of(&msg_guard)
+            };
+            let delay = match delay {
+                Ok(delay) => delay,
+                Err(err) => {
+                    self.report_error(err.to_string(), msg, stop_token.clone()).await;
+                    continue;
+                }
+            };
+
+            let node = self.clone();
+            let cancel = stop_token.child_token();
+            tokio::spawn(async move {
+                let clock = node.clock();
+                if async_util::delay_on(clock.as_ref(), delay, cancel.clone()).await.is_ok() {
+                    if let Err(err) = node.fan_out_one(Envelope { port: 0, msg }, cancel).await {
+                        log::warn!("[delay:{}] Failed to send the delayed message: {}", node.name(), err);
+                    }
+                }
+            });
+        }
+    }
+
+    async fn rate_loop(self: Arc<Self>, stop_token: CancellationToken) {
+        let clock = self.clock();
+        let mut queue: VecDeque<MsgHandle> = VecDeque::new();
+        let mut last_sent: Option<Duration> = None;
+
+        while !stop_token.is_cancelled() {
+            let queue_len = queue.len();
+            let wait = match last_sent {
+                Some(sent_at) => self.rate_interval.saturating_sub(clock.elapsed().saturating_sub(sent_at)),
+                None => Duration::ZERO,
+            };
+
+            tokio::select! {
+                result = self.recv_msg(stop_token.clone()) => match result {
+                    Ok(msg) => {
+                        self.on_rate_msg(msg, &mut queue, &mut last_sent, clock.as_ref(), stop_token.clone()).await;
+                    }
+                    Err(err) => {
+                        if !matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::TaskCancelled)) {
+                            log::warn!("[delay:{}] {}", self.name(), err);
+                        }
+                        break;
+                    }
+                },
+
+                _ = clock.sleep(wait), if !queue.is_empty() => {
+                    if let Some(msg) = queue.pop_front() {
+                        last_sent = Some(clock.elapsed());
+                        self.send_msg(0, msg, stop_token.clone()).await;
+                    }
+                }
+            }
+
+            if queue.len() != queue_len {
+                self.queued.store(queue.len(), Ordering::Relaxed);
+                let status = if queue.is_empty() {
+                    NodeStatus::default()
+                } else {
+                    NodeStatus::new("blue", "ring", queue.len().to_string())
+                };
+                self.set_status(status, stop_token.clone()).await;
+            }
+        }
+    }
+
+    /// Handles a message arriving in the rate mode, the `msg.reset` and `msg.flush` controls are consumed here.
+    async fn on_rate_msg(
+        &self,
+        msg: MsgHandle,
+        queue: &mut VecDeque<MsgHandle>,
+        last_sent: &mut Option<Duration>,
+        clock: &dyn Clock,
+        stop_token: CancellationToken,
+    ) {
+        let (control, flush_count) = {
+            let msg = msg.read().await;
+            // A number of `msg.flush` only sends the first `n` queued messages
+            let flush_count = match msg.get(ControlMsgKind::FLUSH_PROPERTY) {
+                Some(Variant::Number(n)) => Some(n.as_f64().unwrap_or(0.0).max(0.0) as usize),
+                _ => None,
+            };
+            (ControlMsgKind::from_msg(&msg), flush_count)
+        };
+        match control {
+            Some(ControlMsgKind::Reset) => {
+                // Nothing queued is sent, and the next message passes right away
+                queue.clear();
+                *last_sent = None;
+            }
+            Some(ControlMsgKind::Flush) => {
+                let n = flush_count.unwrap_or(queue.len()).min(queue.len());
+                for msg in queue.drain(..n).collect::<Vec<_>>() {
+                    self.send_msg(0, msg, stop_token.clone()).await;
+                }
+                if queue.is_empty() {
+                    *last_sent = None;
+                }
+            }
+            None => {
+                let now = clock.elapsed();
+                let is_idle = queue.is_empty()
+                    && match last_sent {
+                        Some(sent_at) => now - *sent_at >= self.rate_interval,
+                        None => true,
+                    };
+                if is_idle {
+                    *last_sent = Some(now);
+                    self.send_msg(0, msg, stop_token).await;
+                } else if self.config.drop || (self.config.max_queue > 0 && queue.len() >= self.config.max_queue) {
+                    self.drop_msg(msg, stop_token).await;
+                } else {
+                    queue.push_back(msg);
+                }
+            }
+        }
+    }
+
+    async fn send_msg(&self, port: usize, msg: MsgHandle, cancel: CancellationToken) {
+        if let Err(err) = self.fan_out_one(Envelope { port, msg }, cancel).await {
+            log::warn!("[delay:{}] Failed to send the message: {}", self.name(), err);
+        }
+    }
+
+    async fn drop_msg(&self, msg: MsgHandle, cancel: CancellationToken) {
+        if self.config.outputs >= 2 {
+            self.send_msg(1, msg, cancel).await;
+        } else {
+            log::debug!("[delay:{}] Dropped a message exceeding the rate", self.name());
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for DelayNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        match self.config.pause_type {
+            PauseType::Rate => self.rate_loop(stop_token).await,
+            _ => self.delay_loop(stop_token).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::engine::Engine;
+    use crate::utils::time::MockClock;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_delay_msgs_by_the_clock() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "delay", "timeout": "2",
+                "timeoutUnits": "seconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let clock = Arc::new(MockClock::new());
+        engine.set_clock(clock.clone());
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        let msg = MsgHandle::new(Msg::deserialize(json!({"payload": "foo"})).unwrap());
+        engine.inject_msg(&"1".parse().unwrap(), msg, CancellationToken::new()).await.unwrap();
+        wait_for_pending_sleeps(&clock, 1).await;
+
+        clock.advance(Duration::from_secs(1));
+        assert!(sink_rx.try_recv().is_err());
+        clock.advance(Duration::from_secs(1));
+        let (_, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(msg["payload"].as_str(), Some("foo"));
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_send_overflowed_msgs_to_the_second_output() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "rate", "rate": "1", "nbRateUnits": "1",
+                "rateUnits": "second", "maxQueue": 2, "outputs": 2, "wires": [["2"], ["3"]]},
+            {"id": "2", "z": "100", "type": "sink"},
+            {"id": "3", "z": "100", "type": "sink"},
+            {"id": "4", "z": "100", "type": "status", "scope": ["1"], "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "sink"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let clock = Arc::new(MockClock::new());
+        engine.set_clock(clock.clone());
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+
+        for i in 0..5 {
+            let msg = MsgHandle::new(Msg::deserialize(json!({"payload": i})).unwrap());
+            engine.inject_msg(&"1".parse().unwrap(), msg, CancellationToken::new()).await.unwrap();
+        }
+
+        // The first message passes, two are queued and two overflowed
+        let received = recv_from_sinks(&mut sink_rx, 5).await;
+        assert_eq!(received["2"], vec![json!(0)]);
+        assert_eq!(received["3"], vec![json!(3), json!(4)]);
+        assert_eq!(received["5"], vec![json!("1"), json!("2")]);
+
+        wait_for_pending_sleeps(&clock, 1).await;
+        clock.advance(Duration::from_secs(1));
+        let received = recv_from_sinks(&mut sink_rx, 2).await;
+        assert_eq!(received["2"], vec![json!(1)]);
+        assert_eq!(received["5"], vec![json!("1")]);
+        engine.stop().await.unwrap();
+    }
+
+    /// Builds a delay node limiting to 1 msg/s, and sends the payloads `0..n` to it.
+    async fn start_rate_limited(
+        n: usize,
+    ) -> (Engine, Arc<MockClock>, tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>) {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "rate", "rate": "1", "nbRateUnits": "1",
+                "rateUnits": "second", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let clock = Arc::new(MockClock::new());
+        engine.set_clock(clock.clone());
+        let sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+        for i in 0..n {
+            inject(&engine, json!({"payload": i})).await;
+        }
+        (engine, clock, sink_rx)
+    }
+
+    async fn inject(engine: &Engine, msg: serde_json::Value) {
+        let msg = MsgHandle::new(Msg::deserialize(msg).unwrap());
+        engine.inject_msg(&"1".parse().unwrap(), msg, CancellationToken::new()).await.unwrap();
+    }
+
+    async fn wait_for_queued(engine: &Engine, n: usize) {
+        let node = engine.find_flow_node_by_id(&"1".parse().unwrap()).unwrap();
+        let delay_node = node.as_any().downcast_ref::<DelayNode>().unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while delay_node.queued_len() != n {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn recv_payloads(
+        sink_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>,
+        n: usize,
+    ) -> Vec<serde_json::Value> {
+        let mut payloads = Vec::new();
+        for _ in 0..n {
+            let (_, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+            payloads.push(serde_json::to_value(&msg["payload"]).unwrap());
+        }
+        payloads
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_flush_the_queue_in_order() {
+        let (engine, _clock, mut sink_rx) = start_rate_limited(5).await;
+        assert_eq!(recv_payloads(&mut sink_rx, 1).await, vec![json!(0)]);
+        wait_for_queued(&engine, 4).await;
+
+        // Only the first two with a count
+        inject(&engine, json!({"flush": 2})).await;
+        assert_eq!(recv_payloads(&mut sink_rx, 2).await, vec![json!(1), json!(2)]);
+        wait_for_queued(&engine, 2).await;
+
+        inject(&engine, json!({"flush": true})).await;
+        assert_eq!(recv_payloads(&mut sink_rx, 2).await, vec![json!(3), json!(4)]);
+        wait_for_queued(&engine, 0).await;
+
+        // The rate limit starts over after the queue has been emptied
+        inject(&engine, json!({"payload": 5})).await;
+        assert_eq!(recv_payloads(&mut sink_rx, 1).await, vec![json!(5)]);
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_drop_the_queue_on_reset() {
+        let (engine, clock, mut sink_rx) = start_rate_limited(3).await;
+        assert_eq!(recv_payloads(&mut sink_rx, 1).await, vec![json!(0)]);
+        wait_for_queued(&engine, 2).await;
+
+        inject(&engine, json!({"reset": true})).await;
+        wait_for_queued(&engine, 0).await;
+        clock.advance(Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(sink_rx.try_recv().is_err());
+
+        inject(&engine, json!({"payload": 3})).await;
+        assert_eq!(recv_payloads(&mut sink_rx, 1).await, vec![json!(3)]);
+        engine.stop().await.unwrap();
+    }
+
+    async fn wait_for_pending_sleeps(clock: &MockClock, n: usize) {
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while clock.pending_sleeps() < n {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    /// Collects the payloads of the sinks, or the status texts for the sink after the status node.
+    async fn recv_from_sinks(
+        sink_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>,
+        n: usize,
+    ) -> std::collections::HashMap<String, Vec<serde_json::Value>> {
+        let mut received = std::collections::HashMap::<String, Vec<serde_json::Value>>::new();
+        for _ in 0..n {
+            let (node_id, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+            let value = match msg.get_nav("status.text") {
+                Some(text) => serde_json::to_value(text).unwrap(),
+                None => serde_json::to_value(&msg["payload"]).unwrap(),
+            };
+            let key = node_id.to_string().trim_start_matches('0').to_string();
+            received.entry(key).or_default().push(value);
+        }
+        received
+    }
+}
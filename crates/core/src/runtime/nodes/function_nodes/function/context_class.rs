@@ -1,95 +1,105 @@
 use std::sync::Arc;
 
-use rquickjs::{class::Trace, Ctx, Function, IntoJs, Value};
-use rquickjs::{prelude::*, Exception};
+use rquickjs::{class::Trace, CatchResultExt, Ctx, Function, IntoJs, Promise, Value};
+use rquickjs::{function::IntoArgs, prelude::*, Exception};
 
-use crate::runtime::context::Context as RedContext;
+use crate::runtime::context::{Context as RedContext, ContextWatchId};
 use crate::utils::async_util::SyncWaitableFuture;
 
 use super::{UndefinableVariant, Variant};
 
+/// The `context`, `flow` and `global` objects of the function node, every method takes an optional store name, the
+/// default store is used if it is omitted, `undefined` or `null`.
+///
+/// The `*Async` methods return a `Promise` instead of blocking the JS thread until the store is done.
 #[derive(Clone, Trace)]
 #[rquickjs::class(frozen)]
 pub(super) struct ContextClass {
     #[qjs(skip_trace)]
     pub red_ctx: Arc<RedContext>,
+
+    #[qjs(skip_trace)]
+    watches: ContextWatches,
+}
+
+/// The watches of all context objects of a function node, removed by `unwatch_all()` when the node stops.
+pub(super) type ContextWatches = Arc<std::sync::Mutex<Vec<(Arc<RedContext>, ContextWatchId)>>>;
+
+pub(super) fn unwatch_all(watches: &ContextWatches) {
+    for (red_ctx, id) in watches.lock().expect("watches").drain(..) {
+        red_ctx.unwatch(id);
+    }
 }
 
 #[allow(non_snake_case)]
 #[rquickjs::methods]
 impl ContextClass {
     #[qjs(skip)]
-    pub fn new(red_ctx: Arc<RedContext>) -> Self {
-        ContextClass { red_ctx }
+    pub fn new(red_ctx: Arc<RedContext>, watches: ContextWatches) -> Self {
+        ContextClass { red_ctx, watches }
     }
 
+    /// `context.get(key[, store][, callback])`, the value is returned if no callback, otherwise it is passed to the
+    /// `callback(err, value)` once the store has been read.
     #[qjs(rename = "get")]
     pub fn get<'js>(
         self,
         keys: Value<'js>,
-        store: Opt<rquickjs::String<'js>>,
+        store: Opt<Value<'js>>,
         cb: Opt<Function<'js>>,
         ctx: Ctx<'js>,
     ) -> rquickjs::Result<Value<'js>> {
         let keys: String = keys.get()?;
+        let (store, cb) = split_store_and_callback(store, cb)?;
 
-        if let Some(cb) = cb.0 {
+        if let Some(cb) = cb {
             let async_ctx = ctx.clone();
             // User provides the callback, we do it in async
             ctx.spawn(async move {
-                let store = store.0.and_then(|x| x.get::<String>().ok());
-                match self.red_ctx.get_one(store.as_deref(), keys.as_ref(), &[]).await {
-                    Some(ctx_value) => {
-                        let args = (Value::new_undefined(async_ctx.clone()), ctx_value.into_js(&async_ctx));
-                        cb.call::<_, ()>(args).unwrap();
+                let args = match self.red_ctx.try_get_one(store.as_deref(), keys.as_ref(), &[]).await {
+                    Ok(ctx_value) => {
+                        (Ok(Value::new_undefined(async_ctx.clone())), UndefinableVariant(ctx_value).into_js(&async_ctx))
                     }
-                    None => {
-                        let args = (Value::new_undefined(async_ctx.clone()), Value::new_undefined(async_ctx.clone()));
-                        cb.call::<_, ()>(args).unwrap();
-                    }
-                }
+                    Err(err) => (error_to_js(&async_ctx, &err), Ok(Value::new_undefined(async_ctx.clone()))),
+                };
+                invoke_callback(&async_ctx, cb, args);
             });
             Ok(Value::new_undefined(ctx.clone()))
         } else {
             // No callback, we do it in sync
-            let store = store.0.and_then(|x| x.get::<String>().ok());
-            let ctx_value = async move { self.red_ctx.get_one(store.as_deref(), keys.as_ref(), &[]).await }.wait();
+            let ctx_value = async move { self.red_ctx.try_get_one(store.as_deref(), keys.as_ref(), &[]).await }
+                .wait()
+                .map_err(|e| ctx.throw(format!("{}", e).into_js(&ctx).unwrap()))?;
             UndefinableVariant(ctx_value).into_js(&ctx)
         }
     }
 
+    /// `context.set(key, value[, store][, callback])`, the `callback(err)` is called once the store has been written.
     #[qjs(rename = "set")]
     pub fn set<'js>(
         self,
         keys: Value<'js>,
         values: Value<'js>,
-        store: Opt<rquickjs::String<'js>>,
+        store: Opt<Value<'js>>,
         cb: Opt<Function<'js>>,
         ctx: Ctx<'js>,
     ) -> rquickjs::Result<()> {
         let keys: String = keys.get()?;
         let values: Variant = values.get()?;
+        let (store, cb) = split_store_and_callback(store, cb)?;
 
-        if let Some(cb) = cb.0 {
+        if let Some(cb) = cb {
             let async_ctx = ctx.clone();
             // User provides the callback, we do it in async
             ctx.spawn(async move {
-                let store = store.0.and_then(|x| x.get::<String>().ok());
-                match self.red_ctx.set_one(store.as_deref(), keys.as_ref(), Some(values), &[]).await {
-                    Ok(()) => {
-                        let args = (Value::new_undefined(async_ctx.clone()),);
-                        cb.call::<_, ()>(args).unwrap();
-                    }
-                    Err(_) => {
-                        let args =
-                            (Exception::from_message(async_ctx.clone(), "Failed to parse key").into_js(&async_ctx),);
-                        cb.call::<_, ()>(args).unwrap();
-                    }
-                }
+                let args = match self.red_ctx.set_one(store.as_deref(), keys.as_ref(), Some(values), &[]).await {
+                    Ok(()) => (Ok(Value::new_undefined(async_ctx.clone())),),
+                    Err(err) => (error_to_js(&async_ctx, &err),),
+                };
+                invoke_callback(&async_ctx, cb, args);
             });
         } else {
             // No callback, we do it in sync
-            let store = store.0.and_then(|x| x.get::<String>().ok());
             async move { self.red_ctx.set_one(store.as_deref(), keys.as_ref(), Some(values), &[]).await }
                 .wait()
                 .map_err(|e| ctx.throw(format!("{}", e).into_js(&ctx).unwrap()))?;
@@ -97,37 +107,165 @@ impl ContextClass {
         Ok(())
     }
 
+    /// `context.keys([store][, callback])`
     #[qjs(rename = "keys")]
     pub fn keys<'js>(
         self,
-        store: Opt<rquickjs::String<'js>>,
+        store: Opt<Value<'js>>,
         cb: Opt<Function<'js>>,
         ctx: Ctx<'js>,
     ) -> rquickjs::Result<Value<'js>> {
         let async_ctx = ctx.clone();
-        if let Some(cb) = cb.0 {
+        let (store, cb) = split_store_and_callback(store, cb)?;
+        if let Some(cb) = cb {
             // User provides the callback, we do it in async
             ctx.spawn(async move {
-                let store = store.0.and_then(|x| x.get::<String>().ok());
-                match self.red_ctx.keys(store.as_deref()).await {
-                    Some(ctx_keys) => {
-                        let args = (Value::new_undefined(async_ctx.clone()), ctx_keys.into_js(&async_ctx));
-                        cb.call::<_, ()>(args).unwrap();
-                    }
-                    None => {
-                        let args = (Value::new_undefined(async_ctx.clone()), Value::new_undefined(async_ctx.clone()));
-                        cb.call::<_, ()>(args).unwrap();
-                    }
-                }
+                let ctx_keys = self.red_ctx.keys(store.as_deref()).await.unwrap_or_default();
+                let args = (Value::new_undefined(async_ctx.clone()), ctx_keys.into_js(&async_ctx));
+                invoke_callback(&async_ctx, cb, args);
             });
             Ok(Value::new_undefined(ctx.clone()))
         } else {
             // No callback, we do it in sync
-            let store = store.0.and_then(|x| x.get::<String>().ok());
             match async move { self.red_ctx.keys(store.as_deref()).await }.wait() {
                 Some(ctx_keys) => ctx_keys.into_js(&ctx),
                 None => Ok(Value::new_undefined(ctx.clone())),
             }
         }
     }
+
+    /// `context.getAsync(key[, store])`, returns a `Promise` of the value, e.g. `await flow.getAsync('k', 'file')`.
+    #[qjs(rename = "getAsync")]
+    pub fn get_async<'js>(
+        self,
+        keys: Value<'js>,
+        store: Opt<Value<'js>>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<Promise<'js>> {
+        let keys: String = keys.get()?;
+        let (store, _) = split_store_and_callback(store, Opt(None))?;
+        let (promise, resolve, reject) = ctx.promise()?;
+        let async_ctx = ctx.clone();
+        ctx.spawn(async move {
+            match self.red_ctx.try_get_one(store.as_deref(), keys.as_ref(), &[]).await {
+                Ok(ctx_value) => invoke_callback(&async_ctx, resolve, (UndefinableVariant(ctx_value),)),
+                Err(err) => invoke_callback(&async_ctx, reject, (error_to_js(&async_ctx, &err),)),
+            }
+        });
+        Ok(promise)
+    }
+
+    /// `context.setAsync(key, value[, store])`, returns a `Promise` settled once the store has been written.
+    #[qjs(rename = "setAsync")]
+    pub fn set_async<'js>(
+        self,
+        keys: Value<'js>,
+        values: Value<'js>,
+        store: Opt<Value<'js>>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<Promise<'js>> {
+        let keys: String = keys.get()?;
+        let values: Variant = values.get()?;
+        let (store, _) = split_store_and_callback(store, Opt(None))?;
+        let (promise, resolve, reject) = ctx.promise()?;
+        let async_ctx = ctx.clone();
+        ctx.spawn(async move {
+            match self.red_ctx.set_one(store.as_deref(), keys.as_ref(), Some(values), &[]).await {
+                Ok(()) => invoke_callback(&async_ctx, resolve, ()),
+                Err(err) => invoke_callback(&async_ctx, reject, (error_to_js(&async_ctx, &err),)),
+            }
+        });
+        Ok(promise)
+    }
+
+    /// `context.keysAsync([store])`, returns a `Promise` of the keys, rejected if the store fails.
+    #[qjs(rename = "keysAsync")]
+    pub fn keys_async<'js>(self, store: Opt<Value<'js>>, ctx: Ctx<'js>) -> rquickjs::Result<Promise<'js>> {
+        let (store, _) = split_store_and_callback(store, Opt(None))?;
+        let (promise, resolve, reject) = ctx.promise()?;
+        let async_ctx = ctx.clone();
+        ctx.spawn(async move {
+            match self.red_ctx.try_keys(store.as_deref()).await {
+                Ok(ctx_keys) => invoke_callback(&async_ctx, resolve, (ctx_keys,)),
+                Err(err) => invoke_callback(&async_ctx, reject, (error_to_js(&async_ctx, &err),)),
+            }
+        });
+        Ok(promise)
+    }
+
+    /// `context.watch(key[, store], callback)`, the `callback(err, value)` is called with the new value whenever the key
+    /// is set or removed in this context, or with the error of reading it. The ID returned is for `unwatch()`.
+    #[qjs(rename = "watch")]
+    pub fn watch<'js>(
+        self,
+        keys: Value<'js>,
+        store: Opt<Value<'js>>,
+        cb: Opt<Function<'js>>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<u64> {
+        let keys: String = keys.get()?;
+        let (store, cb) = split_store_and_callback(store, cb)?;
+        let cb = cb.ok_or_else(|| Exception::throw_type(&ctx, "The callback of `watch()` is required"))?;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let id = self
+            .red_ctx
+            .watch(store.as_deref(), keys.as_ref(), move |value| {
+                // The node has gone, nothing to do
+                let _ = tx.send(value);
+            })
+            .map_err(|e| ctx.throw(format!("{}", e).into_js(&ctx).unwrap()))?;
+        self.watches.lock().expect("watches").push((self.red_ctx.clone(), id));
+
+        let async_ctx = ctx.clone();
+        ctx.spawn(async move {
+            // Ends once unwatched, the sender is dropped with the watch
+            while let Some(value) = rx.recv().await {
+                let args = match value {
+                    Ok(value) => {
+                        (Ok(Value::new_undefined(async_ctx.clone())), UndefinableVariant(value).into_js(&async_ctx))
+                    }
+                    Err(err) => (error_to_js(&async_ctx, &err), Ok(Value::new_undefined(async_ctx.clone()))),
+                };
+                invoke_callback(&async_ctx, cb.clone(), args);
+            }
+        });
+        Ok(id)
+    }
+
+    /// `context.unwatch(id)`, returns `false` if the watch is not one of this node.
+    #[qjs(rename = "unwatch")]
+    pub fn unwatch(self, id: u64) -> bool {
+        let mut watches = self.watches.lock().expect("watches");
+        match watches.iter().position(|x| x.1 == id) {
+            Some(index) => {
+                let (red_ctx, id) = watches.remove(index);
+                red_ctx.unwatch(id)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Node-RED allows to omit the store before the callback, like `context.get(key, callback)`.
+fn split_store_and_callback<'js>(
+    store: Opt<Value<'js>>,
+    cb: Opt<Function<'js>>,
+) -> rquickjs::Result<(Option<String>, Option<Function<'js>>)> {
+    match (store.0, cb.0) {
+        (Some(store), None) if store.is_function() => Ok((None, store.into_function())),
+        (Some(store), cb) if store.is_undefined() || store.is_null() => Ok((None, cb)),
+        (Some(store), cb) => Ok((Some(store.get::<String>()?), cb)),
+        (None, cb) => Ok((None, cb)),
+    }
+}
+
+pub(super) fn error_to_js<'js>(ctx: &Ctx<'js>, err: &anyhow::Error) -> rquickjs::Result<Value<'js>> {
+    Exception::from_message(ctx.clone(), &err.to_string()).into_js(ctx)
+}
+
+/// Runs the user callback, an exception thrown by it must not take the whole node down.
+pub(super) fn invoke_callback<'js, A: IntoArgs<'js>>(ctx: &Ctx<'js>, cb: Function<'js>, args: A) {
+    if let Err(err) = cb.call::<_, ()>(args).catch(ctx) {
+        log::warn!("Uncaught exception in the callback: {}", err);
+    }
 }
@@ -1,4 +1,305 @@
 // Prelude script for every `function` node
+
+// A minimal Node.js-like `Buffer`, `Variant::Bytes` values are converted into it.
+globalThis.Buffer = (function () {
+
+    function utf8Encode(str) {
+        const bytes = [];
+        for (let i = 0; i < str.length; i++) {
+            let cp = str.codePointAt(i);
+            if (cp > 0xffff) {
+                i++;
+            }
+            if (cp >= 0xd800 && cp <= 0xdfff) {
+                cp = 0xfffd; // Lone surrogate
+            }
+            if (cp < 0x80) {
+                bytes.push(cp);
+            } else if (cp < 0x800) {
+                bytes.push(0xc0 | (cp >> 6), 0x80 | (cp & 0x3f));
+            } else if (cp < 0x10000) {
+                bytes.push(0xe0 | (cp >> 12), 0x80 | ((cp >> 6) & 0x3f), 0x80 | (cp & 0x3f));
+            } else {
+                bytes.push(0xf0 | (cp >> 18), 0x80 | ((cp >> 12) & 0x3f), 0x80 | ((cp >> 6) & 0x3f), 0x80 | (cp & 0x3f));
+            }
+        }
+        return bytes;
+    }
+
+    function utf8Decode(bytes) {
+        let out = '';
+        let i = 0;
+        while (i < bytes.length) {
+            const b0 = bytes[i];
+            let cp = 0xfffd;
+            let n = 1;
+            if (b0 < 0x80) {
+                cp = b0;
+            } else if ((b0 & 0xe0) === 0xc0 && i + 1 < bytes.length && (bytes[i + 1] & 0xc0) === 0x80) {
+                cp = ((b0 & 0x1f) << 6) | (bytes[i + 1] & 0x3f);
+                n = 2;
+            } else if ((b0 & 0xf0) === 0xe0 && i + 2 < bytes.length
+                && (bytes[i + 1] & 0xc0) === 0x80 && (bytes[i + 2] & 0xc0) === 0x80) {
+                cp = ((b0 & 0x0f) << 12) | ((bytes[i + 1] & 0x3f) << 6) | (bytes[i + 2] & 0x3f);
+                n = 3;
+            } else if ((b0 & 0xf8) === 0xf0 && i + 3 < bytes.length && (bytes[i + 1] & 0xc0) === 0x80
+                && (bytes[i + 2] & 0xc0) === 0x80 && (bytes[i + 3] & 0xc0) === 0x80) {
+                cp = ((b0 & 0x07) << 18) | ((bytes[i + 1] & 0x3f) << 12)
+                    | ((bytes[i + 2] & 0x3f) << 6) | (bytes[i + 3] & 0x3f);
+                n = 4;
+            }
+            out += String.fromCodePoint(cp);
+            i += n;
+        }
+        return out;
+    }
+
+    const BASE64_CHARS = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/';
+
+    function base64Encode(bytes) {
+        let out = '';
+        for (let i = 0; i < bytes.length; i += 3) {
+            const n = (bytes[i] << 16) | ((bytes[i + 1] || 0) << 8) | (bytes[i + 2] || 0);
+            out += BASE64_CHARS[(n >> 18) & 0x3f] + BASE64_CHARS[(n >> 12) & 0x3f];
+            out += i + 1 < bytes.length ? BASE64_CHARS[(n >> 6) & 0x3f] : '=';
+            out += i + 2 < bytes.length ? BASE64_CHARS[n & 0x3f] : '=';
+        }
+        return out;
+    }
+
+    function base64Decode(str) {
+        const bytes = [];
+        let bits = 0;
+        let acc = 0;
+        for (const c of str.replace(/-/g, '+').replace(/_/g, '/')) {
+            const v = BASE64_CHARS.indexOf(c);
+            if (v < 0) {
+                continue;
+            }
+            acc = (acc << 6) | v;
+            bits += 6;
+            if (bits >= 8) {
+                bits -= 8;
+                bytes.push((acc >> bits) & 0xff);
+            }
+        }
+        return bytes;
+    }
+
+    function encode(str, encoding) {
+        switch ((encoding || 'utf8').toLowerCase()) {
+            case 'utf8':
+            case 'utf-8':
+                return utf8Encode(str);
+            case 'hex': {
+                const bytes = [];
+                for (let i = 0; i + 1 < str.length; i += 2) {
+                    const b = parseInt(str.substr(i, 2), 16);
+                    if (Number.isNaN(b)) {
+                        break;
+                    }
+                    bytes.push(b);
+                }
+                return bytes;
+            }
+            case 'base64':
+            case 'base64url':
+                return base64Decode(str);
+            case 'ascii':
+            case 'latin1':
+            case 'binary':
+                return Array.from(str, c => c.charCodeAt(0) & 0xff);
+            default:
+                throw new TypeError('Unknown encoding: ' + encoding);
+        }
+    }
+
+    function decode(bytes, encoding) {
+        switch ((encoding || 'utf8').toLowerCase()) {
+            case 'utf8':
+            case 'utf-8':
+                return utf8Decode(bytes);
+            case 'hex':
+                return Array.from(bytes, b => (b < 16 ? '0' : '') + b.toString(16)).join('');
+            case 'base64':
+                return base64Encode(bytes);
+            case 'ascii':
+                return Array.from(bytes, b => String.fromCharCode(b & 0x7f)).join('');
+            case 'latin1':
+            case 'binary':
+                return Array.from(bytes, b => String.fromCharCode(b)).join('');
+            default:
+                throw new TypeError('Unknown encoding: ' + encoding);
+        }
+    }
+
+    class Buffer extends Uint8Array {
+
+        static from(value, encodingOrOffset, length) {
+            if (typeof value === 'string') {
+                return new Buffer(encode(value, encodingOrOffset));
+            }
+            if (value instanceof ArrayBuffer) {
+                const offset = encodingOrOffset || 0;
+                return new Buffer(value, offset, length === undefined ? value.byteLength - offset : length);
+            }
+            if (ArrayBuffer.isView(value)) {
+                return new Buffer(new Uint8Array(value.buffer, value.byteOffset, value.byteLength));
+            }
+            if (value !== null && typeof value === 'object' && value.type === 'Buffer' && Array.isArray(value.data)) {
+                return new Buffer(value.data);
+            }
+            if (Array.isArray(value)) {
+                return new Buffer(value);
+            }
+            throw new TypeError('The first argument must be a string, Buffer, ArrayBuffer or Array');
+        }
+
+        static alloc(size, fill) {
+            const buf = new Buffer(size);
+            if (fill !== undefined) {
+                buf.fill(typeof fill === 'string' ? encode(fill)[0] : fill);
+            }
+            return buf;
+        }
+
+        static allocUnsafe(size) {
+            return new Buffer(size);
+        }
+
+        static isBuffer(obj) {
+            return obj instanceof Buffer;
+        }
+
+        static byteLength(value, encoding) {
+            return typeof value === 'string' ? encode(value, encoding).length : value.byteLength;
+        }
+
+        static concat(list, totalLength) {
+            if (totalLength === undefined) {
+                totalLength = list.reduce((acc, x) => acc + x.length, 0);
+            }
+            const buf = new Buffer(totalLength);
+            let offset = 0;
+            for (const item of list) {
+                if (offset >= totalLength) {
+                    break;
+                }
+                const part = item.subarray(0, totalLength - offset);
+                buf.set(part, offset);
+                offset += part.length;
+            }
+            return buf;
+        }
+
+        static compare(a, b) {
+            return a.compare(b);
+        }
+
+        toString(encoding, start, end) {
+            return decode(this.subarray(start || 0, end === undefined ? this.length : end), encoding);
+        }
+
+        // Like Node.js, `slice()` returns a view instead of a copy.
+        slice(start, end) {
+            return this.subarray(start, end);
+        }
+
+        equals(other) {
+            return this.compare(other) === 0;
+        }
+
+        compare(other) {
+            const len = Math.min(this.length, other.length);
+            for (let i = 0; i < len; i++) {
+                if (this[i] !== other[i]) {
+                    return this[i] < other[i] ? -1 : 1;
+                }
+            }
+            return this.length === other.length ? 0 : (this.length < other.length ? -1 : 1);
+        }
+
+        copy(target, targetStart, sourceStart, sourceEnd) {
+            const part = this.subarray(sourceStart || 0, sourceEnd === undefined ? this.length : sourceEnd);
+            const n = Math.min(part.length, target.length - (targetStart || 0));
+            target.set(part.subarray(0, n), targetStart || 0);
+            return n;
+        }
+
+        write(str, offset, encoding) {
+            const bytes = encode(str, encoding);
+            const n = Math.min(bytes.length, this.length - (offset || 0));
+            this.set(bytes.slice(0, n), offset || 0);
+            return n;
+        }
+
+        toJSON() {
+            return { type: 'Buffer', data: Array.from(this) };
+        }
+    }
+
+    return Buffer;
+})();
+
+// The constants of `runtime.engine.function_global_context`, read-only globals available without `require()`.
+(function () {
+    const constants = globalThis.__edgelinkFunctionGlobalContext || {};
+    delete globalThis.__edgelinkFunctionGlobalContext;
+
+    function deepFreeze(value) {
+        if (value !== null && typeof value === 'object' && !ArrayBuffer.isView(value)) {
+            for (const key of Object.keys(value)) {
+                deepFreeze(value[key]);
+            }
+            Object.freeze(value);
+        }
+        return value;
+    }
+
+    for (const key of Object.keys(constants)) {
+        // The names of the sandbox are rejected by the engine, the built-ins like `Object` are rejected here
+        if (key in globalThis) {
+            throw new TypeError(`The function global context cannot shadow the global '${key}'`);
+        }
+        // An accessor, so the assignments throw even in the sloppy mode of the user code
+        const value = deepFreeze(constants[key]);
+        Object.defineProperty(globalThis, key, {
+            get() {
+                return value;
+            },
+            set() {
+                throw new TypeError(`The function global context '${key}' is read-only`);
+            },
+            enumerable: true,
+            configurable: false,
+        });
+    }
+})();
+
+// There is no `process` in the sandbox, only a read-only `process.env` reading the env store if enabled by
+// `runtime.engine.function_process_env`.
+(function () {
+    const exposed = globalThis.__edgelinkProcessEnv === true;
+    delete globalThis.__edgelinkProcessEnv;
+    if (!exposed) {
+        return;
+    }
+
+    const processEnv = new Proxy(Object.create(null), {
+        get: (_, key) => (typeof key === 'string' ? env.get(key) : undefined),
+        has: (_, key) => typeof key === 'string' && env.get(key) !== undefined,
+        set: () => false,
+        deleteProperty: () => false,
+        defineProperty: () => false,
+    });
+    Object.defineProperty(globalThis, 'process', {
+        value: Object.freeze({ env: processEnv }),
+        writable: false,
+        enumerable: false,
+        configurable: false,
+    });
+})();
+
 const RED = (function () {
     return {
         util: {
@@ -20,6 +321,14 @@ const RED = (function () {
                     return new RegExp(value);
                 }
 
+                if (value instanceof ArrayBuffer) {
+                    return value.slice(0);
+                }
+
+                if (ArrayBuffer.isView(value)) {
+                    return new value.constructor(value);
+                }
+
                 if (Array.isArray(value)) {
                     const clonedArray = value.map(item => this.__cloneDeep(item, map));
                     map.set(value, clonedArray);
@@ -60,7 +369,120 @@ const RED = (function () {
                 }
                 return msg;
 
+            },
+
+            getMessageProperty: function (msg, expr) {
+                return __edgelinkUtil.getMessageProperty(msg, expr);
+            },
+
+            setMessageProperty: function (msg, expr, value, createMissing) {
+                if (typeof createMissing === 'undefined') {
+                    createMissing = (typeof value !== 'undefined');
+                }
+                return __edgelinkUtil.setMessageProperty(msg, expr, value, createMissing);
+            },
+
+            evaluateNodeProperty: function (value, type, node, msg, callback) {
+                return __edgelinkUtil.evaluateNodeProperty(String(value), type, node, msg, callback);
+            },
+        }
+    };
+})();
+
+// The `node.on()` of Node-RED for the copied code, it coexists with the `return` and `node.send()` styles:
+// - The `input` handlers registered by the `initialize` code run for every message before the function body;
+// - The `input` handlers registered by the function body itself only run for the current message after the body,
+//   so the body registering a handler for every message does not accumulate them;
+// - The `close` handlers run after the `finalize` code.
+//
+// Like Node-RED, the function body of every message gets its own `node`, so the messages processed concurrently and
+// interleaving at an `await` never see the `input` handlers or the `done()` of each other.
+const __edgelinkNodeEvents = (function () {
+    const persistent = { input: [], close: [] };
+
+    function addHandler(handlers, event, handler) {
+        if (typeof handler !== 'function') {
+            throw new TypeError('The handler of the event must be a function');
+        }
+        if (!handlers) {
+            node.warn(`Unsupported event of the node: '${event}'`);
+            return;
+        }
+        handlers.push(handler);
+    }
+
+    node.on = function (event, handler) {
+        addHandler(persistent[event], event, handler);
+        return node;
+    };
+
+    // The `node` of a single message, everything but `on()` and `done()` is forwarded to the shared `node`
+    function scopedNode(transient, state) {
+        const scoped = new Proxy(node, {
+            get(target, key) {
+                if (key === 'on') {
+                    return function (event, handler) {
+                        addHandler(event === 'input' ? transient : persistent[event], event, handler);
+                        return scoped;
+                    };
+                }
+                if (key === 'done') {
+                    return function (err) {
+                        if (err) {
+                            state.failure = err;
+                        }
+                    };
+                }
+                const value = Reflect.get(target, key);
+                return typeof value === 'function' ? value.bind(target) : value;
+            },
+        });
+        return scoped;
+    }
+
+    async function invokeInput(handler, self, msg) {
+        let failure = null;
+        const send = (msgs, cloning) => (cloning === undefined ? node.send(msgs) : node.send(msgs, cloning));
+        const done = (err) => {
+            if (err) {
+                failure = err;
             }
+        };
+        await handler.call(self, msg, send, done);
+        if (failure !== null) {
+            throw failure;
         }
+    }
+
+    return {
+        dispatchInput: async function (msg, body) {
+            for (const handler of persistent.input) {
+                await invokeInput(handler, node, msg);
+            }
+            const transient = [];
+            const state = { failure: null };
+            const self = scopedNode(transient, state);
+            const result = await body(msg, self);
+            if (state.failure !== null) {
+                throw state.failure;
+            }
+            for (const handler of transient) {
+                await invokeInput(handler, self, msg);
+            }
+            return result;
+        },
+
+        // Like Node-RED, the arity tells whether the handler takes `removed` and `done`
+        close: async function () {
+            for (const handler of persistent.close) {
+                if (handler.length >= 2) {
+                    await new Promise((resolve) => handler.call(node, false, resolve));
+                } else if (handler.length === 1) {
+                    await new Promise((resolve) => handler.call(node, resolve));
+                } else {
+                    await handler.call(node);
+                }
+            }
+        },
     };
 })();
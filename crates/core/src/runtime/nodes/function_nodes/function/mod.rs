@@ -1,3 +1,4 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use rquickjs::async_with;
@@ -20,10 +21,16 @@ use edgelink_macro::*;
 mod context_class;
 mod edgelink_class;
 mod env_class;
+mod modules;
 mod node_class;
+mod util_class;
 
 const OUTPUT_MSGS_CAP: usize = 4;
 
+/// How long the `finalize` code and the `close` handlers may take each when the node stops, like the close timeout
+/// of Node-RED; the node stops anyway after it.
+const FINALIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
 type OutputMsgs = smallvec::SmallVec<[(usize, Msg); OUTPUT_MSGS_CAP]>;
 
 #[derive(Deserialize, Debug)]
@@ -39,6 +46,12 @@ struct FunctionNodeConfig {
 
     #[serde(default, rename = "outputs")]
     output_count: usize,
+
+    #[serde(flatten)]
+    concurrency: NodeConcurrency,
+
+    #[serde(default)]
+    libs: Vec<modules::FunctionLib>,
 }
 
 #[derive(Debug)]
@@ -47,11 +60,25 @@ struct FunctionNode {
     base: FlowNode,
 
     output_count: usize,
+    concurrency: NodeConcurrency,
     user_script: Vec<u8>,
+    port_overflow_warned: AtomicBool,
+    context_watches: context_class::ContextWatches,
+
+    /// Every node has its own runtime, so the state of the modules is not shared between the nodes.
+    libs: Vec<modules::FunctionLib>,
+
+    /// The token of the running task, the messages sent by the JS code are cancelled with it.
+    stop_token: std::sync::Mutex<CancellationToken>,
 }
 
 const JS_PRELUDE_SCRIPT: &str = include_str!("./function.prelude.js");
 
+/// The globals of Node.js and the other hosts reaching the process, the filesystem or the network, removed from the
+/// sandbox in case any extension registered them.
+const DENIED_GLOBALS: &[&str] =
+    &["process", "require", "module", "exports", "os", "std", "fetch", "XMLHttpRequest", "WebSocket"];
+
 #[async_trait]
 impl FlowNodeBehavior for FunctionNode {
     fn get_node(&self) -> &FlowNode {
@@ -59,13 +86,16 @@ impl FlowNodeBehavior for FunctionNode {
     }
 
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        *self.stop_token.lock().expect("stop_token") = stop_token.clone();
+
         // This is a workaround; ideally, all function nodes should share a runtime. However,
         // for some reason, if the runtime of rquickjs is used as a global variable,
         // the members of node and env will disappear upon the second load.
         let js_rt_this = self.clone();
         log::debug!("[function:{}] Initializing JavaScript AsyncRuntime...", js_rt_this.name());
         let js_rt = js::AsyncRuntime::new().unwrap();
-        let resolver = js::loader::BuiltinResolver::default();
+        let allowed_modules = self.engine().map(|x| x.function_modules().clone()).unwrap_or_default();
+        let resolver = (js::loader::BuiltinResolver::default(), modules::AllowedModuleResolver::new(allowed_modules));
         let loaders = (js::loader::ScriptLoader::default(), js::loader::ModuleLoader::default());
         js_rt.set_loader(resolver, loaders).await;
         js_rt.idle().await;
@@ -83,6 +113,15 @@ impl FlowNodeBehavior for FunctionNode {
             }
             while ctx.execute_pending_job() {}
 
+            if let Err(e) = cloned_this.load_libs(ctx.clone()).await {
+                // It's a fatal error
+                log::error!("[function:{}] Fatal error! Failed to load the libs: {:?}", cloned_this.name(), e);
+
+                stop_token.cancel();
+                stop_token.cancelled().await;
+                return;
+            }
+
             if let Err(e) = cloned_this.init_async(ctx.clone()).await {
                 // It's a fatal error
                 log::error!("[function:{}] Fatal error! Failed to initialize JavaScript environment: {:?}", cloned_this.name(), e);
@@ -93,6 +132,21 @@ impl FlowNodeBehavior for FunctionNode {
             }
             while ctx.execute_pending_job() {}
 
+            // The user functions are async, so the concurrent messages interleave at their `await`s
+            if cloned_this.concurrency.is_concurrent() {
+                let this_node = cloned_this.clone();
+                with_concurrent_uows(cloned_this.as_ref(), cloned_this.concurrency, stop_token.clone(), |_, msg| {
+                    let sub_ctx = ctx.clone();
+                    let this_node = this_node.clone();
+                    async move {
+                        let msg = msg.read().await.clone();
+                        let changed_msgs = this_node.filter_msg(sub_ctx, msg).await?;
+                        Ok(this_node.to_envelopes(changed_msgs))
+                    }
+                })
+                .await;
+            }
+
             while !stop_token.is_cancelled() {
                 let sub_ctx = ctx.clone();
                 let cancel = stop_token.child_token();
@@ -107,12 +161,10 @@ impl FlowNodeBehavior for FunctionNode {
                         Ok(changed_msgs) => {
                             // Pack the new messages
                             if !changed_msgs.is_empty() {
-                                let envelopes = changed_msgs
-                                    .into_iter()
-                                    .map(|x| Envelope { port: x.0, msg: MsgHandle::new(x.1) })
-                                    .collect::<SmallVec<[Envelope; 4]>>();
-
-                                (this_node as Arc<dyn FlowNodeBehavior>).fan_out_many(envelopes, cancel.clone()).await?;
+                                let envelopes = this_node.to_envelopes(changed_msgs);
+                                if !envelopes.is_empty() {
+                                    (this_node as Arc<dyn FlowNodeBehavior>).fan_out_many(envelopes, cancel.clone()).await?;
+                                }
                             }
                         }
                         Err(e) => {
@@ -128,6 +180,8 @@ impl FlowNodeBehavior for FunctionNode {
             if let Err(e) = cloned_this.finalize_async(ctx.clone()).await {
                 log::error!("[function:{}] Fatal error! Failed to finalize JavaScript environment: {:?}", cloned_this.name(), e);
             }
+            // The tasks of the watches only end after unwatched, the runtime cannot go idle otherwise
+            context_class::unwatch_all(&cloned_this.context_watches);
             while ctx.execute_pending_job() {}
         })
         .await;
@@ -145,39 +199,57 @@ impl FunctionNode {
         config: &RedFlowNodeConfig,
     ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
         let mut function_config = FunctionNodeConfig::deserialize(&config.rest)?;
+        // The messages beyond the output count or to the unwired ports are dropped, so only the smaller one is used.
+        // The declared count is compared before it is raised, a node with `outputs: 0` has no port at all.
+        let nports = base_node.ports.len();
+        let declared = function_config.output_count;
+        if config.rest.get("outputs").is_some() && nports != declared {
+            log::warn!(
+                "[function:{}] The node declares {} output(s) but is wired to {} port(s), only {} will be used",
+                base_node.name,
+                declared,
+                nports,
+                super::function_used_outputs(declared, nports)
+            );
+        }
         if function_config.output_count == 0 {
             function_config.output_count = 1;
         }
 
+        // The context objects are bound once at the top level, so `initialize`, the per-message function and
+        // `finalize` all share the same node-scoped context instance.
         let user_script = format!(
             "
+            const global = __edgelinkGlobalContext;
+            const flow = __edgelinkFlowContext;
+            const context = __edgelinkNodeContext;
+            context.flow = flow;
+            context.global = global;
+
             async function __el_init_func() {{ 
-                let global = __edgelinkGlobalContext; 
-                let flow = __edgelinkFlowContext; 
-                let context = __edgelinkNodeContext; 
-                context.flow = flow;
-                context.global = global;
                 \n{}\n
             }}
 
-            async function __el_user_func(msg) {{ 
-                let global = __edgelinkGlobalContext; 
-                let flow = __edgelinkFlowContext; 
-                let context = __edgelinkNodeContext; 
+            async function __el_user_body(msg, node) {{ 
                 let __msgid__ = msg._msgid; 
-                context.flow = flow;
-                context.global = global;
                 \n{}\n
             }}
+
+            async function __el_user_func(msg) {{
+                return await __edgelinkNodeEvents.dispatchInput(msg, __el_user_body);
+            }}
                 
-            async function __el_finalize_func() {{ 
-                let global = __edgelinkGlobalContext; 
-                let flow = __edgelinkFlowContext; 
-                let context = __edgelinkNodeContext; 
-                context.flow = flow;
-                context.global = global;
+            async function __el_finalize_body() {{ 
                 \n{}\n
             }}
+
+            async function __el_finalize_func() {{
+                await __el_finalize_body();
+            }}
+
+            async function __el_close_func() {{
+                await __edgelinkNodeEvents.close();
+            }}
             ",
             function_config.initialize.unwrap_or("".to_string()),
             function_config.func.unwrap_or("return msg;".to_string()),
@@ -187,7 +259,12 @@ impl FunctionNode {
         let node = FunctionNode {
             base: base_node,
             output_count: function_config.output_count,
+            concurrency: function_config.concurrency,
             user_script: user_script.as_bytes().to_vec(),
+            port_overflow_warned: AtomicBool::new(false),
+            context_watches: Default::default(),
+            libs: function_config.libs,
+            stop_token: std::sync::Mutex::new(CancellationToken::new()),
         };
         Ok(Box::new(node))
     }
@@ -197,6 +274,10 @@ impl FunctionNode {
     }
     */
 
+    /// Runs the user function on an owned `msg`.
+    ///
+    /// The msg is copied into a new JS object by `into_js` and the results are copied back by `from_js`, so the JS
+    /// side never holds anything shared with another in-flight message, whatever the user code mutates or keeps.
     async fn filter_msg<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>, msg: Msg) -> crate::Result<OutputMsgs> {
         let origin_msg_id = msg.id();
 
@@ -226,6 +307,36 @@ impl FunctionNode {
         }
     }
 
+    /// Returns a child of the token of the running task.
+    fn stop_token(&self) -> CancellationToken {
+        self.stop_token.lock().expect("stop_token").child_token()
+    }
+
+    /// Warns only once per node, a function that keeps returning too many elements would flood the log otherwise.
+    fn warn_port_overflow(&self) {
+        if !self.port_overflow_warned.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "[function:{}] The returned array has more elements than the {} output port(s), ignored the rest",
+                self.name(),
+                self.output_count
+            );
+        }
+    }
+
+    /// Messages addressed to a port without wires are dropped, just like an unconnected port in Node-RED.
+    fn to_envelopes(&self, msgs: OutputMsgs) -> SmallVec<[Envelope; 4]> {
+        let nports = self.base.ports.len();
+        msgs.into_iter()
+            .filter(|(port, _)| {
+                if *port >= nports {
+                    log::debug!("[function:{}] Dropped the message to the unwired port {}", self.name(), port);
+                }
+                *port < nports
+            })
+            .map(|(port, msg)| Envelope { port, msg: MsgHandle::new(msg) })
+            .collect()
+    }
+
     fn convert_return_value<'js>(
         &self,
         ctx: &js::Ctx<'js>,
@@ -234,38 +345,40 @@ impl FunctionNode {
     ) -> js::Result<OutputMsgs> {
         let mut items = OutputMsgs::new();
         match js_result.type_of() {
-            // Returns an array of Msgs
+            // Returns an array of Msgs, one element per output port.
+            // A `null` element means no message on that port, and a nested array sends multiple messages on it.
             js::Type::Array => {
                 for (port, ele) in js_result.as_array().unwrap().iter::<js::Value>().enumerate() {
-                    match ele {
-                        Ok(ele) => {
-                            if let Some(subarr) = ele.as_array() {
-                                for subele in subarr.iter() {
-                                    let obj: js::Value = subele.unwrap();
-                                    if obj.is_null() {
-                                        continue;
-                                    }
-                                    let mut msg = Msg::from_js(ctx, obj)?;
-                                    if let Some(org_id) = origin_msg_id {
-                                        msg.set_id(org_id);
-                                    }
-                                    items.push((port, msg));
-                                }
-                            } else if ele.is_object() && !ele.is_null() {
-                                let mut msg = Msg::from_js(ctx, ele)?;
-                                if let Some(org_id) = origin_msg_id {
-                                    msg.set_id(org_id);
-                                }
-                                items.push((port, msg));
-                            } else if ele.is_null() {
+                    let ele = match ele {
+                        Ok(ele) => ele,
+                        Err(ref e) => {
+                            log::warn!("[function:{}] Bad msg array item: \n{:#?}", self.name(), e);
+                            continue;
+                        }
+                    };
+                    if ele.is_null() || ele.is_undefined() {
+                        continue;
+                    }
+                    if port >= self.output_count {
+                        self.warn_port_overflow();
+                        break;
+                    }
+                    if let Some(subarr) = ele.as_array() {
+                        for subele in subarr.iter::<js::Value>() {
+                            let obj = subele?;
+                            if obj.is_null() || obj.is_undefined() {
                                 continue;
+                            }
+                            if obj.is_object() {
+                                items.push((port, self.convert_msg(ctx, obj, origin_msg_id)?));
                             } else {
-                                log::warn!("Bad msg array item: \n{:#?}", ele);
+                                log::warn!("[function:{}] Bad msg array item: \n{:#?}", self.name(), obj);
                             }
                         }
-                        Err(ref e) => {
-                            log::warn!("Bad msg array item: \n{:#?}", e);
-                        }
+                    } else if ele.is_object() {
+                        items.push((port, self.convert_msg(ctx, ele, origin_msg_id)?));
+                    } else {
+                        log::warn!("[function:{}] Bad msg array item: \n{:#?}", self.name(), ele);
                     }
                 }
             }
@@ -295,6 +408,38 @@ impl FunctionNode {
         Ok(items)
     }
 
+    fn convert_msg<'js>(
+        &self,
+        ctx: &js::Ctx<'js>,
+        obj: js::Value<'js>,
+        origin_msg_id: Option<ElementId>,
+    ) -> js::Result<Msg> {
+        let mut msg = Msg::from_js(ctx, obj)?;
+        if let Some(org_id) = origin_msg_id {
+            msg.set_id(org_id);
+        }
+        Ok(msg)
+    }
+
+    /// Imports the modules of the `libs` once before the `initialize` code, the messages reuse their namespaces.
+    async fn load_libs<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>) -> crate::Result<()> {
+        for lib in self.libs.iter() {
+            let imported = match js::Module::import(&ctx, lib.module.as_str()).catch(&ctx) {
+                Ok(promise) => promise.into_future::<js::Value>().await.catch(&ctx),
+                Err(e) => Err(e),
+            };
+            match imported {
+                Ok(namespace) => ctx.globals().set(lib.var.as_str(), namespace)?,
+                Err(e) => {
+                    return Err(EdgelinkError::InvalidOperation(e.to_string()))
+                        .with_context(|| format!("Failed to load the module '{}' as `{}`", lib.module, lib.var));
+                }
+            }
+            while ctx.execute_pending_job() {}
+        }
+        Ok(())
+    }
+
     async fn init_async<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>) -> crate::Result<()> {
         log::debug!("[function:{}] Initializing JavaScript context...", self.name());
 
@@ -311,16 +456,30 @@ impl FunctionNode {
         Ok(())
     }
 
+    /// Runs the `finalize` code and then the `close` handlers, either of them taking longer than
+    /// `FINALIZE_TIMEOUT` is logged and left behind.
     async fn finalize_async<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>) -> crate::Result<()> {
-        let final_func: js::Function = ctx.globals().get("__el_finalize_func")?;
-        let promised = final_func.call::<_, rquickjs::Promise>(())?;
-        match promised.into_future().await {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                log::error!("[function:{}] Failed to invoke the `finialize` script code: {}", self.name(), e);
-                Err(EdgelinkError::InvalidOperation(e.to_string()).into())
+        let mut result = Ok(());
+        for (func_name, what) in [("__el_finalize_func", "`finalize` code"), ("__el_close_func", "`close` handlers")] {
+            let func: js::Function = ctx.globals().get(func_name)?;
+            let promised = func.call::<_, rquickjs::Promise>(())?;
+            match tokio::time::timeout(FINALIZE_TIMEOUT, promised.into_future::<()>()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    log::error!("[function:{}] Failed to invoke the {}: {}", self.name(), what, e);
+                    result = Err(EdgelinkError::InvalidOperation(e.to_string()).into());
+                }
+                Err(_) => {
+                    log::warn!(
+                        "[function:{}] The {} did not finish in {:?}, stopping anyway",
+                        self.name(),
+                        what,
+                        FINALIZE_TIMEOUT
+                    );
+                }
             }
         }
+        result
     }
 
     fn prepare_js_ctx(self: &Arc<Self>, ctx: &js::Ctx<'_>) -> crate::Result<()> {
@@ -340,13 +499,23 @@ impl FunctionNode {
         }
         */
         ::rquickjs_extra::timers::init(ctx)?;
+        for name in DENIED_GLOBALS.iter() {
+            ctx.globals().remove(*name)?;
+        }
 
         ctx.globals().set("env", env_class::EnvClass::new(self.envs()))?;
         ctx.globals().set("node", node_class::NodeClass::new(self))?;
+        ctx.globals().set("__edgelinkUtil", util_class::UtilClass::new(self))?;
 
-        // Register the global-scoped context
-        if let Some(global_context) = self.engine().map(|x| x.context()) {
-            ctx.globals().set("__edgelinkGlobalContext", context_class::ContextClass::new(global_context))?;
+        // Register the global-scoped context and the constants, the latter are frozen by the prelude script
+        if let Some(engine) = self.engine() {
+            ctx.globals().set(
+                "__edgelinkGlobalContext",
+                context_class::ContextClass::new(engine.context(), self.context_watches.clone()),
+            )?;
+            ctx.globals()
+                .set("__edgelinkFunctionGlobalContext", Variant::Object(engine.function_global_context().clone()))?;
+            ctx.globals().set("__edgelinkProcessEnv", engine.function_process_env())?;
         } else {
             return Err(EdgelinkError::InvalidOperation("Failed to get global context".into()))
                 .with_context(|| "The engine cannot be released!");
@@ -354,13 +523,19 @@ impl FunctionNode {
 
         // Register the flow-scoped context
         if let Some(flow_context) = self.flow().map(|x| x.context()) {
-            ctx.globals().set("__edgelinkFlowContext", context_class::ContextClass::new(flow_context))?;
+            ctx.globals().set(
+                "__edgelinkFlowContext",
+                context_class::ContextClass::new(flow_context, self.context_watches.clone()),
+            )?;
         } else {
             return Err(EdgelinkError::InvalidOperation("Failed to get flow context".into()).into());
         }
 
         // Register the node-scoped context
-        ctx.globals().set("__edgelinkNodeContext", context_class::ContextClass::new(self.context()))?;
+        ctx.globals().set(
+            "__edgelinkNodeContext",
+            context_class::ContextClass::new(self.context(), self.context_watches.clone()),
+        )?;
 
         let mut eval_options = EvalOptions::default();
         eval_options.promise = true;
@@ -420,4 +595,845 @@ mod tests {
             assert_eq!(msg["count"], "0".into());
         }
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_share_node_context_between_initialize_and_func() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "initialize": "context.set('counter', 42);",
+                "func": "msg.payload = context.get('counter'); return msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_f64(), Some(42.0));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_isolate_send_and_done_of_interleaved_msgs() {
+        // The second message sends and fails while the first one is still awaiting
+        let func = r#"
+            const first = msg.payload === 1;
+            await new Promise(r => setTimeout(r, first ? 200 : 20));
+            node.send({ payload: msg.payload * 10 });
+            node.done(first ? undefined : 'failed');
+            return null;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "concurrency": 2, "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "scope": ["1"], "wires": [["2"]]},
+        ]);
+        let msgs_to_inject = (1..=2).map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": i})).unwrap()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(3, std::time::Duration::from_secs_f64(1.0), msgs_to_inject.collect())
+            .await
+            .unwrap();
+        let (caught, sent): (Vec<_>, Vec<_>) = msgs.iter().partition(|x| x.contains("error"));
+        let mut sent: Vec<_> = sent.iter().map(|x| x["payload"].as_i64().unwrap()).collect();
+        sent.sort();
+        assert_eq!(sent, vec![10, 20]);
+        assert_eq!(caught.len(), 1);
+        assert_eq!(caught[0]["payload"].as_i64(), Some(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_support_sync_and_callback_context_access() {
+        let func = r#"
+            const value = await new Promise((resolve, reject) => {
+                context.set('k', 'v', err => err ? reject(err) : context.get('k', (err, value) => {
+                    err ? reject(err) : resolve(value);
+                }));
+            });
+            const error = await new Promise(resolve => {
+                context.get('k', 'nothing', (err, value) => resolve(err.message));
+            });
+            msg.payload = [context.get('k'), context.get('k', 'memory'), value, error];
+            return msg;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        let payload = msgs[0]["payload"].as_array().unwrap();
+        assert_eq!(&payload[..3], &[Variant::from("v"), Variant::from("v"), Variant::from("v")]);
+        assert!(payload[3].as_str().unwrap().contains("nothing"), "{:?}", payload[3]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_await_the_async_context_operations() {
+        let func = r#"
+            await context.setAsync('k', 'v');
+            await flow.setAsync('f', 1, 'memory');
+            node.send({ payload: 'sent' });
+            msg.payload = [
+                await context.getAsync('k'),
+                await flow.getAsync('f', 'memory'),
+                await context.keysAsync(),
+                (await context.getAsync('missing')) === undefined,
+                await context.getAsync('k', 'nothing').then(() => 'resolved', err => err.message),
+            ];
+            return msg;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": "foo"})).unwrap())];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs.iter().any(|x| x["payload"] == Variant::from("sent")), "{:?}", msgs);
+        let payload = msgs.iter().find_map(|x| x["payload"].as_array()).unwrap();
+        assert_eq!(payload[0], Variant::from("v"));
+        assert_eq!(payload[1].as_i64(), Some(1));
+        assert_eq!(payload[2], Variant::Array(vec![Variant::from("k")]));
+        assert_eq!(payload[3], Variant::Bool(true));
+        assert!(payload[4].as_str().unwrap().contains("nothing"), "{:?}", payload[4]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_call_back_the_watched_context_changes() {
+        let initialize = r#"
+            global.watch('k', (err, v) => node.send({ payload: 'global:' + v }));
+            flow.watch('k', (err, v) => node.send({ payload: 'flow:' + v }));
+            const id = global.watch('other', (err, v) => node.send({ payload: 'unwatched:' + v }));
+            global.unwatch(id);
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "initialize": initialize, "func": "return;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "200", "type": "tab"},
+            {"id": "3", "type": "function", "z": "200", "wires": [],
+                "func": "flow.set('k', 'x'); global.set('other', 1); global.set('k', msg.payload); return null;"},
+        ]);
+        let msgs_to_inject = vec![(ElementId::from(3), Msg::deserialize(json!({"payload": "hello"})).unwrap())];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from("global:hello"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_red_util_should_navigate_properties_like_rust() {
+        let func = r#"
+            const copy = RED.util.cloneMessage(msg);
+            const b = msg.a.b;
+            const results = {
+                got: [RED.util.getMessageProperty(msg, 'msg.a.b[1]'), RED.util.getMessageProperty(msg, 'a["b"]')],
+                notFound: RED.util.getMessageProperty(msg, 'missing.x') === undefined,
+                setWithoutCreating: RED.util.setMessageProperty(msg, 'c.d[0].e', 1, false),
+            };
+            RED.util.setMessageProperty(msg, 'a.b[0]', 'x');
+            RED.util.setMessageProperty(msg, 'msg.c.d[0].e', 1);
+            RED.util.setMessageProperty(msg, 'list[]', 'appended');
+            RED.util.setMessageProperty(msg, 'gone');
+            RED.util.setMessageProperty(msg, 'a[msg.key]', 'nested');
+            results.sameIdentity = msg.a.b === b;
+            results.evaluated = [
+                RED.util.evaluateNodeProperty('a.b', 'msg', node, msg),
+                RED.util.evaluateNodeProperty('42', 'num', node, msg),
+            ];
+            try {
+                RED.util.evaluateNodeProperty('42', 'num', { id: 'ffff' }, msg);
+            } catch (e) {
+                results.unknownNode = String(e);
+            }
+            results.cloned = copy.a.b[0];
+            msg.results = results;
+            return msg;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let input = Msg::deserialize(json!({"a": {"b": [1, 2]}, "list": [0], "gone": true, "key": "k"})).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), vec![(ElementId::from(1), input.clone())])
+            .await
+            .unwrap();
+        let output = &msgs[0];
+
+        // The same operations on the Rust side
+        let mut expected = input.clone();
+        let got =
+            vec![expected.get_nav_stripped("msg.a.b[1]").cloned(), expected.get_nav_stripped("a[\"b\"]").cloned()];
+        assert!(expected.set_nav("c.d[0].e", Variant::from(1), false).is_err());
+        expected.set_nav("a.b[0]", Variant::from("x"), true).unwrap();
+        expected.set_nav_stripped("msg.c.d[0].e", Variant::from(1), true).unwrap();
+        expected.set_nav("list[]", Variant::from("appended"), true).unwrap();
+        expected.remove_nav("gone");
+        expected.set_nav("a[msg.key]", Variant::from("nested"), true).unwrap();
+
+        let results = output["results"].as_object().unwrap();
+        let got: Vec<Variant> = got.into_iter().map(Option::unwrap).collect();
+        assert_eq!(results["got"], Variant::Array(got));
+        assert_eq!(results["notFound"], Variant::Bool(true));
+        assert_eq!(results["setWithoutCreating"], Variant::Bool(false));
+        assert_eq!(results["sameIdentity"], Variant::Bool(true));
+        let evaluated = results["evaluated"].as_array().unwrap();
+        assert_eq!(Some(&evaluated[0]), expected.get_nav("a.b"));
+        assert_eq!(evaluated[1].as_f64(), Some(42.0));
+        assert!(results["unknownNode"].as_str().unwrap().contains("id='000000000000ffff'"), "{:?}", results);
+        assert_eq!(results["cloned"], Variant::from(1));
+        for key in ["a", "c", "list"] {
+            assert_eq!(output.get(key), expected.get(key), "msg.{}", key);
+        }
+        assert!(output.get("gone").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_restore_node_context_after_restarting() {
+        let func = "const n = (context.get('n') || 0) + 1; context.set('n', n); msg.payload = n; return msg;";
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "5", "z": "100", "type": "subflow:200", "wires": [["2"]]},
+            {"id": "200", "type": "subflow", "name": "Subflow", "info": "",
+                "in": [{"wires": [{"id": "3"}]}], "out": [{"wires": [{"id": "3", "port": 0}]}]},
+            {"id": "3", "z": "200", "type": "function", "func": func, "wires": []}
+        ]);
+        let dir = std::env::temp_dir().join(format!("edgelink-function-restart-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let toml = format!(
+            r#"
+            [runtime.context]
+            default = "file"
+
+            [runtime.context.stores]
+            file = {{ provider = "localfs", dir = "{}" }}
+            "#,
+            dir.to_string_lossy().replace('\\', "/")
+        );
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+
+        // Every round is a restart with a new engine
+        for round in 1..=2 {
+            let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json.clone(), Some(&cfg)).unwrap();
+            let msgs_to_inject = ["1", "5"]
+                .iter()
+                .map(|id| (id.parse().unwrap(), Msg::deserialize(json!({"topic": id})).unwrap()))
+                .collect();
+            let msgs =
+                engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+            assert_eq!(msgs.len(), 2);
+            for msg in msgs.iter() {
+                assert_eq!(msg["payload"].as_i64(), Some(round), "{:?}", msg);
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_load_the_libs_once_per_node() {
+        let dir = std::env::temp_dir().join(format!("edgelink-function-libs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let module_path = dir.join("counter.js");
+        std::fs::write(
+            &module_path,
+            "globalThis.__counterInits = (globalThis.__counterInits || 0) + 1;\n\
+             let n = 0;\n\
+             export function next() { return ++n; }\n",
+        )
+        .unwrap();
+
+        let func = "msg.payload = [counter.next(), globalThis.__counterInits]; return msg;";
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["3"]], "func": func,
+                "libs": [{"var": "counter", "module": "counter"}]},
+            {"id": "2", "type": "function", "z": "100", "wires": [["3"]], "func": func,
+                "libs": [{"var": "counter", "module": "counter"}]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let toml = format!(
+            r#"
+            [runtime.engine.function_modules]
+            counter = "{}"
+            "#,
+            module_path.to_string_lossy().replace('\\', "/")
+        );
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+
+        let msgs_to_inject = ["1", "2", "1", "2", "1", "2"]
+            .iter()
+            .map(|id| (id.parse().unwrap(), Msg::deserialize(json!({"topic": id})).unwrap()))
+            .collect();
+        let msgs =
+            engine.run_once_with_inject(6, std::time::Duration::from_secs_f64(0.6), msgs_to_inject).await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        for topic in ["1", "2"] {
+            let mut counts: Vec<(i64, i64)> = msgs
+                .iter()
+                .filter(|x| x["topic"].as_str() == Some(topic))
+                .map(|x| {
+                    let payload = x["payload"].as_array().unwrap();
+                    (payload[0].as_i64().unwrap(), payload[1].as_i64().unwrap())
+                })
+                .collect();
+            counts.sort();
+            // The module state is kept between the messages and not shared with the other node
+            assert_eq!(counts, vec![(1, 1), (2, 1), (3, 1)], "{:?}", msgs);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_deny_the_process_globals_by_default() {
+        let func = "msg.payload = [typeof process, typeof require, typeof os, typeof fetch]; return msg;";
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": 0})).unwrap())];
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].to_json_value(), json!(["undefined", "undefined", "undefined", "undefined"]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_read_the_env_store_by_the_opt_in_process_env() {
+        let func = r#"
+            const denied = (() => {
+                'use strict';
+                try {
+                    process.env.GREETING = 'changed';
+                    return false;
+                } catch (e) {
+                    return true;
+                }
+            })();
+            msg.payload = [process.env.GREETING, 'GREETING' in process.env, process.env.MISSING, denied];
+            return msg;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func,
+                "env": [{"name": "GREETING", "value": "hello", "type": "str"}]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                "[runtime.engine]\nfunction_process_env = true\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": 0})).unwrap())];
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        // The missing variable is `undefined`, which becomes `null`
+        assert_eq!(msgs[0]["payload"].to_json_value(), json!(["hello", true, null, true]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_select_the_store_of_flow_and_global_context() {
+        let func = r#"
+            await new Promise((resolve, reject) => {
+                flow.set('k', 'in-file', 'file', err => err ? reject(err) : resolve());
+            });
+            global.set('g', 'in-file', 'file');
+            flow.set('k', 'in-memory');
+            msg.payload = {
+                flowFile: flow.get('k', 'file'),
+                flowDefault: flow.get('k'),
+                flowDefaultAlias: flow.get('k', 'default'),
+                flowFileKeys: flow.keys('file'),
+                globalFile: await new Promise(resolve => global.get('g', 'file', (err, value) => resolve(value))),
+                globalDefault: global.get('g') ?? null,
+            };
+            return msg;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let dir = std::env::temp_dir().join(format!("edgelink-function-stores-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let toml = format!(
+            r#"
+            [runtime.context]
+            default = "memory"
+
+            [runtime.context.stores]
+            memory = {{ provider = "memory" }}
+            file = {{ provider = "localfs", dir = "{}" }}
+            "#,
+            dir.to_string_lossy().replace('\\', "/")
+        );
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": "foo"})).unwrap())];
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        let expected = json!({
+            "flowFile": "in-file",
+            "flowDefault": "in-memory",
+            "flowDefaultAlias": "in-memory",
+            "flowFileKeys": ["k"],
+            "globalFile": "in-file",
+            "globalDefault": null,
+        });
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(expected).unwrap());
+        assert_eq!(engine.context().get_one(Some("file"), "g", &[]).await, Some(Variant::from("in-file")));
+        assert_eq!(engine.context().get_one(None, "g", &[]).await, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_node_on_input_should_coexist_with_return() {
+        let initialize = r#"
+            node.on('input', function (msg, send, done) {
+                send({ payload: msg.payload * 2, topic: 'handler' });
+                done();
+            });
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "initialize": initialize, "func": "msg.topic = 'body'; return msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = (1..=2).map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": i})).unwrap()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(4, std::time::Duration::from_secs_f64(0.4), msgs_to_inject.collect())
+            .await
+            .unwrap();
+        let mut results = msgs
+            .iter()
+            .map(|x| (x["topic"].as_str().unwrap().to_string(), x["payload"].as_i64().unwrap()))
+            .collect::<Vec<_>>();
+        results.sort();
+        let expected = [("body", 1), ("body", 2), ("handler", 2), ("handler", 4)];
+        assert_eq!(results, expected.map(|(t, p)| (t.to_string(), p)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_node_on_input_in_body_should_handle_only_the_current_msg() {
+        let func = r#"
+            node.on('input', function (msg) {
+                node.send({ payload: msg.payload + 1 });
+            });
+            node.on('close', function (removed, done) {
+                global.set('closed', removed === false);
+                done();
+            });
+        "#;
+        let initialize = "node.on('close', () => global.set('closed_by_initialize', true));";
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "initialize": initialize, "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = (0..3).map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": i})).unwrap()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject.collect())
+            .await
+            .unwrap();
+        let mut sent = payloads(&msgs);
+        sent.sort();
+        assert_eq!(sent, vec![1, 2, 3]);
+
+        // The `close` handlers run when the engine stops
+        let global = engine.context();
+        assert_eq!(global.get_one(None, "closed_by_initialize", &[]).await, Some(Variant::Bool(true)));
+        assert_eq!(global.get_one(None, "closed", &[]).await, Some(Variant::Bool(true)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_node_on_input_in_body_should_not_leak_between_concurrent_msgs() {
+        // The first message is still awaiting when the second one registers its handler and completes
+        let func = r#"
+            node.on('input', function (msg, send, done) {
+                send({ payload: msg.payload * 10 });
+                done();
+            });
+            await new Promise(r => setTimeout(r, msg.payload === 1 ? 200 : 20));
+            return null;
+        "#;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "concurrency": 2, "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = (1..=2).map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": i})).unwrap()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(2, std::time::Duration::from_secs_f64(1.0), msgs_to_inject.collect())
+            .await
+            .unwrap();
+        // Each handler only sends its own message, whichever completes first
+        assert_eq!(sorted_payloads(&msgs), vec![10, 20]);
+    }
+
+    /// Runs 4 messages through a function holding each of them until `concurrency` of them are running together.
+    ///
+    /// The later messages wait less, and every output records the peak of the messages running at the same time.
+    async fn run_slow_function(concurrency: usize, ordered: bool) -> Vec<Msg> {
+        let func = format!(
+            r#"
+            const running = (context.get('running') || 0) + 1;
+            context.set('running', running);
+            context.set('peak', Math.max(context.get('peak') || 0, running));
+            context.set('arrived', (context.get('arrived') || 0) + 1);
+            while (context.get('arrived') < {}) {{
+                await new Promise(r => setTimeout(r, 1));
+            }}
+            await new Promise(r => setTimeout(r, (3 - msg.payload) * 20));
+            context.set('running', context.get('running') - 1);
+            msg.peak = context.get('peak');
+            return msg;
+        "#,
+            concurrency
+        );
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "concurrency": concurrency, "ordered": ordered, "func": func},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = (0..4).map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": i})).unwrap()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.run_once_with_inject(4, std::time::Duration::from_secs_f64(3.0), msgs_to_inject.collect()).await.unwrap()
+    }
+
+    fn payloads(msgs: &[Msg]) -> Vec<i64> {
+        msgs.iter().map(|x| x["payload"].as_i64().unwrap()).collect()
+    }
+
+    fn sorted_payloads(msgs: &[Msg]) -> Vec<i64> {
+        let mut payloads = payloads(msgs);
+        payloads.sort();
+        payloads
+    }
+
+    fn peaks(msgs: &[Msg]) -> Vec<i64> {
+        msgs.iter().map(|x| x["peak"].as_i64().unwrap()).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_process_msgs_concurrently() {
+        // One by one
+        let msgs = run_slow_function(1, false).await;
+        assert_eq!(payloads(&msgs), vec![0, 1, 2, 3]);
+        assert_eq!(peaks(&msgs), vec![1, 1, 1, 1]);
+
+        // All of them are running before any finishes, in whatever order they finish
+        let msgs = run_slow_function(4, false).await;
+        assert_eq!(sorted_payloads(&msgs), vec![0, 1, 2, 3]);
+        assert_eq!(peaks(&msgs), vec![4, 4, 4, 4]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_keep_the_order_of_concurrent_msgs() {
+        let msgs = run_slow_function(4, true).await;
+        assert_eq!(payloads(&msgs), vec![0, 1, 2, 3]);
+        assert_eq!(peaks(&msgs), vec![4, 4, 4, 4]);
+    }
+
+    async fn run_function_with_outputs(func: &str, outputs: usize, nexpected: usize) -> Vec<Msg> {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "outputs": outputs, "func": func,
+                "wires": [["2"], ["3"], ["4"]]},
+            {"id": "2", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "0", "tot": "num"}], "wires": [["5"]]},
+            {"id": "3", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "1", "tot": "num"}], "wires": [["5"]]},
+            {"id": "4", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "2", "tot": "num"}], "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        engine.run_once_with_inject(nexpected, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap()
+    }
+
+    fn sorted_by_port_and_payload(msgs: &[Msg]) -> Vec<(i64, String)> {
+        let mut result = msgs
+            .iter()
+            .map(|x| (x["port"].as_i64().unwrap(), x["payload"].as_str().unwrap().to_string()))
+            .collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_skip_null_elements_in_returned_array() {
+        let func = "return [{payload: 'a'}, null, {payload: 'c'}];";
+        let msgs = run_function_with_outputs(func, 3, 2).await;
+        assert_eq!(sorted_by_port_and_payload(&msgs), vec![(0, "a".to_string()), (2, "c".to_string())]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_send_nested_array_elements_on_the_same_port() {
+        let func = "return [[{payload: 'a'}, null, {payload: 'b'}], null];";
+        let msgs = run_function_with_outputs(func, 2, 2).await;
+        assert_eq!(sorted_by_port_and_payload(&msgs), vec![(0, "a".to_string()), (0, "b".to_string())]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_resolve_promised_array() {
+        let func = "return await Promise.resolve([null, {payload: 'b'}]);";
+        let msgs = run_function_with_outputs(func, 2, 1).await;
+        assert_eq!(sorted_by_port_and_payload(&msgs), vec![(1, "b".to_string())]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_ignore_elements_beyond_the_output_count() {
+        let func = "return [{payload: 'a'}, {payload: 'b'}, {payload: 'c'}];";
+        let msgs = run_function_with_outputs(func, 2, 2).await;
+        assert_eq!(sorted_by_port_and_payload(&msgs), vec![(0, "a".to_string()), (1, "b".to_string())]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_use_the_smaller_of_declared_and_wired_ports() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "outputs": 3, "wires": [["2"], ["2"]],
+                "func": "return [{payload: 'a'}, {payload: 'b'}, {payload: 'c'}];"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json.clone()).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "foo"}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        let mut payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        payloads.sort();
+        assert_eq!(payloads, vec!["a", "b"]);
+
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let diags = crate::runtime::engine::Engine::validate(&registry, &flows_json);
+        assert_eq!(diags.len(), 1, "{:#?}", diags);
+        assert_eq!(diags[0].element_id, Some(ElementId::from(1)));
+        assert!(diags[0].message.contains("declares 3 output(s) but is wired to 2 port(s)"), "{:#?}", diags);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_drop_msgs_to_unwired_ports_without_error() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "outputs": 2, "wires": [["3"]],
+                "func": "return [{payload: 'a'}, {payload: 'b'}, {payload: 'c'}];"},
+            {"id": "2", "z": "100", "type": "catch", "wires": [["3"]]},
+            {"id": "4", "z": "100", "type": "complete", "scope": ["1"], "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "completed", "pt": "msg", "to": "true", "tot": "bool"}], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs.iter().all(|x| !x.contains("error")));
+        assert!(msgs.iter().any(|x| x["payload"].as_str() == Some("a") && !x.contains("completed")));
+        assert!(msgs.iter().any(|x| x.contains("completed")));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_node_send_should_drop_msgs_to_unwired_ports() {
+        let func = "node.send([{payload: 'a'}, {payload: 'b'}, {payload: 'c'}, {payload: 'd'}]); return null;";
+        let msgs = run_function_with_outputs(func, 3, 3).await;
+        assert_eq!(
+            sorted_by_port_and_payload(&msgs),
+            vec![(0, "a".to_string()), (1, "b".to_string()), (2, "c".to_string())]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_send_to_named_link_in() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [],
+                "func": "node.sendTo('Target', {payload: msg.payload + 1}); return null;"},
+            {"id": "2", "type": "link in", "z": "200", "name": "Target", "links": [], "wires": [["3"]]},
+            {"id": "3", "z": "200", "type": "test-once"},
+            // Only the `link in` nodes are looked up, even in the same flow
+            {"id": "4", "type": "junction", "z": "100", "name": "Target", "wires": []},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": 41}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_f64(), Some(42.0));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_handle_buffer_payloads() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "func": "msg.payload = Buffer.from([0x68, 0x65, 0x6c, 0x6c, 0x6f, 0xff]); return msg;"},
+            {"id": "2", "type": "function", "z": "100", "wires": [["3"]],
+                "func": r#"
+                    const text = msg.payload.slice(0, 5).toString('utf8');
+                    msg.isBuffer = Buffer.isBuffer(msg.payload);
+                    msg.text = text + msg.payload.length;
+                    msg.payload = Buffer.concat([Buffer.from(text.toUpperCase(), 'utf8'), msg.payload.slice(5)]);
+                    return msg;
+                "#},
+            {"id": "3", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::Bytes(b"HELLO\xff".to_vec().into()));
+        assert_eq!(msgs[0]["text"], "hello6".into());
+        assert_eq!(msgs[0]["isBuffer"], Variant::Bool(true));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_isolate_concurrently_mutated_msgs() {
+        const COUNT: usize = 64;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "concurrency": 8,
+                "func": r#"
+                    msg.payload.items.push(msg.payload.id);
+                    await new Promise(r => setTimeout(r, (msg.payload.id * 7) % 20));
+                    msg.payload.items.push(msg.payload.id);
+                    msg.payload.owner = msg.payload.id;
+                    return msg;
+                "#},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = (0..COUNT)
+            .map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": {"id": i, "items": [i]}})).unwrap()))
+            .collect();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(COUNT, std::time::Duration::from_secs_f64(3.0), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), COUNT);
+
+        let mut ids = Vec::with_capacity(COUNT);
+        for msg in msgs.iter() {
+            let id = msg.get_nav("payload.id").and_then(|x| x.as_i64()).unwrap();
+            let items: Vec<i64> =
+                msg.get_nav("payload.items").unwrap().as_array().unwrap().iter().map(|x| x.as_i64().unwrap()).collect();
+            assert_eq!(items, vec![id, id, id]);
+            assert_eq!(msg.get_nav("payload.owner").and_then(|x| x.as_i64()), Some(id));
+            ids.push(id);
+        }
+        ids.sort();
+        assert_eq!(ids, (0..COUNT as i64).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_not_alias_fanned_out_or_stored_msgs() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2", "3"]], "func": "return msg;"},
+            {"id": "2", "type": "function", "z": "100", "wires": [["4"]],
+                "func": "global.set('kept', msg.payload); msg.payload.items.push('a'); return msg;"},
+            {"id": "3", "type": "function", "z": "100", "wires": [["4"]],
+                "func": "msg.payload.items.push('b'); return msg;"},
+            {"id": "4", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": {"items": ["x"]}}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+
+        let items: Vec<&Variant> = msgs.iter().map(|x| x.get_nav("payload.items").unwrap()).collect();
+        for expected in [json!(["x", "a"]), json!(["x", "b"])] {
+            let expected = Variant::deserialize(expected).unwrap();
+            assert!(items.contains(&&expected), "{:?}", items);
+        }
+
+        // The stored value is a copy, the later mutation does not leak into it
+        let kept = engine.context().get_one(None, "kept", &[]).await.unwrap();
+        assert_eq!(kept, Variant::deserialize(json!({"items": ["x"]})).unwrap());
+    }
 }
@@ -0,0 +1,37 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use super::js;
+
+/// A module to load for the node, the `libs` of the `function` node in Node-RED.
+#[derive(Deserialize, Debug, Clone)]
+pub(super) struct FunctionLib {
+    /// The name of the global variable bound to the namespace of the module.
+    pub var: String,
+
+    /// The name of the module in `runtime.engine.function_modules`.
+    pub module: String,
+}
+
+/// Resolves only the module names in `runtime.engine.function_modules` to their files.
+#[derive(Debug, Default)]
+pub(super) struct AllowedModuleResolver {
+    modules: BTreeMap<String, PathBuf>,
+}
+
+impl AllowedModuleResolver {
+    pub fn new(modules: BTreeMap<String, PathBuf>) -> Self {
+        Self { modules }
+    }
+}
+
+impl js::loader::Resolver for AllowedModuleResolver {
+    fn resolve<'js>(&mut self, _ctx: &js::Ctx<'js>, base: &str, name: &str) -> js::Result<String> {
+        match self.modules.get(name) {
+            Some(path) => Ok(path.to_string_lossy().into_owned()),
+            None => Err(js::Error::new_resolving_message(base, name, "The module is not in `function_modules`")),
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use std::sync::{Arc, Weak};
 
 use rquickjs::{class::Trace, prelude::Opt, Ctx, FromJs, IntoJs, Value};
-use tokio_util::sync::CancellationToken;
 
 use crate::runtime::js::util;
 
@@ -14,6 +13,10 @@ pub(super) struct NodeClass {
     node: Weak<FunctionNode>,
 }
 
+fn node_released() -> EdgelinkError {
+    EdgelinkError::InvalidOperation("The function node has been released".into())
+}
+
 #[allow(non_snake_case)]
 #[rquickjs::methods]
 impl NodeClass {
@@ -66,7 +69,9 @@ impl NodeClass {
 
     #[qjs(skip)]
     fn send_msgs_internal<'js>(&self, ctx: Ctx<'js>, msgs: rquickjs::Value<'js>, cloning: bool) -> crate::Result<()> {
-        let node = self.node.upgrade().clone().ok_or(rquickjs::Error::UnrelatedRuntime)? as Arc<dyn FlowNodeBehavior>;
+        let function_node = self.node.upgrade().ok_or_else(node_released)?;
+        let cancel = function_node.stop_token();
+        let node = function_node as Arc<dyn FlowNodeBehavior>;
 
         match msgs.type_of() {
             rquickjs::Type::Array => {
@@ -84,8 +89,8 @@ impl NodeClass {
                                 let msg_value =
                                     if cloning && is_first { util::deep_clone(ctx.clone(), msg)? } else { msg };
                                 is_first = false;
-                                let envelope = Envelope { port, msg: MsgHandle::new(Msg::from_js(&ctx, msg_value)?) };
-                                msgs_to_send.push(envelope);
+                                let msg = MsgHandle::new(Msg::from_js(&ctx, msg_value)?);
+                                msgs_to_send.extend(envelope_for_port(node.as_ref(), port, msg));
                             }
                         }
                     } else if msgs_in_port.is_object() {
@@ -96,14 +101,13 @@ impl NodeClass {
                             msgs_in_port
                         };
                         is_first = false;
-                        let envelope = Envelope { port, msg: MsgHandle::new(Msg::from_js(&ctx, msg_value)?) };
-                        msgs_to_send.push(envelope);
+                        let msg = MsgHandle::new(Msg::from_js(&ctx, msg_value)?);
+                        msgs_to_send.extend(envelope_for_port(node.as_ref(), port, msg));
                     } else {
                         log::warn!("Unknown msg type: {}", port);
                     }
                 }
 
-                let cancel = CancellationToken::new();
                 let async_node = node.clone();
                 ctx.spawn(async move {
                     match async_node.fan_out_many(msgs_to_send, cancel).await {
@@ -116,8 +120,6 @@ impl NodeClass {
             rquickjs::Type::Object => {
                 let msg_to_send = MsgHandle::new(Msg::from_js(&ctx, msgs)?);
                 let envelope = Envelope { port: 0, msg: msg_to_send };
-                // FIXME
-                let cancel = CancellationToken::new();
                 let async_node = node.clone();
                 ctx.spawn(async move {
                     match async_node.fan_out_one(envelope, cancel).await {
@@ -134,6 +136,37 @@ impl NodeClass {
         Ok(())
     }
 
+    /// Sends a copy of the message to the `link in` node named `name`, looking in this flow first.
+    #[qjs(rename = "sendTo")]
+    fn send_to<'js>(self, name: String, msg: Value<'js>, ctx: Ctx<'js>) -> rquickjs::Result<()> {
+        self.send_to_internal(ctx.clone(), &name, msg).map_err(|e| ctx.throw(format!("{:#}", e).into_js(&ctx).unwrap()))
+    }
+
+    #[qjs(skip)]
+    fn send_to_internal<'js>(&self, ctx: Ctx<'js>, name: &str, msg: Value<'js>) -> crate::Result<()> {
+        let node = self.node.upgrade().ok_or_else(node_released)?;
+        let flow = node.flow().ok_or(EdgelinkError::InvalidOperation("The flow has been released".into()))?;
+        let target = match flow.get_node_by_type_and_name("link in", name)? {
+            Some(target) => Some(target),
+            None => node
+                .engine()
+                .ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?
+                .find_flow_node_by_type_and_name("link in", name)?,
+        };
+        let target = target
+            .ok_or(EdgelinkError::BadArgument("name"))
+            .with_context(|| format!("Cannot found the `link in` node named '{}'", name))?;
+
+        let msg = MsgHandle::new(Msg::from_js(&ctx, util::deep_clone(ctx.clone(), msg)?)?);
+        let cancel = node.stop_token();
+        ctx.spawn(async move {
+            if let Err(err) = target.inject_msg(msg, cancel).await {
+                log::error!("Failed to send msg to the `link in` node in function node: {}", err);
+            }
+        });
+        Ok(())
+    }
+
     fn log<'js>(&self, text: Value<'js>, ctx: Ctx<'js>) -> rquickjs::Result<()> {
         let node = self.node.upgrade().ok_or(rquickjs::Error::Exception)?;
         let name = &node.get_node().name;
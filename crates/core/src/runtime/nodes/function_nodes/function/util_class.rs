@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Weak};
+
+use rquickjs::{class::Trace, function::This, prelude::Opt, Array, Ctx, FromJs, Function, IntoJs, Object, Value};
+
+use crate::runtime::eval;
+use crate::utils::async_util::SyncWaitableFuture;
+
+use super::context_class::{error_to_js, invoke_callback};
+use super::*;
+
+/// The native part of `RED.util`, the properties are navigated by the same code as the Rust nodes, so the paths
+/// behave exactly like `Msg::get_nav()` and `Msg::set_nav()`.
+#[derive(Clone, Trace)]
+#[rquickjs::class(frozen)]
+pub(super) struct UtilClass {
+    #[qjs(skip_trace)]
+    node: Weak<FunctionNode>,
+}
+
+#[allow(non_snake_case)]
+#[rquickjs::methods]
+impl UtilClass {
+    #[qjs(skip)]
+    pub fn new(node: &Arc<FunctionNode>) -> Self {
+        UtilClass { node: Arc::downgrade(node) }
+    }
+
+    /// `RED.util.getMessageProperty(msg, expr)`, the leading `msg.` of the expression is optional.
+    #[qjs(rename = "getMessageProperty")]
+    fn get_message_property<'js>(&self, msg: Value<'js>, expr: String, ctx: Ctx<'js>) -> rquickjs::Result<Value<'js>> {
+        let red_msg = Msg::from_js(&ctx, msg)?;
+        UndefinableVariant(red_msg.get_nav_stripped(&expr).cloned()).into_js(&ctx)
+    }
+
+    /// `RED.util.setMessageProperty(msg, expr, value[, createMissing])`, the `msg` is changed in place.
+    ///
+    /// Like Node-RED, an `undefined` value deletes the property and `createMissing` defaults to whether the value is
+    /// defined. Returns `false` if the parent of the property does not exist.
+    #[qjs(rename = "setMessageProperty")]
+    fn set_message_property<'js>(
+        &self,
+        msg: Object<'js>,
+        expr: String,
+        value: Value<'js>,
+        create_missing: Opt<bool>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<bool> {
+        let stripped = expr.trim_ascii();
+        let stripped = stripped.strip_prefix("msg.").unwrap_or(stripped);
+        let mut segs = match propex::parse(stripped) {
+            Ok(segs) => segs,
+            Err(err) => {
+                return Err(ctx.throw(format!("Invalid property expression '{}': {}", expr, err).into_js(&ctx)?))
+            }
+        };
+        if !expand_js_segs(&msg, &mut segs)? || !matches!(segs.first(), Some(PropexSegment::Property(_))) {
+            return Ok(false);
+        }
+        let (last, parents) = segs.split_last().expect("The segments are not empty");
+        let deleting = value.is_undefined();
+        let create_missing = !deleting && create_missing.0.unwrap_or(true);
+
+        // The JS object is walked in place, the untouched properties keep their JS identities
+        let mut obj = msg;
+        for (i, seg) in parents.iter().enumerate() {
+            let child = get_js_seg(&obj, seg)?;
+            obj = match child.into_object() {
+                Some(child) => child,
+                None if create_missing && matches!(seg, PropexSegment::Property(_) | PropexSegment::Index(_)) => {
+                    let created = match &segs[i + 1] {
+                        PropexSegment::Index(_) | PropexSegment::Append => Array::new(ctx.clone())?.into_value(),
+                        _ => Object::new(ctx.clone())?.into_value(),
+                    };
+                    if !set_js_seg(&obj, seg, created.clone())? {
+                        return Ok(false);
+                    }
+                    created.into_object().expect("It was created as an object")
+                }
+                None => return Ok(false),
+            };
+        }
+
+        if deleting {
+            return remove_js_seg(&obj, last);
+        }
+        match last {
+            PropexSegment::Append if obj.is_array() => {
+                let len: u32 = obj.get("length")?;
+                obj.set(len, value)?;
+                Ok(true)
+            }
+            PropexSegment::Property(_) | PropexSegment::Index(_) => set_js_seg(&obj, last, value),
+            _ => Ok(false),
+        }
+    }
+
+    /// `RED.util.evaluateNodeProperty(value, type, node, msg[, callback])`, the result is also passed to the
+    /// `callback(err, value)` if provided, otherwise an error is thrown.
+    #[qjs(rename = "evaluateNodeProperty")]
+    fn evaluate_node_property<'js>(
+        &self,
+        value: String,
+        type_: String,
+        node: Opt<Value<'js>>,
+        msg: Opt<Value<'js>>,
+        cb: Opt<Function<'js>>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<Value<'js>> {
+        let node = match self.resolve_node(node.0) {
+            Ok(node) => node,
+            Err(err) => return Err(ctx.throw(format!("{:#}", err).into_js(&ctx)?)),
+        };
+        let red_msg = match msg.0 {
+            Some(msg) if msg.is_object() => Some(Msg::from_js(&ctx, msg)?),
+            _ => None,
+        };
+        // The owned copies are moved in, the future is run by another task of the runtime
+        let result = async move {
+            let prop_type: RedPropertyType = serde_json::from_value(serde_json::Value::String(type_.clone()))
+                .map_err(|_| EdgelinkError::BadArgument("type"))
+                .with_context(|| format!("Unsupported property type: '{}'", type_))?;
+            let flow = node.flow();
+            eval::evaluate_node_property(&value, prop_type, Some(node.as_ref()), flow.as_ref(), red_msg.as_ref()).await
+        }
+        .wait();
+
+        match (result, cb.0) {
+            (Ok(evaluated), Some(cb)) => {
+                invoke_callback(&ctx, cb, (Value::new_undefined(ctx.clone()), evaluated.into_js(&ctx)?));
+                Ok(Value::new_undefined(obj.ctx().clone()))
+            }
+            (Err(err), Some(cb)) => {
+                invoke_callback(&ctx, cb, (error_to_js(&ctx, &err)?, Value::new_undefined(ctx.clone())));
+                Ok(Value::new_undefined(obj.ctx().clone()))
+            }
+            (Ok(evaluated), None) => evaluated.into_js(&ctx),
+            (Err(err), None) => Err(ctx.throw(format!("{:#}", err).into_js(&ctx)?)),
+        }
+    }
+}
+
+impl UtilClass {
+    /// Finds the flow node passed to `RED.util`, by its `id` like `node.id`; this function node if none is passed.
+    fn resolve_node(&self, node: Option<Value<'_>>) -> crate::Result<Arc<dyn FlowNodeBehavior>> {
+        let this_node: Arc<dyn FlowNodeBehavior> = self
+            .node
+            .upgrade()
+            .ok_or_else(|| EdgelinkError::InvalidOperation("The function node has been released".into()))?;
+        let Some(node) = node.and_then(|x| x.into_object()) else {
+            return Ok(this_node);
+        };
+        let id: String = node.get("id")?;
+        let id: ElementId = id.parse().map_err(|_| EdgelinkError::BadArgument("node"))?;
+        if id == this_node.id() {
+            return Ok(this_node);
+        }
+        let found = this_node
+            .engine()
+            .ok_or_else(|| EdgelinkError::InvalidOperation("The engine has been released".into()))?
+            .find_flow_node_by_id(&id);
+        found
+            .ok_or(EdgelinkError::BadArgument("node"))
+            .with_context(|| format!("Cannot found the flow node, id='{}'", id))
+    }
+}
+
+/// Resolves the nested segments like `msg[msg.topic]` by the JS `msg`, returns `false` if any of them cannot be
+/// resolved to a property name or an index.
+fn expand_js_segs(msg: &Object<'_>, segs: &mut [PropexSegment]) -> rquickjs::Result<bool> {
+    for seg in segs.iter_mut() {
+        let PropexSegment::Nested(nested_segs) = seg else {
+            continue;
+        };
+        let Some((PropexSegment::Property(first), rest)) = nested_segs.split_first() else {
+            return Ok(false);
+        };
+        if first != "msg" {
+            return Ok(false);
+        }
+        let mut value = msg.clone().into_value();
+        for nested_seg in rest {
+            value = match value.into_object() {
+                Some(obj) => get_js_seg(&obj, nested_seg)?,
+                None => return Ok(false),
+            };
+        }
+        *seg = if let Some(s) = value.as_string() {
+            PropexSegment::Property(Cow::Owned(s.to_string()?))
+        } else if let Some(index) = value.as_number().filter(|x| x.fract() == 0.0 && *x >= 0.0 && *x <= u32::MAX as f64)
+        {
+            PropexSegment::Index(index as usize)
+        } else {
+            return Ok(false);
+        };
+    }
+    Ok(true)
+}
+
+fn get_js_seg<'js>(obj: &Object<'js>, seg: &PropexSegment) -> rquickjs::Result<Value<'js>> {
+    match seg {
+        PropexSegment::Property(name) => obj.get(name.as_ref()),
+        PropexSegment::Index(index) if obj.is_array() => match u32::try_from(*index) {
+            Ok(index) => obj.get(index),
+            Err(_) => Ok(Value::new_undefined(ctx.clone())),
+        },
+        _ => Ok(Value::new_undefined(ctx.clone())),
+    }
+}
+
+fn set_js_seg<'js>(obj: &Object<'js>, seg: &PropexSegment, value: Value<'js>) -> rquickjs::Result<bool> {
+    match seg {
+        PropexSegment::Property(name) => obj.set(name.as_ref(), value)?,
+        PropexSegment::Index(index) if obj.is_array() => match u32::try_from(*index) {
+            Ok(index) => obj.set(index, value)?,
+            Err(_) => return Ok(false),
+        },
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Removes the property like `Msg::remove_nav()`, the elements of the arrays are spliced out.
+fn remove_js_seg(obj: &Object<'_>, seg: &PropexSegment) -> rquickjs::Result<bool> {
+    match seg {
+        PropexSegment::Property(name) if obj.contains_key(name.as_ref())? => {
+            obj.remove(name.as_ref())?;
+            Ok(true)
+        }
+        PropexSegment::Index(index) if obj.is_array() => {
+            let len: u32 = obj.get("length")?;
+            if *index >= len as usize {
+                return Ok(false);
+            }
+            let splice: Function = obj.get("splice")?;
+            splice.call::<_, ()>((This(obj.clone()), *index as u32, 1))?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
@@ -0,0 +1,217 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+const LOOP_COUNT_PROPERTY: &str = "_loopCount";
+
+const DONE_PORT: usize = 0;
+const LOOP_PORT: usize = 1;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+enum LoopOperator {
+    #[serde(rename = "eq")]
+    Eq,
+
+    #[serde(rename = "neq")]
+    Neq,
+
+    #[serde(rename = "lt")]
+    Lt,
+
+    #[serde(rename = "lte")]
+    Lte,
+
+    #[serde(rename = "gt")]
+    Gt,
+
+    #[serde(rename = "gte")]
+    Gte,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LoopRule {
+    t: LoopOperator,
+
+    #[serde(default)]
+    v: String,
+
+    #[serde(default = "default_rule_vt")]
+    vt: RedPropertyType,
+}
+
+fn default_rule_vt() -> RedPropertyType {
+    RedPropertyType::Str
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoopNodeConfig {
+    #[serde(default = "default_config_property")]
+    property: String,
+
+    rule: LoopRule,
+
+    #[serde(default = "default_config_max_iterations")]
+    max_iterations: usize,
+}
+
+fn default_config_property() -> String {
+    "payload".to_string()
+}
+
+fn default_config_max_iterations() -> usize {
+    100
+}
+
+/// Sends the message to the loop port (1) until the condition on `property` is met,
+/// then sends it to the done port (0).
+///
+/// The iteration count is kept in `msg._loopCount`.
+#[derive(Debug)]
+#[flow_node("loop")]
+struct LoopNode {
+    base: FlowNode,
+    config: LoopNodeConfig,
+}
+
+impl LoopNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let loop_config = LoopNodeConfig::deserialize(&config.rest)?;
+        if loop_config.max_iterations == 0 {
+            return Err(
+                EdgelinkError::BadFlowsJson("The `maxIterations` of the loop node must be positive".into()).into()
+            );
+        }
+        let node = LoopNode { base: state, config: loop_config };
+        Ok(Box::new(node))
+    }
+
+    /// Returns the port the message should be sent to.
+    async fn do_loop(&self, msg: &mut Msg) -> crate::Result<usize> {
+        let rule_value =
+            eval::evaluate_node_property(&self.config.rule.v, self.config.rule.vt, Some(self), None, Some(msg)).await?;
+
+        let met = match msg.get_nav_stripped(&self.config.property) {
+            Some(value) => Self::test_rule(self.config.rule.t, value, &rule_value),
+            None => false,
+        };
+        if met {
+            return Ok(DONE_PORT);
+        }
+
+        let count = msg.get(LOOP_COUNT_PROPERTY).and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+        if count >= self.config.max_iterations {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "The loop has reached the maximum iterations: {}",
+                self.config.max_iterations
+            ))
+            .into());
+        }
+        msg.set(LOOP_COUNT_PROPERTY.to_string(), Variant::from((count + 1) as u64));
+        Ok(LOOP_PORT)
+    }
+
+    /// Tests the rule with the loose comparisons of the `switch` node, so `"5"` equals `5`.
+    fn test_rule(op: LoopOperator, value: &Variant, rule_value: &Variant) -> bool {
+        let ordering = || predicate::compare(value, rule_value);
+        match op {
+            LoopOperator::Eq => predicate::equals(value, rule_value, true),
+            LoopOperator::Neq => !predicate::equals(value, rule_value, true),
+            LoopOperator::Lt => ordering() == Some(Ordering::Less),
+            LoopOperator::Lte => matches!(ordering(), Some(Ordering::Less | Ordering::Equal)),
+            LoopOperator::Gt => ordering() == Some(Ordering::Greater),
+            LoopOperator::Gte => matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for LoopNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let port = {
+                    let mut msg_guard = msg.write().await;
+                    node.do_loop(&mut msg_guard).await?
+                };
+                node.fan_out_one(Envelope { port, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_loop_until_the_condition_is_met() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "loop", "property": "payload",
+                "rule": {"t": "gte", "v": "5", "vt": "num"}, "wires": [["3"], ["2"]]},
+            {"id": "2", "z": "100", "type": "function", "func": "msg.payload += 1; return msg;", "wires": [["1"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": 0}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_f64(), Some(5.0));
+        assert_eq!(msgs[0][LOOP_COUNT_PROPERTY].as_u64(), Some(5));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_stop_at_the_max_iterations() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "loop", "property": "payload", "maxIterations": 3,
+                "rule": {"t": "eq", "v": "never", "vt": "str"}, "wires": [["3"], ["1"]]},
+            {"id": "2", "z": "100", "type": "catch", "scope": null, "uncaught": false, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0][LOOP_COUNT_PROPERTY].as_u64(), Some(3));
+        assert!(msgs[0].contains("error"));
+    }
+
+    #[test]
+    fn test_it_should_compare_the_rules_loosely() {
+        let five_str = Variant::from("5");
+        let five = Variant::from(5);
+        assert!(LoopNode::test_rule(LoopOperator::Eq, &five_str, &five));
+        assert!(LoopNode::test_rule(LoopOperator::Eq, &five, &five_str));
+        assert!(!LoopNode::test_rule(LoopOperator::Neq, &five_str, &five));
+        assert!(LoopNode::test_rule(LoopOperator::Gte, &five_str, &Variant::from(4.5)));
+        assert!(LoopNode::test_rule(LoopOperator::Lt, &Variant::from("10"), &Variant::from(20)));
+        assert!(!LoopNode::test_rule(LoopOperator::Lt, &Variant::from("abc"), &five));
+        assert!(LoopNode::test_rule(LoopOperator::Lte, &Variant::from("a"), &Variant::from("b")));
+    }
+}
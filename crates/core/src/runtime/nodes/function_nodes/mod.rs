@@ -1,6 +1,19 @@
 mod change;
+mod delay;
+mod loop_node;
+mod object_node;
 mod range;
 mod rbe;
+mod switch;
+mod trigger;
 
 #[cfg(feature = "js")]
 mod function;
+
+/// Returns the number of the output ports a function node really uses.
+///
+/// The messages beyond the declared outputs or to the unwired ports are dropped, and a node declaring no output still
+/// has one, so it is the smaller of the raised declared count and the wired one.
+pub(crate) fn function_used_outputs(declared: usize, wired: usize) -> usize {
+    declared.max(1).min(wired)
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum ObjectOperation {
+    SortKeys,
+    Keys,
+    Values,
+    Entries,
+    FromEntries,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectNodeConfig {
+    #[serde(default = "default_config_property")]
+    property: String,
+
+    operation: ObjectOperation,
+}
+
+fn default_config_property() -> String {
+    "payload".to_string()
+}
+
+/// Transforms the object in `property` into its keys, values or `[key, value]` entries,
+/// and turns entries back into an object.
+#[derive(Debug)]
+#[flow_node("object")]
+struct ObjectNode {
+    base: FlowNode,
+    config: ObjectNodeConfig,
+}
+
+impl ObjectNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let object_config = ObjectNodeConfig::deserialize(&config.rest)?;
+        let node = ObjectNode { base: state, config: object_config };
+        Ok(Box::new(node))
+    }
+
+    fn transform(&self, msg: &mut Msg) -> crate::Result<()> {
+        let value = msg.get_nav_stripped(&self.config.property).ok_or_else(|| {
+            EdgelinkError::InvalidOperation(format!("Cannot find the property `msg.{}`", self.config.property))
+        })?;
+
+        let result = match self.config.operation {
+            // The keys of `VariantObjectMap` are always in order.
+            ObjectOperation::SortKeys => value.as_object().map(|_| value.clone()),
+            ObjectOperation::Keys => value.object_keys(),
+            ObjectOperation::Values => value.object_values(),
+            ObjectOperation::Entries => value.object_entries(),
+            ObjectOperation::FromEntries => match value.as_array() {
+                Some(entries) => Some(Variant::from_entries(entries)?),
+                None => None,
+            },
+        };
+
+        let result = result.ok_or_else(|| {
+            EdgelinkError::InvalidOperation(format!(
+                "Cannot apply `{:?}` to the property `msg.{}`",
+                self.config.operation, self.config.property
+            ))
+        })?;
+        msg.set_nav_stripped(&self.config.property, result, true)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ObjectNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.transform(&mut msg_guard)?;
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_round_trip_entries_and_object() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "object", "operation": "entries", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "change",
+                "rules": [{"t": "set", "p": "entries", "pt": "msg", "to": "payload", "tot": "msg"}], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "object", "operation": "fromEntries", "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": {"b": 2, "a": {"c": [1, 2]}}}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["entries"], Variant::from(json!([["a", {"c": [1, 2]}], ["b", 2]])));
+        assert_eq!(msgs[0]["payload"], Variant::from(json!({"b": 2, "a": {"c": [1, 2]}})));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_extract_keys() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "object", "property": "data", "operation": "keys", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"data": {"y": 1, "x": 2}}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["data"], Variant::from(json!(["x", "y"])));
+    }
+}
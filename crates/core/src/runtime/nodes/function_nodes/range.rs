@@ -105,7 +105,8 @@ impl RangeNode {
                     new_value = new_value.round();
                 }
 
-                *value = Variant::Number(serde_json::Number::from_f64(new_value).unwrap());
+                // An empty input range gives `NaN` or `±Infinity`, which become `null` like in the JSON of Node-RED
+                *value = Variant::from(new_value);
                 Ok(())
             } else {
                 Err(EdgelinkError::OutOfRange).with_context(|| format!("The value is not a numner: {:?}", value))
@@ -151,3 +152,28 @@ impl FlowNodeBehavior for RangeNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_scale_with_an_empty_input_range() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "range", "action": "scale", "minin": "5", "maxin": "5", "minout": "0",
+                "maxout": "100", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = vec![
+            (ElementId::from(1), Msg::deserialize(json!({"payload": 5})).unwrap()),
+            (ElementId::from(1), Msg::deserialize(json!({"payload": 6})).unwrap()),
+        ];
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs.iter().all(|x| x["payload"].is_null()), "{:?}", msgs);
+    }
+}
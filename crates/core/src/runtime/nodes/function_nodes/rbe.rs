@@ -1,5 +1,8 @@
 use core::f64;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::Hasher;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -58,6 +61,19 @@ enum Inout {
     Out,
 }
 
+/// What the `rbe` and `rbei` functions compare with the previous message.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Deserialize)]
+enum RbeCompare {
+    /// The value of `property`.
+    #[default]
+    #[serde(rename = "property")]
+    Property,
+
+    /// The whole message, except the `_msgid`.
+    #[serde(rename = "msg")]
+    Msg,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct RbeNodeConfig {
     #[serde(default)]
@@ -83,6 +99,9 @@ struct RbeNodeConfig {
 
     #[serde(default)]
     inout: Inout,
+
+    #[serde(default)]
+    compare: RbeCompare,
 }
 
 fn rbe_setopics_default() -> bool {
@@ -130,15 +149,22 @@ where
     }
 }
 
+#[derive(Debug)]
+struct PrevMsg {
+    hash: u64,
+    msg: Msg,
+}
+
 #[derive(Debug)]
 struct RbeNodeState {
     current_gap: f64,
     prev: HashMap<String, Variant>,
+    prev_msgs: HashMap<String, PrevMsg>,
 }
 
 impl Default for RbeNodeState {
     fn default() -> Self {
-        Self { current_gap: 0.0, prev: HashMap::new() }
+        Self { current_gap: 0.0, prev: HashMap::new(), prev_msgs: HashMap::new() }
     }
 }
 
@@ -159,6 +185,11 @@ impl RbeNode {
         let mut rbe_config = RbeNodeConfig::deserialize(&config.rest)?;
         rbe_config.is_percent =
             config.rest.get("gap").and_then(|x| x.as_str()).is_some_and(|x| x.trim().ends_with('%'));
+        if rbe_config.compare == RbeCompare::Msg && !rbe_config.func.is_rbe() {
+            return Err(EdgelinkError::BadArgument("compare")).with_context(|| {
+                format!("The whole message can only be compared by 'rbe' and 'rbei', got: {:?}", rbe_config.func)
+            });
+        }
 
         let node = RbeNode { base: base_node, config: rbe_config, state: Mutex::new(RbeNodeState::default()) };
 
@@ -170,20 +201,29 @@ impl RbeNode {
         let value = msg.get_nav_stripped(&self.config.property);
 
         // Handle reset logic
-        match (msg.get("reset"), self.config.sep_topics, topic) {
+        match (msg.get(ControlMsgKind::RESET_PROPERTY), self.config.sep_topics, topic) {
             (Some(_), true, Some(Variant::String(topic))) if !topic.is_empty() => {
                 state.prev.remove(topic);
+                state.prev_msgs.remove(topic);
+            }
+            (Some(_), _, _) => {
+                state.prev.clear();
+                state.prev_msgs.clear();
             }
-            (Some(_), _, _) => state.prev.clear(),
             (_, _, _) => {}
         }
 
+        if self.config.compare == RbeCompare::Msg {
+            let state_key =
+                if self.config.sep_topics { topic_key(topic) } else { Cow::Borrowed(wellknown::NO_TOPIC_KEY) };
+            return self.filter_msg(msg, state_key.as_ref(), state);
+        }
+
         // Process value if available
         if let Some(value) = value {
-            let t = match (self.config.sep_topics, topic) {
-                (true, Some(Variant::String(topic))) => topic.as_str(),
-                (_, _) => "_no_topic",
-            };
+            let state_key =
+                if self.config.sep_topics { topic_key(topic) } else { Cow::Borrowed(wellknown::NO_TOPIC_KEY) };
+            let t = state_key.as_ref();
             if self.config.func.is_rbe() {
                 let prev_value = state.prev.get_mut(t);
                 let do_send = self.config.func != RbeFunc::Rbei || prev_value.is_some();
@@ -271,6 +311,25 @@ impl RbeNode {
 
         false
     }
+
+    /// The hashes are compared first, so a changed message is detected without comparing it deeply.
+    fn filter_msg(&self, msg: &Msg, state_key: &str, state: &mut RbeNodeState) -> bool {
+        let mut hasher = DefaultHasher::new();
+        msg.hash_ignoring_id(&mut hasher);
+        let hash = hasher.finish();
+
+        match state.prev_msgs.get_mut(state_key) {
+            Some(prev) if prev.hash == hash && prev.msg.eq_ignoring_id(msg) => false,
+            Some(prev) => {
+                *prev = PrevMsg { hash, msg: msg.clone() };
+                true
+            }
+            None => {
+                state.prev_msgs.insert(state_key.to_string(), PrevMsg { hash, msg: msg.clone() });
+                self.config.func != RbeFunc::Rbei
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -299,3 +358,48 @@ impl FlowNodeBehavior for RbeNode {
         log::debug!("DebugNode process() task has been terminated.");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::engine::build_test_engine;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_only_send_the_first_of_duplicate_msgs() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "rbe", "func": "rbe", "gap": "", "compare": "msg", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let msgs_to_inject_json = json!([
+            ["1", {"topic": "a", "payload": {"v": 1, "items": [1, 2]}, "extra": true}],
+            ["1", {"topic": "a", "payload": {"v": 1, "items": [1, 2]}, "extra": true}],
+            ["1", {"topic": "b", "payload": {"v": 1, "items": [1, 2]}, "extra": true}],
+            ["1", {"topic": "a", "payload": {"v": 1, "items": [1, 2]}, "extra": false}],
+            ["1", {"topic": "b", "payload": {"v": 1, "items": [1, 2]}, "extra": true}],
+            ["1", {"topic": "a", "payload": {"v": 1, "items": [1, 2]}, "extra": false}],
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_millis(300), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+        let received: Vec<(Option<&str>, Option<bool>)> =
+            msgs.iter().map(|x| (x["topic"].as_str(), x["extra"].as_bool())).collect();
+        assert_eq!(received, vec![(Some("a"), Some(true)), (Some("b"), Some(true)), (Some("a"), Some(false))]);
+    }
+
+    #[test]
+    fn test_it_should_hash_msgs_ignoring_the_id() {
+        let lhs = Msg::deserialize(json!({"_msgid": "0000000000000001", "payload": [1, {"a": "b"}]})).unwrap();
+        let rhs = Msg::deserialize(json!({"_msgid": "0000000000000002", "payload": [1, {"a": "b"}]})).unwrap();
+        let hash = |msg: &Msg| {
+            let mut hasher = DefaultHasher::new();
+            msg.hash_ignoring_id(&mut hasher);
+            hasher.finish()
+        };
+        assert!(lhs.eq_ignoring_id(&rhs));
+        assert_eq!(hash(&lhs), hash(&rhs));
+    }
+}
@@ -0,0 +1,770 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use smallvec::SmallVec;
+use tokio::sync::Mutex;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::jsonata;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+enum SwitchRuleOperator {
+    #[serde(rename = "eq")]
+    Equal,
+
+    #[serde(rename = "neq")]
+    NotEqual,
+
+    #[serde(rename = "lt")]
+    LessThan,
+
+    #[serde(rename = "lte")]
+    LessThanEqual,
+
+    #[serde(rename = "gt")]
+    GreatThan,
+
+    #[serde(rename = "gte")]
+    GreatThanEqual,
+
+    #[serde(rename = "btwn")]
+    Between,
+
+    #[serde(rename = "cont")]
+    Contains,
+
+    #[serde(rename = "regex")]
+    Regex,
+
+    #[serde(rename = "true")]
+    IsTrue,
+
+    #[serde(rename = "false")]
+    IsFalse,
+
+    #[serde(rename = "null")]
+    IsNull,
+
+    #[serde(rename = "nnull")]
+    IsNotNull,
+
+    #[serde(rename = "istype")]
+    IsType,
+
+    #[serde(rename = "empty")]
+    IsEmpty,
+
+    #[serde(rename = "nempty")]
+    IsNotEmpty,
+
+    #[serde(rename = "head")]
+    Head,
+
+    #[serde(rename = "tail")]
+    Tail,
+
+    #[serde(rename = "index")]
+    Index,
+
+    #[serde(rename = "hask", alias = "hasKey")]
+    HasKey,
+
+    #[serde(rename = "hasnKey")]
+    HasNotKey,
+
+    #[serde(rename = "jsonata_exp")]
+    JsonataExp,
+
+    #[serde(rename = "else")]
+    Else,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SwitchRule {
+    t: SwitchRuleOperator,
+
+    #[serde(default, deserialize_with = "json::deser::deser_string_or_number")]
+    v: String,
+
+    /// `None` if the type is not a property type, e.g. the type name of the `istype` rule.
+    #[serde(default = "default_rule_vt", deserialize_with = "deser_optional_property_type")]
+    vt: Option<RedPropertyType>,
+
+    #[serde(default, deserialize_with = "json::deser::deser_string_or_number")]
+    v2: String,
+
+    #[serde(default = "default_rule_vt", deserialize_with = "deser_optional_property_type")]
+    v2t: Option<RedPropertyType>,
+
+    #[serde(default)]
+    case: bool,
+
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwitchNodeConfig {
+    #[serde(default = "default_config_property")]
+    property: String,
+
+    #[serde(default = "default_config_property_type")]
+    property_type: RedPropertyType,
+
+    #[serde(default)]
+    rules: Vec<SwitchRule>,
+
+    #[serde(default = "default_config_checkall", deserialize_with = "json::deser::deser_bool_or_string")]
+    checkall: bool,
+
+    /// Holds the messages of a sequence until all of them arrived, then renumbers the `parts` of every port.
+    #[serde(default)]
+    repair: bool,
+}
+
+fn default_config_property() -> String {
+    "payload".to_string()
+}
+
+fn default_config_property_type() -> RedPropertyType {
+    RedPropertyType::Msg
+}
+
+fn default_config_checkall() -> bool {
+    true
+}
+
+fn default_rule_vt() -> Option<RedPropertyType> {
+    Some(RedPropertyType::Str)
+}
+
+fn deser_optional_property_type<'de, D>(deserializer: D) -> Result<Option<RedPropertyType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let jv = serde_json::Value::deserialize(deserializer)?;
+    Ok(RedPropertyType::deserialize(jv).ok())
+}
+
+/// The messages of a sequence held by the `repair` option, keyed by `parts.id`.
+#[derive(Debug, Default)]
+struct PendingSequence {
+    count: Option<usize>,
+    received: usize,
+    routed: Vec<(usize, SmallVec<[usize; 4]>, MsgHandle)>,
+}
+
+/// Routes messages to the ports of the matched rules, the port index is the index of the rule.
+#[derive(Debug)]
+#[flow_node("switch")]
+struct SwitchNode {
+    base: FlowNode,
+    config: SwitchNodeConfig,
+    sequences: Mutex<HashMap<String, PendingSequence>>,
+}
+
+impl SwitchNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let mut switch_config = SwitchNodeConfig::deserialize(&config.rest)?;
+        for rule in switch_config.rules.iter_mut() {
+            if rule.t == SwitchRuleOperator::Regex {
+                let re = crate::text::regex::build_js_regex(&rule.v, if rule.case { "i" } else { "" })
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Invalid regex rule: {}", e)))?;
+                rule.regex = Some(re);
+            }
+        }
+        let node = SwitchNode { base: state, config: switch_config, sequences: Mutex::new(HashMap::new()) };
+        Ok(Box::new(node))
+    }
+
+    /// Evaluates a property, a missing property is treated as `undefined` (`None`).
+    async fn eval_optional(
+        &self,
+        value: &str,
+        vt: Option<RedPropertyType>,
+        msg: &Msg,
+    ) -> crate::Result<Option<Variant>> {
+        let vt = match vt {
+            Some(vt) => vt,
+            None => return Ok(None),
+        };
+        match eval::evaluate_node_property(value, vt, Some(self), None, Some(msg)).await {
+            Ok(v) => Ok(Some(v)),
+            // The JSONata errors are real errors
+            Err(e) if vt == RedPropertyType::Jsonata => Err(e),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Returns the indices of the matched rules.
+    async fn dispatch(&self, msg: &Msg) -> crate::Result<SmallVec<[usize; 4]>> {
+        let prop = self.eval_optional(&self.config.property, Some(self.config.property_type), msg).await?;
+
+        let mut matched: SmallVec<[usize; 4]> = SmallVec::new();
+        for (index, rule) in self.config.rules.iter().enumerate() {
+            let is_matched = match rule.t {
+                SwitchRuleOperator::Else => matched.is_empty(),
+                SwitchRuleOperator::JsonataExp => {
+                    let result =
+                        eval::evaluate_node_property(&rule.v, RedPropertyType::Jsonata, Some(self), None, Some(msg))
+                            .await?;
+                    jsonata::boolean(&result)
+                }
+                _ => {
+                    if rule.t == SwitchRuleOperator::Tail && msg.get_nav_stripped("parts.count").is_none() {
+                        return Err(EdgelinkError::InvalidOperation(
+                            "The `tail` rule of the switch node needs the `parts.count` of the message".into(),
+                        )
+                        .into());
+                    }
+                    let v = self.eval_optional(&rule.v, rule.vt, msg).await?;
+                    let v2 = if rule.t == SwitchRuleOperator::Between || rule.t == SwitchRuleOperator::Index {
+                        self.eval_optional(&rule.v2, rule.v2t, msg).await?
+                    } else {
+                        None
+                    };
+                    Self::test_rule(rule, prop.as_ref(), v.as_ref(), v2.as_ref(), msg)?
+                }
+            };
+            if is_matched {
+                matched.push(index);
+                if !self.config.checkall {
+                    break;
+                }
+            }
+        }
+        Ok(matched)
+    }
+
+    /// Holds the routed message of a sequence, returns the renumbered messages of all ports once it is complete.
+    ///
+    /// Like Node-RED, every port gets its own sequence of the same `parts.id`, numbered from 0 in the original
+    /// order; the messages matching no rule still count for the completion. All the pending sequences are dropped
+    /// with an error once the held messages exceed `node_message_buffer_max_length` of the engine.
+    async fn repair_sequence(
+        &self,
+        msg: MsgHandle,
+        matched: SmallVec<[usize; 4]>,
+    ) -> crate::Result<Option<SmallVec<[Envelope; 4]>>> {
+        let (id, index, count) = {
+            let msg_guard = msg.read().await;
+            let parts = match msg_guard.get("parts").and_then(|x| x.as_object()) {
+                Some(parts) => parts,
+                None => return Ok(None),
+            };
+            match (parts.get("id").and_then(|x| x.to_string().ok()), parts.get("index").and_then(|x| x.as_u64())) {
+                (Some(id), Some(index)) => {
+                    (id, index as usize, parts.get("count").and_then(|x| x.as_u64()).map(|x| x as usize))
+                }
+                _ => return Ok(None),
+            }
+        };
+
+        let max_held = self.engine().and_then(|x| x.node_message_buffer_max_length());
+        let completed = {
+            let mut sequences = self.sequences.lock().await;
+            if let Some(max_held) = max_held {
+                let held: usize = sequences.values().map(|x| x.routed.len()).sum();
+                if held >= max_held {
+                    sequences.clear();
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Too many pending messages in the switch node, the limit is {}",
+                        max_held
+                    ))
+                    .into());
+                }
+            }
+            let pending = sequences.entry(id.clone()).or_default();
+            pending.received += 1;
+            pending.count = pending.count.or(count);
+            pending.routed.push((index, matched, msg));
+            match pending.count {
+                Some(count) if pending.received >= count => sequences.remove(&id),
+                _ => None,
+            }
+        };
+        let mut completed = match completed {
+            Some(completed) => completed,
+            None => return Ok(Some(SmallVec::new())),
+        };
+
+        completed.routed.sort_by_key(|(index, _, _)| *index);
+        let mut ports: SmallVec<[usize; 4]> = completed.routed.iter().flat_map(|(_, x, _)| x.iter().copied()).collect();
+        ports.sort();
+        ports.dedup();
+
+        let mut envelopes = SmallVec::new();
+        let mut sent = vec![false; completed.routed.len()];
+        let mut clones = vec![0usize; completed.routed.len()];
+        for port in ports.into_iter() {
+            let routed: Vec<usize> =
+                (0..completed.routed.len()).filter(|i| completed.routed[*i].1.contains(&port)).collect();
+            let count = routed.len();
+            for (new_index, i) in routed.into_iter().enumerate() {
+                // Every port has its own copy, only the first one keeps the message ID just like without `repair`
+                let msg = completed.routed[i].2.deep_clone(sent[i]).await;
+                sent[i] = true;
+                clones[i] += 1;
+                {
+                    let mut msg_guard = msg.write().await;
+                    if let Some(parts) = msg_guard.get_mut("parts").and_then(|x| x.as_object_mut()) {
+                        parts.insert("index".into(), Variant::from(new_index as u64));
+                        parts.insert("count".into(), Variant::from(count as u64));
+                        // The filtered buffer chunks are no longer contiguous
+                        parts.remove("offset");
+                    }
+                }
+                envelopes.extend(envelope_for_port(self, port, msg));
+            }
+        }
+        for (i, (_, _, msg)) in completed.routed.iter().enumerate() {
+            self.record_deep_clones(msg, clones[i]).await;
+        }
+        Ok(Some(envelopes))
+    }
+
+    fn test_rule(
+        rule: &SwitchRule,
+        a: Option<&Variant>,
+        b: Option<&Variant>,
+        c: Option<&Variant>,
+        msg: &Msg,
+    ) -> crate::Result<bool> {
+        let is_matched = match rule.t {
+            SwitchRuleOperator::Equal => matches!((a, b), (Some(a), Some(b)) if predicate::equals(a, b, true)),
+            SwitchRuleOperator::NotEqual => !matches!((a, b), (Some(a), Some(b)) if predicate::equals(a, b, true)),
+            SwitchRuleOperator::LessThan => cmp_is(a, b, |x| x == Ordering::Less),
+            SwitchRuleOperator::LessThanEqual => cmp_is(a, b, |x| x != Ordering::Greater),
+            SwitchRuleOperator::GreatThan => cmp_is(a, b, |x| x == Ordering::Greater),
+            SwitchRuleOperator::GreatThanEqual => cmp_is(a, b, |x| x != Ordering::Less),
+            SwitchRuleOperator::Between => {
+                let (low, high) = match (b, c) {
+                    (Some(b), Some(c)) if predicate::compare(b, c) == Some(Ordering::Greater) => (Some(c), Some(b)),
+                    _ => (b, c),
+                };
+                cmp_is(a, low, |x| x != Ordering::Less) && cmp_is(a, high, |x| x != Ordering::Greater)
+            }
+            SwitchRuleOperator::Contains => {
+                match (a.and_then(predicate::to_js_string), b.and_then(predicate::to_js_string)) {
+                    (Some(a), Some(b)) => a.contains(b.as_str()),
+                    _ => false,
+                }
+            }
+            SwitchRuleOperator::Regex => match (a.and_then(predicate::to_js_string), rule.regex.as_ref()) {
+                (Some(a), Some(re)) => re.is_match(&a),
+                _ => false,
+            },
+            SwitchRuleOperator::IsTrue => matches!(a, Some(Variant::Bool(true))),
+            SwitchRuleOperator::IsFalse => matches!(a, Some(Variant::Bool(false))),
+            SwitchRuleOperator::IsNull => matches!(a, None | Some(Variant::Null)),
+            SwitchRuleOperator::IsNotNull => !matches!(a, None | Some(Variant::Null)),
+            SwitchRuleOperator::IsType => is_type(a, &rule.v),
+            SwitchRuleOperator::IsEmpty => match a {
+                Some(v @ (Variant::String(_) | Variant::Array(_) | Variant::Bytes(_) | Variant::Object(_))) => {
+                    v.is_empty()
+                }
+                _ => false,
+            },
+            SwitchRuleOperator::IsNotEmpty => match a {
+                Some(v @ (Variant::String(_) | Variant::Array(_) | Variant::Bytes(_) | Variant::Object(_))) => {
+                    !v.is_empty()
+                }
+                _ => false,
+            },
+            SwitchRuleOperator::Index => {
+                let index = msg.get_nav_stripped("parts.index").and_then(|x| x.as_f64());
+                let (low, high) = (b.and_then(|x| x.as_f64()), c.and_then(|x| x.as_f64()));
+                match (index, low, high) {
+                    (Some(index), Some(low), Some(high)) => index >= low && index <= high,
+                    _ => false,
+                }
+            }
+            // The first `b` messages of the sequence
+            SwitchRuleOperator::Head => {
+                let index = msg.get_nav_stripped("parts.index").and_then(|x| x.as_f64());
+                match (index, b.and_then(predicate::to_js_number)) {
+                    (Some(index), Some(n)) => index < n,
+                    _ => false,
+                }
+            }
+            // The last `b` messages of the sequence
+            SwitchRuleOperator::Tail => {
+                let index = msg.get_nav_stripped("parts.index").and_then(|x| x.as_f64());
+                let count = msg.get_nav_stripped("parts.count").and_then(|x| x.as_f64());
+                match (index, count, b.and_then(predicate::to_js_number)) {
+                    (Some(index), Some(count), Some(n)) => count - n <= index,
+                    _ => false,
+                }
+            }
+            SwitchRuleOperator::HasKey => has_key(a, b),
+            SwitchRuleOperator::HasNotKey => matches!(a, Some(Variant::Object(_))) && !has_key(a, b),
+            SwitchRuleOperator::JsonataExp | SwitchRuleOperator::Else => {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "The `{:?}` rule cannot be tested against a single value",
+                    rule.t
+                ))
+                .into());
+            }
+        };
+        Ok(is_matched)
+    }
+}
+
+fn cmp_is(a: Option<&Variant>, b: Option<&Variant>, pred: impl Fn(Ordering) -> bool) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => predicate::compare(a, b).is_some_and(pred),
+        _ => false,
+    }
+}
+
+fn is_type(a: Option<&Variant>, type_name: &str) -> bool {
+    match (type_name, a) {
+        ("undefined", None) => true,
+        ("null", Some(Variant::Null)) => true,
+        ("string", Some(Variant::String(_))) => true,
+        ("number", Some(Variant::Number(_))) => true,
+        ("boolean", Some(Variant::Bool(_))) => true,
+        ("array", Some(Variant::Array(_))) => true,
+        ("buffer", Some(Variant::Bytes(_))) => true,
+        ("object", Some(Variant::Object(_))) => true,
+        ("json", Some(Variant::String(s))) => serde_json::from_str::<serde_json::Value>(s).is_ok(),
+        _ => false,
+    }
+}
+
+/// Tests the presence of a key, the key could be a navigation property expression like `a.b[0]`.
+fn has_key(a: Option<&Variant>, key: Option<&Variant>) -> bool {
+    match (a, key.and_then(predicate::to_js_string)) {
+        (Some(v @ Variant::Object(obj)), Some(key)) => obj.contains_key(key.as_str()) || v.get_nav(&key, &[]).is_some(),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SwitchNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let matched = {
+                    let msg_guard = msg.read().await;
+                    node.dispatch(&msg_guard).await?
+                };
+                if node.config.repair {
+                    if let Some(envelopes) = node.repair_sequence(msg.clone(), matched.clone()).await? {
+                        if envelopes.is_empty() {
+                            return Ok(());
+                        }
+                        return node.fan_out_many(envelopes, cancel.child_token()).await;
+                    }
+                }
+                node.record_deep_clones(&msg, matched.len().saturating_sub(1)).await;
+                let mut envelopes: SmallVec<[Envelope; 4]> = SmallVec::with_capacity(matched.len());
+                for (i, port) in matched.iter().enumerate() {
+                    let msg = if i == 0 { msg.clone() } else { msg.deep_clone(true).await };
+                    // More rules than the wired ports is allowed, the messages of the extra rules are dropped
+                    envelopes.extend(envelope_for_port(node, *port, msg));
+                }
+                if envelopes.is_empty() {
+                    return Ok(());
+                }
+                node.fan_out_many(envelopes, cancel.child_token()).await
+            })
+            .await;
+        }
+        // The incomplete sequences will never be completed by the next run
+        self.sequences.lock().await.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_route_by_key_presence() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "switch", "property": "payload", "checkall": "false",
+                "rules": [
+                    {"t": "hask", "v": "a.b", "vt": "str"},
+                    {"t": "hasnKey", "v": "a", "vt": "str"},
+                    {"t": "else"}
+                ],
+                "wires": [["2"], ["3"], ["4"]]},
+            {"id": "2", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "0", "tot": "num"}], "wires": [["5"]]},
+            {"id": "3", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "1", "tot": "num"}], "wires": [["5"]]},
+            {"id": "4", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "2", "tot": "num"}], "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": {"a": {"b": 1}}, "topic": "nested"}],
+            ["1", {"payload": {"c": 1}, "topic": "missing"}],
+            ["1", {"payload": {"a": {"x": 1}}, "topic": "other"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+        let mut routed = msgs
+            .iter()
+            .map(|x| (x["topic"].as_str().unwrap().to_string(), x["port"].as_i64().unwrap()))
+            .collect::<Vec<_>>();
+        routed.sort();
+        assert_eq!(routed, vec![("missing".to_string(), 1), ("nested".to_string(), 0), ("other".to_string(), 2)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_drop_the_msgs_of_the_rules_without_ports() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "switch", "property": "payload", "checkall": "true",
+                "rules": [{"t": "gt", "v": "0", "vt": "num"}, {"t": "gt", "v": "10", "vt": "num"}],
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject_json = json!([["1", {"payload": 20}], ["1", {"payload": 5}]]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json.clone()).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json.clone()).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        let mut payloads = msgs.iter().map(|x| x["payload"].as_i64().unwrap()).collect::<Vec<_>>();
+        payloads.sort();
+        assert_eq!(payloads, vec![5, 20]);
+
+        // The second rule has no port, the message is dropped without an error for the catch node
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let err =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::Timeout)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_route_the_head_and_the_tail_of_a_sequence() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "switch", "property": "payload", "checkall": "true",
+                "rules": [{"t": "head", "v": "2", "vt": "num"}, {"t": "tail", "v": "2", "vt": "num"}],
+                "wires": [["3"], ["4"]]},
+            {"id": "3", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "0", "tot": "num"}], "wires": [["5"]]},
+            {"id": "4", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "1", "tot": "num"}], "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [1, 2, 3, 4, 5]}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(4, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        let mut routed =
+            msgs.iter().map(|x| (x["port"].as_i64().unwrap(), x["payload"].as_i64().unwrap())).collect::<Vec<_>>();
+        routed.sort();
+        assert_eq!(routed, vec![(0, 1), (0, 2), (1, 4), (1, 5)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_repair_the_sequences_of_every_port() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "switch", "property": "payload", "checkall": "true", "repair": true,
+                "rules": [
+                    {"t": "lte", "v": "3", "vt": "num"},
+                    {"t": "gt", "v": "1", "vt": "num"},
+                    {"t": "gt", "v": "100", "vt": "num"}
+                ],
+                "wires": [["3"], ["4"], ["5"]]},
+            {"id": "3", "z": "100", "type": "join", "mode": "auto", "wires": [["5"]]},
+            {"id": "4", "z": "100", "type": "join", "mode": "auto", "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [1, 2, 3, 4, 5], "topic": "seq"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut switch_tap = engine.tap_output(&"2".parse().unwrap()).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        let mut joined = msgs.iter().map(|x| x["payload"].to_json_value()).collect::<Vec<_>>();
+        joined.sort_by_key(|x| x.as_array().map(|x| x.len()));
+        assert_eq!(joined, vec![json!([1, 2, 3]), json!([2, 3, 4, 5])]);
+        assert!(msgs.iter().all(|x| !x.contains("parts")));
+        // Nothing is sent by the port matching no message
+        assert!(std::iter::from_fn(|| switch_tap.try_recv().ok()).all(|(port, _)| port < 2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_count_the_deep_clones_of_the_ports() {
+        let rules = json!([
+            {"t": "lte", "v": "3", "vt": "num"},
+            {"t": "gt", "v": "1", "vt": "num"},
+            {"t": "gt", "v": "100", "vt": "num"}
+        ]);
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "switch", "property": "payload", "checkall": "true", "repair": true,
+                "rules": rules, "wires": [["5"], ["5"], ["5"]]},
+            {"id": "3", "z": "100", "type": "switch", "property": "payload", "checkall": "true",
+                "rules": rules, "wires": [["5"], ["5"], ["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.engine]
+                clone_stats = true
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = vec![
+            (ElementId::from(1), Msg::deserialize(json!({"payload": [1, 2, 3, 4, 5]})).unwrap()),
+            (ElementId::from(3), Msg::deserialize(json!({"payload": 2})).unwrap()),
+        ];
+        let msgs =
+            engine.run_once_with_inject(9, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 9);
+
+        // Every repaired message is cloned for each of its ports, the others for every port but the first
+        let clones =
+            |id: u64| engine.find_flow_node_by_id(&ElementId::from(id)).unwrap().get_node().clone_stats.clones();
+        assert_eq!(clones(2), 7);
+        assert_eq!(clones(3), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_drop_the_pending_sequences_over_the_limit() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "switch", "property": "payload", "repair": true,
+                "rules": [{"t": "else"}], "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "catch", "scope": ["2"], "uncaught": false, "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.engine]
+                node_message_buffer_max_length = 3
+
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": [1, 2, 3, 4, 5]})).unwrap())];
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let message = msgs[0].get_nav("error.message").and_then(|x| x.as_str()).unwrap();
+        assert!(message.contains("Too many pending messages"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_route_by_jsonata_predicates() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "switch", "property": "payload", "checkall": "false",
+                "rules": [
+                    {"t": "jsonata_exp", "v": "payload.value > 1 and $contains(topic, 'a')", "vt": "jsonata"},
+                    {"t": "jsonata_exp", "v": "payload.tags", "vt": "jsonata"},
+                    {"t": "else"}
+                ],
+                "wires": [["2"], ["3"], ["4"]]},
+            {"id": "2", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "0", "tot": "num"}], "wires": [["5"]]},
+            {"id": "3", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "1", "tot": "num"}], "wires": [["5"]]},
+            {"id": "4", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": "2", "tot": "num"}], "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": {"value": 2}, "topic": "abc"}],
+            ["1", {"payload": {"value": 2, "tags": [0, "x"]}, "topic": "xyz"}],
+            ["1", {"payload": {"value": 1, "tags": [0, ""]}, "topic": "abc"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+        let mut routed = msgs
+            .iter()
+            .map(|x| (x["payload"].as_object().unwrap().contains_key("tags"), x["port"].as_i64().unwrap()))
+            .collect::<Vec<_>>();
+        routed.sort();
+        assert_eq!(routed, vec![(false, 0), (true, 1), (true, 2)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_report_the_failed_jsonata_predicates() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "switch", "property": "payload",
+                "rules": [{"t": "jsonata_exp", "v": "payload + 'x'", "vt": "jsonata"}],
+                "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "catch", "scope": null, "uncaught": false, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": 2}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let message = msgs[0].get_nav("error.message").and_then(|x| x.as_str()).unwrap();
+        assert!(message.contains("must evaluate to numbers"));
+    }
+}
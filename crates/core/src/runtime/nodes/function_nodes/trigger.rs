@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::json::deser::str_to_option_f64;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils::async_util;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum ByTopic {
+    #[serde(rename = "all")]
+    All,
+
+    #[serde(rename = "topic")]
+    Topic,
+}
+
+#[derive(Debug, Deserialize)]
+struct TriggerNodeConfig {
+    #[serde(default = "default_op1")]
+    op1: String,
+
+    #[serde(rename = "op1type", default = "default_op1_type")]
+    op1_type: String,
+
+    #[serde(default = "default_op2")]
+    op2: String,
+
+    #[serde(rename = "op2type", default = "default_op2_type")]
+    op2_type: String,
+
+    /// `0` waits until the node has been reset.
+    #[serde(default, deserialize_with = "str_to_option_f64")]
+    duration: Option<f64>,
+
+    #[serde(default = "default_units")]
+    units: String,
+
+    /// Restarts the timer when a new message arrives before it fired.
+    #[serde(default)]
+    extend: bool,
+
+    /// A payload equal to this also resets the node, just like `msg.reset`.
+    #[serde(default)]
+    reset: String,
+
+    /// With two outputs the second message is sent to the second one.
+    #[serde(default = "default_outputs")]
+    outputs: usize,
+
+    #[serde(rename = "bytopic", default = "default_by_topic")]
+    by_topic: ByTopic,
+
+    #[serde(default = "default_topic")]
+    topic: String,
+
+    /// The maximum number of the topics with a running timer, `0` means unlimited.
+    ///
+    /// The least recently used topic is evicted and its timer cancelled when a new topic exceeds it.
+    #[serde(rename = "maxTopics", default)]
+    max_topics: usize,
+}
+
+fn default_op1() -> String {
+    "1".to_string()
+}
+
+fn default_op1_type() -> String {
+    "num".to_string()
+}
+
+fn default_op2() -> String {
+    "0".to_string()
+}
+
+fn default_op2_type() -> String {
+    "num".to_string()
+}
+
+fn default_units() -> String {
+    "ms".to_string()
+}
+
+fn default_outputs() -> usize {
+    1
+}
+
+fn default_by_topic() -> ByTopic {
+    ByTopic::All
+}
+
+fn default_topic() -> String {
+    "topic".to_string()
+}
+
+fn parse_time_unit(unit: &str) -> crate::Result<Duration> {
+    let secs = match unit {
+        "ms" => return Ok(Duration::from_millis(1)),
+        "s" => 1,
+        "min" => 60,
+        "hr" => 60 * 60,
+        _ => return Err(EdgelinkError::BadFlowsJson(format!("Unsupported time unit: '{}'", unit)).into()),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// What a trigger node sends as one of its two messages.
+#[derive(Debug, Clone)]
+enum TriggerOp {
+    /// The payload of the message starting the timer.
+    Payload,
+
+    /// The payload of the latest message arrived while the timer was running.
+    LatestPayload,
+
+    /// Nothing is sent.
+    Nothing,
+
+    Value(String, RedPropertyType),
+}
+
+impl TriggerOp {
+    fn parse(value: &str, type_: &str) -> crate::Result<Self> {
+        match type_ {
+            "pay" => Ok(TriggerOp::Payload),
+            "payl" => Ok(TriggerOp::LatestPayload),
+            "nul" => Ok(TriggerOp::Nothing),
+            _ => {
+                let type_ = RedPropertyType::deserialize(serde_json::Value::String(type_.to_string()))
+                    .map_err(|_| EdgelinkError::BadFlowsJson(format!("Unsupported value type: '{}'", type_)))?;
+                Ok(TriggerOp::Value(value.to_string(), type_))
+            }
+        }
+    }
+}
+
+/// The timer of a topic, the message starting it and the latest one are kept for the second message.
+#[derive(Debug)]
+struct TopicTimer {
+    /// Identifies the timer so a fired timer does not remove the one replacing it.
+    id: u64,
+    cancel: CancellationToken,
+    first: MsgHandle,
+    latest: MsgHandle,
+    /// The tick of the last message of this topic, the smallest one is evicted first.
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct TriggerState {
+    timers: HashMap<String, TopicTimer>,
+    tick: u64,
+}
+
+impl TriggerState {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Removes the least recently used topic and cancels its timer.
+    fn evict_lru(&mut self) -> Option<String> {
+        let topic = self.timers.iter().min_by_key(|(_, timer)| timer.last_used).map(|(topic, _)| topic.clone())?;
+        if let Some(timer) = self.timers.remove(&topic) {
+            timer.cancel.cancel();
+        }
+        Some(topic)
+    }
+
+    fn cancel_all(&mut self) {
+        for (_, timer) in self.timers.drain() {
+            timer.cancel.cancel();
+        }
+    }
+}
+
+/// Sends a message when triggered, then a second one after a duration unless it has been reset.
+#[derive(Debug)]
+#[flow_node("trigger")]
+struct TriggerNode {
+    base: FlowNode,
+    config: TriggerNodeConfig,
+    op1: TriggerOp,
+    op2: TriggerOp,
+    duration: Duration,
+    state: std::sync::Mutex<TriggerState>,
+}
+
+impl TriggerNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let trigger_config = TriggerNodeConfig::deserialize(&config.rest)?;
+        let duration = trigger_config.duration.unwrap_or(250.0);
+        if duration < 0.0 {
+            return Err(EdgelinkError::NotSupported(
+                "Resending the message of the trigger node periodically is not supported yet".to_string(),
+            )
+            .into());
+        }
+        let op1 = TriggerOp::parse(&trigger_config.op1, &trigger_config.op1_type)?;
+        if matches!(op1, TriggerOp::LatestPayload) {
+            return Err(
+                EdgelinkError::BadFlowsJson("The first message cannot be the latest payload".to_string()).into()
+            );
+        }
+        let op2 = TriggerOp::parse(&trigger_config.op2, &trigger_config.op2_type)?;
+
+        let node = TriggerNode {
+            base: state,
+            duration: parse_time_unit(&trigger_config.units)?.mul_f64(duration),
+            config: trigger_config,
+            op1,
+            op2,
+            state: std::sync::Mutex::new(TriggerState::default()),
+        };
+        Ok(Box::new(node))
+    }
+
+    /// Returns the number of the topics with a running timer.
+    #[cfg(test)]
+    pub(crate) fn timers_len(&self) -> usize {
+        self.state.lock().expect("trigger state").timers.len()
+    }
+
+    async fn on_msg(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) {
+        let (topic, is_reset) = {
+            let msg = msg.read().await;
+            let topic = match self.config.by_topic {
+                ByTopic::All => wellknown::NO_TOPIC_KEY.to_string(),
+                ByTopic::Topic => topic_key(msg.get_nav_stripped(&self.config.topic)).into_owned(),
+            };
+            let is_reset = matches!(ControlMsgKind::from_msg(&msg), Some(ControlMsgKind::Reset))
+                || (!self.config.reset.is_empty()
+                    && msg.get(wellknown::PAYLOAD_PROPERTY).and_then(|p| p.as_str())
+                        == Some(self.config.reset.as_str()));
+            (topic, is_reset)
+        };
+
+        if is_reset {
+            let mut state = self.state.lock().expect("trigger state");
+            match self.config.by_topic {
+                ByTopic::Topic => {
+                    if let Some(timer) = state.timers.remove(&topic) {
+                        timer.cancel.cancel();
+                    }
+                }
+                ByTopic::All => state.cancel_all(),
+            }
+            return;
+        }
+
+        // The first message is sent once the state is unlocked
+        let (is_new, timer) = {
+            let mut state = self.state.lock().expect("trigger state");
+            let tick = state.next_tick();
+            match state.timers.get_mut(&topic) {
+                Some(timer) => {
+                    timer.last_used = tick;
+                    timer.latest = msg.clone();
+                    if self.config.extend && !self.duration.is_zero() {
+                        timer.cancel.cancel();
+                        timer.cancel = stop_token.child_token();
+                        timer.id = tick;
+                        (false, Some((tick, timer.cancel.clone())))
+                    } else {
+                        (false, None)
+                    }
+                }
+                None => {
+                    if self.config.max_topics > 0 && state.timers.len() >= self.config.max_topics {
+                        if let Some(evicted) = state.evict_lru() {
+                            log::debug!("[trigger:{}] Evicted the timer of the topic '{}'", self.name(), evicted);
+                        }
+                    }
+                    let cancel = stop_token.child_token();
+                    let timer = TopicTimer {
+                        id: tick,
+                        cancel: cancel.clone(),
+                        first: msg.clone(),
+                        latest: msg.clone(),
+                        last_used: tick,
+                    };
+                    state.timers.insert(topic.clone(), timer);
+                    // A zero duration blocks the topic until reset
+                    (true, if self.duration.is_zero() { None } else { Some((tick, cancel)) })
+                }
+            }
+        };
+
+        if is_new {
+            self.send_op(&self.op1, 0, &msg, &msg, stop_token.clone()).await;
+        }
+        if let Some((id, cancel)) = timer {
+            self.spawn_timer(topic, id, cancel);
+        }
+    }
+
+    fn spawn_timer(self: &Arc<Self>, topic: String, id: u64, cancel: CancellationToken) {
+        let node = self.clone();
+        let duration = self.duration;
+        tokio::spawn(async move {
+            let clock = node.clock();
+            if async_util::delay_on(clock.as_ref(), duration, cancel.clone()).await.is_err() {
+                return;
+            }
+            let timer = {
+                let mut state = node.state.lock().expect("trigger state");
+                match state.timers.get(&topic) {
+                    Some(timer) if timer.id == id => state.timers.remove(&topic),
+                    _ => None,
+                }
+            };
+            if let Some(timer) = timer {
+                let port = if node.config.outputs >= 2 { 1 } else { 0 };
+                node.send_op(&node.op2, port, &timer.first, &timer.latest, cancel).await;
+            }
+        });
+    }
+
+    /// Sends a copy of the message with the payload of the `op`.
+    async fn send_op(
+        &self,
+        op: &TriggerOp,
+        port: usize,
+        first: &MsgHandle,
+        latest: &MsgHandle,
+        cancel: CancellationToken,
+    ) {
+        let out = match op {
+            TriggerOp::Nothing => return,
+            TriggerOp::Payload => first.deep_clone(true).await,
+            TriggerOp::LatestPayload => latest.deep_clone(true).await,
+            TriggerOp::Value(value, type_) => {
+                let out = first.deep_clone(true).await;
+                let payload = {
+                    let msg = out.read().await;
+                    eval::evaluate_node_property(value, *type_, Some(self), None, Some(&msg)).await
+                };
+                match payload {
+                    Ok(payload) => out.write().await.set(wellknown::PAYLOAD_PROPERTY.to_string(), payload),
+                    Err(err) => {
+                        self.report_error(err.to_string(), first.clone(), cancel).await;
+                        return;
+                    }
+                }
+                out
+            }
+        };
+        if let Err(err) = self.fan_out_one(Envelope { port, msg: out }, cancel).await {
+            log::warn!("[trigger:{}] Failed to send the message: {}", self.name(), err);
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for TriggerNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            match self.recv_msg(stop_token.clone()).await {
+                Ok(msg) => self.on_msg(msg, stop_token.clone()).await,
+                Err(err) => {
+                    if !matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::TaskCancelled)) {
+                        log::warn!("[trigger:{}] {}", self.name(), err);
+                    }
+                    break;
+                }
+            }
+        }
+        self.state.lock().expect("trigger state").cancel_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::engine::Engine;
+    use crate::utils::time::MockClock;
+    use serde_json::json;
+
+    async fn start_trigger(
+        max_topics: usize,
+    ) -> (Engine, Arc<MockClock>, tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>) {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "on", "op1type": "str", "op2": "off", "op2type": "str",
+                "duration": "1", "units": "s", "bytopic": "topic", "topic": "topic", "maxTopics": max_topics,
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let clock = Arc::new(MockClock::new());
+        engine.set_clock(clock.clone());
+        let sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+        (engine, clock, sink_rx)
+    }
+
+    async fn inject(engine: &Engine, msg: serde_json::Value) {
+        let msg = MsgHandle::new(Msg::deserialize(msg).unwrap());
+        engine.inject_msg(&"1".parse().unwrap(), msg, CancellationToken::new()).await.unwrap();
+    }
+
+    /// Receives `n` messages as `(topic, payload)`, sorted since the timers of the topics fire concurrently.
+    async fn recv_sorted(
+        sink_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>,
+        n: usize,
+    ) -> Vec<(String, String)> {
+        let mut received = Vec::new();
+        for _ in 0..n {
+            let (_, msg) = tokio::time::timeout(Duration::from_secs(1), sink_rx.recv()).await.unwrap().unwrap();
+            received.push((msg["topic"].as_str().unwrap().to_string(), msg["payload"].as_str().unwrap().to_string()));
+        }
+        received.sort();
+        received
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(t, p)| (t.to_string(), p.to_string())).collect()
+    }
+
+    async fn wait_for_timers(engine: &Engine, clock: &MockClock, n: usize) {
+        let node = engine.find_flow_node_by_id(&"1".parse().unwrap()).unwrap();
+        let trigger_node = node.as_any().downcast_ref::<TriggerNode>().unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while trigger_node.timers_len() != n || clock.pending_sleeps() != n {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_send_the_second_msg_per_topic() {
+        let (engine, clock, mut sink_rx) = start_trigger(0).await;
+        for topic in ["a", "b", "a"] {
+            inject(&engine, json!({"topic": topic, "payload": 1})).await;
+        }
+        // The second message of the topic `a` is ignored while its timer runs
+        assert_eq!(recv_sorted(&mut sink_rx, 2).await, pairs(&[("a", "on"), ("b", "on")]));
+        wait_for_timers(&engine, &clock, 2).await;
+
+        inject(&engine, json!({"topic": "b", "reset": true})).await;
+        wait_for_timers(&engine, &clock, 1).await;
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(recv_sorted(&mut sink_rx, 1).await, pairs(&[("a", "off")]));
+        wait_for_timers(&engine, &clock, 0).await;
+        assert!(sink_rx.try_recv().is_err());
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_evict_the_least_recently_used_topic_over_the_cap() {
+        let (engine, clock, mut sink_rx) = start_trigger(2).await;
+        for topic in ["a", "b"] {
+            inject(&engine, json!({"topic": topic, "payload": 1})).await;
+        }
+        assert_eq!(recv_sorted(&mut sink_rx, 2).await, pairs(&[("a", "on"), ("b", "on")]));
+        // Using `a` again makes `b` the least recently used one
+        inject(&engine, json!({"topic": "a", "payload": 2})).await;
+        inject(&engine, json!({"topic": "c", "payload": 1})).await;
+        assert_eq!(recv_sorted(&mut sink_rx, 1).await, pairs(&[("c", "on")]));
+
+        // The timer of `b` has been cancelled, not only forgotten
+        wait_for_timers(&engine, &clock, 2).await;
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(recv_sorted(&mut sink_rx, 2).await, pairs(&[("a", "off"), ("c", "off")]));
+        wait_for_timers(&engine, &clock, 0).await;
+        assert!(sink_rx.try_recv().is_err());
+
+        // The evicted topic triggers again as a new one
+        inject(&engine, json!({"topic": "b", "payload": 1})).await;
+        assert_eq!(recv_sorted(&mut sink_rx, 1).await, pairs(&[("b", "on")]));
+        engine.stop().await.unwrap();
+    }
+}
@@ -1,8 +1,9 @@
 use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Weak};
 
 use async_trait::async_trait;
-use runtime::engine::Engine;
+use runtime::engine::{CloneWarningThreshold, Engine};
 use runtime::group::{Group, WeakGroup};
 use smallvec::SmallVec;
 use tokio::select;
@@ -17,7 +18,9 @@ use crate::EdgelinkError;
 use crate::*;
 
 pub(crate) mod common_nodes;
-mod function_nodes;
+pub(crate) mod function_nodes;
+mod sequence_nodes;
+mod storage_nodes;
 
 #[cfg(feature = "net")]
 mod network_nodes;
@@ -39,15 +42,21 @@ pub enum NodeKind {
     Global = 1,
 }
 
-impl fmt::Display for NodeKind {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
         match *self {
-            NodeKind::Flow => write!(f, "GlobalNode"),
-            NodeKind::Global => write!(f, "FlowoNode"),
+            NodeKind::Flow => "FlowNode",
+            NodeKind::Global => "GlobalNode",
         }
     }
 }
 
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 type GlobalNodeFactoryFn = fn(&Engine, &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>>;
 
 type FlowNodeFactoryFn = fn(&Flow, FlowNode, &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>>;
@@ -66,6 +75,111 @@ pub struct MetaNode {
     pub factory: NodeFactory,
 }
 
+/// The status shown under a node in the editor of Node-RED, see `FlowNodeBehavior::set_status()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NodeStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<String>,
+
+    #[serde(default)]
+    pub text: String,
+}
+
+impl NodeStatus {
+    pub fn new(fill: &str, shape: &str, text: impl Into<String>) -> Self {
+        Self { fill: Some(fill.to_string()), shape: Some(shape.to_string()), text: text.into() }
+    }
+}
+
+/// Keeps the last message for the nodes with the `retain` option, see `FlowNodeBehavior::retained_msg()`.
+#[derive(Debug, Default)]
+pub struct MsgRetainer {
+    last: std::sync::Mutex<Option<Msg>>,
+}
+
+impl MsgRetainer {
+    /// Keeps a snapshot of the message, so the later changes made by the downstream nodes are not retained.
+    pub async fn retain(&self, msg: &MsgHandle) {
+        let snapshot = msg.read().await.clone();
+        *self.last.lock().expect("retained msg") = Some(snapshot);
+    }
+
+    /// Returns a clone of the retained message.
+    pub fn get(&self) -> Option<Msg> {
+        self.last.lock().expect("retained msg").clone()
+    }
+}
+
+/// Counts the messages deep-cloned by a node to send them to more than one wire or port, only if `clone_stats` is
+/// enabled in the `[runtime.engine]` section.
+///
+/// A node cloning many large messages in a short time is a clone storm, it warns once `clone_warning_count` large
+/// clones happened within `clone_warning_window_secs` in the `[runtime.engine]` section. Only the bytes actually
+/// copied count, the shared buffers do not.
+#[derive(Debug, Default)]
+pub struct CloneStats {
+    clones: AtomicU64,
+    cloned_bytes: AtomicU64,
+    large_clones: AtomicU64,
+    storm_warnings: AtomicU64,
+    /// The start of the current window by the engine clock and the large clones within it.
+    window: std::sync::Mutex<(std::time::Duration, u64)>,
+}
+
+impl CloneStats {
+    /// Returns the number of the deep clones.
+    pub fn clones(&self) -> u64 {
+        self.clones.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total approximate bytes copied by the deep clones.
+    pub fn cloned_bytes(&self) -> u64 {
+        self.cloned_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of the deep clones not smaller than `clone_warning_size`.
+    pub fn large_clones(&self) -> u64 {
+        self.large_clones.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of the clone storm warnings logged.
+    pub fn storm_warnings(&self) -> u64 {
+        self.storm_warnings.load(Ordering::Relaxed)
+    }
+
+    /// Records `n` deep clones copying `size` bytes each at the time `now` of the engine clock, returns the number of
+    /// the large clones within the window if a warning is due.
+    fn record(&self, size: usize, n: u64, now: std::time::Duration, threshold: &CloneWarningThreshold) -> Option<u64> {
+        self.clones.fetch_add(n, Ordering::Relaxed);
+        self.cloned_bytes.fetch_add(size as u64 * n, Ordering::Relaxed);
+        if size < threshold.size {
+            return None;
+        }
+        self.large_clones.fetch_add(n, Ordering::Relaxed);
+
+        let mut window = self.window.lock().expect("clone stats window");
+        let (start, large_clones) = &mut *window;
+        if now.saturating_sub(*start) > threshold.window {
+            *start = now;
+            *large_clones = 0;
+        }
+        *large_clones += n;
+        if *large_clones >= threshold.count {
+            let warned = *large_clones;
+            // Starts a new window, so a lasting storm warns once per window
+            *start = now;
+            *large_clones = 0;
+            self.storm_warnings.fetch_add(1, Ordering::Relaxed);
+            Some(warned)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FlowNode {
     pub id: ElementId,
@@ -79,12 +193,22 @@ pub struct FlowNode {
     pub msg_rx: MsgReceiverHolder,
     pub ports: Vec<Port>,
     pub group: Option<WeakGroup>,
+    /// The `z` property, the ID of the flow which contains this node.
+    pub flow_id: ElementId,
+    /// The `g` property, the ID of the group which directly contains this node.
+    pub group_id: Option<ElementId>,
+    pub status: std::sync::Mutex<NodeStatus>,
     pub envs: Envs,
     pub context: Arc<Context>,
 
     pub on_received: MsgEventSender,
     pub on_completed: MsgEventSender,
     pub on_error: MsgEventSender,
+
+    pub clone_stats: CloneStats,
+
+    /// Whether any `Engine::inject_and_collect_at()` is collecting the messages received by this node.
+    pub received_tapped: AtomicBool,
 }
 
 #[derive(Debug)]
@@ -100,6 +224,18 @@ pub struct GlobalNode {
 #[async_trait]
 pub trait GlobalNodeBehavior: Send + Sync + FlowsElement {
     fn get_node(&self) -> &GlobalNode;
+
+    // events
+
+    /// Called after the node has been built, an error fails the loading of the engine.
+    fn on_loaded(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Called before any flow starts, an error aborts the starting of the engine.
+    async fn on_starting(&self) -> crate::Result<()> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -116,6 +252,21 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         self.get_node().flow.upgrade()
     }
 
+    /// Returns the ID of the flow which contains this node, even if the flow has been released.
+    fn flow_id(&self) -> ElementId {
+        self.get_node().flow_id
+    }
+
+    /// Returns the ID of the group which directly contains this node, if any.
+    fn group_id(&self) -> Option<ElementId> {
+        self.get_node().group_id
+    }
+
+    /// Returns `true` if the node itself or any of its enclosing groups is disabled.
+    fn is_effectively_disabled(&self) -> bool {
+        self.get_node().disabled || self.group().is_some_and(|g| g.is_disabled_recursively())
+    }
+
     fn envs(&self) -> &Envs {
         &self.get_node().envs
     }
@@ -128,6 +279,14 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         self.get_node().flow.upgrade()?.engine()
     }
 
+    /// Returns the clock of the engine, all timers of the node should be measured by it.
+    fn clock(&self) -> Arc<dyn crate::utils::time::Clock> {
+        match self.engine() {
+            Some(engine) => engine.clock(),
+            None => Arc::new(crate::utils::time::SystemClock::new()),
+        }
+    }
+
     async fn inject_msg(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
         select! {
             result = self.get_node().msg_tx.send(msg) => result.map_err(|e| e.into()),
@@ -140,6 +299,12 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         if self.get_node().on_received.receiver_count() > 0 {
             self.get_node().on_received.send(msg.clone())?;
         }
+        // Nothing is awaited once dequeued, so the message is never lost if this future is dropped
+        if self.get_node().received_tapped.load(Ordering::Relaxed) {
+            if let Some(engine) = self.engine() {
+                engine.notify_received(&self.id(), &msg);
+            }
+        }
         Ok(msg)
     }
 
@@ -153,16 +318,33 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
     }
 
     async fn fan_out_one(&self, envelope: Envelope, cancel: CancellationToken) -> crate::Result<()> {
-        if self.get_node().ports.is_empty() {
-            log::warn!("No output wires in this node: Node(id='{}', name='{}')", self.id(), self.name());
-            return Ok(());
-        }
-        if envelope.port >= self.get_node().ports.len() {
+        let ports = &self.get_node().ports;
+        if !ports.is_empty() && envelope.port >= ports.len() {
             return Err(crate::EdgelinkError::BadArgument("envelope"))
                 .with_context(|| format!("Invalid port index {}", envelope.port));
         }
 
-        let port = &self.get_node().ports[envelope.port];
+        if let Some(flow) = self.flow().filter(|x| x.is_order_preserved()) {
+            let mut msg = envelope.msg.write().await;
+            if !msg.contains(wellknown::MSG_SEQ_PROPERTY) {
+                msg.set(wellknown::MSG_SEQ_PROPERTY.to_string(), Variant::from(flow.next_msg_seq()));
+            }
+        }
+
+        if let Some(engine) = self.engine().filter(|x| x.has_output_callbacks()) {
+            let msg = envelope.msg.read().await;
+            engine.notify_output(&self.id(), envelope.port, &msg);
+        }
+
+        if ports.is_empty() {
+            log::warn!("No output wires in this node: Node(id='{}', name='{}')", self.id(), self.name());
+            return Ok(());
+        }
+
+        let port = &ports[envelope.port];
+
+        // Every wire but the first gets a deep clone
+        self.record_deep_clones(&envelope.msg, port.wires.len().saturating_sub(1)).await;
 
         let mut msg_sent = false;
         for wire in port.wires.iter() {
@@ -174,33 +356,96 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         Ok(())
     }
 
-    async fn fan_out_many(&self, envelopes: SmallVec<[Envelope; 4]>, cancel: CancellationToken) -> crate::Result<()> {
-        if self.get_node().ports.is_empty() {
-            log::warn!("No output wires in this node: Node(id='{}')", self.id());
-            return Ok(());
+    /// Records `clones` deep clones of the `msg` in the `CloneStats` of the node, if they are enabled.
+    async fn record_deep_clones(&self, msg: &MsgHandle, clones: usize) {
+        let Some(engine) = self.engine().filter(|x| x.clone_stats_enabled()) else {
+            return;
+        };
+        if clones == 0 {
+            return;
+        }
+        let size = msg.read().await.approx_copied_size();
+        let threshold = engine.clone_warning_threshold();
+        let now = engine.clock().elapsed();
+        if let Some(large_clones) = self.get_node().clone_stats.record(size, clones as u64, now, &threshold) {
+            log::warn!(
+                "Clone storm: Node(id='{}', name='{}') has deep-cloned {} messages copying {} bytes or more \
+                 within {:?} to send them to multiple wires, the last message copies approx. {} bytes",
+                self.id(),
+                self.name(),
+                large_clones,
+                threshold.size,
+                threshold.window,
+                size
+            );
         }
+    }
 
+    async fn fan_out_many(&self, envelopes: SmallVec<[Envelope; 4]>, cancel: CancellationToken) -> crate::Result<()> {
         for e in envelopes.into_iter() {
             self.fan_out_one(e, cancel.child_token()).await?;
         }
         Ok(())
     }
 
-    async fn report_error(&self, log_message: String, msg: MsgHandle, cancel: CancellationToken) {
-        let handled = if let Some(flow) = self.flow() {
-            let node = self.as_any().downcast_ref::<Arc<dyn FlowNodeBehavior>>().unwrap(); // FIXME
-            flow.handle_error(node.as_ref(), &log_message, Some(msg), None, cancel).await.unwrap_or(false)
-        } else {
-            false
+    /// Routes the error to the catch nodes, logs it if no catch node handled it.
+    async fn report_error(&self, log_message: String, msg: MsgHandle, cancel: CancellationToken)
+    where
+        Self: Sized,
+    {
+        let handled = match self.flow() {
+            Some(flow) => flow.handle_error(self, &log_message, Some(msg), None, cancel).await.unwrap_or(false),
+            None => false,
         };
         if !handled {
             log::error!("[{}:{}] {}", self.type_str(), self.name(), log_message);
         }
     }
 
+    /// Returns the current status of the node.
+    fn status(&self) -> NodeStatus {
+        self.get_node().status.lock().expect("status").clone()
+    }
+
+    /// Returns a clone of the last message retained for the late subscribers, `None` if the node does not retain.
+    ///
+    /// The retained message is delivered by `Engine::on_output()` and `Engine::sink_receiver()` right away.
+    fn retained_msg(&self) -> Option<Msg> {
+        None
+    }
+
+    /// Updates the status of the node and notifies the `status` nodes watching it.
+    async fn set_status(&self, status: NodeStatus, cancel: CancellationToken)
+    where
+        Self: Sized,
+    {
+        {
+            let mut current = self.get_node().status.lock().expect("status");
+            if *current == status {
+                return;
+            }
+            *current = status.clone();
+        }
+        if let Some(flow) = self.flow() {
+            if let Err(err) = flow.handle_status(self, &status, cancel).await {
+                log::warn!("[{}:{}] Failed to report the status: {}", self.type_str(), self.name(), err);
+            }
+        }
+    }
+
     // events
-    fn on_loaded(&self) {}
-    async fn on_starting(&self) {}
+
+    /// Called after the node has been built, an error fails the loading of the engine.
+    ///
+    /// The `on_loaded` of all nodes are called before any `on_starting`.
+    fn on_loaded(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Called before the task of any node in the flow runs, an error aborts the starting of the engine.
+    async fn on_starting(&self) -> crate::Result<()> {
+        Ok(())
+    }
 }
 
 impl dyn GlobalNodeBehavior {
@@ -212,7 +457,8 @@ impl dyn GlobalNodeBehavior {
 impl fmt::Debug for dyn GlobalNodeBehavior {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
-            "GlobalNode(id='{}', type='{}', name='{}')",
+            "{}(id='{}', type='{}', name='{}')",
+            NodeKind::Global,
             self.id(),
             self.get_node().type_str,
             self.name(),
@@ -223,7 +469,8 @@ impl fmt::Debug for dyn GlobalNodeBehavior {
 impl fmt::Display for dyn GlobalNodeBehavior {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
-            "GlobalNode(id='{}', type='{}', name='{}')",
+            "{}(id='{}', type='{}', name='{}')",
+            NodeKind::Global,
             self.id(),
             self.get_node().type_str,
             self.name(),
@@ -239,13 +486,25 @@ impl dyn FlowNodeBehavior {
 
 impl fmt::Debug for dyn FlowNodeBehavior {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_fmt(format_args!("FlowNode(id='{}', type='{}', name='{}')", self.id(), self.type_str(), self.name(),))
+        f.write_fmt(format_args!(
+            "{}(id='{}', type='{}', name='{}')",
+            NodeKind::Flow,
+            self.id(),
+            self.type_str(),
+            self.name(),
+        ))
     }
 }
 
 impl fmt::Display for dyn FlowNodeBehavior {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_fmt(format_args!("FlowNode(id='{}', type='{}', name='{}')", self.id(), self.type_str(), self.name(),))
+        f.write_fmt(format_args!(
+            "{}(id='{}', type='{}', name='{}')",
+            NodeKind::Flow,
+            self.id(),
+            self.type_str(),
+            self.name(),
+        ))
     }
 }
 
@@ -257,20 +516,8 @@ where
 {
     match node.recv_msg(cancel.clone()).await {
         Ok(msg) => {
-            if let Err(ref err) = proc(node, msg.clone()).await {
-                let flow = node.flow().expect("flow");
-                let error_message = err.to_string();
-
-                match flow.handle_error(node, &error_message, Some(msg.clone()), None, cancel.clone()).await {
-                    Ok(_) => (),
-                    Err(e) => {
-                        log::error!("Failed to handle error: {:?}", e);
-                    }
-                }
-            }
-
-            // Report the completion
-            node.notify_uow_completed(msg, cancel.clone()).await;
+            let result = proc(node, msg.clone()).await;
+            complete_uow(node, msg, result, cancel).await;
         }
         Err(ref err) => {
             if let Some(EdgelinkError::TaskCancelled) = err.downcast_ref::<EdgelinkError>() {
@@ -282,6 +529,153 @@ where
     }
 }
 
+/// Reports the error of the unit of work if any, and then the completion.
+async fn complete_uow<B: FlowNodeBehavior>(
+    node: &B,
+    msg: MsgHandle,
+    result: crate::Result<()>,
+    cancel: CancellationToken,
+) {
+    if let Err(ref err) = result {
+        let flow = node.flow().expect("flow");
+        let error_message = err.to_string();
+
+        match flow.handle_error(node, &error_message, Some(msg.clone()), None, cancel.clone()).await {
+            Ok(_) => (),
+            Err(e) => {
+                log::error!("Failed to handle error: {:?}", e);
+            }
+        }
+    }
+
+    // Report the completion
+    node.notify_uow_completed(msg, cancel).await;
+}
+
+/// The opt-in concurrency of the message processing, the `concurrency` and `ordered` properties of the nodes
+/// supporting it, an extension of EdgeLink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub struct NodeConcurrency {
+    /// The maximum number of the messages processed at the same time, `1` means one by one.
+    #[serde(default = "NodeConcurrency::default_limit", rename = "concurrency")]
+    pub limit: usize,
+
+    /// Sends the results in the order of the received messages, instead of the order of completion.
+    #[serde(default)]
+    pub ordered: bool,
+}
+
+impl NodeConcurrency {
+    fn default_limit() -> usize {
+        1
+    }
+
+    pub fn is_concurrent(&self) -> bool {
+        self.limit > 1
+    }
+}
+
+impl Default for NodeConcurrency {
+    fn default() -> Self {
+        Self { limit: Self::default_limit(), ordered: false }
+    }
+}
+
+/// Keeps processing up to `concurrency.limit` messages at the same time until `cancel` was cancelled, the concurrent
+/// counterpart of the `with_uow()` loop.
+///
+/// `proc` returns the envelopes to send instead of sending them, so the ordered mode can hold back the results of
+/// the later messages. All the futures are polled in the calling task, so they may hold `!Send` states like a JS
+/// context.
+pub async fn with_concurrent_uows<'a, B, F, T>(
+    node: &'a B,
+    concurrency: NodeConcurrency,
+    cancel: CancellationToken,
+    proc: F,
+) where
+    B: FlowNodeBehavior,
+    F: Fn(&'a B, MsgHandle) -> T,
+    T: std::future::Future<Output = crate::Result<SmallVec<[Envelope; 4]>>> + 'a,
+{
+    use futures_util::stream::{FuturesOrdered, FuturesUnordered, StreamExt};
+
+    let limit = concurrency.limit.max(1);
+    let start_uow = |msg: MsgHandle| {
+        let processing = proc(node, msg.clone());
+        async move { (msg, processing.await) }
+    };
+    let mut ordered_uows = FuturesOrdered::new();
+    let mut unordered_uows = FuturesUnordered::new();
+
+    loop {
+        let inflight = ordered_uows.len() + unordered_uows.len();
+        let (msg, result) = select! {
+            _ = cancel.cancelled() => break,
+
+            received = node.recv_msg(cancel.clone()), if inflight < limit => {
+                match received {
+                    Ok(msg) if concurrency.ordered => ordered_uows.push_back(start_uow(msg)),
+                    Ok(msg) => unordered_uows.push(start_uow(msg)),
+                    Err(ref err) => {
+                        if let Some(EdgelinkError::TaskCancelled) = err.downcast_ref::<EdgelinkError>() {
+                            break;
+                        }
+                        log::warn!("[{}:{}] {}", node.type_str(), node.name(), err);
+                    }
+                }
+                continue;
+            }
+
+            Some(done) = ordered_uows.next(), if !ordered_uows.is_empty() => done,
+
+            Some(done) = unordered_uows.next(), if !unordered_uows.is_empty() => done,
+        };
+
+        let result = match result {
+            Ok(envelopes) if !envelopes.is_empty() => node.fan_out_many(envelopes, cancel.child_token()).await,
+            Ok(_) => Ok(()),
+            Err(err) => Err(err),
+        };
+        complete_uow(node, msg, result, cancel.clone()).await;
+    }
+}
+
+/// Builds the envelope addressing the output `port` of the node, for the nodes choosing the port of every message.
+///
+/// An out-of-range port drops the message with a warning instead of failing the unit of work.
+pub fn envelope_for_port<B>(node: &B, port: usize, msg: MsgHandle) -> Option<Envelope>
+where
+    B: FlowNodeBehavior + ?Sized,
+{
+    let nports = node.get_node().ports.len();
+    if port < nports {
+        return Some(Envelope { port, msg });
+    }
+    log::warn!("[{}:{}] Dropped the message to port {} out of {} port(s)", node.type_str(), node.name(), port, nports);
+    None
+}
+
+/// Builds the envelope addressing the output port taken from the message property `port_expr`, like `port` for
+/// `msg.port`, see `envelope_for_port()`.
+///
+/// A missing or non-integer port drops the message with a warning too.
+pub async fn envelope_for_msg_port<B>(node: &B, msg: MsgHandle, port_expr: &str) -> Option<Envelope>
+where
+    B: FlowNodeBehavior + ?Sized,
+{
+    let port = {
+        let guard = msg.read().await;
+        guard.get_nav(port_expr).and_then(|x| x.as_u64()).map(|x| x as usize)
+    };
+    match port {
+        Some(port) => envelope_for_port(node, port, msg),
+        None => {
+            log::warn!("[{}:{}] Dropped the message without a valid `msg.{}`", node.type_str(), node.name(), port_expr);
+            None
+        }
+    }
+}
+
 #[async_trait]
 pub trait LinkCallNodeBehavior: Send + Sync + FlowNodeBehavior {
     /// Receive the returning message
@@ -294,3 +688,395 @@ pub trait LinkCallNodeBehavior: Send + Sync + FlowNodeBehavior {
         cancel: CancellationToken,
     ) -> crate::Result<()>;
 }
+
+/// The generic control messages shared by the stateful nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMsgKind {
+    /// `msg.reset`: Clear the state of the topic, or all of it.
+    Reset,
+
+    /// `msg.flush`: Emit the buffered content immediately.
+    Flush,
+}
+
+impl ControlMsgKind {
+    pub const RESET_PROPERTY: &'static str = "reset";
+    pub const FLUSH_PROPERTY: &'static str = "flush";
+
+    /// Recognizes the control message, `reset` takes precedence if both properties exist.
+    pub fn from_msg(msg: &Msg) -> Option<ControlMsgKind> {
+        if msg.contains(Self::RESET_PROPERTY) {
+            Some(ControlMsgKind::Reset)
+        } else if msg.contains(Self::FLUSH_PROPERTY) {
+            Some(ControlMsgKind::Flush)
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+pub trait ControlMsgNodeBehavior: Send + Sync + FlowNodeBehavior {
+    /// Handle the `msg.reset` or `msg.flush` control message
+    async fn handle_control_msg(
+        &self,
+        kind: ControlMsgKind,
+        msg: MsgHandle,
+        cancel: CancellationToken,
+    ) -> crate::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn node_kind_should_display_its_own_label() {
+        assert_eq!(NodeKind::Flow.as_str(), "FlowNode");
+        assert_eq!(NodeKind::Global.as_str(), "GlobalNode");
+        assert_eq!(NodeKind::Flow.to_string(), "FlowNode");
+        assert_eq!(NodeKind::Global.to_string(), "GlobalNode");
+    }
+
+    #[tokio::test]
+    async fn flow_nodes_should_report_their_placement() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "tab"},
+            {"id": "300", "type": "group", "z": "200", "nodes": ["4"]},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "junction", "wires": []},
+            {"id": "4", "z": "200", "g": "300", "type": "junction", "wires": []},
+            {"id": "900", "type": "unknown.global"},
+            {"id": "901", "type": "unknown.global"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let node = |id: &str| engine.find_flow_node_by_id(&id.parse().unwrap()).unwrap();
+
+        let orderings: Vec<usize> = ["1", "2", "3"]
+            .iter()
+            .map(|id| {
+                let node = node(id);
+                assert_eq!(node.flow_id(), "100".parse().unwrap());
+                assert_eq!(node.group_id(), None);
+                node.ordering()
+            })
+            .collect();
+        // The wired nodes are loaded before the nodes sending to them
+        assert_eq!(orderings, vec![2, 1, 0]);
+
+        let grouped = node("4");
+        assert_eq!(grouped.flow_id(), "200".parse().unwrap());
+        assert_eq!(grouped.group_id(), Some("300".parse().unwrap()));
+        assert_eq!(grouped.group().map(|g| g.id()), grouped.group_id());
+        assert_eq!(grouped.ordering(), 0);
+
+        let global_orderings: Vec<usize> = ["900", "901"]
+            .iter()
+            .map(|id| engine.find_global_node_by_id(&id.parse().unwrap()).unwrap().ordering())
+            .collect();
+        assert_eq!(global_orderings, vec![0, 1]);
+    }
+
+    /// Builds the engine with the `engine_toml` in the `[runtime.engine]` section.
+    fn build_engine_with(flows_json: serde_json::Value, engine_toml: &str) -> Engine {
+        let toml = format!(
+            "[runtime.engine]\n{}\n[runtime.context]\ndefault = \"memory\"\n[runtime.context.stores]\n\
+             memory = {{ provider = \"memory\" }}\n",
+            engine_toml
+        );
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fan_out_should_count_the_deep_clones_and_warn_the_clone_storm() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "3", "4", "5", "6", "7"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "test-once"},
+            {"id": "5", "z": "100", "type": "test-once"},
+            {"id": "6", "z": "100", "type": "test-once"},
+            {"id": "7", "z": "100", "type": "test-once"}
+        ]);
+        let engine =
+            build_engine_with(flows_json, "clone_stats = true\nclone_warning_size = 4096\nclone_warning_count = 5");
+
+        let big_msg = Msg::deserialize(json!({"payload": "x".repeat(8192)})).unwrap();
+        let small_msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), big_msg), (ElementId::from(1), small_msg)];
+        let msgs =
+            engine.run_once_with_inject(12, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 12);
+
+        let node = engine.find_flow_node_by_id(&ElementId::from(1)).unwrap();
+        let stats = &node.get_node().clone_stats;
+        assert_eq!(stats.clones(), 10);
+        assert_eq!(stats.large_clones(), 5);
+        assert!(stats.cloned_bytes() > 5 * 8192);
+        assert_eq!(stats.storm_warnings(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fan_out_should_not_count_the_deep_clones_by_default() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), Msg::deserialize(json!({"payload": "foo"})).unwrap())];
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+
+        let node = engine.find_flow_node_by_id(&ElementId::from(1)).unwrap();
+        assert_eq!(node.get_node().clone_stats.clones(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fan_out_should_share_the_bytes_until_mutated() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "3", "4"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let engine = build_engine_with(flows_json, "clone_stats = true");
+        let mut msg = Msg::default();
+        msg["payload"] = Variant::from(vec![0u8; 1024 * 1024]);
+        let msgs_to_inject = vec![(ElementId::from(1), msg)];
+        let mut msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+
+        let shared = |msg: &Msg| match msg["payload"] {
+            Variant::Bytes(ref bytes) => bytes.clone(),
+            _ => panic!("Not a buffer"),
+        };
+        let (first, second, third) = (shared(&msgs[0]), shared(&msgs[1]), shared(&msgs[2]));
+        assert!(SharedBytes::ptr_eq(&first, &second));
+        assert!(SharedBytes::ptr_eq(&first, &third));
+        drop((first, second, third));
+
+        msgs[0]["payload"].as_bytes_mut().unwrap()[0] = 0xff;
+        assert_eq!(msgs[0]["payload"].as_bytes().unwrap()[0], 0xff);
+        assert_eq!(msgs[1]["payload"].as_bytes().unwrap()[0], 0);
+        assert!(!SharedBytes::ptr_eq(&shared(&msgs[0]), &shared(&msgs[1])));
+        assert!(SharedBytes::ptr_eq(&shared(&msgs[1]), &shared(&msgs[2])));
+
+        // The shared buffer is not copied by the clones
+        let node = engine.find_flow_node_by_id(&ElementId::from(1)).unwrap();
+        let stats = &node.get_node().clone_stats;
+        assert_eq!(stats.clones(), 2);
+        assert_eq!(stats.large_clones(), 0);
+        assert!(stats.cloned_bytes() < 1024);
+    }
+
+    #[test]
+    fn clone_stats_should_warn_the_large_clones_within_the_window() {
+        let threshold = CloneWarningThreshold { size: 100, count: 3, window: std::time::Duration::from_secs(10) };
+        let stats = CloneStats::default();
+        let secs = std::time::Duration::from_secs;
+
+        // Spread over more than the window
+        assert_eq!(stats.record(200, 1, secs(0), &threshold), None);
+        assert_eq!(stats.record(200, 1, secs(8), &threshold), None);
+        assert_eq!(stats.record(200, 1, secs(16), &threshold), None);
+        assert_eq!(stats.record(200, 1, secs(30), &threshold), None);
+        assert_eq!(stats.storm_warnings(), 0);
+
+        // The small clones never count
+        assert_eq!(stats.record(50, 5, secs(31), &threshold), None);
+
+        assert_eq!(stats.record(200, 2, secs(32), &threshold), Some(3));
+        assert_eq!(stats.storm_warnings(), 1);
+        assert_eq!(stats.record(200, 1, secs(33), &threshold), None);
+        assert_eq!(stats.clones(), 12);
+        assert_eq!(stats.large_clones(), 7);
+        assert_eq!(stats.cloned_bytes(), 7 * 200 + 5 * 50);
+    }
+
+    /// The lifecycle events of the `test-lifecycle` nodes, as `"{name}:{event}"`.
+    static LIFECYCLE_EVENTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    fn lifecycle_events(prefix: &str) -> Vec<String> {
+        let events = LIFECYCLE_EVENTS.lock().unwrap();
+        events.iter().filter(|x| x.starts_with(prefix)).cloned().collect()
+    }
+
+    #[derive(Debug)]
+    #[edgelink_macro::flow_node("test-lifecycle")]
+    struct LifecycleNode {
+        base: FlowNode,
+        fail_on_starting: bool,
+    }
+
+    impl LifecycleNode {
+        fn build(
+            _flow: &Flow,
+            state: FlowNode,
+            config: &RedFlowNodeConfig,
+        ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+            let fail_on_starting = config.rest.get("failOnStarting").and_then(|x| x.as_bool()).unwrap_or(false);
+            Ok(Box::new(LifecycleNode { base: state, fail_on_starting }))
+        }
+
+        fn record(&self, event: &str) {
+            LIFECYCLE_EVENTS.lock().unwrap().push(format!("{}:{}", self.name(), event));
+        }
+    }
+
+    #[async_trait]
+    impl FlowNodeBehavior for LifecycleNode {
+        fn get_node(&self) -> &FlowNode {
+            &self.base
+        }
+
+        fn on_loaded(&self) -> crate::Result<()> {
+            self.record("loaded");
+            Ok(())
+        }
+
+        async fn on_starting(&self) -> crate::Result<()> {
+            self.record("starting");
+            if self.fail_on_starting {
+                return Err(EdgelinkError::InvalidOperation("cannot start".into()).into());
+            }
+            Ok(())
+        }
+
+        async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+            self.record("running");
+            stop_token.cancelled().await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn lifecycle_hooks_should_fire_in_order() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-lifecycle", "name": "order-a"},
+            {"id": "2", "z": "200", "type": "test-lifecycle", "name": "order-b"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut events = lifecycle_events("order-");
+        events.sort();
+        assert_eq!(events, vec!["order-a:loaded", "order-b:loaded"]);
+
+        engine.start().await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while lifecycle_events("order-").len() < 6 {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        engine.stop().await.unwrap();
+
+        let events = lifecycle_events("order-");
+        let position = |event: &str| events.iter().position(|x| x == event).unwrap();
+        for name in ["order-a", "order-b"] {
+            assert!(position(&format!("{}:starting", name)) > position("order-a:loaded"));
+            assert!(position(&format!("{}:starting", name)) > position("order-b:loaded"));
+            assert!(position(&format!("{}:running", name)) > position(&format!("{}:starting", name)));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn failed_on_starting_should_abort_the_engine_start() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-lifecycle", "name": "fail-a"},
+            {"id": "2", "z": "100", "type": "test-lifecycle", "name": "fail-b", "failOnStarting": true}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let err = engine.start().await.err().unwrap();
+        assert!(format!("{:?}", err).contains("cannot start"), "{:?}", err);
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let events = lifecycle_events("fail-");
+        assert!(events.iter().all(|x| !x.ends_with(":running")), "{:?}", events);
+    }
+
+    #[derive(Debug)]
+    #[edgelink_macro::flow_node("test-msg-port")]
+    struct MsgPortNode {
+        base: FlowNode,
+    }
+
+    impl MsgPortNode {
+        fn build(
+            _flow: &Flow,
+            state: FlowNode,
+            _config: &RedFlowNodeConfig,
+        ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+            Ok(Box::new(MsgPortNode { base: state }))
+        }
+    }
+
+    #[async_trait]
+    impl FlowNodeBehavior for MsgPortNode {
+        fn get_node(&self) -> &FlowNode {
+            &self.base
+        }
+
+        async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+            while !stop_token.is_cancelled() {
+                let cancel = stop_token.child_token();
+                with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                    if let Some(envelope) = envelope_for_msg_port(node, msg, "port").await {
+                        node.fan_out_one(envelope, cancel.child_token()).await?;
+                    }
+                    Ok(())
+                })
+                .await;
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn envelope_for_msg_port_should_route_by_msg_port() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-msg-port", "wires": [["2"], ["3"]]},
+            {"id": "2", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "routed", "pt": "msg", "to": "0", "tot": "num"}], "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "routed", "pt": "msg", "to": "1", "tot": "num"}], "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        // The invalid ports come first, so they would have been delivered before the last valid one
+        let msgs_to_inject_json = json!([
+            ["1", {"topic": "overflow", "port": 5}],
+            ["1", {"topic": "text", "port": "x"}],
+            ["1", {"topic": "missing"}],
+            ["1", {"topic": "a", "port": 0}],
+            ["1", {"topic": "b", "port": 1}],
+            ["1", {"topic": "c", "port": 1}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        let mut routed = msgs
+            .iter()
+            .map(|x| (x["topic"].as_str().unwrap().to_string(), x["routed"].as_i64().unwrap()))
+            .collect::<Vec<_>>();
+        routed.sort();
+        assert_eq!(routed, vec![("a".to_string(), 0), ("b".to_string(), 1), ("c".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,1010 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::jsonata;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+const CUSTOM_GROUP_ID: &str = "_";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum JoinMode {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+
+    #[serde(rename = "custom")]
+    Custom,
+
+    /// Reduces the sequence with a JSONata expression
+    #[serde(rename = "reduce")]
+    Reduce,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum JoinBuild {
+    #[serde(rename = "string")]
+    String,
+
+    #[default]
+    #[serde(rename = "array")]
+    Array,
+
+    #[serde(rename = "object")]
+    Object,
+
+    #[serde(rename = "merged")]
+    Merged,
+
+    #[serde(rename = "buffer")]
+    Buffer,
+}
+
+impl JoinBuild {
+    fn from_parts_type(t: &str) -> Option<JoinBuild> {
+        match t {
+            "string" => Some(JoinBuild::String),
+            "array" => Some(JoinBuild::Array),
+            "object" => Some(JoinBuild::Object),
+            "buffer" => Some(JoinBuild::Buffer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum JoinPropertyType {
+    #[default]
+    #[serde(rename = "msg")]
+    Msg,
+
+    #[serde(rename = "full")]
+    Full,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JoinNodeConfig {
+    #[serde(default)]
+    mode: JoinMode,
+
+    #[serde(default)]
+    build: JoinBuild,
+
+    #[serde(default = "default_config_property")]
+    property: String,
+
+    #[serde(default)]
+    property_type: JoinPropertyType,
+
+    #[serde(default = "default_config_key")]
+    key: String,
+
+    #[serde(default = "default_config_joiner")]
+    joiner: String,
+
+    #[serde(default, deserialize_with = "json::deser::deser_usize_or_zero")]
+    count: usize,
+
+    #[serde(default)]
+    accumulate: bool,
+
+    /// The accumulator of the reduce mode, sees the message as the input and `$A`, `$I`, `$N`
+    #[serde(default)]
+    reduce_exp: String,
+
+    #[serde(default)]
+    reduce_init: String,
+
+    #[serde(default, deserialize_with = "deser_optional_property_type")]
+    reduce_init_type: Option<RedPropertyType>,
+
+    /// Reduces from the last message of the sequence
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_string")]
+    reduce_right: bool,
+
+    /// Fixes up the result of the reduce mode, sees `$A` and `$N`
+    #[serde(default)]
+    reduce_fixup: String,
+}
+
+fn deser_optional_property_type<'de, D>(deserializer: D) -> Result<Option<RedPropertyType>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let jv = serde_json::Value::deserialize(deserializer)?;
+    Ok(RedPropertyType::deserialize(jv).ok())
+}
+
+fn default_config_property() -> String {
+    "payload".to_string()
+}
+
+fn default_config_key() -> String {
+    "topic".to_string()
+}
+
+fn default_config_joiner() -> String {
+    "\\n".to_string()
+}
+
+#[derive(Debug)]
+struct JoinGroup {
+    build: JoinBuild,
+    items: Vec<Variant>,
+    /// The `msg._seq` of the appended items, only used in the "preserve order" mode of the flow.
+    seqs: Vec<Option<u64>>,
+    preserve_order: bool,
+    object: VariantObjectMap,
+    current_count: usize,
+    target_count: usize,
+    /// The `node_message_buffer_max_length` of the engine, `None` for unlimited
+    max_items: Option<usize>,
+    join_char: String,
+    /// The delimiter between the chunks of a buffer
+    join_bytes: Vec<u8>,
+    /// The byte offsets of the chunks of a split buffer by their indices
+    offsets: HashMap<usize, usize>,
+    array_len: usize,
+    property: String,
+    msg: Msg,
+}
+
+impl JoinGroup {
+    fn new(build: JoinBuild, target_count: usize, join_char: String, property: String, msg: Msg) -> Self {
+        Self {
+            build,
+            items: Vec::new(),
+            seqs: Vec::new(),
+            preserve_order: false,
+            object: VariantObjectMap::new(),
+            current_count: 0,
+            target_count,
+            max_items: None,
+            join_bytes: join_char.as_bytes().to_vec(),
+            join_char,
+            offsets: HashMap::new(),
+            array_len: 1,
+            property,
+            msg,
+        }
+    }
+
+    /// Checks the `parts.count` of the group against the `node_message_buffer_max_length` of the engine.
+    fn check_target_count(&self) -> crate::Result<()> {
+        match self.max_items {
+            Some(max_items) if self.target_count > max_items => Err(EdgelinkError::InvalidOperation(format!(
+                "The sequence of {} messages is longer than the limit {} of the join node",
+                self.target_count, max_items
+            ))
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds the item at its `parts.index`, which must be less than the `parts.count`, or the limit of the held
+    /// messages if the count is unknown yet, so a bad index cannot grow the items.
+    fn add_item(&mut self, value: Variant, index: Option<usize>, seq: Option<u64>) -> crate::Result<()> {
+        if let Some(max_items) = self.max_items {
+            if self.current_count >= max_items {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "Too many pending messages in the join node, the limit is {}",
+                    max_items
+                ))
+                .into());
+            }
+        }
+        let limit = if self.target_count > 0 { Some(self.target_count) } else { self.max_items };
+        if let (Some(index), Some(limit)) = (index, limit) {
+            if index >= limit {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "The `msg.parts.index` {} is out of the sequence of {} messages",
+                    index, limit
+                ))
+                .into());
+            }
+        }
+        match index {
+            Some(index) => {
+                if index >= self.items.len() {
+                    self.items.resize(index + 1, Variant::Null);
+                }
+                self.items[index] = value;
+            }
+            None => {
+                self.items.push(value);
+                self.seqs.push(seq);
+            }
+        }
+        self.current_count += 1;
+        Ok(())
+    }
+
+    /// Restores the order of the appended items by their `msg._seq`.
+    fn sort_items_by_seq(&mut self) {
+        let seqs = std::mem::take(&mut self.seqs);
+        if self.preserve_order && seqs.len() == self.items.len() && seqs.iter().all(Option::is_some) {
+            let items = std::mem::take(&mut self.items);
+            let mut pairs = seqs.into_iter().zip(items).collect::<Vec<_>>();
+            pairs.sort_by_key(|x| x.0);
+            self.items = pairs.into_iter().map(|x| x.1).collect();
+        }
+    }
+
+    fn is_completed(&self) -> bool {
+        self.target_count > 0 && self.current_count >= self.target_count
+    }
+
+    /// Assembles the joined message and takes the group's content.
+    fn take_joined_msg(&mut self) -> crate::Result<Msg> {
+        self.sort_items_by_seq();
+        let items = std::mem::take(&mut self.items);
+        let joined = match self.build {
+            JoinBuild::String => {
+                let mut s = String::new();
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        s.push_str(&self.join_char);
+                    }
+                    match item {
+                        Variant::String(x) => s.push_str(x),
+                        Variant::Null => {}
+                        _ => s.push_str(&item.to_string().unwrap_or_default()),
+                    }
+                }
+                Variant::String(s)
+            }
+            JoinBuild::Array if self.array_len > 1 => {
+                // Flatten the chunks produced by the split node
+                let mut flatten = Vec::with_capacity(items.len() * self.array_len);
+                for item in items.into_iter() {
+                    match item {
+                        Variant::Array(mut chunk) => flatten.append(&mut chunk),
+                        _ => flatten.push(item),
+                    }
+                }
+                Variant::Array(flatten)
+            }
+            JoinBuild::Array => Variant::Array(items),
+            JoinBuild::Buffer => Variant::Bytes(self.assemble_buffer(items)?.into()),
+            JoinBuild::Object | JoinBuild::Merged => Variant::Object(std::mem::take(&mut self.object)),
+        };
+        self.current_count = 0;
+
+        let mut msg = self.msg.clone();
+        msg.set_nav_stripped(&self.property, joined, true)?;
+        if let Some(Variant::Object(mut parts)) = msg.remove("parts") {
+            // Restore the parts of the upper level sequence
+            if let Some(parent_parts) = parts.remove("parts") {
+                msg.set("parts".into(), parent_parts);
+            }
+        }
+        msg.remove("complete");
+        Ok(msg)
+    }
+
+    /// Puts the chunks of a split buffer back at their byte offsets.
+    ///
+    /// The chunks must be `parts.count` and follow each other in the order of their indices, either directly or
+    /// with the delimiter in between, so a gap or an overlap is an error and the buffer never grows past the chunks
+    /// and their delimiters. Without the offsets of all chunks, they are concatenated with the delimiter in between.
+    fn assemble_buffer(&mut self, items: Vec<Variant>) -> crate::Result<Vec<u8>> {
+        let offsets = std::mem::take(&mut self.offsets);
+        let chunks = items
+            .into_iter()
+            .map(|x| match x {
+                Variant::Bytes(bytes) => bytes.into_vec(),
+                Variant::Null => Vec::new(),
+                other => other.to_bytes().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        let total = chunks
+            .iter()
+            .map(Vec::len)
+            .fold(0usize, usize::saturating_add)
+            .saturating_add(chunks.len().saturating_mul(self.join_bytes.len()));
+        let mut buffer = Vec::with_capacity(total);
+
+        if !chunks.is_empty() && (0..chunks.len()).all(|i| offsets.contains_key(&i)) {
+            if self.target_count > 0 && chunks.len() != self.target_count {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "Got {} chunks of the buffer, `msg.parts.count` is {}",
+                    chunks.len(),
+                    self.target_count
+                ))
+                .into());
+            }
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let offset = offsets[&i];
+                match offset.checked_sub(buffer.len()) {
+                    Some(0) => {}
+                    Some(gap) if i > 0 && gap == self.join_bytes.len() => buffer.extend_from_slice(&self.join_bytes),
+                    Some(gap) => {
+                        return Err(EdgelinkError::InvalidOperation(format!(
+                            "A gap of {} bytes before the chunk #{} of the buffer at the offset {}",
+                            gap, i, offset
+                        ))
+                        .into());
+                    }
+                    None => {
+                        return Err(EdgelinkError::InvalidOperation(format!(
+                            "The chunk #{} of the buffer at the offset {} overlaps the previous one ending at {}",
+                            i,
+                            offset,
+                            buffer.len()
+                        ))
+                        .into());
+                    }
+                }
+                buffer.extend_from_slice(&chunk);
+            }
+        } else {
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                if i > 0 {
+                    buffer.extend_from_slice(&self.join_bytes);
+                }
+                buffer.extend_from_slice(&chunk);
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+/// The messages of a sequence held by the reduce mode, keyed by `parts.id`.
+#[derive(Debug, Default)]
+struct PendingReduce {
+    count: Option<usize>,
+    msgs: Vec<Msg>,
+}
+
+#[derive(Debug)]
+#[flow_node("join")]
+struct JoinNode {
+    base: FlowNode,
+    config: JoinNodeConfig,
+    preserve_order: bool,
+    inflight: Mutex<HashMap<String, JoinGroup>>,
+    reduce_exp: Option<jsonata::Expression>,
+    reduce_fixup: Option<jsonata::Expression>,
+    reductions: Mutex<HashMap<String, PendingReduce>>,
+}
+
+impl JoinNode {
+    fn build(flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let mut join_config = JoinNodeConfig::deserialize(&config.rest)?;
+        join_config.joiner = super::unescape_delimiter(&join_config.joiner);
+        let parse = |source: &str| match source.trim() {
+            "" => Ok(None),
+            source => jsonata::Expression::parse(source)
+                .map(Some)
+                .map_err(|e| EdgelinkError::BadFlowsJson(format!("Invalid reduce expression: {}", e))),
+        };
+        let (reduce_exp, reduce_fixup) = if join_config.mode == JoinMode::Reduce {
+            let reduce_exp = parse(&join_config.reduce_exp)?
+                .ok_or(EdgelinkError::BadFlowsJson("The reduce mode of the join node needs an expression".into()))?;
+            (Some(reduce_exp), parse(&join_config.reduce_fixup)?)
+        } else {
+            (None, None)
+        };
+        let node = JoinNode {
+            base: state,
+            config: join_config,
+            preserve_order: flow.is_order_preserved(),
+            inflight: Mutex::new(HashMap::new()),
+            reduce_exp,
+            reduce_fixup,
+            reductions: Mutex::new(HashMap::new()),
+        };
+        Ok(Box::new(node))
+    }
+
+    /// Returns the joined messages that are ready to send
+    async fn join_msg(&self, msg: &Msg) -> crate::Result<Vec<Msg>> {
+        match self.config.mode {
+            JoinMode::Custom => self.join_msg_custom(msg).await,
+            JoinMode::Auto => self.join_msg_auto(msg).await,
+            JoinMode::Reduce => self.join_msg_reduce(msg).await,
+        }
+    }
+
+    /// Holds the messages of a sequence until `parts.count` of them arrive, then reduces them in the order of
+    /// `parts.index` to a new message.
+    ///
+    /// Like Node-RED, the accumulator sees the message as the input, the accumulated value as `$A`, the
+    /// `parts.index` as `$I` and the number of messages as `$N`; the fixup sees `$A` and `$N`.
+    async fn join_msg_reduce(&self, msg: &Msg) -> crate::Result<Vec<Msg>> {
+        let (id, count) = match msg.get("parts").and_then(|x| x.as_object()) {
+            Some(parts) if parts.contains_key("index") => match parts.get("id").and_then(|x| x.to_string().ok()) {
+                Some(id) => (id, parts.get("count").and_then(|x| x.as_u64()).map(|x| x as usize)),
+                None => return Err(Self::missing_parts_error()),
+            },
+            _ => return Err(Self::missing_parts_error()),
+        };
+
+        let max_held = self.engine().and_then(|x| x.node_message_buffer_max_length());
+        let completed = {
+            let mut reductions = self.reductions.lock().await;
+            if let Some(max_held) = max_held {
+                let held: usize = reductions.values().map(|x| x.msgs.len()).sum();
+                if held >= max_held {
+                    reductions.clear();
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Too many pending messages in the join node, the limit is {}",
+                        max_held
+                    ))
+                    .into());
+                }
+            }
+            let pending = reductions.entry(id.clone()).or_default();
+            pending.count = pending.count.or(count);
+            pending.msgs.push(msg.clone());
+            match pending.count {
+                Some(count) if pending.msgs.len() >= count => reductions.remove(&id),
+                _ => None,
+            }
+        };
+        match completed {
+            Some(completed) => Ok(vec![self.reduce(completed.msgs, msg).await?]),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn missing_parts_error() -> anyhow::Error {
+        EdgelinkError::InvalidOperation("Message missing msg.parts property - cannot join in 'reduce' mode".into())
+            .into()
+    }
+
+    async fn reduce(&self, mut msgs: Vec<Msg>, last_msg: &Msg) -> crate::Result<Msg> {
+        let exp = self.reduce_exp.as_ref().ok_or(EdgelinkError::InvalidOperation("No reduce expression".into()))?;
+        let index_of = |msg: &Msg| msg.get_nav("parts.index").and_then(|x| x.as_u64()).unwrap_or(0);
+        msgs.sort_by_key(index_of);
+        if self.config.reduce_right {
+            msgs.reverse();
+        }
+        let count = Variant::from(msgs.len() as u64);
+
+        let mut accumulator = match self.config.reduce_init_type {
+            Some(init_type) if !self.config.reduce_init.is_empty() => Some(
+                eval::evaluate_node_property(&self.config.reduce_init, init_type, Some(self), None, Some(last_msg))
+                    .await?,
+            ),
+            _ => None,
+        };
+        let context_values = eval::read_jsonata_context(exp, Some(self), None, Some(last_msg)).await?;
+        for msg in msgs.iter() {
+            let mut variables = vec![("I", Variant::from(index_of(msg))), ("N", count.clone())];
+            variables.extend(accumulator.take().map(|x| ("A", x)));
+            accumulator = eval::evaluate_jsonata_expression(
+                exp,
+                msg.as_variant(),
+                &variables,
+                &context_values,
+                Some(self),
+                None,
+            )?;
+        }
+
+        if let Some(fixup) = self.reduce_fixup.as_ref() {
+            let context_values = eval::read_jsonata_context(fixup, Some(self), None, Some(last_msg)).await?;
+            let mut variables = vec![("N", count)];
+            variables.extend(accumulator.take().map(|x| ("A", x)));
+            accumulator = eval::evaluate_jsonata_expression(
+                fixup,
+                &Variant::empty_object(),
+                &variables,
+                &context_values,
+                Some(self),
+                None,
+            )?;
+        }
+
+        let mut reduced = Msg::default();
+        reduced.set(wellknown::PAYLOAD_PROPERTY.into(), accumulator.unwrap_or(Variant::Null));
+        Ok(reduced)
+    }
+
+    async fn join_msg_custom(&self, msg: &Msg) -> crate::Result<Vec<Msg>> {
+        let property = match self.config.property_type {
+            JoinPropertyType::Msg => msg.get_nav_stripped(&self.config.property).cloned(),
+            JoinPropertyType::Full => Some(msg.as_variant().clone()),
+        };
+        let is_complete = msg.contains("complete");
+        let max_items = self.engine().and_then(|x| x.node_message_buffer_max_length());
+
+        let mut inflight = self.inflight.lock().await;
+        let group = inflight.entry(CUSTOM_GROUP_ID.to_string()).or_insert_with(|| {
+            let mut group = JoinGroup::new(
+                self.config.build,
+                self.config.count,
+                self.config.joiner.clone(),
+                self.config.property.clone(),
+                msg.clone(),
+            );
+            group.preserve_order = self.preserve_order;
+            group.max_items = max_items;
+            group
+        });
+        if group.target_count == 0 {
+            group.target_count = msg.get_nav("parts.count").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+        }
+        if let Err(e) = group.check_target_count() {
+            inflight.remove(CUSTOM_GROUP_ID);
+            return Err(e);
+        }
+
+        if let Some(property) = property {
+            match self.config.build {
+                JoinBuild::Object => {
+                    let key = msg.get_nav_stripped(&self.config.key).and_then(|x| x.to_string().ok());
+                    if let Some(key) = key {
+                        group.object.insert(key.into(), property);
+                        group.current_count = group.object.len();
+                    }
+                }
+                JoinBuild::Merged => match property {
+                    Variant::Object(map) => {
+                        group.object.extend(map);
+                        group.current_count = group.object.len();
+                    }
+                    _ if !is_complete => {
+                        log::warn!("[join:{}] Cannot merge non-object types", self.name());
+                    }
+                    _ => {}
+                },
+                _ => {
+                    let index = msg
+                        .get_nav("parts.index")
+                        .and_then(|x| x.as_u64())
+                        .map(|x| x as usize)
+                        .filter(|_| matches!(self.config.build, JoinBuild::Array | JoinBuild::Buffer));
+                    let seq = msg.get(wellknown::MSG_SEQ_PROPERTY).and_then(|x| x.as_u64());
+                    group.add_item(property, index, seq)?;
+                }
+            }
+        }
+
+        // Keep the latest properties
+        Self::merge_msg(&mut group.msg, msg);
+
+        if group.is_completed() || is_complete {
+            self.complete_group(&mut inflight, CUSTOM_GROUP_ID, is_complete).map(|x| vec![x])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    async fn join_msg_auto(&self, msg: &Msg) -> crate::Result<Vec<Msg>> {
+        let parts = match msg.get("parts").and_then(|x| x.as_object()) {
+            Some(parts) if parts.contains_key("id") && parts.contains_key("index") => parts,
+            _ => {
+                return Err(EdgelinkError::InvalidOperation(
+                    "Message missing msg.parts property - cannot join in 'auto' mode".into(),
+                )
+                .into())
+            }
+        };
+        let group_id = parts["id"].to_string()?;
+        let build = parts.get("type").and_then(|x| x.as_str()).and_then(JoinBuild::from_parts_type).ok_or(
+            EdgelinkError::InvalidOperation("Unsupported `msg.parts.type` - cannot join in 'auto' mode".into()),
+        )?;
+        let property_name = parts.get("property").and_then(|x| x.as_str()).unwrap_or("payload").to_string();
+        let property = msg.get_nav_stripped(&property_name).cloned().unwrap_or_default();
+        let index = parts.get("index").and_then(|x| x.as_u64()).map(|x| x as usize);
+        let is_complete = msg.contains("complete");
+        let max_items = self.engine().and_then(|x| x.node_message_buffer_max_length());
+
+        let mut inflight = self.inflight.lock().await;
+        let group = inflight.entry(group_id.clone()).or_insert_with(|| {
+            let target_count = parts.get("count").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+            let join_char = parts.get("ch").and_then(|x| x.as_str()).unwrap_or_default().to_string();
+            let mut group = JoinGroup::new(build, target_count, join_char, property_name, msg.clone());
+            group.array_len = parts.get("len").and_then(|x| x.as_u64()).unwrap_or(1) as usize;
+            if let Some(ch) = parts.get("ch").and_then(|x| x.to_bytes()) {
+                group.join_bytes = ch;
+            }
+            group.max_items = max_items;
+            group
+        });
+        if let Err(e) = group.check_target_count() {
+            inflight.remove(&group_id);
+            return Err(e);
+        }
+
+        match build {
+            JoinBuild::Object => {
+                if let Some(key) = parts.get("key").and_then(|x| x.to_string().ok()) {
+                    group.object.insert(key.into(), property);
+                    group.current_count = group.object.len();
+                }
+            }
+            JoinBuild::Buffer => {
+                if let (Some(index), Some(offset)) = (index, parts.get("offset").and_then(|x| x.as_u64())) {
+                    group.offsets.insert(index, usize::try_from(offset).unwrap_or(usize::MAX));
+                }
+                group.add_item(property, index, None)?;
+            }
+            _ => group.add_item(property, index, None)?,
+        }
+        Self::merge_msg(&mut group.msg, msg);
+
+        if group.is_completed() || is_complete {
+            self.complete_group(&mut inflight, &group_id, is_complete).map(|x| vec![x])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn complete_group(
+        &self,
+        inflight: &mut HashMap<String, JoinGroup>,
+        group_id: &str,
+        is_complete: bool,
+    ) -> crate::Result<Msg> {
+        if !self.config.accumulate || is_complete {
+            let mut group = inflight.remove(group_id).ok_or(EdgelinkError::InvalidOperation("No group".into()))?;
+            group.take_joined_msg()
+        } else {
+            let group = inflight.get_mut(group_id).ok_or(EdgelinkError::InvalidOperation("No group".into()))?;
+            let items = group.items.clone();
+            let seqs = group.seqs.clone();
+            let object = group.object.clone();
+            let offsets = group.offsets.clone();
+            let current_count = group.current_count;
+            let joined = group.take_joined_msg();
+            group.items = items;
+            group.seqs = seqs;
+            group.object = object;
+            group.offsets = offsets;
+            group.current_count = current_count;
+            joined
+        }
+    }
+
+    fn merge_msg(target: &mut Msg, msg: &Msg) {
+        for (k, v) in msg.as_variant_object().iter() {
+            target.set(k.to_string(), v.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl ControlMsgNodeBehavior for JoinNode {
+    async fn handle_control_msg(
+        &self,
+        kind: ControlMsgKind,
+        msg: MsgHandle,
+        cancel: CancellationToken,
+    ) -> crate::Result<()> {
+        let group_id = {
+            let msg_guard = msg.read().await;
+            match self.config.mode {
+                JoinMode::Custom => Some(CUSTOM_GROUP_ID.to_string()),
+                JoinMode::Auto | JoinMode::Reduce => msg_guard.get_nav("parts.id").and_then(|x| x.to_string().ok()),
+            }
+        };
+        match kind {
+            ControlMsgKind::Reset => {
+                let mut inflight = self.inflight.lock().await;
+                let mut reductions = self.reductions.lock().await;
+                match group_id {
+                    Some(group_id) => {
+                        inflight.remove(&group_id);
+                        reductions.remove(&group_id);
+                    }
+                    None => {
+                        inflight.clear();
+                        reductions.clear();
+                    }
+                }
+                Ok(())
+            }
+            ControlMsgKind::Flush => {
+                let joined_msgs = {
+                    let mut inflight = self.inflight.lock().await;
+                    let group_ids = match group_id {
+                        Some(group_id) if inflight.contains_key(&group_id) => vec![group_id],
+                        Some(_) => Vec::new(),
+                        None => inflight.keys().cloned().collect(),
+                    };
+                    let mut joined_msgs = Vec::with_capacity(group_ids.len());
+                    for group_id in group_ids.iter() {
+                        joined_msgs.push(self.complete_group(&mut inflight, group_id, true)?);
+                    }
+                    joined_msgs
+                };
+                for joined in joined_msgs.into_iter() {
+                    self.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(joined) }, cancel.child_token()).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for JoinNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let control = ControlMsgKind::from_msg(&*msg.read().await);
+                if let Some(kind) = control {
+                    return node.handle_control_msg(kind, msg, cancel.child_token()).await;
+                }
+
+                let joined_msgs = {
+                    let msg_guard = msg.read().await;
+                    node.join_msg(&msg_guard).await?
+                };
+                for joined in joined_msgs.into_iter() {
+                    node.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(joined) }, cancel.child_token()).await?;
+                }
+                Ok(())
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_join_split_msgs_in_auto_mode() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "a\nb\nc", "topic": "foo"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_str(), Some("a\nb\nc"));
+        assert_eq!(msgs[0]["topic"].as_str(), Some("foo"));
+        assert!(!msgs[0].contains("parts"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_rejoin_split_buffer_exactly() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "splt": "256", "spltType": "len", "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let bytes = (0..1000).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+        let mut msg = Msg::default();
+        msg.set(wellknown::PAYLOAD_PROPERTY.into(), Variant::Bytes(bytes.clone().into()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(5, std::time::Duration::from_secs_f64(0.4), vec![(ElementId::from(1), msg)])
+            .await
+            .unwrap();
+        assert_eq!(msgs.len(), 5);
+
+        let (chunks, joined): (Vec<_>, Vec<_>) = msgs.iter().partition(|x| x.contains("parts"));
+        let sizes = chunks.iter().map(|x| x["payload"].as_bytes().unwrap().len()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![256, 256, 256, 232]);
+        let offsets = chunks.iter().map(|x| x["parts"].as_object().unwrap()["offset"].as_u64().unwrap());
+        assert_eq!(offsets.collect::<Vec<_>>(), vec![0, 256, 512, 768]);
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["payload"], Variant::Bytes(bytes.into()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_rejoin_buffer_split_by_delimiter() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "splt": "[13, 10]", "spltType": "bin", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let bytes = b"first\r\n\r\nthe third one\r\n\xff\x00".to_vec();
+        let mut msg = Msg::default();
+        msg.set(wellknown::PAYLOAD_PROPERTY.into(), Variant::Bytes(bytes.clone().into()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), vec![(ElementId::from(1), msg)])
+            .await
+            .unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::Bytes(bytes.into()));
+    }
+
+    #[test]
+    fn assemble_buffer_should_reject_the_gaps_and_the_overlaps() {
+        let assemble = |offsets: &[usize], count: usize| {
+            let mut group = JoinGroup::new(JoinBuild::Buffer, count, ",".into(), "payload".into(), Msg::default());
+            group.offsets = offsets.iter().copied().enumerate().collect();
+            let items = vec![Variant::Bytes(b"ab".to_vec().into()), Variant::Bytes(b"cd".to_vec().into())];
+            group.assemble_buffer(items)
+        };
+        assert_eq!(assemble(&[0, 2], 2).unwrap(), b"abcd");
+        assert_eq!(assemble(&[0, 3], 2).unwrap(), b"ab,cd");
+        assert!(assemble(&[0, 5], 2).is_err());
+        assert!(assemble(&[1, 3], 2).is_err());
+        assert!(assemble(&[0, 1], 2).is_err());
+        assert!(assemble(&[0, usize::MAX], 2).is_err());
+        assert!(assemble(&[0, 2], 3).is_err());
+    }
+
+    #[test]
+    fn add_item_should_keep_the_index_in_the_sequence() {
+        let mut group = JoinGroup::new(JoinBuild::Array, 3, ",".into(), "payload".into(), Msg::default());
+        assert!(group.add_item("a".into(), Some(2), None).is_ok());
+        assert!(group.add_item("b".into(), Some(3), None).is_err());
+        assert!(group.add_item("c".into(), Some(usize::MAX), None).is_err());
+        assert_eq!(group.items.len(), 3);
+
+        // Without `parts.count`, the index and the number of the items are capped by the limit of the engine
+        let mut group = JoinGroup::new(JoinBuild::Array, 0, ",".into(), "payload".into(), Msg::default());
+        group.max_items = Some(2);
+        assert!(group.add_item("a".into(), Some(1_000_000), None).is_err());
+        assert!(group.add_item("a".into(), Some(1), None).is_ok());
+        assert!(group.add_item("b".into(), None, None).is_ok());
+        assert!(group.add_item("c".into(), None, None).is_err());
+
+        group.target_count = 3;
+        assert!(group.check_target_count().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_reset_should_clear_the_buffer() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "array", "count": 2,
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "a"}],
+            ["1", {"reset": true}],
+            ["1", {"payload": "b"}],
+            ["1", {"payload": "c"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::Array(vec!["b".into(), "c".into()]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_flush_should_send_the_buffer() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "string", "joiner": ",",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": "b"}],
+            ["1", {"flush": true}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"].as_str(), Some("a,b"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_restore_the_order_of_reconverged_msgs_in_ordered_flow() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab", "preserveOrder": true},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "function", "wires": [["4"]],
+                "func": "await new Promise(r => setTimeout(r, (10 - msg.payload) * 5)); return msg;"},
+            {"id": "3", "z": "100", "type": "junction", "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "join", "mode": "custom", "build": "array", "count": 20,
+                "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = (0..10).map(|i| (ElementId::from(1), Msg::deserialize(json!({"payload": i})).unwrap()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(1, std::time::Duration::from_secs_f64(2.0), msgs_to_inject.collect())
+            .await
+            .unwrap();
+        assert_eq!(msgs.len(), 1);
+        let expected = (0..10).flat_map(|i| [i as f64, i as f64]).collect::<Vec<_>>();
+        let payload = msgs[0]["payload"].as_array().unwrap().iter().map(|x| x.as_f64().unwrap()).collect::<Vec<_>>();
+        assert_eq!(payload, expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_sum_a_sequence_in_reduce_mode() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "reduce", "reduceExp": "$A + payload",
+                "reduceInit": "0", "reduceInitType": "num", "reduceRight": false, "reduceFixup": "",
+                "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [1, 2, 3, 4.5]}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from(10.5));
+        assert!(!msgs[0].contains("parts"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_reduce_right_with_the_index_count_and_fixup() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "reduce",
+                "reduceExp": "$A & payload & $string($I) & '/' & $string($N) & ' '",
+                "reduceInit": "", "reduceInitType": "str", "reduceRight": true, "reduceFixup": "$trim($A) & '!'",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        // Out of order, the sequence is complete once `parts.count` messages arrive
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "b", "parts": {"id": "x", "index": 1, "count": 3}}],
+            ["1", {"payload": "a", "parts": {"id": "x", "index": 0, "count": 3}}],
+            ["1", {"payload": "c", "parts": {"id": "x", "index": 2, "count": 3}}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from("c2/3 b1/3 a0/3!"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_report_the_msgs_out_of_sequence_in_reduce_mode() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "2", "z": "100", "type": "join", "mode": "reduce", "reduceExp": "$A + payload",
+                "reduceInit": "0", "reduceInitType": "num", "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "catch", "scope": ["2"], "uncaught": false, "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["2", {"payload": 1}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let message = msgs[0].get_nav("error.message").and_then(|x| x.as_str()).unwrap();
+        assert!(message.contains("cannot join in 'reduce' mode"));
+    }
+}
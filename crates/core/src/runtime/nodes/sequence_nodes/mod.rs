@@ -0,0 +1,13 @@
+mod join;
+mod sort;
+mod split;
+
+/// Unescapes the control characters in the delimiters of the `split` and `join` nodes, like `\n` and `\t`.
+fn unescape_delimiter(s: &str) -> String {
+    s.replace("\\n", "\n")
+        .replace("\\r", "\r")
+        .replace("\\t", "\t")
+        .replace("\\e", "\x1b")
+        .replace("\\f", "\x0c")
+        .replace("\\0", "\0")
+}
@@ -0,0 +1,391 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use smallvec::SmallVec;
+use tokio::sync::Mutex;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::jsonata;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum SortTargetType {
+    /// Sorts the array in a property of the message
+    #[default]
+    #[serde(rename = "msg")]
+    Msg,
+
+    /// Sorts the messages of a sequence by their `parts.index`
+    #[serde(rename = "seq")]
+    Seq,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum SortKeyType {
+    /// The elements of the array themselves
+    #[serde(rename = "elem")]
+    Elem,
+
+    /// A property of the messages of a sequence
+    #[default]
+    #[serde(rename = "msg")]
+    Msg,
+
+    #[serde(rename = "jsonata")]
+    Jsonata,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum SortOrder {
+    #[default]
+    #[serde(rename = "ascending")]
+    Ascending,
+
+    #[serde(rename = "descending")]
+    Descending,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SortNodeConfig {
+    #[serde(default = "default_config_payload")]
+    target: String,
+
+    #[serde(default)]
+    target_type: SortTargetType,
+
+    #[serde(default = "default_config_payload")]
+    msg_key: String,
+
+    #[serde(default = "default_config_msg_key_type")]
+    msg_key_type: SortKeyType,
+
+    #[serde(default = "default_config_payload")]
+    seq_key: String,
+
+    #[serde(default)]
+    seq_key_type: SortKeyType,
+
+    #[serde(default)]
+    order: SortOrder,
+
+    /// Compares the keys as numbers
+    #[serde(default, rename = "as_num", deserialize_with = "json::deser::deser_bool_or_string")]
+    as_num: bool,
+}
+
+fn default_config_payload() -> String {
+    "payload".to_string()
+}
+
+fn default_config_msg_key_type() -> SortKeyType {
+    SortKeyType::Elem
+}
+
+#[derive(Debug, Default)]
+struct PendingSequence {
+    count: Option<usize>,
+    msgs: Vec<MsgHandle>,
+}
+
+/// Sorts an array in a message, or the messages of a sequence, by the elements, a property or a JSONata
+/// expression.
+///
+/// The sort is stable, the items with the equal keys or with the keys that cannot be compared keep their order.
+#[derive(Debug)]
+#[flow_node("sort")]
+struct SortNode {
+    base: FlowNode,
+    config: SortNodeConfig,
+    /// The parsed key expression when the key type is `jsonata`
+    key_exp: Option<jsonata::Expression>,
+    sequences: Mutex<HashMap<String, PendingSequence>>,
+}
+
+impl SortNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let sort_config = SortNodeConfig::deserialize(&config.rest)?;
+        let (key, key_type) = match sort_config.target_type {
+            SortTargetType::Msg => (&sort_config.msg_key, sort_config.msg_key_type),
+            SortTargetType::Seq => (&sort_config.seq_key, sort_config.seq_key_type),
+        };
+        let key_exp = match key_type {
+            SortKeyType::Jsonata => Some(
+                jsonata::Expression::parse(key)
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Invalid key expression: {}", e)))?,
+            ),
+            _ => None,
+        };
+        let node = SortNode { base: state, config: sort_config, key_exp, sequences: Mutex::new(HashMap::new()) };
+        Ok(Box::new(node))
+    }
+
+    /// Evaluates the key expression for every item, the first error fails the whole sort.
+    async fn eval_keys(&self, expr: &jsonata::Expression, items: &[Variant], msg: &Msg) -> crate::Result<Vec<Variant>> {
+        let context_values = eval::read_jsonata_context(expr, Some(self), None, Some(msg)).await?;
+        let mut keys = Vec::with_capacity(items.len());
+        for item in items.iter() {
+            let key = eval::evaluate_jsonata_expression(expr, item, &[], &context_values, Some(self), None)?;
+            keys.push(key.unwrap_or(Variant::Null));
+        }
+        Ok(keys)
+    }
+
+    fn compare_keys(&self, a: &Variant, b: &Variant) -> Ordering {
+        let ordering = if self.config.as_num {
+            match (predicate::to_js_number(a), predicate::to_js_number(b)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => None,
+            }
+        } else {
+            predicate::compare(a, b)
+        };
+        let ordering = ordering.unwrap_or(Ordering::Equal);
+        match self.config.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+
+    /// Returns the permutation that sorts the keys.
+    fn sorted_indices(&self, keys: &[Variant]) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..keys.len()).collect();
+        // `sort_by` is stable
+        indices.sort_by(|a, b| self.compare_keys(&keys[*a], &keys[*b]));
+        indices
+    }
+
+    async fn sort_msg(&self, msg: &mut Msg) -> crate::Result<()> {
+        let items = match msg.get_nav_stripped(&self.config.target) {
+            Some(Variant::Array(items)) => items.clone(),
+            // Like Node-RED, the messages without an array are sent as they are
+            _ => return Ok(()),
+        };
+        let keys = match &self.key_exp {
+            Some(expr) => self.eval_keys(expr, &items, msg).await?,
+            None => items.clone(),
+        };
+        let mut items: Vec<Option<Variant>> = items.into_iter().map(Some).collect();
+        let sorted = self.sorted_indices(&keys).into_iter().filter_map(|i| items[i].take()).collect();
+        msg.set_nav_stripped(&self.config.target, Variant::Array(sorted), false)?;
+        Ok(())
+    }
+
+    /// Holds the message of a sequence, returns the renumbered messages once the sequence is complete.
+    ///
+    /// All the pending sequences are dropped with an error once the held messages exceed
+    /// `node_message_buffer_max_length` of the engine. A completed sequence is dropped with an error if any of its
+    /// keys cannot be evaluated.
+    async fn sort_seq(&self, msg: MsgHandle) -> crate::Result<Vec<MsgHandle>> {
+        let (id, count) = {
+            let msg_guard = msg.read().await;
+            let parts = match msg_guard.get("parts").and_then(|x| x.as_object()) {
+                Some(parts) if parts.contains_key("index") => parts,
+                // Like Node-RED, the messages out of any sequence are dropped
+                _ => return Ok(Vec::new()),
+            };
+            match parts.get("id").and_then(|x| x.to_string().ok()) {
+                Some(id) => (id, parts.get("count").and_then(|x| x.as_u64()).map(|x| x as usize)),
+                None => return Ok(Vec::new()),
+            }
+        };
+
+        let max_held = self.engine().and_then(|x| x.node_message_buffer_max_length());
+        let completed = {
+            let mut sequences = self.sequences.lock().await;
+            if let Some(max_held) = max_held {
+                let held: usize = sequences.values().map(|x| x.msgs.len()).sum();
+                if held >= max_held {
+                    sequences.clear();
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Too many pending messages in the sort node, the limit is {}",
+                        max_held
+                    ))
+                    .into());
+                }
+            }
+            let pending = sequences.entry(id.clone()).or_default();
+            pending.count = pending.count.or(count);
+            pending.msgs.push(msg);
+            match pending.count {
+                Some(count) if pending.msgs.len() >= count => sequences.remove(&id),
+                _ => None,
+            }
+        };
+        let completed = match completed {
+            Some(completed) => completed,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut snapshots = Vec::with_capacity(completed.msgs.len());
+        for msg in completed.msgs.iter() {
+            snapshots.push(msg.read().await.clone());
+        }
+        let keys = match &self.key_exp {
+            Some(expr) => {
+                let inputs: Vec<Variant> = snapshots.iter().map(|x| x.as_variant().clone()).collect();
+                self.eval_keys(expr, &inputs, &snapshots[0]).await?
+            }
+            None => snapshots
+                .iter()
+                .map(|x| x.get_nav_stripped(&self.config.seq_key).cloned().unwrap_or_default())
+                .collect(),
+        };
+
+        let mut msgs: Vec<Option<MsgHandle>> = completed.msgs.into_iter().map(Some).collect();
+        let mut sorted = Vec::with_capacity(msgs.len());
+        for (new_index, i) in self.sorted_indices(&keys).into_iter().enumerate() {
+            let msg = msgs[i].take().expect("a permutation");
+            if let Some(parts) = msg.write().await.get_mut("parts").and_then(|x| x.as_object_mut()) {
+                parts.insert("index".into(), Variant::from(new_index as u64));
+            }
+            sorted.push(msg);
+        }
+        Ok(sorted)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SortNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                match node.config.target_type {
+                    SortTargetType::Msg => {
+                        {
+                            let mut msg_guard = msg.write().await;
+                            node.sort_msg(&mut msg_guard).await?;
+                        }
+                        node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+                    }
+                    SortTargetType::Seq => {
+                        let envelopes: SmallVec<[Envelope; 4]> =
+                            node.sort_seq(msg).await?.into_iter().map(|msg| Envelope { port: 0, msg }).collect();
+                        if envelopes.is_empty() {
+                            return Ok(());
+                        }
+                        node.fan_out_many(envelopes, cancel.child_token()).await
+                    }
+                }
+            })
+            .await;
+        }
+        // The incomplete sequences will never be completed by the next run
+        self.sequences.lock().await.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_sort_the_array_by_a_jsonata_key() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "target": "payload", "targetType": "msg",
+                "msgKey": "$.a + $.b", "msgKeyType": "jsonata", "order": "ascending", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [
+                {"a": 3, "b": 3, "id": "x"},
+                {"a": 1, "b": 1, "id": "y"},
+                {"a": 5, "b": 1, "id": "z"},
+                {"a": 0, "b": 2, "id": "w"}
+            ]}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let ids = msgs[0]["payload"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x.get_nav("id", &[]).unwrap().as_str().unwrap())
+            .collect::<Vec<_>>();
+        // The equal keys keep their order
+        assert_eq!(ids, vec!["y", "w", "x", "z"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_sort_the_elements_as_numbers_descending() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "order": "descending", "as_num": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": ["2", "10", 1]}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!(["10", "2", 1])));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_sort_the_msgs_of_a_sequence_by_a_jsonata_key() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sort", "targetType": "seq", "seqKey": "payload.a + payload.b",
+                "seqKeyType": "jsonata", "order": "descending", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "join", "mode": "auto", "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [{"a": 1, "b": 1}, {"a": 2, "b": 3}, {"a": 0, "b": 2}, {"a": 4, "b": 0}]}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(
+            msgs[0]["payload"],
+            Variant::from(json!([{"a": 2, "b": 3}, {"a": 4, "b": 0}, {"a": 1, "b": 1}, {"a": 0, "b": 2}]))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_report_the_key_errors_once() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "msgKey": "$.a + 1", "msgKeyType": "jsonata", "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "catch", "scope": null, "uncaught": false, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [{"a": "x"}, {"a": "y"}, {"a": 1}], "topic": "bad"}],
+            ["1", {"payload": [{"a": 2}, {"a": 1}], "topic": "good"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        // Only one error for the whole array, and the unsorted message is not sent
+        let (errors, sorted): (Vec<_>, Vec<_>) = msgs.iter().partition(|x| x.contains("error"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["topic"].as_str(), Some("bad"));
+        assert_eq!(sorted[0]["payload"], Variant::from(json!([{"a": 1}, {"a": 2}])));
+    }
+}
@@ -0,0 +1,424 @@
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum SplitType {
+    #[default]
+    #[serde(rename = "str")]
+    Str,
+
+    #[serde(rename = "bin")]
+    Bin,
+
+    #[serde(rename = "len")]
+    Len,
+
+    #[serde(rename = "re")]
+    Regexp,
+}
+
+#[derive(Debug, Deserialize)]
+struct SplitNodeConfig {
+    #[serde(default = "default_config_splt")]
+    splt: String,
+
+    #[serde(default, rename = "spltType")]
+    splt_type: SplitType,
+
+    #[serde(
+        default = "default_config_array_splt",
+        rename = "arraySplt",
+        deserialize_with = "json::deser::deser_usize_lossy"
+    )]
+    array_splt: usize,
+
+    #[serde(default, rename = "addname")]
+    add_name: String,
+
+    #[serde(default = "default_config_property")]
+    property: String,
+}
+
+fn default_config_splt() -> String {
+    "\\n".to_string()
+}
+
+fn default_config_array_splt() -> usize {
+    1
+}
+
+fn default_config_property() -> String {
+    "payload".to_string()
+}
+
+/// The way to split strings
+#[derive(Debug)]
+enum StringSplitter {
+    Delimiter(String),
+    Length(usize),
+    Regexp(Regex),
+}
+
+impl StringSplitter {
+    fn split(&self, s: &str) -> Vec<String> {
+        match self {
+            StringSplitter::Delimiter(ch) => s.split(ch.as_str()).map(|x| x.to_string()).collect(),
+            StringSplitter::Length(n) => {
+                let chars = s.chars().collect::<Vec<_>>();
+                chars.chunks(*n).map(|x| x.iter().collect()).collect()
+            }
+            StringSplitter::Regexp(re) => split_by_regex(s, re),
+        }
+    }
+
+    /// Splits the buffer into the chunks and their byte offsets in it, the chunks borrow the buffer.
+    fn split_bytes<'a>(&self, bytes: &'a [u8], delimiter: &[u8]) -> crate::Result<Vec<(usize, &'a [u8])>> {
+        match self {
+            StringSplitter::Delimiter(_) => Ok(split_bytes_by_delimiter(bytes, delimiter)),
+            StringSplitter::Length(n) => Ok(bytes.chunks(*n).enumerate().map(|(i, x)| (i * n, x)).collect()),
+            StringSplitter::Regexp(_) => {
+                Err(EdgelinkError::InvalidOperation("Cannot split a buffer by a regular expression".into()).into())
+            }
+        }
+    }
+
+    fn ch(&self) -> &str {
+        match self {
+            StringSplitter::Delimiter(ch) => ch,
+            StringSplitter::Length(_) => "",
+            StringSplitter::Regexp(re) => re.as_str(),
+        }
+    }
+}
+
+/// Splits the string like the `String.prototype.split()` of JavaScript does with a regular expression:
+/// the matched delimiters are discarded unless they are captured by groups, and empty trailing fields are kept.
+fn split_by_regex(s: &str, re: &Regex) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut last = 0;
+    for caps in re.captures_iter(s) {
+        let m = caps.get(0).unwrap();
+        // An empty match at either end of the string does not split anything
+        if m.start() == m.end() && (m.start() == 0 || m.start() == s.len()) {
+            continue;
+        }
+        fields.push(s[last..m.start()].to_string());
+        for group in caps.iter().skip(1) {
+            fields.push(group.map(|x| x.as_str().to_string()).unwrap_or_default());
+        }
+        last = m.end();
+    }
+    fields.push(s[last..].to_string());
+    fields
+}
+
+/// Splits the buffer like the string delimiter does, the empty trailing chunk is kept.
+fn split_bytes_by_delimiter<'a>(bytes: &'a [u8], delimiter: &[u8]) -> Vec<(usize, &'a [u8])> {
+    if delimiter.is_empty() {
+        return vec![(0, bytes)];
+    }
+    let mut chunks = Vec::new();
+    let (mut start, mut pos) = (0, 0);
+    while pos + delimiter.len() <= bytes.len() {
+        if &bytes[pos..pos + delimiter.len()] == delimiter {
+            chunks.push((start, &bytes[start..pos]));
+            pos += delimiter.len();
+            start = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    chunks.push((start, &bytes[start..]));
+    chunks
+}
+
+#[derive(Debug)]
+#[flow_node("split")]
+struct SplitNode {
+    base: FlowNode,
+    config: SplitNodeConfig,
+    splitter: StringSplitter,
+    /// The delimiter to split buffers, the binary delimiter is kept as it is
+    delimiter: Vec<u8>,
+}
+
+impl SplitNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let split_config = SplitNodeConfig::deserialize(&config.rest)?;
+        let mut delimiter = Vec::new();
+        let splitter = match split_config.splt_type {
+            SplitType::Str => {
+                let splt = super::unescape_delimiter(&split_config.splt);
+                delimiter = splt.as_bytes().to_vec();
+                StringSplitter::Delimiter(splt)
+            }
+            SplitType::Bin => {
+                delimiter = serde_json::from_str(&split_config.splt)
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad binary delimiter: {}", e)))?;
+                StringSplitter::Delimiter(String::from_utf8_lossy(&delimiter).into_owned())
+            }
+            SplitType::Len => {
+                let n = split_config.splt.trim().parse::<usize>().ok().filter(|x| *x > 0).unwrap_or(1);
+                StringSplitter::Length(n)
+            }
+            SplitType::Regexp => StringSplitter::Regexp(Regex::new(&split_config.splt)?),
+        };
+        let node = SplitNode { base: state, config: split_config, splitter, delimiter };
+        Ok(Box::new(node))
+    }
+
+    fn split_msg(&self, msg: &Msg) -> crate::Result<Vec<Msg>> {
+        let value = match msg.get_nav_stripped(&self.config.property) {
+            Some(value) => value.clone(),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut template = msg.clone();
+        template.remove(wellknown::MSG_ID_PROPERTY);
+        let mut parts = VariantObjectMap::new();
+        if let Some(parent_parts) = template.remove("parts") {
+            // Push the existing parts to a stack
+            parts.insert("parts".into(), parent_parts);
+        }
+        parts.insert("id".into(), Variant::String(Msg::generate_id().to_string()));
+        if self.config.property != "payload" {
+            parts.insert("property".into(), Variant::String(self.config.property.clone()));
+        }
+
+        let items: Vec<(Variant, VariantObjectMap)> = match value {
+            Variant::String(s) => {
+                let fields = self.splitter.split(&s);
+                let count = fields.len();
+                parts.insert("type".into(), "string".into());
+                parts.insert("ch".into(), self.splitter.ch().into());
+                if let StringSplitter::Length(n) = self.splitter {
+                    parts.insert("len".into(), Variant::from(n as u64));
+                }
+                fields
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, x)| (Variant::String(x), Self::make_parts(&parts, i, count, None)))
+                    .collect()
+            }
+
+            // Every chunk carries its byte offset, so the join node can put it back exactly
+            Variant::Bytes(bytes) => {
+                let chunks = self.splitter.split_bytes(&bytes, &self.delimiter)?;
+                let count = chunks.len();
+                parts.insert("type".into(), "buffer".into());
+                match self.splitter {
+                    StringSplitter::Length(n) => {
+                        parts.insert("ch".into(), Variant::Bytes(SharedBytes::new()));
+                        parts.insert("len".into(), Variant::from(n as u64));
+                    }
+                    _ => {
+                        parts.insert("ch".into(), Variant::Bytes(self.delimiter.clone().into()));
+                    }
+                }
+                chunks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (offset, chunk))| {
+                        let mut p = Self::make_parts(&parts, i, count, None);
+                        p.insert("offset".into(), Variant::from(offset as u64));
+                        (Variant::Bytes(chunk.to_vec().into()), p)
+                    })
+                    .collect()
+            }
+
+            Variant::Array(arr) => {
+                let n = self.config.array_splt;
+                let count = arr.len().div_ceil(n);
+                parts.insert("type".into(), "array".into());
+                parts.insert("len".into(), Variant::from(n as u64));
+                arr.chunks(n)
+                    .enumerate()
+                    .map(|(i, x)| {
+                        let item = if n == 1 { x[0].clone() } else { Variant::Array(x.to_vec()) };
+                        (item, Self::make_parts(&parts, i, count, None))
+                    })
+                    .collect()
+            }
+
+            Variant::Object(map) => {
+                let count = map.len();
+                parts.insert("type".into(), "object".into());
+                map.into_iter()
+                    .enumerate()
+                    .map(|(i, (k, v))| {
+                        let p = Self::make_parts(&parts, i, count, Some(&k));
+                        (v, p)
+                    })
+                    .collect()
+            }
+
+            // Otherwise drop the message
+            _ => Vec::new(),
+        };
+
+        let mut msgs = Vec::with_capacity(items.len());
+        for (item, item_parts) in items.into_iter() {
+            let mut new_msg = template.clone();
+            new_msg.set_nav_stripped(&self.config.property, item, true)?;
+            if !self.config.add_name.is_empty() {
+                if let Some(key) = item_parts.get("key") {
+                    new_msg.set(self.config.add_name.clone(), key.clone());
+                }
+            }
+            new_msg.set("parts".into(), Variant::Object(item_parts));
+            new_msg.set_id(Msg::generate_id());
+            msgs.push(new_msg);
+        }
+        Ok(msgs)
+    }
+
+    fn make_parts(parts: &VariantObjectMap, index: usize, count: usize, key: Option<&str>) -> VariantObjectMap {
+        let mut p = parts.clone();
+        p.insert("index".into(), Variant::from(index as u64));
+        p.insert("count".into(), Variant::from(count as u64));
+        if let Some(key) = key {
+            p.insert("key".into(), Variant::String(key.to_string()));
+        }
+        p
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SplitNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let msgs = {
+                    let msg_guard = msg.read().await;
+                    node.split_msg(&msg_guard)?
+                };
+                for new_msg in msgs.into_iter() {
+                    node.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(new_msg) }, cancel.child_token()).await?;
+                }
+                Ok(())
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn run_split(node_json: serde_json::Value, payload: serde_json::Value, nexpected: usize) -> Vec<Msg> {
+        run_split_payload(node_json, Variant::deserialize(payload).unwrap(), nexpected).await
+    }
+
+    async fn run_split_payload(node_json: serde_json::Value, payload: Variant, nexpected: usize) -> Vec<Msg> {
+        let mut node_json = node_json;
+        node_json["id"] = json!("1");
+        node_json["z"] = json!("100");
+        node_json["type"] = json!("split");
+        node_json["wires"] = json!([["2"]]);
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            node_json,
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let mut msg = Msg::default();
+        msg.set(wellknown::PAYLOAD_PROPERTY.into(), payload);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = vec![(ElementId::from(1), msg)];
+        engine.run_once_with_inject(nexpected, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap()
+    }
+
+    #[test]
+    fn split_by_regex_should_behave_like_js() {
+        let re = Regex::new(r"\s*,\s*").unwrap();
+        assert_eq!(split_by_regex("a , b,c ,  d", &re), vec!["a", "b", "c", "d"]);
+        assert_eq!(split_by_regex("a,b,", &re), vec!["a", "b", ""]);
+        assert_eq!(split_by_regex("abc", &Regex::new("").unwrap()), vec!["a", "b", "c"]);
+
+        // Captured delimiters are preserved
+        let re = Regex::new(r"(,)").unwrap();
+        assert_eq!(split_by_regex("a,b", &re), vec!["a", ",", "b"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_split_string_by_regex() {
+        let msgs = run_split(json!({"splt": r"\s*,\s*", "spltType": "re"}), json!("a , b,c ,  d"), 4).await;
+        assert_eq!(msgs.len(), 4);
+        for (i, expected) in ["a", "b", "c", "d"].iter().enumerate() {
+            assert_eq!(msgs[i]["payload"].as_str(), Some(*expected));
+            let parts = msgs[i]["parts"].as_object().unwrap();
+            assert_eq!(parts["index"].as_u64(), Some(i as u64));
+            assert_eq!(parts["count"].as_u64(), Some(4));
+            assert_eq!(parts["type"].as_str(), Some("string"));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_split_string_by_delimiter() {
+        let msgs = run_split(json!({"splt": "\\n"}), json!("a\nb\n"), 3).await;
+        let payloads = msgs.iter().map(|x| x["payload"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(payloads, vec!["a", "b", ""]);
+        assert_eq!(msgs[0]["parts"].as_object().unwrap()["ch"].as_str(), Some("\n"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_split_array_and_object() {
+        let msgs = run_split(json!({}), json!([1, 2, 3]), 3).await;
+        assert_eq!(msgs.iter().map(|x| x["payload"].as_i64().unwrap()).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let msgs = run_split(json!({"addname": "topic"}), json!({"a": 1, "b": 2}), 2).await;
+        assert_eq!(msgs[0]["topic"].as_str(), Some("a"));
+        assert_eq!(msgs[1]["topic"].as_str(), Some("b"));
+        assert_eq!(msgs[1]["parts"].as_object().unwrap()["key"].as_str(), Some("b"));
+    }
+
+    #[test]
+    fn split_bytes_by_delimiter_should_keep_offsets() {
+        let chunks = split_bytes_by_delimiter(b"ab\r\nc\r\n", b"\r\n");
+        assert_eq!(chunks, vec![(0, &b"ab"[..]), (4, &b"c"[..]), (7, &b""[..])]);
+        assert_eq!(split_bytes_by_delimiter(b"abc", b""), vec![(0, &b"abc"[..])]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_split_buffer_by_binary_delimiter() {
+        let payload = Variant::Bytes(vec![1, 0, 2, 3, 0, 4].into());
+        let msgs = run_split_payload(json!({"splt": "[0]", "spltType": "bin"}), payload, 3).await;
+        let chunks = msgs.iter().map(|x| x["payload"].as_bytes().unwrap().to_vec()).collect::<Vec<_>>();
+        assert_eq!(chunks, vec![vec![1], vec![2, 3], vec![4]]);
+        let offsets = msgs.iter().map(|x| x["parts"].as_object().unwrap()["offset"].as_u64().unwrap());
+        assert_eq!(offsets.collect::<Vec<_>>(), vec![0, 2, 5]);
+        assert_eq!(msgs[0]["parts"].as_object().unwrap()["type"].as_str(), Some("buffer"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_split_array_into_chunks() {
+        let msgs = run_split(json!({"arraySplt": "3"}), json!([0, 1, 2, 3, 4, 5, 6, 7, 8, 9]), 4).await;
+        assert_eq!(msgs.len(), 4);
+        let chunks = msgs
+            .iter()
+            .map(|x| x["payload"].as_array().unwrap().iter().map(|v| v.as_i64().unwrap()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, vec![vec![0, 1, 2], vec![3, 4, 5], vec![6, 7, 8], vec![9]]);
+        for (i, msg) in msgs.iter().enumerate() {
+            let parts = msg["parts"].as_object().unwrap();
+            assert_eq!(parts["index"].as_u64(), Some(i as u64));
+            assert_eq!(parts["count"].as_u64(), Some(4));
+            assert_eq!(parts["len"].as_u64(), Some(3));
+            assert_eq!(parts["type"].as_str(), Some("array"));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The longest line in bytes read by the `lines` format, so a file without any `\n` is never loaded as a whole.
+const DEFAULT_MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum FileInFormat {
+    /// The whole file as a single UTF-8 string
+    #[default]
+    #[serde(rename = "utf8")]
+    Utf8,
+
+    /// One message per line, streamed without loading the whole file
+    #[serde(rename = "lines")]
+    Lines,
+
+    /// The whole file as a single buffer
+    #[serde(rename = "")]
+    Buffer,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileInNodeConfig {
+    #[serde(default)]
+    filename: String,
+
+    #[serde(default, rename = "filenameType")]
+    filename_type: Option<RedPropertyType>,
+
+    #[serde(default)]
+    format: FileInFormat,
+
+    /// Copies all properties of the incoming message to every line
+    #[serde(default, rename = "allProps")]
+    all_props: bool,
+
+    /// The longest line in bytes of the `lines` format, a longer line fails the reading
+    #[serde(default = "default_max_line_length", rename = "maxLineLength")]
+    max_line_length: usize,
+}
+
+fn default_max_line_length() -> usize {
+    DEFAULT_MAX_LINE_LENGTH
+}
+
+#[derive(Debug)]
+#[flow_node("file in")]
+struct FileInNode {
+    base: FlowNode,
+    config: FileInNodeConfig,
+}
+
+impl FileInNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let file_in_config = FileInNodeConfig::deserialize(&config.rest)?;
+        let node = FileInNode { base: state, config: file_in_config };
+        Ok(Box::new(node))
+    }
+
+    async fn filename(&self, msg: &Msg) -> crate::Result<String> {
+        // The legacy flows leave the filename empty to take `msg.filename`
+        let filename_type = match self.config.filename_type {
+            Some(t) => t,
+            None if self.config.filename.is_empty() => RedPropertyType::Msg,
+            None => RedPropertyType::Str,
+        };
+        let filename = match filename_type {
+            RedPropertyType::Msg if self.config.filename.is_empty() => "filename",
+            _ => self.config.filename.as_str(),
+        };
+        let filename = eval::evaluate_node_property(filename, filename_type, Some(self), None, Some(msg)).await?;
+        match filename {
+            Variant::String(s) if !s.is_empty() => Ok(s),
+            _ => Err(EdgelinkError::InvalidOperation("No filename specified".into()).into()),
+        }
+    }
+
+    /// The template of the output messages.
+    fn new_output_msg(&self, msg: &Msg, filename: &str) -> Msg {
+        let mut new_msg = if self.config.all_props {
+            let mut cloned = msg.clone();
+            cloned.remove(wellknown::MSG_ID_PROPERTY);
+            cloned
+        } else {
+            let mut new_msg = Msg::default();
+            if let Some(topic) = msg.get(wellknown::TOPIC_PROPERTY) {
+                new_msg.set(wellknown::TOPIC_PROPERTY.into(), topic.clone());
+            }
+            new_msg
+        };
+        new_msg.set("filename".into(), Variant::String(filename.to_string()));
+        new_msg.set_id(Msg::generate_id());
+        new_msg
+    }
+
+    async fn read_whole(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let (filename, mut new_msg) = {
+            let msg = msg.read().await;
+            let filename = self.filename(&msg).await?;
+            let new_msg = self.new_output_msg(&msg, &filename);
+            (filename, new_msg)
+        };
+        let data = tokio::fs::read(&filename).await.with_context(|| format!("Failed to read '{}'", filename))?;
+        let payload = match self.config.format {
+            FileInFormat::Buffer => Variant::Bytes(data.into()),
+            _ => Variant::String(String::from_utf8_lossy(&data).into_owned()),
+        };
+        new_msg.set(wellknown::PAYLOAD_PROPERTY.into(), payload);
+        self.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(new_msg) }, cancel).await
+    }
+
+    /// Sends a message per line as soon as it has been read, a slow downstream node blocks the sending and then the
+    /// reading, so the file is never loaded as a whole.
+    ///
+    /// Just like Node-RED, the rest after the last `\n` is sent at last with `parts.count`, even if it is empty, to
+    /// complete the sequence. The invalid UTF-8 sequences are replaced like the `utf8` format does and a line longer
+    /// than `maxLineLength` is an error of the node.
+    async fn read_lines(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let (filename, template, parts_id) = {
+            let msg = msg.read().await;
+            let filename = self.filename(&msg).await?;
+            let template = self.new_output_msg(&msg, &filename);
+            (filename, template, msg.id().unwrap_or_else(Msg::generate_id))
+        };
+        let file = tokio::fs::File::open(&filename).await.with_context(|| format!("Failed to open '{}'", filename))?;
+        let mut reader = BufReader::new(file);
+
+        let max_len = self.config.max_line_length;
+        let mut line = Vec::new();
+        let mut index = 0;
+        loop {
+            line.clear();
+            // At most one byte more than the limit, a longer line is detected without reading it all
+            let nread = (&mut reader)
+                .take(max_len as u64 + 1)
+                .read_until(b'\n', &mut line)
+                .await
+                .with_context(|| format!("Failed to read '{}'", filename))?;
+            let last = nread == 0 || line.last() != Some(&b'\n');
+            if !last {
+                line.pop();
+            }
+            if line.len() > max_len {
+                return Err(EdgelinkError::OutOfRange).with_context(|| {
+                    format!("The line {} of '{}' is longer than {} bytes", index + 1, filename, max_len)
+                });
+            }
+
+            let mut parts = VariantObjectMap::new();
+            parts.insert("id".into(), Variant::String(parts_id.to_string()));
+            parts.insert("type".into(), "string".into());
+            parts.insert("ch".into(), "\n".into());
+            parts.insert("index".into(), Variant::from(index as u64));
+            if last {
+                parts.insert("count".into(), Variant::from(index as u64 + 1));
+            }
+
+            let mut new_msg = template.clone();
+            new_msg
+                .set(wellknown::PAYLOAD_PROPERTY.into(), Variant::String(String::from_utf8_lossy(&line).into_owned()));
+            new_msg.set("parts".into(), Variant::Object(parts));
+            new_msg.set_id(Msg::generate_id());
+            self.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(new_msg) }, cancel.clone()).await?;
+
+            if last {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for FileInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                match node.config.format {
+                    FileInFormat::Lines => node.read_lines(msg, cancel.child_token()).await,
+                    FileInFormat::Utf8 | FileInFormat::Buffer => node.read_whole(msg, cancel.child_token()).await,
+                }
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_stream_lines() {
+        let path = std::env::temp_dir().join(format!("edgelink-file-in-lines-{}.txt", std::process::id()));
+        let lines: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file in", "filename": "filename", "filenameType": "msg",
+                "format": "lines", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msg = Msg::deserialize(json!({"filename": path.to_string_lossy(), "topic": "t"})).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(101, std::time::Duration::from_secs_f64(2.0), vec![(ElementId::from(1), msg)])
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(msgs.len(), 101);
+        for (i, msg) in msgs.iter().enumerate() {
+            let parts = msg["parts"].as_object().unwrap();
+            assert_eq!(parts["index"].as_u64(), Some(i as u64));
+            assert_eq!(msg["topic"].as_str(), Some("t"));
+            if i < 100 {
+                assert_eq!(msg["payload"].as_str(), Some(lines[i].as_str()));
+                assert!(!parts.contains_key("count"));
+            }
+        }
+        // The completion of the sequence
+        let last = msgs.last().unwrap();
+        assert_eq!(last["payload"].as_str(), Some(""));
+        assert_eq!(last["parts"].as_object().unwrap()["count"].as_u64(), Some(101));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_replace_the_bad_utf8_and_reject_the_long_lines() {
+        let path = std::env::temp_dir().join(format!("edgelink-file-in-long-{}.txt", std::process::id()));
+        std::fs::write(&path, b"ok\n\xffbad\n0123456789abcdef\nnever\n").unwrap();
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file in", "filename": path.to_string_lossy(), "filenameType": "str",
+                "format": "lines", "maxLineLength": 8, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "scope": ["1"], "uncaught": false, "wires": [["2"]]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(
+                3,
+                std::time::Duration::from_secs_f64(0.4),
+                vec![(ElementId::from(1), Msg::default())],
+            )
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0]["payload"].as_str(), Some("ok"));
+        assert_eq!(msgs[1]["payload"].as_str(), Some("\u{fffd}bad"));
+        let message = msgs[2].get_nav("error.message").and_then(|x| x.as_str()).unwrap();
+        assert!(message.contains("The line 3"), "{}", message);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_read_whole_file() {
+        let path = std::env::temp_dir().join(format!("edgelink-file-in-utf8-{}.txt", std::process::id()));
+        std::fs::write(&path, "a\nb").unwrap();
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file in", "filename": path.to_string_lossy(), "filenameType": "str",
+                "format": "utf8", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(
+                1,
+                std::time::Duration::from_secs_f64(0.4),
+                vec![(ElementId::from(1), Msg::default())],
+            )
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(msgs[0]["payload"].as_str(), Some("a\nb"));
+        assert_eq!(msgs[0]["filename"].as_str(), Some(path.to_string_lossy().as_ref()));
+    }
+}
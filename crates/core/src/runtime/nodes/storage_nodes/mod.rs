@@ -0,0 +1,4 @@
+mod file_in;
+
+#[cfg(feature = "nodes_watch")]
+mod watch;
@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils::async_util;
+use edgelink_macro::*;
+
+/// The quiet period to coalesce the burst of events of a single write.
+const DEFAULT_DEBOUNCE_MS: u64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChangeType {
+    Create,
+    Modify,
+    Delete,
+}
+
+impl ChangeType {
+    fn from_event_kind(kind: &EventKind) -> Option<Self> {
+        match kind {
+            EventKind::Create(_) => Some(ChangeType::Create),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Some(ChangeType::Delete),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some(ChangeType::Create),
+            EventKind::Modify(_) => Some(ChangeType::Modify),
+            EventKind::Remove(_) => Some(ChangeType::Delete),
+            // The accesses do not change anything
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeType::Create => "create",
+            ChangeType::Modify => "modify",
+            ChangeType::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchNodeConfig {
+    /// The comma separated files and directories to watch
+    #[serde(default)]
+    files: String,
+
+    #[serde(default)]
+    recursive: bool,
+
+    /// The quiet period in milliseconds before the changes of a path are emitted
+    #[serde(default = "default_debounce")]
+    debounce: u64,
+}
+
+fn default_debounce() -> u64 {
+    DEFAULT_DEBOUNCE_MS
+}
+
+#[derive(Debug)]
+#[flow_node("watch")]
+struct WatchNode {
+    base: FlowNode,
+    config: WatchNodeConfig,
+}
+
+impl WatchNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let watch_config = WatchNodeConfig::deserialize(&config.rest)?;
+        let node = WatchNode { base: state, config: watch_config };
+        Ok(Box::new(node))
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        self.config.files.split(',').map(str::trim).filter(|x| !x.is_empty()).map(PathBuf::from).collect()
+    }
+
+    fn new_msg(&self, path: &Path, change: ChangeType) -> Msg {
+        let path_str = path.to_string_lossy().to_string();
+        let file = path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        let mut msg = Msg::default();
+        msg.set_id(Msg::generate_id());
+        msg.set(wellknown::PAYLOAD_PROPERTY.into(), Variant::String(path_str.clone()));
+        msg.set(wellknown::TOPIC_PROPERTY.into(), Variant::String(self.config.files.clone()));
+        msg.set("file".into(), Variant::String(file));
+        msg.set("filename".into(), Variant::String(path_str));
+        msg.set("type".into(), Variant::String(change.as_str().into()));
+        msg
+    }
+
+    async fn watch_task(&self, stop_token: CancellationToken) -> crate::Result<()> {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let node_id = self.id();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) => {
+                if let Some(change) = ChangeType::from_event_kind(&event.kind) {
+                    for path in event.paths {
+                        let _ = tx.send((path, change));
+                    }
+                }
+            }
+            Err(err) => log::warn!("[WATCH:{}] Failed to watch: {}", node_id, err),
+        })?;
+
+        let mode = if self.config.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        for path in self.paths() {
+            watcher.watch(&path, mode).with_context(|| format!("Failed to watch '{}'", path.display()))?;
+        }
+
+        let debounce = Duration::from_millis(self.config.debounce);
+        let clock = self.clock();
+        // Dropping the watcher at return tears down the OS watches
+        loop {
+            let mut pending: Vec<(PathBuf, ChangeType)> = Vec::new();
+            let received =
+                async_util::recv_debounced(&mut rx, debounce, clock.as_ref(), &stop_token, |(path, change)| {
+                    coalesce(&mut pending, path, change)
+                })
+                .await;
+            if !received {
+                return Ok(());
+            }
+
+            for (path, change) in pending {
+                let envelope = Envelope { port: 0, msg: MsgHandle::new(self.new_msg(&path, change)) };
+                self.fan_out_one(envelope, stop_token.clone()).await?;
+            }
+        }
+    }
+}
+
+/// Merges the events of the same path, a new file written right after creation is still reported as created.
+fn coalesce(pending: &mut Vec<(PathBuf, ChangeType)>, path: PathBuf, change: ChangeType) {
+    match pending.iter_mut().find(|x| x.0 == path) {
+        Some((_, ChangeType::Create)) if change == ChangeType::Modify => {}
+        Some((_, existed)) => *existed = change,
+        None => pending.push((path, change)),
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for WatchNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        if let Err(e) = self.watch_task(stop_token.child_token()).await {
+            log::warn!("The WatchNode(id='{}', name='{}') failed: {:#}", self.id(), self.name(), e);
+            stop_token.cancelled().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("edgelink-watch-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn flows_json(dir: &Path, recursive: bool) -> serde_json::Value {
+        json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "watch", "files": dir.to_string_lossy(), "recursive": recursive,
+                "debounce": 100, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink"}
+        ])
+    }
+
+    const PROBE_FILE: &str = "probe.txt";
+
+    /// Receives the next change except the ones of the probe file, the OS may need a while to report it.
+    async fn recv_change(sink_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>) -> Msg {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let (_, msg) = sink_rx.recv().await.unwrap();
+                if msg["file"].as_str() != Some(PROBE_FILE) {
+                    return msg;
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Writes the probe file in `dir` until its change has been received, so the watcher is known to be ready.
+    async fn wait_for_watching(dir: &Path, sink_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(ElementId, Msg)>) {
+        let probe = dir.join(PROBE_FILE);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                std::fs::write(&probe, "probe").unwrap();
+                let probed = tokio::time::timeout(Duration::from_millis(500), sink_rx.recv()).await;
+                if let Ok(Some((_, msg))) = probed {
+                    if msg["file"].as_str() == Some(PROBE_FILE) {
+                        return;
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_emit_created_and_modified_files() {
+        let dir = test_dir("create");
+        let existed = dir.join("existed.txt");
+        std::fs::write(&existed, "1").unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json(&dir, false)).unwrap();
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+        wait_for_watching(&dir, &mut sink_rx).await;
+
+        let created = dir.join("new.txt");
+        std::fs::write(&created, "hello").unwrap();
+        let created_msg = recv_change(&mut sink_rx).await;
+        std::fs::write(&existed, "2").unwrap();
+        let modified_msg = recv_change(&mut sink_rx).await;
+        engine.stop().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(created_msg["payload"].as_str(), Some(created.to_string_lossy().as_ref()));
+        assert_eq!(created_msg["file"].as_str(), Some("new.txt"));
+        assert_eq!(created_msg["type"].as_str(), Some("create"));
+        assert_eq!(modified_msg["file"].as_str(), Some("existed.txt"));
+        assert_eq!(modified_msg["type"].as_str(), Some("modify"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_watch_sub_directories_recursively() {
+        let dir = test_dir("recursive");
+        let sub_dir = dir.join("sub");
+        std::fs::create_dir_all(&sub_dir).unwrap();
+        let removed = sub_dir.join("removed.txt");
+        std::fs::write(&removed, "x").unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json(&dir, true)).unwrap();
+        let mut sink_rx = engine.sink_receiver();
+        engine.start().await.unwrap();
+        wait_for_watching(&sub_dir, &mut sink_rx).await;
+
+        std::fs::remove_file(&removed).unwrap();
+        let msg = recv_change(&mut sink_rx).await;
+        engine.stop().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(msg["filename"].as_str(), Some(removed.to_string_lossy().as_ref()));
+        assert_eq!(msg["type"].as_str(), Some("delete"));
+    }
+}
@@ -0,0 +1,148 @@
+//! A harness running the flows in the tests of the nodes, enabled by the `testing` feature.
+//!
+//! The flows JSON is loaded into a new engine, the messages are injected into the flow nodes and the results are
+//! collected from the `sink` nodes:
+//!
+//! ```ignore
+//! let msgs = run_flow(flows_json, json!([["1", {"payload": [1, 2, 3]}]]), 1).await?;
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::runtime::engine::Engine;
+use crate::runtime::model::*;
+use crate::runtime::registry::RegistryBuilder;
+use crate::utils::time::Clock;
+use crate::*;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Injects the `[[node_id, msg], ...]` messages and returns the first `expected` messages arrived at any `sink` node.
+pub async fn run_flow(
+    flows_json: serde_json::Value,
+    injects_json: serde_json::Value,
+    expected: usize,
+) -> crate::Result<Vec<Msg>> {
+    let injects = Vec::<(ElementId, Msg)>::deserialize(injects_json)?;
+    FlowHarness::new(flows_json)?.run(injects, expected).await
+}
+
+/// Runs the flows once for a test, a generalized `Engine::run_once_with_inject()` collecting at the `sink` nodes.
+pub struct FlowHarness {
+    engine: Engine,
+    sink_id: Option<ElementId>,
+    timeout: Duration,
+}
+
+impl FlowHarness {
+    pub fn new(flows_json: serde_json::Value) -> crate::Result<Self> {
+        let registry = RegistryBuilder::default().build()?;
+        let engine = Engine::with_json(&registry, flows_json, None)?;
+        Ok(Self { engine, sink_id: None, timeout: DEFAULT_TIMEOUT })
+    }
+
+    /// Collects only the messages arrived at the `sink` node named `name`, the others are ignored.
+    pub fn sink(mut self, name: &str) -> crate::Result<Self> {
+        let node = self
+            .engine
+            .find_flow_node_by_name(name)?
+            .filter(|x| x.type_str() == "sink")
+            .ok_or(EdgelinkError::BadArgument("name"))
+            .with_context(|| format!("Cannot found the sink node, name='{}'", name))?;
+        self.sink_id = Some(node.id());
+        Ok(self)
+    }
+
+    /// The run fails with `EdgelinkError::Timeout` if the expected messages did not arrive in time.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Drives the timers of the nodes by the `clock`, a `MockClock` makes the timed nodes deterministic.
+    pub fn clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.engine.set_clock(clock);
+        self
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Starts the engine, injects the messages in order and stops the engine once `expected` messages arrived.
+    pub async fn run(&self, injects: Vec<(ElementId, Msg)>, expected: usize) -> crate::Result<Vec<Msg>> {
+        let mut sink_rx = self.engine.sink_receiver();
+        self.engine.start().await?;
+
+        let collected = async {
+            let cancel = CancellationToken::new();
+            for (node_id, msg) in injects.into_iter() {
+                self.engine.inject_msg(&node_id, MsgHandle::new(msg), cancel.clone()).await?;
+            }
+            let mut received = Vec::with_capacity(expected);
+            while received.len() < expected {
+                let Some((sink_id, msg)) = sink_rx.recv().await else {
+                    break;
+                };
+                if self.sink_id.is_none() || self.sink_id == Some(sink_id) {
+                    received.push(msg);
+                }
+            }
+            crate::Result::Ok(received)
+        };
+        let result = tokio::time::timeout(self.timeout, collected).await;
+
+        self.engine.stop().await?;
+        match result {
+            Ok(received) => received,
+            Err(_) => Err(EdgelinkError::Timeout.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_split_map_and_join_through_the_harness() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "function", "func": "msg.payload *= 10; return msg;", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "join", "mode": "auto", "wires": [["4", "5"]]},
+            {"id": "4", "z": "100", "type": "sink", "name": "joined"},
+            {"id": "5", "z": "100", "type": "sink", "name": "other"},
+        ]);
+        let injects = json!([["1", {"payload": [1, 2, 3], "topic": "t"}]]);
+
+        let msgs = run_flow(flows_json.clone(), injects.clone(), 2).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+
+        let injects = Vec::<(ElementId, Msg)>::deserialize(injects).unwrap();
+        let msgs = FlowHarness::new(flows_json).unwrap().sink("joined").unwrap().run(injects, 1).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::Array(vec![Variant::from(10), Variant::from(20), Variant::from(30)]));
+        assert_eq!(msgs[0]["topic"], Variant::from("t"));
+        assert!(!msgs[0].contains("parts"), "{:?}", msgs[0]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_time_out_without_the_expected_msgs() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "function", "func": "return null;", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "sink", "name": "out"},
+        ]);
+        assert!(FlowHarness::new(flows_json.clone()).unwrap().sink("nothing").is_err());
+
+        let harness = FlowHarness::new(flows_json).unwrap().timeout(Duration::from_millis(200));
+        let err = harness.run(vec![(ElementId::from(1), Msg::default())], 1).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::Timeout)), "{:?}", err);
+    }
+}
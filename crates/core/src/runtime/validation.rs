@@ -0,0 +1,453 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde_json::Map as JsonMap;
+use serde_json::Value as JsonValue;
+
+use crate::runtime::model::json::deser::{load_flows_json_value, parse_red_id_value};
+use crate::runtime::model::ElementId;
+use crate::runtime::nodes::function_nodes::function_used_outputs;
+use crate::runtime::nodes::NodeFactory;
+use crate::runtime::registry::Registry;
+
+/// The properties without which the built-in nodes cannot be built.
+const REQUIRED_PROPERTIES: &[(&str, &[&str])] = &[
+    ("delay", &["pauseType"]),
+    ("loop", &["rule"]),
+    ("object", &["operation"]),
+    ("range", &["action", "minin", "maxin", "minout", "maxout"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found by `Engine::validate()`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    /// The element causing the problem, `None` if it is a problem of the whole flows JSON.
+    pub element_id: Option<ElementId>,
+
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.element_id {
+            Some(id) => write!(f, "{}: [{}] {}", severity, id, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Validator {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn error(&mut self, element_id: Option<ElementId>, message: String) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Error, element_id, message });
+    }
+
+    fn warning(&mut self, element_id: Option<ElementId>, message: String) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Warning, element_id, message });
+    }
+
+    fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|x| x.severity == Severity::Error)
+    }
+}
+
+/// Checks the flows JSON without building any node, every problem found is reported.
+pub fn validate_flows_json(reg: &dyn Registry, root: &JsonValue) -> Vec<Diagnostic> {
+    let mut v = Validator::default();
+    let Some(entries) = root.as_array() else {
+        v.error(None, "The flows JSON must be an array".into());
+        return v.diagnostics;
+    };
+
+    // Collect the elements first, so the references can be checked in any order
+    let mut elements: Vec<(ElementId, &str, &JsonMap<String, JsonValue>)> = Vec::with_capacity(entries.len());
+    let mut by_id: HashMap<ElementId, &str> = HashMap::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let Some(obj) = entry.as_object() else {
+            v.error(None, format!("The entry #{} must be an object", index));
+            continue;
+        };
+        let Some(id) = obj.get("id").and_then(parse_red_id_value) else {
+            v.error(None, format!("The entry #{} has a missing or invalid `id`: {:?}", index, obj.get("id")));
+            continue;
+        };
+        let Some(type_name) = obj.get("type").and_then(|x| x.as_str()) else {
+            v.error(Some(id), "The element has no `type`".into());
+            continue;
+        };
+        if by_id.insert(id, type_name).is_some() {
+            v.error(Some(id), "Duplicated element ID".into());
+            continue;
+        }
+        elements.push((id, type_name, obj));
+    }
+
+    let is_flow = |id: &ElementId| matches!(by_id.get(id), Some(&"tab") | Some(&"subflow"));
+    let flow_node_ids: HashSet<ElementId> = elements
+        .iter()
+        .filter(|(_, type_name, obj)| obj.contains_key("z") && !matches!(*type_name, "group" | "comment"))
+        .map(|(id, _, _)| *id)
+        .collect();
+    let is_flow_node = |id: &ElementId| flow_node_ids.contains(id);
+    let used_subflows: HashSet<&str> =
+        elements.iter().filter_map(|(_, type_name, _)| type_name.strip_prefix("subflow:")).collect();
+
+    let mut graph: HashMap<ElementId, Vec<ElementId>> = HashMap::new();
+    for (id, type_name, obj) in elements.iter() {
+        let id = *id;
+        match *type_name {
+            "tab" | "comment" => continue,
+
+            "subflow" => {
+                if !used_subflows.contains(obj.get("id").and_then(|x| x.as_str()).unwrap_or_default()) {
+                    v.warning(Some(id), "The subflow has no instance node, it is never run".into());
+                }
+                for port_key in ["in", "out"] {
+                    let wires = obj.get(port_key).and_then(|x| x.as_array()).into_iter().flatten();
+                    for wire in wires.filter_map(|x| x.get("wires")).filter_map(|x| x.as_array()).flatten() {
+                        match wire.get("id").and_then(parse_red_id_value) {
+                            Some(target) if is_flow_node(&target) => {}
+                            _ => {
+                                v.error(Some(id), format!("The `{}` port wires to a missing node: {}", port_key, wire))
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+
+            _ => {}
+        }
+
+        // The placement
+        match obj.get("z") {
+            Some(z) => match parse_red_id_value(z) {
+                Some(z) if is_flow(&z) => {}
+                _ => v.error(Some(id), format!("The element belongs to a missing flow: {}", z)),
+            },
+            None if *type_name == "group" => v.error(Some(id), "The group must have a `z` property".into()),
+            None => {}
+        }
+        if let Some(g) = obj.get("g") {
+            match parse_red_id_value(g) {
+                Some(g) if by_id.get(&g) == Some(&"group") => {}
+                _ => v.error(Some(id), format!("The element belongs to a missing group: {}", g)),
+            }
+        }
+        if *type_name == "group" {
+            continue;
+        }
+
+        // The node type
+        if let Some(subflow_id) = type_name.strip_prefix("subflow:") {
+            if !subflow_id.parse::<ElementId>().is_ok_and(|x| by_id.get(&x) == Some(&"subflow")) {
+                v.error(Some(id), format!("The subflow instance refers to a missing subflow: '{}'", subflow_id));
+            }
+        } else {
+            match reg.get(type_name) {
+                None => v.error(Some(id), format!("Unknown node type: '{}'", type_name)),
+                Some(meta) => match (&meta.factory, obj.contains_key("z")) {
+                    (NodeFactory::Global(_), true) => {
+                        v.error(Some(id), format!("The config node type '{}' cannot be placed in a flow", type_name))
+                    }
+                    (NodeFactory::Flow(_), false) => {
+                        v.error(Some(id), format!("The flow node type '{}' must belong to a flow", type_name))
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        // The references to the other nodes
+        if let Some(wires) = obj.get("wires") {
+            match wires.as_array().filter(|ports| ports.iter().all(|p| p.is_array())) {
+                Some(ports) => {
+                    for target in ports.iter().filter_map(|x| x.as_array()).flatten() {
+                        match parse_red_id_value(target) {
+                            Some(target_id) if is_flow_node(&target_id) => graph.entry(id).or_default().push(target_id),
+                            _ => v.error(Some(id), format!("Wired to a missing node: {}", target)),
+                        }
+                    }
+                }
+                None => v.error(Some(id), "The `wires` must be an array of arrays".into()),
+            }
+        }
+
+        if matches!(*type_name, "link out" | "link call") {
+            for link in obj.get("links").and_then(|x| x.as_array()).into_iter().flatten() {
+                match parse_red_id_value(link) {
+                    Some(link_id) if by_id.get(&link_id) == Some(&"link in") => {}
+                    _ => v.error(Some(id), format!("Linked to a missing `link in` node: {}", link)),
+                }
+            }
+        }
+
+        if *type_name == "function" {
+            let declared = obj.get("outputs").and_then(|x| x.as_u64().or_else(|| x.as_str()?.trim().parse().ok()));
+            let declared = declared.map(|x| x as usize);
+            let wired = obj.get("wires").and_then(|x| x.as_array()).map(|x| x.len());
+            if let (Some(declared), Some(wired)) = (declared, wired) {
+                if declared != wired {
+                    v.warning(
+                        Some(id),
+                        format!(
+                            "The function node declares {} output(s) but is wired to {} port(s), only {} will be used",
+                            declared,
+                            wired,
+                            function_used_outputs(declared, wired)
+                        ),
+                    );
+                }
+            }
+        }
+
+        if matches!(*type_name, "catch" | "status" | "complete") {
+            for scoped in obj.get("scope").and_then(|x| x.as_array()).into_iter().flatten() {
+                if !parse_red_id_value(scoped).is_some_and(|x| by_id.contains_key(&x)) {
+                    v.warning(Some(id), format!("The `scope` refers to a missing node: {}", scoped));
+                }
+            }
+        }
+
+        if let Some((_, props)) = REQUIRED_PROPERTIES.iter().find(|(t, _)| t == type_name) {
+            for prop in props.iter().filter(|p| !obj.contains_key(**p)) {
+                v.error(Some(id), format!("The required property `{}` is missing", prop));
+            }
+        }
+    }
+
+    for cycle in find_cycles(&graph) {
+        let ids = cycle.iter().map(|x| format!("'{}'", x)).collect::<Vec<_>>().join(", ");
+        v.warning(
+            cycle.first().copied(),
+            format!("The nodes form a wiring loop, make sure the messages can leave it: {}", ids),
+        );
+    }
+
+    // The loader has some more structural checks, it only runs once the references are sound
+    if !v.has_errors() {
+        if let Err(err) = load_flows_json_value(root.clone()) {
+            v.error(None, err.to_string());
+        }
+    }
+
+    v.diagnostics
+}
+
+/// Finds the strongly connected components with loops in the wiring graph, by the Tarjan's algorithm.
+///
+/// The depth-first search keeps its own stack, a long chain of nodes cannot overflow the thread stack.
+fn find_cycles(graph: &HashMap<ElementId, Vec<ElementId>>) -> Vec<Vec<ElementId>> {
+    #[derive(Default)]
+    struct State {
+        index: usize,
+        indices: HashMap<ElementId, usize>,
+        lowlinks: HashMap<ElementId, usize>,
+        stack: Vec<ElementId>,
+        on_stack: HashSet<ElementId>,
+        cycles: Vec<Vec<ElementId>>,
+    }
+
+    impl State {
+        fn enter(&mut self, node: ElementId) {
+            self.indices.insert(node, self.index);
+            self.lowlinks.insert(node, self.index);
+            self.index += 1;
+            self.stack.push(node);
+            self.on_stack.insert(node);
+        }
+
+        fn lower(&mut self, node: ElementId, low: usize) {
+            let lowlink = self.lowlinks.get_mut(&node).expect("visited node");
+            *lowlink = (*lowlink).min(low);
+        }
+    }
+
+    fn visit(root: ElementId, graph: &HashMap<ElementId, Vec<ElementId>>, s: &mut State) {
+        // Every frame is a node being visited and the position of its next edge
+        let mut frames: Vec<(ElementId, usize)> = vec![(root, 0)];
+        s.enter(root);
+        while let Some(frame) = frames.last_mut() {
+            let (node, edge) = *frame;
+            if let Some(next) = graph.get(&node).and_then(|x| x.get(edge)).copied() {
+                frame.1 += 1;
+                if !s.indices.contains_key(&next) {
+                    s.enter(next);
+                    frames.push((next, 0));
+                } else if s.on_stack.contains(&next) {
+                    s.lower(node, s.indices[&next]);
+                }
+                continue;
+            }
+
+            // Every edge has been followed
+            frames.pop();
+            if s.lowlinks[&node] == s.indices[&node] {
+                let mut component = Vec::new();
+                while let Some(top) = s.stack.pop() {
+                    s.on_stack.remove(&top);
+                    component.push(top);
+                    if top == node {
+                        break;
+                    }
+                }
+                let is_self_loop = graph.get(&node).is_some_and(|x| x.contains(&node));
+                if component.len() > 1 || is_self_loop {
+                    component.sort();
+                    s.cycles.push(component);
+                }
+            }
+            if let Some((parent, _)) = frames.last() {
+                s.lower(*parent, s.lowlinks[&node]);
+            }
+        }
+    }
+
+    let mut state = State::default();
+    let mut nodes: Vec<&ElementId> = graph.keys().collect();
+    nodes.sort();
+    for node in nodes {
+        if !state.indices.contains_key(node) {
+            visit(*node, graph, &mut state);
+        }
+    }
+    state.cycles.sort();
+    state.cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validate(flows_json: JsonValue) -> Vec<Diagnostic> {
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        crate::runtime::engine::Engine::validate(&registry, &flows_json)
+    }
+
+    #[test]
+    fn test_it_should_report_all_problems() {
+        let diags = validate(json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2", "999"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": [["1"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": []},
+            {"id": "3", "z": "100", "type": "no-such-node", "wires": []},
+            {"id": "4", "z": "888", "type": "junction", "wires": []},
+            {"id": "5", "z": "100", "type": "link call", "links": ["6"], "wires": [[]]},
+            {"id": "6", "z": "100", "type": "junction", "wires": []},
+            {"id": "7", "z": "100", "type": "range", "action": "scale", "wires": [[]]},
+            {"id": "8", "z": "100", "type": "catch", "scope": ["777"], "wires": [[]]},
+            {"id": "9", "z": "100", "type": "subflow:666", "wires": []},
+            {"id": "a", "z": "100", "type": "function", "outputs": 2, "wires": [[], [], []]},
+            {"type": "junction"}
+        ]));
+        let errors: Vec<String> =
+            diags.iter().filter(|x| x.severity == Severity::Error).map(|x| x.to_string()).collect();
+        let warnings: Vec<String> =
+            diags.iter().filter(|x| x.severity == Severity::Warning).map(|x| x.to_string()).collect();
+
+        let has_error = |id: &str, text: &str| errors.iter().any(|x| x.contains(id) && x.contains(text));
+        assert!(has_error("0000000000000001", "Wired to a missing node: \"999\""), "{:#?}", errors);
+        assert!(has_error("0000000000000002", "Duplicated element ID"), "{:#?}", errors);
+        assert!(has_error("0000000000000003", "Unknown node type: 'no-such-node'"), "{:#?}", errors);
+        assert!(has_error("0000000000000004", "missing flow"), "{:#?}", errors);
+        assert!(has_error("0000000000000005", "missing `link in` node"), "{:#?}", errors);
+        assert!(has_error("0000000000000007", "`minin` is missing"), "{:#?}", errors);
+        assert!(has_error("0000000000000007", "`maxout` is missing"), "{:#?}", errors);
+        assert!(has_error("0000000000000009", "missing subflow: '666'"), "{:#?}", errors);
+        assert!(errors.iter().any(|x| x.contains("The entry #12")), "{:#?}", errors);
+        assert_eq!(errors.len(), 11, "{:#?}", errors);
+
+        assert!(warnings.iter().any(|x| x.contains("0000000000000008") && x.contains("\"777\"")), "{:#?}", warnings);
+        assert!(
+            warnings.iter().any(|x| x.contains("wiring loop") && x.contains("'0000000000000002'")),
+            "{:#?}",
+            warnings
+        );
+        assert!(
+            warnings.iter().any(|x| x.contains("000000000000000a") && x.contains("declares 2 output(s)")),
+            "{:#?}",
+            warnings
+        );
+        assert_eq!(warnings.len(), 3, "{:#?}", warnings);
+    }
+
+    #[test]
+    fn test_it_should_report_the_outputs_used_by_the_function_nodes() {
+        let diags = validate(json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "function", "outputs": 0, "wires": [[]]},
+            {"id": "2", "z": "100", "type": "function", "outputs": "3", "wires": [[], []]}
+        ]));
+        let warnings: Vec<String> =
+            diags.iter().filter(|x| x.severity == Severity::Warning).map(|x| x.to_string()).collect();
+        let has_warning = |id: &str, text: &str| warnings.iter().any(|x| x.contains(id) && x.contains(text));
+        assert!(has_warning("0000000000000001", "wired to 1 port(s), only 1 will be used"), "{:#?}", warnings);
+        assert!(has_warning("0000000000000002", "wired to 2 port(s), only 2 will be used"), "{:#?}", warnings);
+    }
+
+    #[test]
+    fn test_it_should_accept_valid_flows() {
+        let diags = validate(json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "link in", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": []},
+            {"id": "3", "z": "100", "type": "link call", "links": ["1"], "wires": [[]]}
+        ]));
+        assert!(diags.is_empty(), "{:#?}", diags);
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        let ids: Vec<ElementId> = (1..=4).map(|x| x.to_string().parse().unwrap()).collect();
+        let graph = HashMap::from([
+            (ids[0], vec![ids[1]]),
+            (ids[1], vec![ids[0], ids[2]]),
+            (ids[2], vec![ids[3]]),
+            (ids[3], vec![ids[3]]),
+        ]);
+        assert_eq!(find_cycles(&graph), vec![vec![ids[0], ids[1]], vec![ids[3]]]);
+    }
+
+    #[test]
+    fn test_find_cycles_in_a_long_chain() {
+        let ids: Vec<ElementId> = (1..=200_000u64).map(ElementId::from).collect();
+        let mut graph: HashMap<ElementId, Vec<ElementId>> = ids.windows(2).map(|x| (x[0], vec![x[1]])).collect();
+        assert!(find_cycles(&graph).is_empty());
+
+        graph.insert(ids[ids.len() - 1], vec![ids[0]]);
+        let cycles = find_cycles(&graph);
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].len(), ids.len());
+    }
+
+    #[test]
+    fn test_it_should_warn_the_unused_subflows() {
+        let diags = validate(json!([
+            {"id": "100", "type": "tab"},
+            {"id": "200", "type": "subflow", "name": "unused", "in": [], "out": []},
+            {"id": "1", "z": "200", "type": "junction", "wires": []}
+        ]));
+        assert_eq!(diags.len(), 1, "{:#?}", diags);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert!(diags[0].message.contains("no instance node"));
+    }
+}
@@ -1,3 +1,55 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+use crate::*;
+
+/// Builds a `Regex` from a JavaScript pattern and its flags, e.g. the `source` and `flags` of a JS `RegExp`.
+///
+/// The `i`, `m` and `s` flags are kept as the inline flags of the pattern, so `to_js_regex()` can restore them.
+/// The matching is always Unicode-aware and stateless, so `u` is implied and `g` is ignored: there is no
+/// `lastIndex` to carry between matches, the helpers like `Variant::regex_replace()` take the "all" option instead.
+/// `d` only affects the result objects of JS and is ignored too, the other flags are rejected.
+pub fn build_js_regex(pattern: &str, flags: &str) -> crate::Result<Regex> {
+    let mut inline_flags = String::new();
+    for (i, flag) in flags.char_indices() {
+        if flags[..i].contains(flag) {
+            return Err(EdgelinkError::BadArgument("flags"))
+                .with_context(|| format!("Duplicated flag '{}' in the regular expression flags '{}'", flag, flags));
+        }
+        match flag {
+            'i' | 'm' | 's' => inline_flags.push(flag),
+            'g' | 'u' | 'd' => {}
+            _ => {
+                return Err(EdgelinkError::NotSupported(format!("The regular expression flag '{}'", flag)).into());
+            }
+        }
+    }
+
+    let full_pattern = if inline_flags.is_empty() {
+        Cow::Borrowed(pattern)
+    } else {
+        Cow::Owned(format!("(?{}){}", inline_flags, pattern))
+    };
+    let re =
+        Regex::new(&full_pattern).with_context(|| format!("Invalid regular expression: /{}/{}", pattern, flags))?;
+    Ok(re)
+}
+
+/// Splits a `Regex` built by `build_js_regex()` back into the JavaScript pattern and flags.
+pub fn to_js_regex(re: &Regex) -> (&str, String) {
+    let s = re.as_str();
+    if let Some(rest) = s.strip_prefix("(?") {
+        if let Some(end) = rest.find(')') {
+            let flags = &rest[..end];
+            if !flags.is_empty() && flags.chars().all(|c| matches!(c, 'i' | 'm' | 's')) {
+                return (&rest[end + 1..], flags.to_string());
+            }
+        }
+    }
+    (s, String::new())
+}
+
 pub mod serde_regex {
     use regex::Regex;
     use serde::{self, Deserialize, Deserializer, Serializer};
@@ -44,3 +96,25 @@ pub mod serde_optional_regex {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_js_regex() {
+        let re = build_js_regex("^hello", "gi").unwrap();
+        assert!(re.is_match("HELLO world"));
+        assert_eq!(to_js_regex(&re), ("^hello", "i".to_string()));
+
+        let re = build_js_regex("a.b", "").unwrap();
+        assert!(!re.is_match("a\nb"));
+        assert_eq!(to_js_regex(&re), ("a.b", String::new()));
+        assert!(build_js_regex("a.b", "s").unwrap().is_match("a\nb"));
+        assert!(build_js_regex("^b$", "m").unwrap().is_match("a\nb"));
+
+        assert!(build_js_regex("a", "ii").is_err());
+        assert!(build_js_regex("a", "y").is_err());
+        assert!(build_js_regex("(a", "").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::utils::time::Clock;
 use crate::EdgelinkError;
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
@@ -15,6 +16,45 @@ pub async fn delay(dur: Duration, cancel: CancellationToken) -> crate::Result<()
     }
 }
 
+/// Same as `delay()` but measured by the `clock`.
+pub async fn delay_on(clock: &dyn Clock, dur: Duration, cancel: CancellationToken) -> crate::Result<()> {
+    tokio::select! {
+        _ = cancel.cancelled() => Err(EdgelinkError::TaskCancelled.into()),
+        _ = clock.sleep(dur) => Ok(()),
+    }
+}
+
+/// Waits for a burst of items from `rx` and collects them by `on_item`, the burst ends once nothing more arrived for
+/// `quiet` measured by the `clock`, every new item restarts the timer.
+///
+/// Returns `false` if cancelled or the sender has gone, the pending burst is discarded then.
+pub async fn recv_debounced<T>(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<T>,
+    quiet: Duration,
+    clock: &dyn Clock,
+    cancel: &CancellationToken,
+    mut on_item: impl FnMut(T),
+) -> bool {
+    tokio::select! {
+        _ = cancel.cancelled() => return false,
+        received = rx.recv() => match received {
+            Some(item) => on_item(item),
+            None => return false,
+        }
+    }
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return false,
+            _ = clock.sleep(quiet) => return true,
+            received = rx.recv() => match received {
+                Some(item) => on_item(item),
+                None => return false,
+            }
+        }
+    }
+}
+
 pub async fn delay_secs_f64(secs: f64, cancel: CancellationToken) -> crate::Result<()> {
     delay(Duration::from_secs_f64(secs), cancel).await
 }
@@ -1,5 +1,8 @@
-use chrono::prelude::Utc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::prelude::{DateTime, Utc};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub fn unix_now() -> i64 {
     let now = SystemTime::now();
@@ -17,3 +20,141 @@ pub fn millis_now() -> String {
     let now = Utc::now();
     now.timestamp_millis().to_string()
 }
+
+/// The source of time for the timing nodes, so the tests can drive the timers deterministically.
+///
+/// The time is monotonic and measured from the creation of the clock, only `now()` reads the wall time.
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Returns the time elapsed since the creation of this clock.
+    fn elapsed(&self) -> Duration;
+
+    /// Returns the wall time, it is not monotonic and may jump when the system time is adjusted.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Returns a future completing after `dur` of this clock.
+    fn sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+}
+
+/// The clock used in production, backed by the monotonic clock of tokio.
+///
+/// It also follows `tokio::time::pause()` and `tokio::time::advance()` in tests.
+#[derive(Debug)]
+pub struct SystemClock {
+    origin: tokio::time::Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { origin: tokio::time::Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(tokio::time::sleep(dur))
+    }
+}
+
+/// A clock that only moves when `MockClock::advance()` was called, its wall time starts at the Unix epoch.
+#[derive(Debug, Default)]
+pub struct MockClock {
+    state: Mutex<MockClockState>,
+}
+
+#[derive(Debug, Default)]
+struct MockClockState {
+    elapsed: Duration,
+    wall: DateTime<Utc>,
+    sleepers: Vec<(Duration, tokio::sync::oneshot::Sender<()>)>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward and wakes up all the sleepers due.
+    pub fn advance(&self, dur: Duration) {
+        let mut state = self.state.lock().expect("MockClock");
+        state.elapsed += dur;
+        state.wall += dur;
+        let now = state.elapsed;
+        let (due, pending): (Vec<_>, Vec<_>) = state.sleepers.drain(..).partition(|(deadline, _)| *deadline <= now);
+        state.sleepers = pending;
+        for (_, waker) in due.into_iter() {
+            // The sleeper may have been dropped
+            let _ = waker.send(());
+        }
+    }
+
+    /// Sets the wall time without moving the monotonic time, like the system time being adjusted.
+    pub fn set_now(&self, now: DateTime<Utc>) {
+        self.state.lock().expect("MockClock").wall = now;
+    }
+
+    /// Returns the number of sleeps waiting for this clock.
+    pub fn pending_sleeps(&self) -> usize {
+        let mut state = self.state.lock().expect("MockClock");
+        state.sleepers.retain(|(_, waker)| !waker.is_closed());
+        state.sleepers.len()
+    }
+}
+
+impl Clock for MockClock {
+    fn elapsed(&self) -> Duration {
+        self.state.lock().expect("MockClock").elapsed
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.state.lock().expect("MockClock").wall
+    }
+
+    fn sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut state = self.state.lock().expect("MockClock");
+        if dur.is_zero() {
+            return Box::pin(std::future::ready(()));
+        }
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let deadline = state.elapsed + dur;
+        state.sleepers.push((deadline, tx));
+        Box::pin(async move {
+            let _ = rx.await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_clock_should_wake_sleepers_when_advanced() {
+        let clock = MockClock::new();
+        let first = tokio::spawn(clock.sleep(Duration::from_secs(1)));
+        let second = tokio::spawn(clock.sleep(Duration::from_secs(3)));
+        assert_eq!(clock.pending_sleeps(), 2);
+
+        clock.advance(Duration::from_secs(2));
+        first.await.unwrap();
+        assert!(!second.is_finished());
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+
+        clock.advance(Duration::from_secs(1));
+        second.await.unwrap();
+        assert_eq!(clock.pending_sleeps(), 0);
+    }
+}
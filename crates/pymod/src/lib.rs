@@ -1,14 +1,67 @@
-use edgelink_core::runtime::model::{ElementId, Msg};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use edgelink_core::runtime::model::{ElementId, Msg, MsgHandle};
 use pyo3::{prelude::*, wrap_pyfunction};
 use serde::Deserialize;
 
 use edgelink_core::runtime::engine::Engine;
 mod json;
+mod variant;
+
+/// How the messages are converted between Python and the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MsgFormat {
+    /// Through the JSON, the `bytes` become lists of integers and the dates become timestamps in milliseconds, negative
+    /// before the epoch. Converting the outgoing messages cannot fail.
+    Json,
+    /// Directly to `Variant`, keeps the `bytes` and `datetime` values.
+    Native,
+}
+
+impl MsgFormat {
+    fn parse(s: &str) -> PyResult<Self> {
+        match s {
+            "json" => Ok(MsgFormat::Json),
+            "native" => Ok(MsgFormat::Native),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown message format '{}', expected 'json' or 'native'",
+                s
+            ))),
+        }
+    }
+}
+
+fn py_to_msgs_to_inject(format: MsgFormat, msgs: &PyAny) -> PyResult<Vec<(ElementId, Msg)>> {
+    match format {
+        MsgFormat::Json => {
+            let json_msgs = json::py_object_to_json_value(msgs)?;
+            Vec::<(ElementId, Msg)>::deserialize(json_msgs)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+        }
+        MsgFormat::Native => {
+            let mut result = Vec::new();
+            for item in msgs.iter()? {
+                let (nid, msg): (String, &PyAny) = item?.extract()?;
+                let nid = nid
+                    .parse::<ElementId>()
+                    .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+                match variant::py_to_variant(msg)? {
+                    edgelink_core::runtime::model::Variant::Object(body) => result.push((nid, Msg::with_body(body))),
+                    _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("The msg must be a dict")),
+                }
+            }
+            Ok(result)
+        }
+    }
+}
 
 #[pymodule]
 fn edgelink_pymod(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(rust_sleep, m)?)?;
     m.add_function(wrap_pyfunction!(run_flows_once, m)?)?;
+    m.add_function(wrap_pyfunction!(stream_flows, m)?)?;
+    m.add_class::<FlowStream>()?;
 
     let stderr = log4rs::append::console::ConsoleAppender::builder()
         .target(log4rs::append::console::Target::Stderr)
@@ -34,21 +87,8 @@ fn rust_sleep(py: Python) -> PyResult<&PyAny> {
     })
 }
 
-#[pyfunction]
-fn run_flows_once<'a>(
-    py: Python<'a>,
-    _expected_msgs: usize,
-    _timeout: f64,
-    py_json: &'a PyAny,
-    msgs_json: &'a PyAny,
-    app_cfg: &'a PyAny,
-) -> PyResult<&'a PyAny> {
+fn build_engine(py_json: &PyAny, app_cfg: &PyAny) -> PyResult<Engine> {
     let flows_json = json::py_object_to_json_value(py_json)?;
-    let msgs_to_inject = {
-        let json_msgs = json::py_object_to_json_value(msgs_json)?;
-        Vec::<(ElementId, Msg)>::deserialize(json_msgs)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?
-    };
     let app_cfg = {
         if !app_cfg.is_none() {
             let app_cfg_json = json::py_object_to_json_value(app_cfg)?;
@@ -64,8 +104,39 @@ fn run_flows_once<'a>(
         .build()
         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
 
-    let engine = Engine::with_json(&registry, flows_json, app_cfg.as_ref())
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+    Engine::with_json(&registry, flows_json, app_cfg.as_ref())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+}
+
+fn runtime_error(e: impl std::fmt::Display) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e))
+}
+
+fn msg_to_py(py: Python, format: MsgFormat, msg: &Msg) -> PyResult<PyObject> {
+    match format {
+        MsgFormat::Json => json::json_value_to_py_object(py, &msg.to_json_value()),
+        MsgFormat::Native => variant::variant_to_py(py, msg.as_variant()),
+    }
+}
+
+/// The `format` is either `"json"` (the default) or `"native"`, see `MsgFormat`.
+///
+/// In the `"native"` format a naive `datetime` is read as the local time, like `datetime.timestamp()` does, pass an
+/// aware one to avoid depending on the time zone of the host. The values of the other types raise a `TypeError`.
+#[pyfunction]
+#[pyo3(signature = (_expected_msgs, _timeout, py_json, msgs_json, app_cfg, *, format = "json"))]
+fn run_flows_once<'a>(
+    py: Python<'a>,
+    _expected_msgs: usize,
+    _timeout: f64,
+    py_json: &'a PyAny,
+    msgs_json: &'a PyAny,
+    app_cfg: &'a PyAny,
+    format: &str,
+) -> PyResult<&'a PyAny> {
+    let format = MsgFormat::parse(format)?;
+    let msgs_to_inject = py_to_msgs_to_inject(format, msgs_json)?;
+    let engine = build_engine(py_json, app_cfg)?;
 
     pyo3_asyncio::tokio::future_into_py(py, async move {
         let msgs = engine
@@ -73,12 +144,122 @@ fn run_flows_once<'a>(
             .await
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
 
-        let result_value = serde_json::to_value(&msgs)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
-
         Python::with_gil(|py| {
-            let pyo = json::json_value_to_py_object(py, &result_value)?;
-            Ok(pyo.to_object(py))
+            let list = pyo3::types::PyList::empty(py);
+            for msg in msgs.iter() {
+                list.append(msg_to_py(py, format, msg)?)?;
+            }
+            Ok(list.to_object(py))
         })
     })
 }
+
+/// Starts the flows and returns a `FlowStream` yielding the messages arrived at the `test-once` nodes as they come.
+///
+/// The messages are pulled one by one, the flows wait when the engine holds too many of them not pulled yet. The
+/// messages are converted like `run_flows_once()`.
+#[pyfunction]
+#[pyo3(signature = (py_json, msgs_json, app_cfg, *, format = "json"))]
+fn stream_flows<'a>(
+    py: Python<'a>,
+    py_json: &'a PyAny,
+    msgs_json: &'a PyAny,
+    app_cfg: &'a PyAny,
+    format: &str,
+) -> PyResult<&'a PyAny> {
+    let format = MsgFormat::parse(format)?;
+    let msgs_to_inject = py_to_msgs_to_inject(format, msgs_json)?;
+    let engine = build_engine(py_json, app_cfg)?;
+
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        engine.start().await.map_err(runtime_error)?;
+        let cancel = tokio_util::sync::CancellationToken::new();
+        for (nid, msg) in msgs_to_inject.into_iter() {
+            engine.inject_msg(&nid, MsgHandle::new(msg), cancel.clone()).await.map_err(runtime_error)?;
+        }
+        let stream = FlowStream { engine, format, stopped: Arc::new(AtomicBool::new(false)) };
+        Python::with_gil(|py| Ok(stream.into_py(py)))
+    })
+}
+
+/// An async iterator of the messages of the running flows, see `stream_flows()`.
+///
+/// It ends after `stop()`, and cancelling a pending `__anext__()` from Python stops the engine too.
+#[pyclass]
+struct FlowStream {
+    engine: Engine,
+    format: MsgFormat,
+    stopped: Arc<AtomicBool>,
+}
+
+/// Stops the engine if the future owning it is dropped before completion, e.g. cancelled by asyncio.
+struct StopOnDrop {
+    engine: Option<Engine>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl StopOnDrop {
+    /// Keeps the engine running when dropped.
+    fn disarm(&mut self) {
+        self.engine = None;
+    }
+}
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            stop_engine(engine, &self.stopped);
+        }
+    }
+}
+
+fn stop_engine(engine: Engine, stopped: &AtomicBool) {
+    if !stopped.swap(true, Ordering::SeqCst) {
+        pyo3_asyncio::tokio::get_runtime().spawn(async move {
+            if let Err(e) = engine.stop().await {
+                log::warn!("Failed to stop the engine: {}", e);
+            }
+        });
+    }
+}
+
+#[pymethods]
+impl FlowStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'a>(&self, py: Python<'a>) -> PyResult<Option<&'a PyAny>> {
+        let engine = self.engine.clone();
+        let format = self.format;
+        let mut guard = StopOnDrop { engine: Some(engine.clone()), stopped: self.stopped.clone() };
+        let future = pyo3_asyncio::tokio::future_into_py(py, async move {
+            let msg = engine.next_final_msg().await;
+            // Received or ended normally, nothing to stop
+            guard.disarm();
+            match msg {
+                Some(msg) => Python::with_gil(|py| msg_to_py(py, format, &msg)),
+                None => Err(PyErr::new::<pyo3::exceptions::PyStopAsyncIteration, _>("The flows have stopped")),
+            }
+        })?;
+        Ok(Some(future))
+    }
+
+    /// Stops the engine, the pending and the later `__anext__()` end the iteration.
+    fn stop<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
+        let engine = self.engine.clone();
+        let stopped = self.stopped.clone();
+        pyo3_asyncio::tokio::future_into_py(py, async move {
+            if !stopped.swap(true, Ordering::SeqCst) {
+                engine.stop().await.map_err(runtime_error)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for FlowStream {
+    fn drop(&mut self) {
+        stop_engine(self.engine.clone(), &self.stopped);
+    }
+}
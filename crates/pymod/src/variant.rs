@@ -0,0 +1,100 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use edgelink_core::runtime::model::{Variant, VariantObjectMap};
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyByteArray, PyBytes, PyDateTime, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+
+/// Converts a Python object to `Variant` directly, unlike the JSON path the `bytes` and `datetime` are kept.
+///
+/// Raises a `TypeError` for the objects of the other types instead of dropping them silently.
+pub fn py_to_variant(obj: &PyAny) -> PyResult<Variant> {
+    if obj.is_none() {
+        Ok(Variant::Null)
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        Ok(Variant::Array(list.iter().map(py_to_variant).collect::<PyResult<Vec<_>>>()?))
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        Ok(Variant::Array(tuple.iter().map(py_to_variant).collect::<PyResult<Vec<_>>>()?))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = VariantObjectMap::new();
+        for (key, value) in dict.iter() {
+            map.insert(key.extract::<String>()?.into(), py_to_variant(value)?);
+        }
+        Ok(Variant::Object(map))
+    } else if let Ok(boolean) = obj.downcast::<PyBool>() {
+        Ok(Variant::Bool(boolean.is_true()))
+    } else if let Ok(int) = obj.downcast::<PyInt>() {
+        if let Ok(i) = int.extract::<i64>() {
+            Ok(Variant::from(i))
+        } else if let Ok(u) = int.extract::<u64>() {
+            Ok(Variant::from(u))
+        } else {
+            Ok(Variant::from(int.extract::<f64>()?))
+        }
+    } else if let Ok(float) = obj.downcast::<PyFloat>() {
+        Ok(Variant::from(float.value()))
+    } else if let Ok(string) = obj.downcast::<PyString>() {
+        Ok(Variant::String(string.to_str()?.to_string()))
+    } else if let Ok(bytes) = obj.downcast::<PyBytes>() {
+        Ok(Variant::Bytes(bytes.as_bytes().to_vec().into()))
+    } else if let Ok(bytes) = obj.downcast::<PyByteArray>() {
+        Ok(Variant::Bytes(bytes.to_vec().into()))
+    } else if obj.downcast::<PyDateTime>().is_ok() {
+        // A naive `datetime` is in the local time, just like `datetime.timestamp()` takes it
+        let ms = (obj.call_method0("timestamp")?.extract::<f64>()? * 1000.0).round();
+        let time = if ms >= 0.0 {
+            UNIX_EPOCH + Duration::from_millis(ms as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_millis(-ms as u64)
+        };
+        Ok(Variant::Date(time))
+    } else {
+        let type_name = obj.get_type().name().unwrap_or("<unknown>");
+        Err(PyTypeError::new_err(format!("Cannot convert an object of type '{}' to a message value", type_name)))
+    }
+}
+
+/// The reverse of `py_to_variant()`, a `Date` becomes an aware `datetime` in UTC.
+pub fn variant_to_py(py: Python, value: &Variant) -> PyResult<PyObject> {
+    match value {
+        Variant::Null => Ok(py.None()),
+        Variant::Bool(b) => Ok(b.into_py(py)),
+        Variant::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(i.to_object(py))
+            } else if let Some(u) = n.as_u64() {
+                Ok(u.to_object(py))
+            } else if let Some(f) = n.as_f64() {
+                Ok(PyFloat::new(py, f).into())
+            } else {
+                Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("Invalid number type"))
+            }
+        }
+        Variant::String(s) => Ok(PyString::new(py, s).into()),
+        Variant::Regexp(re) => Ok(PyString::new(py, re.as_str()).into()),
+        Variant::Bytes(bytes) => Ok(PyBytes::new(py, bytes).into()),
+        Variant::Date(time) => {
+            let secs = match time.duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_millis() as f64 / 1000.0,
+                Err(e) => -(e.duration().as_millis() as f64) / 1000.0,
+            };
+            let datetime = py.import("datetime")?;
+            let utc = datetime.getattr("timezone")?.getattr("utc")?;
+            Ok(datetime.getattr("datetime")?.call_method1("fromtimestamp", (secs, utc))?.into())
+        }
+        Variant::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items.iter() {
+                list.append(variant_to_py(py, item)?)?;
+            }
+            Ok(list.into())
+        }
+        Variant::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map.iter() {
+                dict.set_item(key.as_str(), variant_to_py(py, value)?)?;
+            }
+            Ok(dict.into())
+        }
+    }
+}
@@ -14,7 +14,7 @@ For more information, visit the website: https://github.com/oldrev/edgelink
 #[derive(Parser, Debug, Clone)]
 #[command(version, about, author, long_about=LONG_ABOUT, color=clap::ColorChoice::Always)]
 pub struct CliArgs {
-    /// Path of the 'flows.json' file.
+    /// Path or `http(s)://` URL of the 'flows.json' file.
     #[clap(default_value_t = default_flows_path(), conflicts_with = "stdin")]
     pub flows_path: String,
 
@@ -26,6 +26,13 @@ pub struct CliArgs {
     #[arg(short, long)]
     pub log_path: Option<String>,
 
+    /// Format of the log lines written to stderr, `json` writes one JSON object per line.
+    ///
+    /// The log configuration file of `--log-path` sets the encoders of its own appenders, e.g. `encoder = { kind =
+    /// "json" }`, so the two options cannot be used together.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, conflicts_with = "log_path")]
+    pub log_format: LogFormat,
+
     /// Use verbose output, '0' means quiet, no output printed to stdout.
     #[arg(short, long, default_value_t = 2)]
     pub verbose: usize,
@@ -34,11 +41,25 @@ pub struct CliArgs {
     #[arg(long, default_value_t = false)]
     pub stdin: bool,
 
+    /// Validate the flows JSON without running it, prints the diagnostics and exits non-zero on errors.
+    #[arg(long, default_value_t = false)]
+    pub check: bool,
+
+    /// Reload the flows when the flows file changed.
+    #[arg(long, default_value_t = false)]
+    pub watch: bool,
+
     /// Set the running environment in 'dev' or 'prod', default is `dev`
     #[arg(long)]
     pub env: Option<String>,
 }
 
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
 fn default_flows_path() -> String {
     dirs_next::home_dir()
         .expect("Can not found the $HOME dir!!!")
@@ -47,3 +68,21 @@ fn default_flows_path() -> String {
         .to_string_lossy()
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format_should_conflict_with_log_path() {
+        let err =
+            CliArgs::try_parse_from(["edgelinkd", "flows.json", "--log-path", "log.toml", "--log-format", "json"])
+                .unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+
+        let args = CliArgs::try_parse_from(["edgelinkd", "flows.json", "--log-path", "log.toml"]).unwrap();
+        assert_eq!(args.log_format, LogFormat::Text);
+        let args = CliArgs::try_parse_from(["edgelinkd", "flows.json", "--log-format", "json"]).unwrap();
+        assert_eq!(args.log_format, LogFormat::Json);
+    }
+}
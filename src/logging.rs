@@ -1,12 +1,23 @@
-use crate::CliArgs;
+use std::io::Write as _;
+
+use log4rs::config::Deserializers;
+use log4rs::encode::Encode;
+
+use crate::{CliArgs, LogFormat};
 
 pub(crate) fn log_init(elargs: &CliArgs) {
     if let Some(ref log_path) = elargs.log_path {
-        log4rs::init_file(log_path, Default::default()).unwrap();
+        let mut deserializers = Deserializers::default();
+        deserializers.insert("json", JsonEncoderDeserializer);
+        log4rs::init_file(log_path, deserializers).unwrap();
     } else {
+        let encoder: Box<dyn Encode> = match elargs.log_format {
+            LogFormat::Text => Box::new(log4rs::encode::pattern::PatternEncoder::new("[{h({l})}]\t{m}{n}")),
+            LogFormat::Json => Box::new(JsonEncoder),
+        };
         let stderr = log4rs::append::console::ConsoleAppender::builder()
             .target(log4rs::append::console::Target::Stderr)
-            .encoder(Box::new(log4rs::encode::pattern::PatternEncoder::new("[{h({l})}]\t{m}{n}")))
+            .encoder(encoder)
             .build();
 
         let level = match elargs.verbose {
@@ -25,3 +36,92 @@ pub(crate) fn log_init(elargs: &CliArgs) {
         let _ = log4rs::init_config(config).unwrap();
     }
 }
+
+/// Writes every record as a single-line JSON object for the log aggregators.
+///
+/// The `[type:name]` prefix of the node logs becomes the `node_type` and `node` fields. It is also available as the
+/// `json` encoder kind in the log configuration file, e.g. `encoder = { kind = "json" }`.
+#[derive(Debug, Default)]
+pub(crate) struct JsonEncoder;
+
+impl JsonEncoder {
+    fn to_json(record: &log::Record) -> serde_json::Value {
+        let message = record.args().to_string();
+        let mut entry = serde_json::json!({
+            "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "thread": std::thread::current().name().unwrap_or_default(),
+        });
+        if let Some((node_type, node)) = parse_node_prefix(&message) {
+            entry["node_type"] = node_type.into();
+            entry["node"] = node.into();
+        }
+        entry["message"] = message.into();
+        entry
+    }
+}
+
+impl Encode for JsonEncoder {
+    fn encode(&self, w: &mut dyn log4rs::encode::Write, record: &log::Record) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut *w, &Self::to_json(record))?;
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonEncoderConfig {}
+
+struct JsonEncoderDeserializer;
+
+impl log4rs::config::Deserialize for JsonEncoderDeserializer {
+    type Trait = dyn Encode;
+
+    type Config = JsonEncoderConfig;
+
+    fn deserialize(&self, _config: JsonEncoderConfig, _: &Deserializers) -> anyhow::Result<Box<dyn Encode>> {
+        Ok(Box::new(JsonEncoder))
+    }
+}
+
+/// Splits the `[debug:dbg]` prefix of a node log into the node type and the node name or ID.
+fn parse_node_prefix(message: &str) -> Option<(&str, &str)> {
+    let (prefix, _) = message.strip_prefix('[')?.split_once(']')?;
+    let (node_type, node) = prefix.split_once(':')?;
+    let is_type = !node_type.is_empty() && node_type.chars().all(|c| c.is_ascii_alphanumeric() || "-_ ".contains(c));
+    is_type.then_some((node_type, node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log4rs::encode::writer::simple::SimpleWriter;
+
+    fn encode(level: log::Level, args: std::fmt::Arguments) -> serde_json::Value {
+        let mut writer = SimpleWriter(Vec::new());
+        let record = log::Record::builder().args(args).level(level).target("edgelink_core::runtime").build();
+        JsonEncoder.encode(&mut writer, &record).unwrap();
+        let line = String::from_utf8(writer.0).unwrap();
+        assert_eq!(line.matches('\n').count(), 1);
+        assert!(line.ends_with('\n'));
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn test_it_should_write_a_json_line_per_record() {
+        let entry = encode(log::Level::Warn, format_args!("[function:fn1] Javascript user function \"error\"\n42"));
+        assert_eq!(entry["level"], "WARN");
+        assert_eq!(entry["target"], "edgelink_core::runtime");
+        assert_eq!(entry["node_type"], "function");
+        assert_eq!(entry["node"], "fn1");
+        assert_eq!(entry["message"], "[function:fn1] Javascript user function \"error\"\n42");
+        assert!(chrono::DateTime::parse_from_rfc3339(entry["time"].as_str().unwrap()).is_ok());
+
+        let entry = encode(log::Level::Info, format_args!("Starting EdgeLink run-time engine..."));
+        assert_eq!(entry["level"], "INFO");
+        assert_eq!(entry["message"], "Starting EdgeLink run-time engine...");
+        assert!(entry.get("node_type").is_none() && entry.get("node").is_none());
+    }
+}
@@ -1,15 +1,17 @@
 // use std::env;
-use std::io::{self, Read};
+use std::io;
+use std::path::Path;
 use std::process;
 use std::str::FromStr;
 use std::sync::Arc;
 
 // 3rd-party libs
 use clap::Parser;
-use runtime::engine::Engine;
+use runtime::engine::{Engine, RuntimeArgs};
 use runtime::registry::RegistryHandle;
+use runtime::validation::Severity;
 use serde::Deserialize;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 use tokio_util::sync::CancellationToken;
 
 use edgelink_core::runtime::model::*;
@@ -22,6 +24,7 @@ include!(concat!(env!("OUT_DIR"), "/__use_node_plugins.rs"));
 mod cliargs;
 mod consts;
 mod logging;
+mod watcher;
 
 pub use cliargs::*;
 
@@ -35,24 +38,28 @@ pub struct MsgInjectionEntry {
 
 #[derive(Debug)]
 struct App {
-    _registry: RegistryHandle,
-    engine: Engine,
+    registry: RegistryHandle,
+    app_config: Option<config::Config>,
+    engine: RwLock<Engine>,
+    /// The flows JSON of the running engine if it was loaded from a file, to restart it if a reload fails.
+    flows_json: Mutex<Option<serde_json::Value>>,
     msgs_to_inject: Mutex<Vec<MsgInjectionEntry>>,
+    /// The flows file to reload on changes, see the `--watch` option.
+    watch_path: Option<String>,
 }
 
 impl App {
-    pub fn default(elargs: Arc<CliArgs>, app_config: Option<&config::Config>) -> edgelink_core::Result<Self> {
+    pub async fn default(elargs: Arc<CliArgs>, app_config: Option<&config::Config>) -> edgelink_core::Result<Self> {
         log::info!("Discovering all nodes...");
         // edgelink_core::runtime::registry::collect_nodes();
         log::info!("Loading node registry...");
         let reg = RegistryBuilder::default().build()?;
 
         let mut msgs_to_inject = Vec::new();
+        let mut flows_json = None;
 
-        log::info!("Loading flows file: {}", elargs.flows_path);
         let engine = if elargs.stdin {
-            let mut buffer = Vec::new();
-            io::stdin().read_to_end(&mut buffer)?;
+            let buffer = edgelink_core::runtime::engine::read_flows_json(io::stdin())?;
 
             // This is a flow JSON and following some messages to inject
             let flows_json_value = if !buffer.is_empty() && buffer[0] == json_seq::RS_CHAR {
@@ -98,28 +105,140 @@ impl App {
                 serde_json::from_str(&json_str)?
             };
             Engine::with_json(&reg, flows_json_value, app_config)?
+        } else if is_url(&elargs.flows_path) {
+            log::info!("Loading flows from URL: {}", elargs.flows_path);
+            Self::load_flows_url(&reg, &elargs.flows_path, app_config).await?
         } else {
-            Engine::with_flows_file(&reg, &elargs.flows_path, app_config)?
+            log::info!("Loading flows file: {}", elargs.flows_path);
+            let flows_json_value = Self::read_flows_file(&elargs.flows_path)?;
+            flows_json = Some(flows_json_value.clone());
+            Engine::with_json(&reg, flows_json_value, app_config)?
         };
 
-        Ok(App { _registry: reg, engine, msgs_to_inject: Mutex::new(msgs_to_inject) })
+        let summary = engine.summary();
+        log::info!("Loaded {}", summary);
+        for (type_name, ids) in summary.unknown_types.iter() {
+            log::warn!("Unknown node type '{}' used by {} node(s), these nodes will do nothing", type_name, ids.len());
+        }
+
+        let watch_path = match elargs.watch {
+            true if elargs.stdin || is_url(&elargs.flows_path) => {
+                log::warn!("Only the local flows file can be watched, ignored the `--watch` option");
+                None
+            }
+            true => Some(elargs.flows_path.clone()),
+            false => None,
+        };
+        Ok(App::new(reg, engine, flows_json, app_config.cloned(), msgs_to_inject, watch_path))
+    }
+
+    fn new(
+        registry: RegistryHandle,
+        engine: Engine,
+        flows_json: Option<serde_json::Value>,
+        app_config: Option<config::Config>,
+        msgs_to_inject: Vec<MsgInjectionEntry>,
+        watch_path: Option<String>,
+    ) -> Self {
+        App {
+            registry,
+            app_config,
+            engine: RwLock::new(engine),
+            flows_json: Mutex::new(flows_json),
+            msgs_to_inject: Mutex::new(msgs_to_inject),
+            watch_path,
+        }
+    }
+
+    /// Replaces the running engine with a new one loaded from the flows file.
+    ///
+    /// The current engine is stopped before the new one starts, so the listeners are never bound twice and the
+    /// `once` injects never fire twice. The current engine keeps running if the new flows cannot be loaded, and the
+    /// previous flows are loaded again if the new ones fail to start.
+    async fn reload_flows(&self, flows_path: &str) -> crate::Result<()> {
+        let flows_json = Self::read_flows_file(flows_path)?;
+        let new_engine = Engine::with_json(&self.registry, flows_json.clone(), self.app_config.as_ref())?;
+        let mut engine = self.engine.write().await;
+        engine.stop().await?;
+        if let Err(err) = new_engine.start().await {
+            // A stopped engine cannot be started again, so the previous flows are loaded from scratch
+            if let Some(previous_json) = self.flows_json.lock().await.clone() {
+                let previous_engine = Engine::with_json(&self.registry, previous_json, self.app_config.as_ref())?;
+                previous_engine.start().await?;
+                *engine = previous_engine;
+                log::warn!("Failed to start the reloaded flows, the previous flows have been restarted.");
+            }
+            return Err(err);
+        }
+        *engine = new_engine;
+        *self.flows_json.lock().await = Some(flows_json);
+        log::info!("The flows have been reloaded.");
+        Ok(())
+    }
+
+    fn read_flows_file(flows_path: &str) -> crate::Result<serde_json::Value> {
+        let buffer = edgelink_core::runtime::engine::read_flows_json(std::fs::File::open(flows_path)?)?;
+        Ok(serde_json::from_slice(&buffer)?)
+    }
+
+    #[cfg(feature = "net")]
+    async fn load_flows_url(
+        reg: &RegistryHandle,
+        url: &str,
+        app_config: Option<&config::Config>,
+    ) -> edgelink_core::Result<Engine> {
+        Engine::with_flows_url(reg, url, app_config).await
+    }
+
+    #[cfg(not(feature = "net"))]
+    async fn load_flows_url(
+        _reg: &RegistryHandle,
+        url: &str,
+        _app_config: Option<&config::Config>,
+    ) -> edgelink_core::Result<Engine> {
+        Err(EdgelinkError::NotSupported(format!("Loading flows from '{}' requires the `net` feature", url)).into())
     }
 
     async fn main_flow_task(self: Arc<Self>, cancel: CancellationToken) -> crate::Result<()> {
-        self.engine.start().await?;
+        self.engine.read().await.start().await?;
 
         // Inject msgs
         {
+            let engine = self.engine.read().await;
             let mut entries = self.msgs_to_inject.lock().await;
             for e in entries.iter() {
-                self.engine.inject_msg(&e.nid, e.msg.clone(), cancel.clone()).await?;
+                engine.inject_msg(&e.nid, e.msg.clone(), cancel.clone()).await?;
             }
             entries.clear();
         }
 
+        if let Some(watch_path) = self.watch_path.clone() {
+            let app = self.clone();
+            let on_change = || {
+                let app = app.clone();
+                let watch_path = watch_path.clone();
+                async move {
+                    // Never let a bad flows file crash the process
+                    if let Err(err) = app.reload_flows(&watch_path).await {
+                        log::error!("Failed to reload the flows: {:?}", err);
+                    }
+                }
+            };
+            let watch_result = watcher::watch_flows_file(
+                Path::new(&watch_path),
+                watcher::DEFAULT_WATCH_DEBOUNCE,
+                cancel.clone(),
+                on_change,
+            )
+            .await;
+            if let Err(err) = watch_result {
+                log::error!("Failed to watch the flows file '{}': {:?}", watch_path, err);
+            }
+        }
+
         cancel.cancelled().await;
 
-        self.engine.stop().await?;
+        self.engine.read().await.stop().await?;
         log::info!("The flows engine stopped.");
         Ok(())
     }
@@ -150,6 +269,49 @@ impl App {
     }
 }
 
+fn is_url(flows_path: &str) -> bool {
+    flows_path.starts_with("http://") || flows_path.starts_with("https://")
+}
+
+/// Validates the flows JSON for the `--check` option, one diagnostic per line like `flows.json: error: [id] ...`.
+///
+/// Returns `false` if there is any error, the warnings are only printed.
+fn check_flows(cli_args: &CliArgs) -> bool {
+    let source = if cli_args.stdin { "<stdin>" } else { cli_args.flows_path.as_str() };
+    let json = if is_url(source) {
+        Err(anyhow::anyhow!("Checking the flows from a URL is not supported"))
+    } else if cli_args.stdin {
+        runtime::engine::read_flows_json(io::stdin())
+    } else {
+        std::fs::File::open(source).map_err(Into::into).and_then(runtime::engine::read_flows_json)
+    }
+    .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).map_err(Into::into));
+    let json = match json {
+        Ok(json) => json,
+        Err(err) => {
+            println!("{}: error: {:#}", source, err);
+            return false;
+        }
+    };
+
+    let reg = match RegistryBuilder::default().build() {
+        Ok(reg) => reg,
+        Err(err) => {
+            println!("{}: error: Failed to build the node registry: {:#}", source, err);
+            return false;
+        }
+    };
+    let diagnostics = Engine::validate(&reg, &json);
+    for diagnostic in diagnostics.iter() {
+        println!("{}: {}", source, diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|x| x.severity == Severity::Error).count();
+    if cli_args.verbose > 0 {
+        eprintln!("{}: {} error(s), {} warning(s)", source, errors, diagnostics.len() - errors);
+    }
+    errors == 0
+}
+
 fn load_config(cli_args: &CliArgs) -> anyhow::Result<Option<config::Config>> {
     // Load configuration from default, development, and production files
     let home_dir = dirs_next::home_dir()
@@ -192,13 +354,7 @@ fn load_config(cli_args: &CliArgs) -> anyhow::Result<Option<config::Config>> {
     Ok(None)
 }
 
-async fn app_main(cli_args: Arc<CliArgs>) -> anyhow::Result<()> {
-    if cli_args.verbose > 0 {
-        eprintln!("EdgeLink v{} - #{}\n", consts::APP_VERSION, consts::GIT_HASH);
-        eprintln!("Loading configuration..");
-    }
-    let cfg = load_config(&cli_args)?;
-
+async fn app_main(cli_args: Arc<CliArgs>, cfg: Option<config::Config>) -> anyhow::Result<()> {
     if cli_args.verbose > 0 {
         eprintln!("Initializing logging sub-system...\n");
     }
@@ -226,7 +382,7 @@ async fn app_main(cli_args: Arc<CliArgs>) -> anyhow::Result<()> {
     log::info!("Starting EdgeLink run-time engine...");
     log::info!("Press CTRL+C to terminate.");
 
-    let app = Arc::new(App::default(cli_args, cfg.as_ref())?);
+    let app = Arc::new(App::default(cli_args, cfg.as_ref()).await?);
     let app_result = app.run(cancel.child_token()).await;
 
     tokio::time::timeout(tokio::time::Duration::from_secs(10), cancel.cancelled()).await?;
@@ -235,12 +391,74 @@ async fn app_main(cli_args: Arc<CliArgs>) -> anyhow::Result<()> {
     app_result
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let args = Arc::new(CliArgs::parse());
-    if let Err(ref err) = app_main(args).await {
+    if args.check {
+        process::exit(if check_flows(&args) { 0 } else { 1 });
+    }
+    if args.verbose > 0 {
+        eprintln!("EdgeLink v{} - #{}\n", consts::APP_VERSION, consts::GIT_HASH);
+        eprintln!("Loading configuration..");
+    }
+
+    // The runtime is tuned by the configuration, so it has to be loaded first
+    let result = load_config(&args).and_then(|cfg| {
+        let runtime = RuntimeArgs::load(cfg.as_ref())?.build_runtime()?;
+        runtime.block_on(app_main(args, cfg))
+    });
+    if let Err(ref err) = result {
         eprintln!("Application error: {}", err);
         process::exit(-1);
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_it_should_reload_flows_when_the_file_changed() {
+        let dir = std::env::temp_dir().join(format!("edgelink-watch-{}", ElementId::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("flows.json");
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": []}
+        ]);
+        std::fs::write(&path, flows_json.to_string()).unwrap();
+
+        let reg = RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&reg, flows_json.clone(), None).unwrap();
+        let watch_path = Some(path.to_string_lossy().to_string());
+        let app = Arc::new(App::new(reg, engine, Some(flows_json), None, Vec::new(), watch_path));
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn(app.clone().main_flow_task(cancel.clone()));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // A broken file is reported and the running flows are kept
+        std::fs::write(&path, "[{").unwrap();
+        tokio::time::sleep(watcher::DEFAULT_WATCH_DEBOUNCE * 2).await;
+        assert!(app.engine.read().await.find_flow_node_by_id(&"1".parse().unwrap()).is_some());
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "junction", "wires": []}
+        ]);
+        std::fs::write(&path, flows_json.to_string()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while app.engine.read().await.find_flow_node_by_id(&"2".parse().unwrap()).is_none() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        cancel.cancel();
+        task.await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,50 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use edgelink_core::utils::async_util;
+use edgelink_core::utils::time::SystemClock;
+use notify::{RecursiveMode, Watcher};
+use tokio_util::sync::CancellationToken;
+
+/// The quiet period after the last change before the flows file is reloaded.
+pub const DEFAULT_WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the flows file and calls `on_change` once the file stopped changing for `debounce`.
+///
+/// The parent directory is watched instead of the file itself, so the editors that save by renaming a
+/// temporary file are also covered.
+pub async fn watch_flows_file<F, Fut>(
+    path: &Path,
+    debounce: Duration,
+    cancel: CancellationToken,
+    mut on_change: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let path = std::fs::canonicalize(path)?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    let file_name = path.file_name().map(|x| x.to_os_string());
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+        Ok(event) => {
+            if event.paths.iter().any(|p| p.file_name().map(|x| x.to_os_string()) == file_name) {
+                let _ = tx.send(());
+            }
+        }
+        Err(err) => log::warn!("Failed to watch the flows file: {}", err),
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    log::info!("Watching the flows file: {}", path.display());
+
+    let clock = SystemClock::new();
+    // Wait until the writer has finished, every new change restarts the timer
+    while async_util::recv_debounced(&mut rx, debounce, &clock, &cancel, |_| {}).await {
+        log::info!("The flows file has been changed, reloading...");
+        on_change().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Runs `edgelinkd --check` against the flows files.
+
+use std::process::{Command, Output};
+
+fn run_check(name: &str, flows_json: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("edgelink-check-{}-{}.json", std::process::id(), name));
+    std::fs::write(&path, flows_json).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_edgelinkd")).arg("--check").arg(&path).output().unwrap();
+    let _ = std::fs::remove_file(&path);
+    output
+}
+
+#[test]
+fn test_it_should_fail_with_the_diagnostics_of_invalid_flows() {
+    let flows_json = r#"[
+        {"id": "100", "type": "tab"},
+        {"id": "1", "z": "100", "type": "junction", "wires": [["999"]]},
+        {"id": "1", "z": "100", "type": "junction", "wires": []}
+    ]"#;
+    let output = run_check("invalid", flows_json);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+
+    let errors: Vec<&str> = stdout.lines().filter(|x| x.contains(": error: ")).collect();
+    assert!(errors.iter().any(|x| x.contains("Duplicated element ID")), "{}", stdout);
+    assert!(errors.iter().any(|x| x.contains("Wired to a missing node")), "{}", stdout);
+    assert!(errors.iter().all(|x| x.contains("edgelink-check-")), "{}", stdout);
+}
+
+#[test]
+fn test_it_should_succeed_with_valid_flows() {
+    let flows_json = r#"[
+        {"id": "100", "type": "tab"},
+        {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+        {"id": "2", "z": "100", "type": "junction", "wires": []}
+    ]"#;
+    let output = run_check("valid", flows_json);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_it_should_fail_on_malformed_json() {
+    let output = run_check("malformed", "[{");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(": error: "));
+}
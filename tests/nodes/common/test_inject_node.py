@@ -427,7 +427,7 @@ class TestInjectNode:
                     {"p": "topic", "v": "t1", "vt": "str"},
                     {"p": "payload", "v": "foo", "vt": "str"},
                     {"p": "x", "v": "10", "vt": "num"},
-                    # {"p": "y", "v": "x+2", "vt": "jsonata"} #TODO FIXME
+                    {"p": "y", "v": "x+2", "vt": "jsonata"}
                 ],
                 "wires": [["2"]],
             },
@@ -439,7 +439,7 @@ class TestInjectNode:
         assert msg["topic"] == "t1"
         assert msg["payload"] == "foo"
         assert msg["x"] == 10
-        # assert msg["y"] == 12
+        assert msg["y"] == 12
 
     """
     # EdgeLink doesn't support the msg injection for `inject` node
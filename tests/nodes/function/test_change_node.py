@@ -377,6 +377,39 @@ class TestChangeNode:
             msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
             assert msgs[0]['payload'] == {"a": 123}
 
+        @pytest.mark.asyncio
+        @pytest.mark.it('''merges a js object into the existing object if deepMerge is set''')
+        async def test_set_20_deep_merge(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "z": "100",
+                 "rules": [{"t": "set", "p": "payload", "to": '{"b":{"d":4},"e":[3]}', "tot": "json",
+                            "deepMerge": True, "mergeArrays": "concat"}],
+                 "name": "changeNode", "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": {"a": 1, "b": {"c": 3}, "e": [1, 2]}}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+            assert msgs[0]['payload'] == {"a": 1, "b": {"c": 3, "d": 4}, "e": [1, 2, 3]}
+
+        @pytest.mark.asyncio
+        @pytest.mark.it('''replaces the existing object if deepMerge is not set''')
+        async def test_set_20_replace(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "z": "100",
+                 "rules": [{"t": "set", "p": "payload", "to": '{"b":2}', "tot": "json"}],
+                 "name": "changeNode", "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": {"a": 1}}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+            assert msgs[0]['payload'] == {"b": 2}
+
         @pytest.mark.asyncio
         @pytest.mark.it('''changes the value to a buffer object''')
         async def test_set_21(self):
@@ -508,6 +541,24 @@ class TestChangeNode:
             msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
             assert msgs[0]["payload"] == 2
 
+        @pytest.mark.asyncio
+        @pytest.mark.it('appends to a missing array property')
+        async def test_set_append(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "name": "", "z": "100", "rules": [
+                    {"t": "set", "p": "payload.items[]", "pt": "msg", "to": "a", "tot": "str"},
+                    {"t": "move", "p": "topic", "pt": "msg", "to": "payload.items[]", "tot": "msg"}],
+                    "action": "", "property": "", "from": "", "to": "", "reg": False, "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": {}, "topic": "b"}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+            assert msgs[0]["payload"]["items"] == ["a", "b"]
+            assert "topic" not in msgs[0]
+
         @pytest.mark.asyncio
         @pytest.mark.it('sets the value of a nested message property using a message property')
         async def test_it_sets_the_value_of_a_nested_message_property_using_a_message_property(self):
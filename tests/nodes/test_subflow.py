@@ -168,7 +168,7 @@ class TestSubflow:
                     {"name": "KJ", "type": "json", "value": "[1,2,3]"},
                     {"name": "Kb", "type": "bin", "value": "[65,65]"},
                     {"name": "Ke", "type": "env", "value": "KS"},
-                    # FIXME {"name": "Kj", "type": "jsonata", "value": "1+2"}
+                    {"name": "Kj", "type": "jsonata", "value": "1+2"}
                 ],
                 "wires": [["2"]]},
             {"id": "2", "z": "999", "type": "test-once", "wires": []},
@@ -178,7 +178,7 @@ class TestSubflow:
              "env": [{"name": "KS", "type": "str", "value": "STR"}]
              },
             {"id": "101", "z": "100", "type": "function",
-                "func": "msg.VE = env.get('Ke'); msg.VS = env.get('KS'); msg.VN = env.get('KN'); msg.VB = env.get('KB'); msg.VJ = env.get('KJ'); msg.Vb = env.get('Kb'); /*msg.Vj = env.get('Kj');*/ return msg;",
+                "func": "msg.VE = env.get('Ke'); msg.VS = env.get('KS'); msg.VN = env.get('KN'); msg.VB = env.get('KB'); msg.VJ = env.get('KJ'); msg.Vb = env.get('Kb'); msg.Vj = env.get('Kj'); return msg;",
                 "wires": []
              }
         ]
@@ -194,7 +194,7 @@ class TestSubflow:
         assert msg["VJ"] == [1, 2, 3]
         assert msg["Vb"] == [65, 65]
         assert msg["VE"] == "STR"
-        # assert msg["Vj"] == 3 #FIXME
+        assert msg["Vj"] == 3
 
     @pytest.mark.asyncio
     @pytest.mark.it('should overwrite env var of subflow template by env var of subflow instance')
@@ -0,0 +1,43 @@
+import datetime
+import pytest
+
+from tests import *
+
+FLOWS = [
+    {"id": "100", "type": "tab"},
+    {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+    {"id": "2", "z": "100", "type": "test-once"},
+]
+
+
+@pytest.mark.describe('pymod message formats')
+class TestMsgFormats:
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should keep bytes and dates in the native format')
+    async def test_0001(self):
+        date = datetime.datetime(2024, 5, 6, 7, 8, 9, 123000, tzinfo=datetime.timezone.utc)
+        msg = {"payload": b"\x00\x01\xfe\xff", "date": date, "items": [bytearray(b"ab"), 1, 2.5, None]}
+        msgs = await edgelink.run_flows_once(1, 3.0, FLOWS, [("1", msg)], TEST_EDGELINLKD_CONFIG, format="native")
+        assert msgs[0]["payload"] == b"\x00\x01\xfe\xff"
+        assert msgs[0]["date"] == date
+        assert msgs[0]["items"] == [b"ab", 1, 2.5, None]
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should convert bytes to lists in the json format')
+    async def test_0002(self):
+        msgs = await edgelink.run_flows_once(1, 3.0, FLOWS, [("1", {"payload": [0, 1, 2]})], TEST_EDGELINLKD_CONFIG)
+        assert msgs[0]["payload"] == [0, 1, 2]
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should reject an unknown format')
+    async def test_0003(self):
+        with pytest.raises(ValueError):
+            await edgelink.run_flows_once(1, 3.0, FLOWS, [], TEST_EDGELINLKD_CONFIG, format="xml")
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should reject an unsupported type in the native format')
+    async def test_0004(self):
+        with pytest.raises(TypeError, match="set"):
+            await edgelink.run_flows_once(1, 3.0, FLOWS, [("1", {"payload": {1, 2}})], TEST_EDGELINLKD_CONFIG,
+                                          format="native")
@@ -0,0 +1,36 @@
+import asyncio
+import pytest
+
+from tests import *
+
+FLOWS = [
+    {"id": "100", "type": "tab"},
+    {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+    {"id": "2", "z": "100", "type": "test-once"},
+]
+
+
+@pytest.mark.describe('pymod stream_flows')
+class TestStreamFlows:
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should yield the messages as they arrive')
+    async def test_0001(self):
+        injections = [("1", {"payload": i}) for i in range(3)]
+        stream = await edgelink.stream_flows(FLOWS, injections, TEST_EDGELINLKD_CONFIG)
+        payloads = []
+        async for msg in stream:
+            payloads.append(msg["payload"])
+            if len(payloads) == 3:
+                await stream.stop()
+        assert sorted(payloads) == [0, 1, 2]
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should stop the engine when the pending iteration is cancelled')
+    async def test_0002(self):
+        stream = await edgelink.stream_flows(FLOWS, [], TEST_EDGELINLKD_CONFIG)
+        with pytest.raises(asyncio.TimeoutError):
+            await asyncio.wait_for(stream.__anext__(), 0.2)
+        await asyncio.sleep(0.2)
+        with pytest.raises(StopAsyncIteration):
+            await asyncio.wait_for(stream.__anext__(), 1.0)